@@ -0,0 +1,121 @@
+//! Throughput benchmarks for the hot path of `LogBoop`
+//!
+//! [`determine_date`] is called once per line of every file processed, and
+//! [`split_lines`] is the loop driving it, so those are what we measure.
+//!
+//! Run with `cargo bench`. Benchmarks are measured by criterion, which
+//! reports the line throughput of every one of them, and how it changed
+//! since the last run (so that a change can be compared against `master`).
+use std::hint::black_box;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+
+use logboop::options::Options;
+use logboop::process::{determine_date, split_lines, CustomFormat, LogType};
+
+/// Number of lines in the synthetic input of every benchmark
+const LINES: usize = 100_000;
+
+/// Build one representative line for a given log type, on a given day
+fn sample_line(lt: &LogType, day: usize) -> String {
+    let day = 1 + day % 28;
+    match lt {
+        LogType::Syslog =>
+            format!("May {:2} 02:07:16 host sshd[1234]: Connection closed", day),
+        LogType::Syslog5424 =>
+            format!("<34>1 2020-05-{:02}T02:07:16.003Z host su - ID47 - 'su root' failed", day),
+        LogType::Iso =>
+            format!("2020-05-{:02} 02:07:16,656 fail2ban.filter [1234]: INFO", day),
+        LogType::IsoBasic =>
+            format!("202005{:02} 02:07:16 watchdog: ping", day),
+        LogType::IisW3c =>
+            format!("2020-05-{:02} 02:07:16 10.0.0.1 GET /index.html 200", day),
+        LogType::DayMonthYear2 =>
+            format!("{:02}-May-20 02:07:16 eth0: link up", day),
+        LogType::ApacheAccess =>
+            format!("127.0.0.1 - - [{:02}/May/2020:02:07:16 +0200] \"GET / HTTP/1.1\" 200 42", day),
+        LogType::ApacheVhost =>
+            format!("example.com:443 127.0.0.1 - - [{:02}/May/2020:02:07:16 +0200] \"GET / HTTP/1.1\" 200 42", day),
+        LogType::NginxAccess =>
+            format!("10.0.0.2 127.0.0.1 - - [{:02}/May/2020:02:07:16] \"GET / HTTP/1.1\" 200 42", day),
+        LogType::HaproxyHttp =>
+            format!("10.0.1.2:33317 [{:02}/May/2020:02:07:16.656] http-in static/srv1 10/0/30/69/109 200 2750", day),
+        LogType::ApacheError =>
+            format!("[{} May {:02} 02:07:16.656808 2020] [core:error] [pid 1234] oops",
+                    chrono::NaiveDate::from_ymd_opt(2020, 5, day as u32).unwrap().format("%a"),
+                    day),
+        LogType::GrafanaLogs =>
+            format!("t=2020-05-{:02}T02:07:16+0200 lvl=info msg=\"Request Completed\"", day),
+        LogType::MysqlSlow =>
+            format!("# Time: 2020-05-{:02}T02:07:16.656808Z", day),
+        LogType::JournalExport =>
+            format!("__REALTIME_TIMESTAMP={}000000\nMESSAGE=hi\n", 1_588_291_200 + day * 86400),
+        LogType::CiscoSyslog =>
+            format!("<189>123: host: *May {:2} 02:07:16.003: %SYS-5-CONFIG_I: Configured", day),
+        LogType::EcsJson =>
+            format!("{{\"@timestamp\":\"2020-05-{:02}T02:07:16.656Z\",\"@version\":\"1\",\"message\":\"hi\"}}", day),
+        LogType::DockerJson =>
+            format!("{{\"log\":\"hi\\n\",\"stream\":\"stdout\",\"time\":\"2020-05-{:02}T02:07:16.656808123Z\"}}", day),
+        LogType::Json =>
+            format!("{{\"level\":30,\"time\":\"2020-05-{:02}T02:07:16.656Z\",\"msg\":\"hi\"}}", day),
+        LogType::Custom(_) =>
+            format!("|202005{:02} 02h07| request done", day)
+    }
+}
+
+/// Build a synthetic input of [`LINES`] lines, changing day every 1000 lines
+fn sample_input(lt: &LogType) -> String {
+    (0..LINES)
+        .map(|n| sample_line(lt, n / 1000))
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+/// Date every line of an input, one log type after the other
+fn bench_determine_date(c: &mut Criterion) {
+    let mut group = c.benchmark_group("determine_date");
+    group.throughput(Throughput::Elements(LINES as u64));
+    for lt in LogType::builtins() {
+        let input = sample_input(lt);
+        let lines: Vec<&str> = input.lines().collect();
+        group.bench_with_input(BenchmarkId::from_parameter(format!("{:?}", lt)), &lines,
+                               |b, lines| b.iter(|| for line in lines {
+            let _ = black_box(determine_date(lt, black_box(line)));
+        }));
+    }
+    group.finish();
+}
+
+/// Split a whole input into buckets, one log type after the other
+fn bench_split_lines(c: &mut Criterion) {
+    let mut group = c.benchmark_group("split_lines");
+    group.throughput(Throughput::Elements(LINES as u64));
+    for lt in LogType::builtins() {
+        let input = sample_input(lt);
+        group.bench_with_input(BenchmarkId::from_parameter(format!("{:?}", lt)), &input,
+                               |b, input| b.iter(|| {
+            split_lines(lt, black_box(input.as_bytes()), &Options::default(),
+                        |_| Ok(std::io::sink())).unwrap();
+        }));
+    }
+
+    // Custom formats are only known to the options of the run
+    let custom = LogType::Custom("myapp".to_owned());
+    let options = Options {
+        formats: vec![CustomFormat {
+            name: "myapp".to_owned(),
+            regex: r"^\|(?P<ts>\d{8} \d{2}h\d{2})\|".parse().unwrap(),
+            format: "%Y%m%d %Hh%M".to_owned()
+        }],
+        ..Options::default()
+    };
+    let input = sample_input(&custom);
+    group.bench_function("Custom", |b| b.iter(|| {
+        split_lines(&custom, black_box(input.as_bytes()), &options,
+                    |_| Ok(std::io::sink())).unwrap();
+    }));
+    group.finish();
+}
+
+criterion_group!(benches, bench_determine_date, bench_split_lines);
+criterion_main!(benches);
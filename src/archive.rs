@@ -0,0 +1,167 @@
+//! Module to write (and read back) simple tar archives
+//!
+//! ## Provided by this module
+//!
+//! Bundling output files together only requires regular files, with a
+//! name, a size, a mode and a modification time. This module provides a
+//! streaming [`TarWriter`] able to add such files to an archive, or to copy
+//! the entries of an existing archive, and a [`read_entries`] function
+//! reading the regular files of an archive back in memory. The archives
+//! themselves are read and written by the [`tar`] crate.
+//!
+//! ## Example
+//!
+//! ```rust
+//! # use logboop::archive::{TarWriter, read_entries};
+//! let mut writer = TarWriter::new(Vec::new());
+//! writer.append("hello.txt", 6, 0o644, 0, &mut "hello\n".as_bytes())?;
+//! let archive = writer.finish()?;
+//! let entries = read_entries(&archive[..])?;
+//! assert_eq!(entries, vec![("hello.txt".to_owned(), b"hello\n".to_vec())]);
+//! # Ok::<(), std::io::Error>(())
+//! ```
+//!
+//! ## Details of imports
+//!
+//! Besides the [I/O prelude](std::io::prelude), along with
+//! [`ErrorKind`](std::io::ErrorKind) to report entries missing data, the
+//! [`Archive`], [`Builder`], [`EntryType`] and [`Header`] of [`tar`] are
+//! used.
+use std::io::prelude::*;
+use std::io::ErrorKind;
+
+use tar::{Archive, Builder, EntryType, Header};
+
+/// Streaming writer of a tar archive
+///
+/// Entries are written one after the other with [`TarWriter::append`] or
+/// copied from another archive with [`TarWriter::copy_entries`], and the
+/// archive must be closed with [`TarWriter::finish`].
+pub struct TarWriter<W: Write> {
+    /// Where the archive is written
+    inner: Builder<W>
+}
+
+impl<W: Write> TarWriter<W> {
+    /// Constructor for the `TarWriter`
+    pub fn new(inner: W) -> TarWriter<W> {
+        TarWriter { inner: Builder::new(inner) }
+    }
+
+    /// Add a regular file to the archive
+    ///
+    /// # Arguments
+    /// The entry is described by its `name`, its `size` in bytes, its Unix
+    /// `mode` and its modification time `mtime` (seconds since the epoch).
+    /// Exactly `size` bytes are then streamed from `data`.
+    ///
+    /// Headers follow the GNU format, so that names of any length (through
+    /// its long name extension) and sizes of 8 GiB or more fit.
+    ///
+    /// ```rust
+    /// # use logboop::archive::{TarWriter, read_entries};
+    /// let name = format!("{}/{}", "d".repeat(80), "f".repeat(180));
+    /// let mut writer = TarWriter::new(Vec::new());
+    /// writer.append(&name, 0, 0o644, 0, &mut std::io::empty())?;
+    /// let archive = writer.finish()?;
+    /// assert_eq!(read_entries(&archive[..])?, vec![(name, Vec::new())]);
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    ///
+    /// # Errors
+    /// An `UnexpectedEof` error is returned when `data` holds less than `size`
+    /// bytes, the archive being then left unusable. Any other I/O error flows
+    /// upwards.
+    pub fn append<R: Read>(&mut self, name: &str, size: u64, mode: u32,
+                           mtime: u64, data: &mut R) -> std::io::Result<()> {
+        let mut header = Header::new_gnu();
+        header.set_entry_type(EntryType::Regular);
+        header.set_size(size);
+        header.set_mode(mode);
+        header.set_mtime(mtime);
+        self.inner.append_data(&mut header, name,
+                               Exactly { inner: data, name, size, left: size })
+    }
+
+    /// Copy all of the entries of an existing archive
+    ///
+    /// Entries are streamed one after the other, whatever their type, and
+    /// the end-of-archive marker of the input is not copied, so that more
+    /// entries can be added afterwards.
+    ///
+    /// # Errors
+    /// An `InvalidData` error is returned for a malformed archive, and any
+    /// other I/O error flows upwards.
+    pub fn copy_entries<R: Read>(&mut self, input: R) -> std::io::Result<()> {
+        for entry in Archive::new(input).entries()? {
+            let mut entry = entry?;
+            let mut header = entry.header().clone();
+            let path = entry.path()?.into_owned();
+            self.inner.append_data(&mut header, path, &mut entry)?;
+        }
+        Ok(())
+    }
+
+    /// Write the end-of-archive marker, and give back the inner writer
+    ///
+    /// # Errors
+    /// Any I/O error while writing the marker flows upwards.
+    pub fn finish(self) -> std::io::Result<W> {
+        self.inner.into_inner()
+    }
+}
+
+/// Read all of the regular files of an archive in memory
+///
+/// Every regular file is returned as its name and its contents, in the
+/// order of the archive. Entries of any other type (like directories or
+/// links) are skipped.
+///
+/// # Errors
+/// An `InvalidData` error is returned for a malformed archive, and any
+/// other I/O error flows upwards.
+pub fn read_entries<R: Read>(input: R)
+    -> std::io::Result<Vec<(String, Vec<u8>)>> {
+    let mut entries = Vec::new();
+    for entry in Archive::new(input).entries()? {
+        let mut entry = entry?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let name = entry.path()?.to_string_lossy().into_owned();
+        let mut data = Vec::new();
+        entry.read_to_end(&mut data)?;
+        entries.push((name, data));
+    }
+    Ok(entries)
+}
+
+/// Reader of exactly `size` bytes of an entry, failing with
+/// `UnexpectedEof` should its data end before
+struct Exactly<'n, R> {
+    /// Where the data of the entry is read from
+    inner: R,
+    /// Name of the entry, to report missing data
+    name: &'n str,
+    /// Size of the entry
+    size: u64,
+    /// Bytes still to be read
+    left: u64
+}
+
+impl<R: Read> Read for Exactly<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let wanted = self.left.min(buf.len() as u64) as usize;
+        if wanted == 0 {
+            return Ok(0);
+        }
+        let read = self.inner.read(&mut buf[..wanted])?;
+        if read == 0 {
+            return Err(std::io::Error::new(ErrorKind::UnexpectedEof,
+                format!("{} ended after {} bytes instead of {}",
+                        self.name, self.size - self.left, self.size)));
+        }
+        self.left -= read as u64;
+        Ok(read)
+    }
+}
@@ -9,15 +9,30 @@
 //! (named after their original counterparts in my script,
 //! themself named after the command typically used to perform this operation).
 //!
+//! Their Zstandard counterparts, [`dezstd`] and [`zstd`](zstd()),
+//! work the same way with `.zst` files, and their bzip2 counterparts,
+//! [`debzip2`] and [`bzip2`](bzip2()), with `.bz2` files, and [`dexz`]
+//! inflates `.xz` files. Compressed files which are not named so are
+//! recognized by [`format_of`] (or [`is_gzip`]), and [`inflated_len`] tells
+//! the size of a compressed file once inflated, to check it.
+//!
+//! Every file they write is first written to a `.tmp` file next to it, and
+//! only renamed into place once complete, so that a run dying in the middle
+//! never leaves a truncated file behind under the final name.
+//!
 //! ## Example
 //!
 //! They can be invoked thusly :
-//! ```rust
-//! fn function_that_returns_error() -> std::io::Result<()> {
+//! ```rust,no_run
+//! # use std::path::Path;
+//! # use logboop::compress::{degunzip, gunzip};
+//! # use logboop::error::LogBoopError;
+//! # use flate2::Compression;
+//! fn function_that_returns_error() -> Result<(), LogBoopError> {
 //!     let p = Path::new("my_file.gz");
-//!     degunzip(&p)?;
+//!     degunzip(&p, false)?;
 //!     let u = Path::new("my_file");
-//!     gunzip(&u)
+//!     gunzip(&u, Compression::default(), false)
 //! }
 //! ```
 //!
@@ -28,104 +43,535 @@
 //! We need things to do I/O, and some fs manipulation
 //!  - The [I/O prelude](std::io::prelude)
 //!  - [Paths](std::path::Path)
-//!  - filesystem manipulation tools like [`OpenOptions`](std::fs::OpenOptions)
-//!  (used to chose write/create modes), [`File`](std::fs::File), and
-//!  [`remove_file`](std::fs::remove_file)
+//!  - The [`UNIX_EPOCH`] and [`TryFrom`], to write
+//!    modification times in gzip headers
+//!  - filesystem manipulation tools like [`File`](std::fs::File),
+//!    [`rename`](std::fs::rename) and [`remove_file`](std::fs::remove_file)
 //!
 //! ### Crate imports
 //!
 //! In line with the statements from the previous section, we also import
 //!  - Our own [`filesystem`](crate::filesystem), to use the [`add_extension`](crate::filesystem::add_extension)
-//!  method when creating the compressed file
-//!  - The [`GzEncoder`] and [`GzDecoder`]
+//!    method when creating the compressed file, and
+//!    [`copy_permissions`](crate::filesystem::copy_permissions) to give it
+//!    the permissions of the original, and
+//!    [`remove_source`](crate::filesystem::remove_source) to remove it
+//!  - Our own [`LogBoopError`], returned by every function
+//!  - The [`GzBuilder`] and [`MultiGzDecoder`], reading every member of a
+//!    file, as written by `cat a.gz b.gz`
 //!  - The structure [`Compression`] from `flate2` to
-//!  indicate a default level of compression
+//!    indicate the level of compression
+//!  - The Zstandard [`Encoder`](ZstdEncoder) and [`Decoder`](ZstdDecoder)
+//!  - The bzip2 [`BzEncoder`] and [`MultiBzDecoder`], reading every stream
+//!    of a file, along with its own [`Compression`](BzCompression) level
+//!  - The [`XzDecoder`], reading every stream of a file too
+use std::convert::TryFrom;
 use std::io::prelude::*;
+use std::io::{BufReader, BufWriter};
 use std::path::Path;
-use std::fs::{OpenOptions, File, remove_file};
+use std::fs::{File, rename, remove_file};
+use std::time::UNIX_EPOCH;
 
+use crate::error::LogBoopError;
 use crate::filesystem;
-use flate2::read::GzDecoder;
-use flate2::write::GzEncoder;
+use flate2::read::MultiGzDecoder;
 use flate2::Compression;
+use flate2::GzBuilder;
+use zstd::stream::read::Decoder as ZstdDecoder;
+use zstd::stream::write::Encoder as ZstdEncoder;
+use bzip2::read::MultiBzDecoder;
+use bzip2::write::BzEncoder;
+use bzip2::Compression as BzCompression;
+use xz2::read::XzDecoder;
+
+/// Extensions of the compression formats, along with the magic number
+/// their files start with
+const MAGIC_NUMBERS: [(&str, &[u8]); 4] = [
+    ("gz", &[0x1f, 0x8b]),
+    ("zst", &[0x28, 0xb5, 0x2f, 0xfd]),
+    ("bz2", b"BZh"),
+    ("xz", &[0xfd, b'7', b'z', b'X', b'Z', 0x00])
+];
+
+/// Check whether a file starts like GZ data, whatever its name
+///
+/// # Behaviour
+/// The first two bytes of the file are compared with the magic number of
+/// the GZ format (`1f 8b`). Shorter files are never GZ data.
+///
+/// # Exceptions
+/// This method may throw an I/O error when opening or reading the file.
+///
+/// # Example
+/// ```no_run
+/// # use std::path::Path;
+/// # use logboop::compress::is_gzip;
+/// // Rotated, and compressed without being renamed
+/// assert!(is_gzip(Path::new("/var/log/messages.1"))?);
+/// # Ok::<(), std::io::Error>(())
+/// ```
+pub fn is_gzip(filepath: &Path) -> std::io::Result<bool> {
+    let mut magic: Vec<u8> = Vec::with_capacity(2);
+    File::open(filepath)?.take(2).read_to_end(&mut magic)?;
+    Ok(magic == [0x1f, 0x8b])
+}
+
+/// Tell the compression format of a file from its first bytes, whatever
+/// its name
+///
+/// # Behaviour
+/// The first bytes of the file are compared with the magic numbers of the
+/// GZ, Zstandard, bzip2 and XZ formats, and the extension of the format
+/// they match (`gz`, `zst`, `bz2` or `xz`) is returned. `None` is returned
+/// for files starting like none of them.
+///
+/// # Exceptions
+/// This method may throw an I/O error when opening or reading the file.
+///
+/// # Example
+/// ```no_run
+/// # use std::path::Path;
+/// # use logboop::compress::format_of;
+/// // Compressed by logrotate with `compresscmd xz`, but not renamed
+/// assert_eq!(format_of(Path::new("/var/log/messages.1"))?, Some("xz"));
+/// # Ok::<(), std::io::Error>(())
+/// ```
+pub fn format_of(filepath: &Path) -> std::io::Result<Option<&'static str>> {
+    let mut magic: Vec<u8> = Vec::with_capacity(6);
+    File::open(filepath)?.take(6).read_to_end(&mut magic)?;
+    Ok(MAGIC_NUMBERS.iter()
+       .find(|(_, number)| magic.starts_with(number))
+       .map(|(extension, _)| *extension))
+}
+
+/// Count the bytes of a compressed file once inflated
+///
+/// # Behaviour
+/// Files whose name ends with `.zst` are decoded as Zstandard data, those
+/// whose name ends with `.bz2` as bzip2 data, those whose name ends with
+/// `.xz` as XZ data, and all others as GZ data, without writing anything.
+///
+/// # Exceptions
+/// This method may throw an [`IoError`](LogBoopError::IoError) when opening
+/// the file, and a [`DecompressError`](LogBoopError::DecompressError) when
+/// it cannot be decoded.
+///
+/// # Example
+/// ```no_run
+/// # use std::path::Path;
+/// # use logboop::compress::inflated_len;
+/// let size = inflated_len(Path::new("syslog-2020-05-17.gz"))?;
+/// # Ok::<(), logboop::error::LogBoopError>(())
+/// ```
+pub fn inflated_len(filepath: &Path) -> Result<u64, LogBoopError> {
+    let fptr = File::open(filepath)?;
+    let decompress_error = |source| LogBoopError::DecompressError {
+        path: filepath.to_path_buf(), source };
+    match filepath.extension().and_then(|ext| ext.to_str()) {
+        Some("zst") => std::io::copy(&mut ZstdDecoder::new(fptr)?, &mut std::io::sink()),
+        Some("bz2") => std::io::copy(&mut MultiBzDecoder::new(fptr), &mut std::io::sink()),
+        Some("xz") => std::io::copy(&mut XzDecoder::new_multi_decoder(fptr),
+                                    &mut std::io::sink()),
+        _ => std::io::copy(&mut MultiGzDecoder::new(fptr), &mut std::io::sink())
+    }.map_err(decompress_error)
+}
+
+/// Write a whole file through a temporary file, renamed into place once
+/// complete
+///
+/// # Behaviour
+/// `write` is given the `.tmp` file next to `destination` (created, or
+/// truncated if a previous run left it behind). Once it succeeds, the file
+/// is given the modification time of `source`, so that the year of syslog
+/// lines is still inferred from it once inflated (see
+/// [`infer_year`](crate::process::infer_year)), and its permissions (see
+/// [`copy_permissions`](crate::filesystem::copy_permissions)), and renamed
+/// to `destination`, which is atomic on the same filesystem. Should it fail,
+/// the `.tmp` file is removed, and `destination` left as it was.
+///
+/// # Exceptions
+/// Errors of `write` flow upwards, as well as any I/O error while creating,
+/// renaming or removing the `.tmp` file.
+fn write_then_rename<F>(destination: &Path, source: &Path, write: F)
+    -> Result<(), LogBoopError>
+    where F: FnOnce(&mut File) -> Result<(), LogBoopError> {
+    let mut temporary = destination.to_path_buf();
+    filesystem::add_extension(&mut temporary, "tmp");
+    let mtime = source.metadata()?.modified().ok();
+    let mut out_fptr = File::create(&temporary)?;
+    if let Err(e) = write(&mut out_fptr) {
+        drop(out_fptr);
+        remove_file(&temporary)?;
+        return Err(e);
+    }
+    if let Some(mtime) = mtime {
+        out_fptr.set_modified(mtime)?;
+    }
+    drop(out_fptr);
+    filesystem::copy_permissions(source, &temporary)?;
+    rename(&temporary, destination)?;
+    Ok(())
+}
 
 /// Inflate a given file with default GZ compression
 ///
 /// # Arguments
 /// Given a [`&Path`](std::path::Path), find and inflate the contents
-/// using a GZ decoder.
+/// using a GZ decoder. Files made of several GZ members one after the other
+/// (like `cat a.gz b.gz > c.gz`) are inflated whole.
+///
+/// Unless `keep_source` is set, the original file is removed once inflated.
 ///
 /// # Exceptions
-/// This method may throw an I/O [`Error`](std::io::Error) when opening
-/// the file, reading its content, decoding said contents, creating the
-/// output file, writing to it, or removing the original file.
+/// This method may throw an [`IoError`](LogBoopError::IoError) when opening
+/// the file, reading its content, creating the output file, writing to it, or
+/// removing the original file, and a
+/// [`DecompressError`](LogBoopError::DecompressError) when decoding said
+/// contents.
 ///
 /// # Example
 /// This is a minimal example.
-/// ```
-/// let p: Path = Path::new("my_file.gz");
-/// if let Err(e) = degunzip(&p) {
+/// ```no_run
+/// # use std::path::Path;
+/// # use logboop::compress::degunzip;
+/// let p = Path::new("my_file.gz");
+/// if let Err(e) = degunzip(&p, false) {
 ///     eprintln!("Error when inflating : {}", e);
 /// }
 /// // There must now be a file called "my_file"
 /// ```
-pub fn degunzip(filepath: &Path) -> std::io::Result<()> {
+pub fn degunzip(filepath: &Path, keep_source: bool) -> Result<(), LogBoopError> {
     let mut fptr = File::open(filepath)?;
     let mut outbuf: Vec<u8> = Vec::new();
     fptr.read_to_end(&mut outbuf)?;
-    // Get a GZ decoder
-    let mut decoder = GzDecoder::new(&outbuf[..]);
+    // Get a GZ decoder, going on past the end of every member
+    let mut decoder = MultiGzDecoder::new(&outbuf[..]);
     let mut sout: Vec<u8> = Vec::new();
-    decoder.read_to_end(&mut sout)?;
+    decoder.read_to_end(&mut sout)
+        .map_err(|source| LogBoopError::DecompressError {
+            path: filepath.to_path_buf(), source })?;
 
     // Build the file name of the destination
     let final_destination = filepath.with_extension("");
-    let mut out_fptr = File::create(final_destination)?;
-    out_fptr.write_all(&sout)?;
+    write_then_rename(&final_destination, filepath,
+                      |out_fptr| Ok(out_fptr.write_all(&sout)?))?;
 
     // And remove the original
-    remove_file(filepath)
+    if keep_source {
+        return Ok(());
+    }
+    Ok(filesystem::remove_source(filepath)?)
 }
 
-/// Compress a given file with default GZ compression
+/// Compress a given file with GZ compression
 ///
 /// # Arguments
 /// Given a [`&Path`](std::path::Path), find and deflate the contents
-/// using a GZ decoder.
+/// using a GZ encoder, at the given [`Compression`] level
+/// (`Compression::default()` unless you have a reason to pick another).
+///
+/// The gzip header records the name and modification time of the original
+/// file, which `gunzip -N` restores and `gzip -l -v` shows.
+///
+/// Unless `keep_source` is set, the original file is removed once compressed.
 ///
 /// # Exceptions
-/// This method may throw an I/O [`Error`](std::io::Error) when opening
+/// This method may throw an [`IoError`](LogBoopError::IoError) when opening
 /// the file, reading its content, creating the output file and opening it,
 /// writing the content of the first file into the encoder, finalizing the
 /// encoding, and removing the original file.
 ///
 /// # Example
 /// This is a minimal example.
-/// ```
-/// let p: Path = Path::new("my_file");
-/// if let Err(e) = gunzip(&p) {
+/// ```no_run
+/// # use std::path::Path;
+/// # use logboop::compress::gunzip;
+/// # use flate2::Compression;
+/// let p = Path::new("my_file");
+/// // Cold archive, squeeze it as much as possible
+/// if let Err(e) = gunzip(&p, Compression::best(), false) {
 ///     eprintln!("Error when compressing : {}", e);
 /// }
 /// // There must now be a file called "my_file.gz"
 /// ```
-pub fn gunzip(filepath: &Path) -> std::io::Result<()> {
+pub fn gunzip(filepath: &Path, level: Compression, keep_source: bool)
+    -> Result<(), LogBoopError> {
     // Read the data from the raw file
     let mut fptr = File::open(filepath)?;
     let mut outbuf: Vec<u8> = Vec::new();
     fptr.read_to_end(&mut outbuf)?;
+    // Seconds since the epoch fit the header until 2106, and 0 means unknown
+    let mtime = fptr.metadata()?.modified().ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .and_then(|since| u32::try_from(since.as_secs()).ok())
+        .unwrap_or(0);
+    let name = filepath.file_name().unwrap_or_default().to_string_lossy().into_owned();
     // Open the output file
     let mut owned_path = filepath.to_path_buf();
     filesystem::add_extension(&mut owned_path, "gz");
-    let out_fptr = OpenOptions::new()
-        .write(true)
-        .create(true)
-        .open(owned_path)?;
+    write_then_rename(&owned_path, filepath, |out_fptr| {
+        // Get a GZ encoder, naming the original file in the header
+        let mut encoder = GzBuilder::new().filename(name).mtime(mtime)
+            .write(out_fptr, level);
+        encoder.write_all(&outbuf)?;
+        encoder.finish()?;
+        Ok(())
+    })?;
+
+    // Remove the file
+    if keep_source {
+        return Ok(());
+    }
+    Ok(filesystem::remove_source(filepath)?)
+}
+
+/// Inflate a given Zstandard file
+///
+/// # Arguments
+/// Given a [`&Path`](std::path::Path), find and inflate the contents
+/// using a Zstandard decoder.
+///
+/// Unless `keep_source` is set, the original file is removed once inflated.
+///
+/// # Exceptions
+/// This method may throw an [`IoError`](LogBoopError::IoError) when opening
+/// the file, reading its content, creating the output file, writing to it, or
+/// removing the original file, and a
+/// [`DecompressError`](LogBoopError::DecompressError) when decoding said
+/// contents.
+///
+/// # Example
+/// This is a minimal example.
+/// ```no_run
+/// # use std::path::Path;
+/// # use logboop::compress::dezstd;
+/// let p = Path::new("my_file.zst");
+/// if let Err(e) = dezstd(&p, false) {
+///     eprintln!("Error when inflating : {}", e);
+/// }
+/// // There must now be a file called "my_file"
+/// ```
+pub fn dezstd(filepath: &Path, keep_source: bool) -> Result<(), LogBoopError> {
+    let fptr = File::open(filepath)?;
+    // Get a Zstandard decoder, which reads every frame of the file
+    let mut decoder = ZstdDecoder::new(fptr)?;
+    let mut sout: Vec<u8> = Vec::new();
+    decoder.read_to_end(&mut sout)
+        .map_err(|source| LogBoopError::DecompressError {
+            path: filepath.to_path_buf(), source })?;
+
+    // Build the file name of the destination
+    let final_destination = filepath.with_extension("");
+    write_then_rename(&final_destination, filepath,
+                      |out_fptr| Ok(out_fptr.write_all(&sout)?))?;
+
+    // And remove the original
+    if keep_source {
+        return Ok(());
+    }
+    Ok(filesystem::remove_source(filepath)?)
+}
+
+/// Compress a given file with default Zstandard compression
+///
+/// # Arguments
+/// Given a [`&Path`](std::path::Path), find and deflate the contents
+/// using a Zstandard encoder.
+///
+/// Unless `keep_source` is set, the original file is removed once compressed.
+///
+/// # Exceptions
+/// This method may throw an [`IoError`](LogBoopError::IoError) when opening
+/// the file, reading its content, creating the output file and opening it,
+/// writing the content of the first file into the encoder, finalizing the
+/// encoding, and removing the original file.
+///
+/// # Example
+/// This is a minimal example.
+/// ```no_run
+/// # use std::path::Path;
+/// # use logboop::compress::zstd;
+/// let p = Path::new("my_file");
+/// if let Err(e) = zstd(&p, false) {
+///     eprintln!("Error when compressing : {}", e);
+/// }
+/// // There must now be a file called "my_file.zst"
+/// ```
+pub fn zstd(filepath: &Path, keep_source: bool) -> Result<(), LogBoopError> {
+    // Read the data from the raw file
+    let mut fptr = File::open(filepath)?;
+    let mut outbuf: Vec<u8> = Vec::new();
+    fptr.read_to_end(&mut outbuf)?;
+    // Open the output file
+    let mut owned_path = filepath.to_path_buf();
+    filesystem::add_extension(&mut owned_path, "zst");
+    write_then_rename(&owned_path, filepath, |out_fptr| {
+        // Get a Zstandard encoder, with its default level
+        let mut encoder = ZstdEncoder::new(out_fptr, 0)?;
+        encoder.write_all(&outbuf)?;
+        encoder.finish()?;
+        Ok(())
+    })?;
+
+    // Remove the file
+    if keep_source {
+        return Ok(());
+    }
+    Ok(filesystem::remove_source(filepath)?)
+}
+
+/// Inflate a given bzip2 file
+///
+/// # Arguments
+/// Given a [`&Path`](std::path::Path), find and inflate the contents
+/// using a bzip2 decoder. Files made of several bzip2 streams (like those
+/// appended to) are inflated whole.
+///
+/// Unlike [`degunzip`], the file is inflated as it is read, so that memory
+/// stays bounded whatever its size. Should it fail to decode, what was
+/// inflated of it is removed.
+///
+/// Unless `keep_source` is set, the original file is removed once inflated.
+///
+/// # Exceptions
+/// This method may throw an [`IoError`](LogBoopError::IoError) when opening
+/// the file, creating the output file, writing to it, or removing the
+/// original file, and a [`DecompressError`](LogBoopError::DecompressError)
+/// when decoding its contents.
+///
+/// # Example
+/// This is a minimal example.
+/// ```no_run
+/// # use std::path::Path;
+/// # use logboop::compress::debzip2;
+/// let p = Path::new("my_file.bz2");
+/// if let Err(e) = debzip2(&p, false) {
+///     eprintln!("Error when inflating : {}", e);
+/// }
+/// // There must now be a file called "my_file"
+/// ```
+pub fn debzip2(filepath: &Path, keep_source: bool) -> Result<(), LogBoopError> {
+    let mut decoder = MultiBzDecoder::new(BufReader::new(File::open(filepath)?));
+
+    // Build the file name of the destination, and inflate into it
+    let final_destination = filepath.with_extension("");
+    write_then_rename(&final_destination, filepath, |out_fptr| {
+        let mut out_fptr = BufWriter::new(out_fptr);
+        let mut buffer = [0u8; 64 * 1024];
+        loop {
+            let read = decoder.read(&mut buffer)
+                .map_err(|source| LogBoopError::DecompressError {
+                    path: filepath.to_path_buf(), source })?;
+            if read == 0 {
+                return Ok(out_fptr.flush()?);
+            }
+            out_fptr.write_all(&buffer[..read])?;
+        }
+    })?;
 
-    // Get a GZ encoder
-    let mut encoder = GzEncoder::new(out_fptr, Compression::default());
-    encoder.write_all(&outbuf)?;
-    encoder.finish()?;
+    // And remove the original
+    if keep_source {
+        return Ok(());
+    }
+    Ok(filesystem::remove_source(filepath)?)
+}
+
+/// Inflate a given XZ file
+///
+/// # Arguments
+/// Given a [`&Path`](std::path::Path), find and inflate the contents
+/// using an XZ decoder, reading every stream of the file, into the file
+/// of the same name without its `.xz` extension. The file is inflated as it
+/// is read, so that memory stays bounded whatever its size. Should it fail
+/// to decode, what was inflated of it is removed.
+///
+/// Unless `keep_source` is set, the original file is removed once inflated.
+///
+/// # Exceptions
+/// This method may throw an [`IoError`](LogBoopError::IoError) when opening
+/// the file, creating the output file, writing to it, or removing the
+/// original file, and a [`DecompressError`](LogBoopError::DecompressError)
+/// when decoding its contents.
+///
+/// # Example
+/// This is a minimal example.
+/// ```no_run
+/// # use std::path::Path;
+/// # use logboop::compress::dexz;
+/// let p = Path::new("my_file.xz");
+/// if let Err(e) = dexz(&p, false) {
+///     eprintln!("Error when inflating : {}", e);
+/// }
+/// // There must now be a file called "my_file"
+/// ```
+pub fn dexz(filepath: &Path, keep_source: bool) -> Result<(), LogBoopError> {
+    let mut decoder = XzDecoder::new_multi_decoder(BufReader::new(File::open(filepath)?));
+
+    // Build the file name of the destination, and inflate into it
+    let final_destination = filepath.with_extension("");
+    write_then_rename(&final_destination, filepath, |out_fptr| {
+        let mut out_fptr = BufWriter::new(out_fptr);
+        let mut buffer = [0u8; 64 * 1024];
+        loop {
+            let read = decoder.read(&mut buffer)
+                .map_err(|source| LogBoopError::DecompressError {
+                    path: filepath.to_path_buf(), source })?;
+            if read == 0 {
+                return Ok(out_fptr.flush()?);
+            }
+            out_fptr.write_all(&buffer[..read])?;
+        }
+    })?;
+
+    // And remove the original
+    if keep_source {
+        return Ok(());
+    }
+    Ok(filesystem::remove_source(filepath)?)
+}
+
+/// Compress a given file with default bzip2 compression
+///
+/// # Arguments
+/// Given a [`&Path`](std::path::Path), find and deflate the contents
+/// using a bzip2 encoder. The file is compressed as it is read, so that
+/// memory stays bounded whatever its size.
+///
+/// Unless `keep_source` is set, the original file is removed once compressed.
+///
+/// # Exceptions
+/// This method may throw an [`IoError`](LogBoopError::IoError) when opening
+/// the file, reading its content, creating the output file and opening it,
+/// writing the content of the first file into the encoder, finalizing the
+/// encoding, and removing the original file.
+///
+/// # Example
+/// This is a minimal example.
+/// ```no_run
+/// # use std::path::Path;
+/// # use logboop::compress::bzip2;
+/// let p = Path::new("my_file");
+/// if let Err(e) = bzip2(&p, false) {
+///     eprintln!("Error when compressing : {}", e);
+/// }
+/// // There must now be a file called "my_file.bz2"
+/// ```
+pub fn bzip2(filepath: &Path, keep_source: bool) -> Result<(), LogBoopError> {
+    let mut fptr = BufReader::new(File::open(filepath)?);
+    // Open the output file
+    let mut owned_path = filepath.to_path_buf();
+    filesystem::add_extension(&mut owned_path, "bz2");
+    write_then_rename(&owned_path, filepath, |out_fptr| {
+        // Get a bzip2 encoder, with its default level
+        let mut encoder = BzEncoder::new(BufWriter::new(out_fptr),
+                                         BzCompression::default());
+        std::io::copy(&mut fptr, &mut encoder)?;
+        Ok(encoder.finish()?.flush()?)
+    })?;
 
     // Remove the file
-    remove_file(&filepath)
+    if keep_source {
+        return Ok(());
+    }
+    Ok(filesystem::remove_source(filepath)?)
 }
@@ -0,0 +1,116 @@
+//! Module defining the errors of `LogBoop`
+//!
+//! ## Provided by this module
+//!
+//! Every fallible function of `LogBoop` returns a [`LogBoopError`], which
+//! tells apart a failing disk ([`LogBoopError::IoError`]) from a corrupted
+//! compressed file ([`LogBoopError::DecompressError`]), a line whose date
+//! cannot be read ([`LogBoopError::ParseError`]), a file that was most
+//! likely misdetected ([`LogBoopError::TooManyDates`]), a file whose
+//! outputs do not match it ([`LogBoopError::VerifyError`]) or an invalid
+//! file of log formats ([`LogBoopError::FormatError`]).
+//!
+//! I/O errors convert into a [`LogBoopError`], so that `?` can be used on
+//! them directly.
+//!
+//! ## Example
+//!
+//! ```rust
+//! # use logboop::error::LogBoopError;
+//! # use logboop::process::{determine_date, LogType};
+//! match determine_date(&LogType::Iso, "2020-13-45 nope") {
+//!     Err(LogBoopError::ParseError { line, logtype }) =>
+//!         assert_eq!((line.as_str(), logtype), ("2020-13-45 nope", LogType::Iso)),
+//!     other => panic!("unexpected {:?}", other)
+//! }
+//! ```
+//!
+//! ## Details of imports
+//!
+//! [`PathBuf`] names the files that could not be decompressed, and
+//! [`LogType`] the format a line was expected to follow.
+use std::path::PathBuf;
+
+use crate::process::LogType;
+
+/// Errors of `LogBoop`
+#[derive(std::fmt::Debug)]
+pub enum LogBoopError {
+    /// An I/O operation failed (reading, writing, creating or removing a
+    /// file or a directory)
+    IoError(std::io::Error),
+    /// A compressed file could not be decoded
+    DecompressError {
+        /// The compressed file
+        path: PathBuf,
+        /// The error of the decoder
+        source: std::io::Error
+    },
+    /// A line holds no valid date for its log type
+    ParseError {
+        /// The offending line
+        line: String,
+        /// The log type the line was expected to follow
+        logtype: LogType
+    },
+    /// A file yielded more distinct dates than allowed (see
+    /// [`Options::max_dates_per_file`](crate::options::Options::max_dates_per_file))
+    TooManyDates {
+        /// The limit that was exceeded
+        limit: usize
+    },
+    /// What was written from a file does not match it, so the file was not
+    /// deleted (see [`Options::verify`](crate::options::Options::verify))
+    VerifyError {
+        /// The file that was kept
+        path: PathBuf,
+        /// Number of lines (or bytes) of the file
+        expected: u64,
+        /// Number of lines (or bytes) found in what was written
+        found: u64
+    },
+    /// A file of log formats cannot be loaded (see
+    /// [`CustomFormat::load`](crate::process::CustomFormat::load))
+    FormatError {
+        /// The file of log formats
+        path: PathBuf,
+        /// What is wrong with it
+        reason: String
+    }
+}
+
+impl std::fmt::Display for LogBoopError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LogBoopError::IoError(e) => write!(f, "{}", e),
+            LogBoopError::DecompressError { path, source } =>
+                write!(f, "cannot decompress {} : {}", path.display(), source),
+            LogBoopError::ParseError { line, logtype } =>
+                write!(f, "no valid {:?} date in line \"{}\"", logtype, line),
+            LogBoopError::TooManyDates { limit } =>
+                write!(f, "more than {} distinct dates found, the log type was \
+                           probably misdetected", limit),
+            LogBoopError::VerifyError { path, expected, found } =>
+                write!(f, "cannot verify what was written from {} : {} expected, \
+                           {} found", path.display(), expected, found),
+            LogBoopError::FormatError { path, reason } =>
+                write!(f, "invalid log formats in {} : {}", path.display(), reason)
+        }
+    }
+}
+
+impl std::error::Error for LogBoopError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            LogBoopError::IoError(e)
+                | LogBoopError::DecompressError { source: e, .. } => Some(e),
+            _ => None
+        }
+    }
+}
+
+impl From<std::io::Error> for LogBoopError {
+    fn from(e: std::io::Error) -> LogBoopError {
+        LogBoopError::IoError(e)
+    }
+}
@@ -6,9 +6,12 @@
 //!
 //! # Provided by this module
 //! Various methods to simplify repetitive filesystem manipulation operations
-//! are provided (adding an extension to a path, degunzip'ing all `.gz` files
-//! in a folder, and gunzip'ing all files with the correct name format in
-//! a directory).
+//! are provided (adding an extension to a path, copying the permissions of a
+//! file onto another, degunzip'ing all `.gz`, `.zst`, `.bz2` and `.xz` files
+//! in a folder, gunzip'ing all files with the correct name format in a
+//! directory, rolling old output files up into monthly archives,
+//! bundling output files into daily archives, merging the small output
+//! files of a month into one, and removing the directories left empty).
 //!
 //! Examples are provided for each individual function.
 //!
@@ -18,39 +21,68 @@
 //!
 //! We import things needed to manipulate I/O, Paths, and some OS-specific
 //! strings :
-//!  - The entire [I/O `prelude`](std::io::prelude)
-//!  - [`Path`] and its owned version, [`PathBuf`]
-//!  - The OS-specific [`OsString`], needed to specify one argument when
-//!  extracting and inspecting extensions recursively (in
-//!  [`degunzip_all_the_files`])
+//!  - The entire [I/O `prelude`](std::io::prelude), and the [`ErrorKind`]
+//!    of I/O errors to tell those worth trying again
+//!  - [`Path`] and its owned version, [`PathBuf`], along with their
+//!    [`Component`]s to resolve paths
+//!  - The OS-specific [`OsStr`], needed to inspect extensions recursively
+//!    (in [`degunzip_all_the_files`])
+//!  - [`File`], [`rename`] and [`remove_file`] to build monthly archives
+//!    and files, and [`create_dir_all`] to create output directories
+//!  - The [`BTreeMap`] collection to group daily files by month
+//!  - [`Command`] to run the [`Options::postrotate`] command, and the
+//!    [`Ordering`] to count its failures
 //!
 //! ## Crate imports
 //!
 //! Some crate imports are needed as well :
-//!  - We need to define a [`Regex`] to match the end of files we want
-//!  to compress again
+//!  - We need to define a [`Regex`] to match the names of daily files we
+//!    want to roll up
 //!  - [`WalkDir`] will let us easily walk recursively in the directories
-//!  we inspect
+//!    we inspect
 //!  - [`compress`] since we call [`gunzip`](crate::compress::gunzip)
-//!  and [`degunzip`](crate::compress::degunzip) on individual
-//!  files.
+//!    and [`degunzip`](crate::compress::degunzip) (or their Zstandard,
+//!    bzip2 and XZ counterparts) on individual files.
+//!  - [`Options`] and [`Codec`], the settings of the run
+//!  - [`LogBoopError`], returned by every fallible function
+//!  - [`TarWriter`], [`GzEncoder`] and [`GzDecoder`] to build monthly
+//!    archives and files, and the [`MultiGzDecoder`], Zstandard
+//!    [`Decoder`](ZstdDecoder) and bzip2 [`MultiBzDecoder`] to read daily
+//!    files
+//!  - [`Local`], [`NaiveDate`] and [`Duration`] to know the age of files
+//!  - The [`log`] macros and their [`Level`], to tell what was done with
+//!    every file
 
 use std::io::prelude::*;
-use std::path::{Path,PathBuf};
-use std::ffi::OsString;
+use std::io::ErrorKind;
+use std::path::{Component, Path, PathBuf};
+use std::ffi::OsStr;
+use std::fs::{File, create_dir_all, rename, remove_file};
+use std::collections::BTreeMap;
+use std::process::Command;
+use std::sync::atomic::Ordering;
 
-use regex::Regex;
+use regex::{Captures, Regex};
 use walkdir::WalkDir;
+use chrono::{Duration, Local, NaiveDate};
+use flate2::read::{GzDecoder, MultiGzDecoder};
+use flate2::write::GzEncoder;
+use flate2::Compression;
 
+use zstd::stream::read::Decoder as ZstdDecoder;
+use bzip2::read::MultiBzDecoder;
+use log::{debug, error, info, warn, Level};
+
+use crate::archive::TarWriter;
 use crate::compress;
+use crate::error::LogBoopError;
+use crate::options::{Codec, Options, OutputMtime};
 
 lazy_static! {
-    /// Regex object used to match the ISO 8601 date format at the end of
-    /// a file name
-    ///
-    /// Its exact regex is `-\d{4}-\d{2}-\d{2}` (a hyphen is added before
-    /// the date when we create the file)
-    static ref ISO_DATE_REGEX: Regex = Regex::new(r"-\d{4}-\d{2}-\d{2}$").unwrap();
+    /// Regex object used to match the name of a daily (or hourly) output
+    /// file, compressed or not, capturing its base name, month, day, hour
+    /// and offset
+    static ref DAILY_FILE_REGEX: Regex = Regex::new(r"^(?P<base>.+)-(?P<month>\d{4}-\d{2})-(?P<day>\d{2})(?P<hour>T\d{2})?(?P<offset>[+-]\d{4})?(?P<suffix>(?:\.[\w-]+)*?)(?:\.(?P<ext>gz|zst|bz2))?$").unwrap();
 }
 
 /// Add an extension to a path
@@ -58,7 +90,7 @@ lazy_static! {
 /// # Arguments
 /// We receive two arguments :
 /// - A mutable reference to an owned path
-/// ([`&mut PathBuf`](std::path::PathBuf))
+///   ([`&mut PathBuf`](std::path::PathBuf))
 /// - The addition, a slice str [`&str`]
 ///
 /// # Behaviour
@@ -67,6 +99,8 @@ lazy_static! {
 ///
 /// Giving an empty string changes nothing :
 /// ```
+/// # use std::path::PathBuf;
+/// # use logboop::filesystem::add_extension;
 /// let mut path_ex = PathBuf::from("a_file");
 /// add_extension(&mut path_ex, "");
 /// assert_eq!(path_ex, PathBuf::from("a_file"));
@@ -83,92 +117,1031 @@ pub fn add_extension(path: &mut PathBuf, addition: &str) {
     }
 }
 
-/// Recursively inflate all GZ files in a directory
+/// Make a path safe to use as an object name
+///
+/// # Behaviour
+/// Every component of the path is lowercased, and any character other than
+/// `a` to `z`, `0` to `9`, `.`, `_` and `-` is replaced with `_`. The
+/// separators between components, and any root, are kept.
+///
+/// ```
+/// # use std::path::{Path, PathBuf};
+/// # use logboop::filesystem::sanitize_path;
+/// assert_eq!(sanitize_path(Path::new("Web Server/Access Log.log")),
+///            PathBuf::from("web_server/access_log.log"));
+/// assert_eq!(sanitize_path(Path::new("café+app")), PathBuf::from("caf__app"));
+/// ```
+pub fn sanitize_path(path: &Path) -> PathBuf {
+    path.components()
+        .map(|component| match component {
+            std::path::Component::Normal(name) => name.to_string_lossy()
+                .chars()
+                .map(|c| c.to_ascii_lowercase())
+                .map(|c| match c {
+                    'a'..='z' | '0'..='9' | '.' | '_' | '-' => c,
+                    _ => '_'
+                })
+                .collect::<String>()
+                .into(),
+            other => other.as_os_str().to_os_string()
+        })
+        .collect()
+}
+
+/// Recursively list the paths under a directory
+///
+/// # Behaviour
+/// Symbolic links are followed when `follow_links` is set, and listed as
+/// they are otherwise. Entries which cannot be read, and symbolic links
+/// leading to one of their own parent directories (which would make the
+/// walk endless), are skipped with an error message.
+///
+/// With a `max_depth`, nothing deeper than that many levels below the root
+/// is listed, as with [`WalkDir::max_depth`] : the root itself is at depth
+/// `0`, so `Some(1)` lists only what is right in the root, and `Some(0)`
+/// nothing but the root.
+///
+/// When `sorted` is set, the entries of every directory are listed by file
+/// name (see [`WalkDir::sort_by_file_name`]), so that walking the same tree
+/// twice always gives the same order. This reads every directory whole
+/// before listing it. Otherwise, entries come in the order the operating
+/// system gives them.
+///
+/// # Example
+/// ```no_run
+/// # use std::path::Path;
+/// # use logboop::filesystem::walk;
+/// let rotated = walk(Path::new("/var/log"), true, None, true)
+///     .filter(|path| path.extension().is_some_and(|ext| ext == "1"))
+///     .count();
+/// ```
+pub fn walk(root: &Path, follow_links: bool, max_depth: Option<usize>, sorted: bool)
+    -> impl Iterator<Item = PathBuf> {
+    let root = root.to_path_buf();
+    let walker = WalkDir::new(&root)
+        .follow_links(follow_links)
+        .max_depth(max_depth.unwrap_or(usize::MAX));
+    let walker = match sorted {
+        true => walker.sort_by_file_name(),
+        false => walker
+    };
+    walker.into_iter()
+        .filter_map(move |entry| match entry {
+            Ok(entry) => Some(entry.into_path()),
+            Err(e) => {
+                warn!("Error while walking {} : {}", root.display(), e);
+                None
+            }
+        })
+}
+
+/// Delay before trying a file again after a transient error, doubled for
+/// every other try (see [`retry`])
+pub const RETRY_DELAY: std::time::Duration = std::time::Duration::from_millis(100);
+
+/// Tell whether an error may go away when trying again
+///
+/// # Behaviour
+/// Only I/O errors may, when interrupted, timed out, or hitting a busy
+/// resource, a full queue (`EAGAIN`) or a stale handle of a network file
+/// system. Missing files, denied permissions, corrupted compressed files and
+/// any other error will not, and are never tried again.
+///
+/// # Example
+/// ```
+/// # use std::io::{Error, ErrorKind};
+/// # use logboop::filesystem::is_transient;
+/// assert!(is_transient(&Error::from(ErrorKind::Interrupted).into()));
+/// assert!(!is_transient(&Error::from(ErrorKind::NotFound).into()));
+/// ```
+pub fn is_transient(e: &LogBoopError) -> bool {
+    match e {
+        LogBoopError::IoError(e) => matches!(e.kind(),
+            ErrorKind::Interrupted | ErrorKind::WouldBlock | ErrorKind::TimedOut
+                | ErrorKind::ResourceBusy | ErrorKind::StaleNetworkFileHandle),
+        _ => false
+    }
+}
+
+/// Run an operation on a file, trying it again after transient errors
+///
+/// # Behaviour
+/// Whenever `operation` fails with an error which may go away (see
+/// [`is_transient`]), it is run again, up to [`Options::retries`] more
+/// times, waiting [`RETRY_DELAY`] before the first retry and twice as long
+/// before every other one. Nothing is tried again once
+/// [`Options::stop_requested`].
+///
+/// # Errors
+/// The last error of `operation` flows upwards.
+///
+/// # Example
+/// ```no_run
+/// # use std::path::Path;
+/// # use logboop::filesystem::{degunzip_file, retry};
+/// # use logboop::options::Options;
+/// let options = Options { retries: 3, ..Options::default() };
+/// let entry = Path::new("/mnt/nfs/syslog.2.gz");
+/// retry(entry, &options, || degunzip_file(entry, &options))?;
+/// # Ok::<(), logboop::error::LogBoopError>(())
+/// ```
+pub fn retry<T, F>(entry: &Path, options: &Options, mut operation: F)
+    -> Result<T, LogBoopError>
+    where F: FnMut() -> Result<T, LogBoopError> {
+    let mut delay = RETRY_DELAY;
+    let mut tries = 0;
+    loop {
+        match operation() {
+            Err(e) if tries < options.retries && is_transient(&e)
+                && !options.stop_requested() => {
+                tries += 1;
+                warn!("Error with {} : {}, trying again in {}ms ({}/{})",
+                      entry.display(), e, delay.as_millis(), tries, options.retries);
+                std::thread::sleep(delay);
+                delay *= 2;
+            },
+            result => return result
+        }
+    }
+}
+
+/// Give up on a file whose transient errors outlasted its retries, so that
+/// the other files are handled anyway
+///
+/// `None` is returned for a file given up on. Without [`Options::retries`],
+/// and for any other error, the error flows upwards as it is.
+pub(crate) fn give_up<T>(entry: &Path, options: &Options, result: Result<T, LogBoopError>)
+    -> Result<Option<T>, LogBoopError> {
+    match result {
+        Err(e) if options.retries > 0 && is_transient(&e) => {
+            error!("Giving up on {} after {} retries : {}",
+                   entry.display(), options.retries, e);
+            Ok(None)
+        },
+        result => result.map(Some)
+    }
+}
+
+/// Give a file the permissions (and, when possible, the owner) of another
+///
+/// # Behaviour
+/// On Unix, the mode of `from` is copied onto `to`, without the special
+/// bits, and with the write permission of the owner always kept so that
+/// later runs can still append to `to`. The owner and group of `from` are
+/// copied as well, which only succeeds with enough privileges : failing to
+/// do so is not an error.
+///
+/// On other platforms, nothing is done.
+///
+/// # Errors
+/// Any I/O error while reading the metadata of `from` or changing the mode
+/// of `to` flows upwards.
+#[cfg(unix)]
+pub fn copy_permissions(from: &Path, to: &Path) -> std::io::Result<()> {
+    use std::os::unix::fs::{chown, MetadataExt, PermissionsExt};
+    let metadata = from.metadata()?;
+    // Only privileged users may give files away
+    let _ = chown(to, Some(metadata.uid()), Some(metadata.gid()));
+    let mode = metadata.permissions().mode() & 0o777 | 0o200;
+    std::fs::set_permissions(to, std::fs::Permissions::from_mode(mode))
+}
+
+/// Give a file the permissions of another, which does nothing on this
+/// platform
+#[cfg(not(unix))]
+pub fn copy_permissions(_from: &Path, _to: &Path) -> std::io::Result<()> {
+    Ok(())
+}
+
+/// Create a directory and its missing parents, giving them a mode
+///
+/// # Behaviour
+/// Directories are created like [`create_dir_all`] does. On Unix, those
+/// which did not exist before are then given `mode` exactly, whatever the
+/// umask, while those which already existed are left as they are, so that
+/// their permissions are never widened. Without a mode, or on other
+/// platforms, directories get the default permissions.
+///
+/// # Errors
+/// Any I/O error while creating the directories or changing their mode
+/// flows upwards.
+///
+/// # Example
+/// ```no_run
+/// # use std::path::Path;
+/// # use logboop::filesystem::create_dirs;
+/// create_dirs(Path::new("/srv/logs/web"), Some(0o750))?;
+/// # Ok::<(), std::io::Error>(())
+/// ```
+pub fn create_dirs(path: &Path, mode: Option<u32>) -> std::io::Result<()> {
+    // Directories missing, from the deepest to the shallowest
+    let missing: Vec<&Path> = match mode {
+        Some(_) => path.ancestors()
+            .filter(|dir| !dir.as_os_str().is_empty())
+            .take_while(|dir| !dir.exists())
+            .collect(),
+        None => Vec::new()
+    };
+    create_dir_all(path)?;
+    missing.iter().rev().try_for_each(|dir| set_mode(dir, mode.unwrap_or_default()))
+}
+
+/// Whether one of two paths is inside the other (or both are the same)
+///
+/// # Behaviour
+/// Both paths are resolved first, following symbolic links and `..`, so
+/// that `/var/log/../log/processed` and a link to `/var/log` are seen for
+/// what they are. The part of a path which does not exist yet is taken as
+/// written.
+///
+/// An output directory inside the input directory would have its files
+/// split again by the next run (and the other way around), which this
+/// tells before anything is written.
+///
+/// # Errors
+/// Any I/O error while resolving the paths flows upwards.
+///
+/// # Example
+/// ```no_run
+/// # use std::path::Path;
+/// # use logboop::filesystem::overlap;
+/// assert!(overlap(Path::new("/var/log"), Path::new("/var/log/processed"))?);
+/// assert!(!overlap(Path::new("/var/log"), Path::new("/srv/logs"))?);
+/// # Ok::<(), std::io::Error>(())
+/// ```
+pub fn overlap(first: &Path, second: &Path) -> std::io::Result<bool> {
+    let (first, second) = (resolve(first)?, resolve(second)?);
+    Ok(first.starts_with(&second) || second.starts_with(&first))
+}
+
+/// Resolve the part of a path which exists, and append the rest to it
+///
+/// The rest holds no symbolic link, so its `..` are simply applied.
+fn resolve(path: &Path) -> std::io::Result<PathBuf> {
+    let absolute = std::path::absolute(path)?;
+    // The root always exists
+    let existing = absolute.ancestors().find(|dir| dir.exists()).unwrap_or(&absolute);
+    let missing = absolute.strip_prefix(existing).unwrap_or(Path::new(""));
+    Ok(missing.components().fold(existing.canonicalize()?, |mut resolved, component| {
+        match component {
+            Component::ParentDir => {
+                resolved.pop();
+            },
+            Component::Normal(name) => resolved.push(name),
+            _ => ()
+        }
+        resolved
+    }))
+}
+
+/// Give a file or a directory a mode
+#[cfg(unix)]
+fn set_mode(path: &Path, mode: u32) -> std::io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))
+}
+
+/// Give a file or a directory a mode, which does nothing on this platform
+#[cfg(not(unix))]
+fn set_mode(_path: &Path, _mode: u32) -> std::io::Result<()> {
+    Ok(())
+}
+
+/// Number of hard links to a file
+///
+/// # Errors
+/// Any I/O error while reading the metadata of the file flows upwards.
+#[cfg(unix)]
+pub fn link_count(path: &Path) -> std::io::Result<u64> {
+    use std::os::unix::fs::MetadataExt;
+    Ok(path.metadata()?.nlink())
+}
+
+/// Number of hard links to a file, always 1 on this platform
+#[cfg(not(unix))]
+pub fn link_count(_path: &Path) -> std::io::Result<u64> {
+    Ok(1)
+}
+
+/// Remove a file once done with it, warning when it has other hard links
+///
+/// # Behaviour
+/// Removing a hard link leaves the content of the file to its other links,
+/// which another program (like a log shipper) may still be reading : this
+/// is worth a warning, since that program then sees the file grow no more.
+/// See [`Options::no_touch_hardlinks`] to leave such files alone.
+///
+/// # Errors
+/// Any I/O error while removing the file flows upwards.
+pub fn remove_source(path: &Path) -> std::io::Result<()> {
+    if let Ok(links @ 2..) = link_count(path) {
+        warn!("{} has {} other hard link(s), removing it anyway", path.display(), links - 1);
+    }
+    remove_file(path)
+}
+
+/// Recursively inflate all GZ, Zstandard, bzip2 and XZ files in a directory
 ///
 /// # Arguments
-/// This method only needs one argument, a [`&Path`](std::path::Path).
+/// This method needs two arguments, a [`&Path`](std::path::Path) and the
+/// [`Options`] of the run.
 ///
 /// # Behaviour
 ///
 /// When given a path, this method recursively iterates all files in the
-/// folder (and at this point in the program it must be a folder),
-/// inspects the extension (if any) of the file name, and if it is "gz",
-/// trigger a [`degunzip`](crate::compress::degunzip).
+/// folder (and at this point in the program it must be a folder), following
+/// symbolic links if [`Options::follow_links`] is set, and no deeper than
+/// [`Options::max_depth`] (see [`walk`]), inspects the extension (if any)
+/// of the file name, and if it is "gz", trigger a
+/// [`degunzip`](crate::compress::degunzip), if it is "zst", a
+/// [`dezstd`](crate::compress::dezstd), if it is "bz2", a
+/// [`debzip2`](crate::compress::debzip2), or if it is "xz", a
+/// [`dexz`](crate::compress::dexz). Directories mixing all of them
+/// (and plain files) are handled in one pass. Should the first bytes of
+/// such a file tell another of those formats (see
+/// [`format_of`](crate::compress::format_of)), they are trusted over its
+/// name.
+///
+/// Other files starting like compressed data, such as rotated files
+/// compressed without being renamed, are inflated under their own name :
+/// they are renamed with the extension of their format first, and inflated
+/// from there. Should they fail to decode, they are renamed back and left
+/// as plain text.
+///
+/// A compressed file is left alone when the file it would be inflated to
+/// already exists, so that a `.gz` and a `.zst` of the same base never
+/// overwrite each other (nor an existing plain file). The file left alone is
+/// inflated by a later run, once its counterpart has been processed.
+///
+/// If [`Options::keep_source`] is set, compressed files are kept once
+/// inflated. Running the scan again is harmless, since their inflated
+/// counterparts already exist.
+///
+/// Once [`Options::stop_requested`], no other file is inflated. Files
+/// modified longer than [`Options::since`] ago are left alone (see
+/// [`Options::is_recent`]), and so are files not selected by
+/// [`Options::exclude`] and [`Options::include`] (see
+/// [`Options::is_selected`]) and, with [`Options::no_touch_hardlinks`],
+/// files with other hard links.
+///
+/// A file failing with a transient error is tried again up to
+/// [`Options::retries`] times (see [`retry`]). With retries, a file still
+/// failing after them is reported and given up on, and the others are
+/// inflated anyway.
 ///
 /// # Errors
-/// This method will return a `std::io::Result<()>`, and can be invoked
+/// This method will return a `Result<(), LogBoopError>`, and can be invoked
 /// with the `?` syntax sugar. When an internal error occurs (with printing,
 /// or with degunzip), that error will flow upwards.
 ///
 /// # Example
 /// This method can be used thusly.
-/// ```
+/// ```no_run
+/// # use std::path::Path;
+/// # use logboop::filesystem::degunzip_all_the_files;
+/// # use logboop::options::Options;
 /// let my_files_path = Path::new("var/log");
-/// degunzip_all_the_files(&my_files_path)?;
+/// degunzip_all_the_files(&my_files_path, &Options::default())?;
+/// # Ok::<(), logboop::error::LogBoopError>(())
 /// ```
-pub fn degunzip_all_the_files(inpath: &Path) -> std::io::Result<()> {
+pub fn degunzip_all_the_files(inpath: &Path, options: &Options)
+    -> Result<(), LogBoopError> {
     // Within all the folders, we need to find and de-gunzip all the files
     // That end with a `.gz` extension
     // Open the directory, and iterate
-    WalkDir::new(inpath)
-        .into_iter()
-        .filter_map(|entry| entry.map(walkdir::DirEntry::into_path).ok())
-        .filter(|entry| entry.is_file())
-        .filter_map(|entry| entry.extension().map(|e| (entry.clone(), e.to_owned())))
-        .try_for_each(
-            |(entry, ext): (PathBuf, OsString)| -> std::io::Result<_> {
-                print!("{} ", entry.display());
-                std::io::stdout().flush()?;
-                if ext == "gz" {
-                    compress::degunzip(&entry)?;
-                    println!("\u{2713}");
-                } else {
-                    println!("-");
-                }
-                Ok(())
+    walk(inpath, options.follow_links, options.max_depth, !options.unsorted)
+        .filter(|entry| entry.is_file() && options.is_recent(entry)
+                && options.is_selected(entry, inpath) && options.may_touch(entry))
+        .take_while(|_| !options.stop_requested())
+        .try_for_each(|entry: PathBuf| give_up(&entry, options,
+            retry(&entry, options, || degunzip_file(&entry, options))).map(|_| ()))
+}
+
+/// Inflate a single file, if it is compressed
+///
+/// # Behaviour
+/// This is what [`degunzip_all_the_files`] does with every file it finds,
+/// reporting what was done. The path of the file now holding the plain
+/// contents is returned : the inflated file, or the file itself if it was
+/// not compressed (or inflated under its own name). `None` is returned for
+/// a compressed file left alone, whose inflated counterpart already exists.
+///
+/// # Errors
+/// Any error while inflating the file flows upwards.
+///
+/// # Example
+/// ```no_run
+/// # use std::path::Path;
+/// # use logboop::filesystem::degunzip_file;
+/// # use logboop::options::Options;
+/// let plain = degunzip_file(Path::new("var/log/syslog.2.gz"), &Options::default())?;
+/// assert_eq!(plain.as_deref(), Some(Path::new("var/log/syslog.2")));
+/// # Ok::<(), logboop::error::LogBoopError>(())
+/// ```
+pub fn degunzip_file(entry: &Path, options: &Options)
+    -> Result<Option<PathBuf>, LogBoopError> {
+    let bytes = entry.metadata().map_or(0, |m| m.len());
+    let inflate = match (entry.extension().and_then(OsStr::to_str),
+                         compress::format_of(entry)?) {
+        // The first bytes are trusted over the name
+        (Some("gz" | "zst" | "bz2" | "xz"), Some(format)) => inflater(format),
+        (Some(extension @ ("gz" | "zst" | "bz2" | "xz")), None) => inflater(extension),
+        (_, Some(format)) => {
+            let status = inflate_in_place(entry, format, options)?;
+            options.report(Level::Info, entry, bytes,
+                           format_args!("{} {}", entry.display(), status));
+            // Still compressed when it could not be inflated in place
+            if compress::format_of(entry)?.is_some() {
+                return Ok(None);
             }
-        )
+            options.inflated.lock().unwrap().insert(entry.to_path_buf());
+            return Ok(Some(entry.to_path_buf()));
+        },
+        (_, None) => {
+            options.report(Level::Debug, entry, bytes,
+                           format_args!("{} -", entry.display()));
+            return Ok(Some(entry.to_path_buf()));
+        }
+    };
+    let plain = entry.with_extension("");
+    if plain.exists() {
+        options.report(Level::Info, entry, bytes, format_args!(
+            "{} - (already inflated, kept)", entry.display()));
+        return Ok(None);
+    }
+    inflate(entry, options.keep_source || options.verify)?;
+    if options.verify {
+        verify_then_remove(&plain, entry, entry, options.keep_source)?;
+    }
+    options.report(Level::Debug, entry, bytes,
+                   format_args!("{} \u{2713}", entry.display()));
+    options.inflated.lock().unwrap().insert(plain.clone());
+    Ok(Some(plain))
+}
+
+/// Function inflating the files of a compression format, given by its
+/// extension (see [`format_of`](crate::compress::format_of))
+fn inflater(format: &str) -> fn(&Path, bool) -> Result<(), LogBoopError> {
+    match format {
+        "zst" => compress::dezstd,
+        "bz2" => compress::debzip2,
+        "xz" => compress::dexz,
+        _ => compress::degunzip
+    }
+}
+
+/// Inflate a compressed file whose name does not end with the extension of
+/// its format, keeping its name
+///
+/// See [`degunzip_all_the_files`], which reports the status returned here.
+fn inflate_in_place(path: &Path, format: &str, options: &Options)
+    -> Result<String, LogBoopError> {
+    let mut compressed = path.to_path_buf();
+    add_extension(&mut compressed, format);
+    if compressed.exists() {
+        return Ok(format!("- ({}, but {} exists, kept)", format, compressed.display()));
+    }
+    rename(path, &compressed)?;
+    match inflater(format)(&compressed, options.keep_source || options.verify) {
+        // Plain text after all, starting with a magic number by chance
+        Err(LogBoopError::DecompressError { .. }) => {
+            rename(&compressed, path)?;
+            Ok(format!("- (not {}, kept as is)", format))
+        },
+        Err(e) => Err(e),
+        Ok(()) if options.verify => {
+            verify_then_remove(path, &compressed, &compressed, options.keep_source)?;
+            Ok("\u{2713}".to_owned())
+        },
+        Ok(()) => Ok("\u{2713}".to_owned())
+    }
+}
+
+/// Check that a compressed file inflates to the size of a plain file, and
+/// then remove `source` (one of the two) unless `keep_source` is set
+///
+/// See [`Options::verify`]. The source is kept when the sizes differ.
+fn verify_then_remove(plain: &Path, compressed: &Path, source: &Path,
+                      keep_source: bool) -> Result<(), LogBoopError> {
+    let expected = plain.metadata()?.len();
+    let found = compress::inflated_len(compressed)?;
+    if expected != found {
+        return Err(LogBoopError::VerifyError { path: source.to_path_buf(),
+                                               expected, found });
+    }
+    if !keep_source {
+        remove_source(source)?;
+    }
+    Ok(())
 }
 
 /// Recursively compress the appropriate files in a directory
 ///
 /// # Arguments
-/// This method only needs one argument, a [`&Path`](std::path::Path).
+/// This method needs two arguments, a [`&Path`](std::path::Path) and the
+/// [`Options`] of the run.
 ///
 /// # Behaviour
 ///
 /// When given a path, this method recursively iterates all files in the
-/// folder (and at this point in the program it must be a folder),
-/// inspects the end of the file name, and if it matches a simple ISO 8601 date
-/// format, compress it using [`gunzip`](crate::compress::gunzip).
+/// folder (and at this point in the program it must be a folder), following
+/// symbolic links if [`Options::follow_links`] is set (see [`walk`]),
+/// and compresses the output files (see [`Options::is_output_file`]), whose
+/// names end with a simple ISO 8601 date format (possibly followed by an
+/// hour) unless [`Options::output_template`] says otherwise, with the codec
+/// of [`Options::codec`], using
+/// [`gunzip`](crate::compress::gunzip) (at the level of
+/// [`Options::compression`]), [`zstd`](crate::compress::zstd()) or
+/// [`bzip2`](crate::compress::bzip2()). Output files not selected by
+/// [`Options::exclude`] and [`Options::include`] are left alone (see
+/// [`Options::is_selected`]).
+///
+/// If [`Options::compress_only_newer`] is set, files whose compressed
+/// counterpart already exists and was modified after them are left alone
+/// (see [`is_already_compressed`]). So are files smaller than
+/// [`Options::min_size`].
+///
+/// If [`Options::keep_source`] is set, plain files are kept once compressed.
+/// If [`Options::verify`] is set, they are only removed once their compressed
+/// counterpart is checked.
+///
+/// Once [`Options::stop_requested`], no other file is compressed.
+///
+/// A file failing with a transient error is tried again up to
+/// [`Options::retries`] times (see [`retry`]). With retries, a file still
+/// failing after them is reported and given up on, and the others are
+/// compressed anyway.
 ///
 /// # Errors
-/// This method will return a `std::io::Result<()>`, and can be invoked
+/// This method will return a `Result<(), LogBoopError>`, and can be invoked
 /// with the `?` syntax sugar. When an internal error occurs (with printing,
 /// or with gunzip), that error will flow upwards.
 ///
 /// # Example
 /// This method can be used thusly.
-/// ```
+/// ```no_run
+/// # use std::path::Path;
+/// # use logboop::filesystem::gunzip_all_the_files;
+/// # use logboop::options::Options;
 /// let my_files_path = Path::new("var/log");
-/// gunzip_all_the_files(&my_files_path)?;
+/// gunzip_all_the_files(&my_files_path, &Options::default())?;
+/// # Ok::<(), logboop::error::LogBoopError>(())
 /// ```
-pub fn gunzip_all_the_files(outpath: &Path) -> std::io::Result<()> {
+pub fn gunzip_all_the_files(outpath: &Path, options: &Options)
+    -> Result<(), LogBoopError> {
     //
-    WalkDir::new(outpath)
-        .into_iter()
-        .filter_map(|entry| entry.map(walkdir::DirEntry::into_path).ok())
+    walk(outpath, options.follow_links, None, !options.unsorted)
         .filter(|entry| entry.is_file())
-        .filter(|entry| entry
-                .to_str()
-                .map_or(false,
-                        |fname| ISO_DATE_REGEX.is_match(fname)
-        ))
-        .try_for_each(|entry: PathBuf| -> std::io::Result<_> {
-            print!("Compressing {}... ", entry.display());
-            std::io::stdout().flush()?;
-            compress::gunzip(&entry)?;
-            println!("\u{2713}");
-            Ok(())
-        })
+        .filter(|entry| options.is_output_file(entry) && options.is_selected(entry, outpath))
+        .take_while(|_| !options.stop_requested())
+        .try_for_each(|entry: PathBuf| give_up(&entry, options,
+            retry(&entry, options, || gunzip_file(&entry, options))).map(|_| ()))
+}
+
+/// Compress a single output file
+///
+/// # Behaviour
+/// This is what [`gunzip_all_the_files`] does with every output file it
+/// finds (see [`Options::is_output_file`]), with the codec of
+/// [`Options::codec`], unless it is up to date (with
+/// [`Options::compress_only_newer`]) or smaller than [`Options::min_size`].
+/// Unless [`Options::output_mtime`] is [`OutputMtime::Now`], the compressed
+/// file gets the modification time of the plain one.
+///
+/// # Errors
+/// Any error while compressing the file flows upwards.
+pub fn gunzip_file(entry: &Path, options: &Options) -> Result<(), LogBoopError> {
+    let bytes = entry.metadata().map_or(0, |m| m.len());
+    if options.compress_only_newer
+        && is_already_compressed(entry, options.codec) {
+        options.report(Level::Debug, entry, bytes, format_args!(
+            "Compressing {}... up to date", entry.display()));
+        return Ok(());
+    }
+    if bytes < options.min_size {
+        options.report(Level::Debug, entry, bytes, format_args!(
+            "Compressing {}... too small", entry.display()));
+        return Ok(());
+    }
+    if !options.may_touch(entry) {
+        options.report(Level::Debug, entry, bytes, format_args!(
+            "Compressing {}... hard linked", entry.display()));
+        return Ok(());
+    }
+    // Dated outputs stay dated once compressed
+    let mtime = entry.metadata().and_then(|m| m.modified()).ok()
+        .filter(|_| options.output_mtime != OutputMtime::Now);
+    let keep = options.keep_source || options.verify;
+    match options.codec {
+        Codec::Gzip => compress::gunzip(entry, options.compression, keep)?,
+        Codec::Zstd => compress::zstd(entry, keep)?,
+        Codec::Bzip2 => compress::bzip2(entry, keep)?
+    }
+    let mut compressed = entry.to_path_buf();
+    add_extension(&mut compressed, options.codec.extension());
+    if let Some(mtime) = mtime {
+        std::fs::OpenOptions::new().write(true).open(&compressed)?.set_modified(mtime)?;
+    }
+    if options.verify {
+        verify_then_remove(entry, &compressed, entry, options.keep_source)?;
+    }
+    options.report(Level::Debug, entry, bytes, format_args!(
+        "Compressing {}... \u{2713}", entry.display()));
+    if let Some(command) = &options.postrotate {
+        postrotate(command, &compressed, options);
+    }
+    Ok(())
+}
+
+/// Run the [`Options::postrotate`] command on a compressed file
+///
+/// # Behaviour
+/// The command is split on whitespace, and every `{}` in it replaced with
+/// the path of the file, before being run without a shell, waiting for it
+/// to end. A command which cannot be started, or exits with a failure, is
+/// logged and counted in [`Options::hook_failures`].
+fn postrotate(command: &str, compressed: &Path, options: &Options) {
+    let path = compressed.to_string_lossy();
+    let mut words = command.split_whitespace().map(|word| word.replace("{}", &path));
+    let program = match words.next() {
+        Some(program) => program,
+        None => return
+    };
+    let failure = match Command::new(&program).args(words).status() {
+        Ok(status) if status.success() => {
+            debug!("Postrotate command for {}... \u{2713}", compressed.display());
+            return;
+        },
+        Ok(status) => status.to_string(),
+        Err(e) => e.to_string()
+    };
+    error!("Postrotate command for {} failed : {}", compressed.display(), failure);
+    options.hook_failures.fetch_add(1, Ordering::SeqCst);
+}
+
+/// Check whether a file already has an up-to-date compressed counterpart
+///
+/// # Behaviour
+///
+/// The counterpart of the path is built by adding the extension of the
+/// [`Codec`] with [`add_extension`]. It is up to date when it exists and
+/// its modification time is not older than the one of the plain file.
+/// Whenever any of the two modification times cannot be obtained, the file
+/// is considered as not compressed, so that we never skip a file by mistake.
+pub fn is_already_compressed(path: &Path, codec: Codec) -> bool {
+    let mut compressed = path.to_path_buf();
+    add_extension(&mut compressed, codec.extension());
+    let mtime = |p: &Path| p.metadata().and_then(|m| m.modified()).ok();
+    match (mtime(path), mtime(&compressed)) {
+        (Some(plain), Some(gz)) => gz >= plain,
+        _ => false
+    }
+}
+
+/// Roll old daily output files up into monthly archives
+///
+/// # Arguments
+/// This method needs two arguments, a [`&Path`](std::path::Path) to the
+/// output directory, and the number of days after which a daily file is
+/// rolled up.
+///
+/// # Behaviour
+///
+/// This method recursively iterates all files in the folder, and selects
+/// the daily (or hourly) output files, compressed or not, whose date, as
+/// found in their name, is more than `older_than` days ago. Those files are
+/// grouped by directory, base name and month, and every group is added to a
+/// `{base}-YYYY-MM.tar.gz` archive next to them, which is created if needed.
+/// Archives store the files decompressed, under their uncompressed name.
+///
+/// Every archive is first written to a `.tmp` file, which replaces the
+/// previous archive once complete, and only then are the daily files
+/// removed. The archives of [`bundle_days`], and `.tmp` files left behind by
+/// an interrupted run, are left alone.
+///
+/// # Errors
+/// Any I/O error, when reading the daily files or writing an archive,
+/// flows upwards. Compressed daily files that cannot be decoded yield a
+/// [`DecompressError`](LogBoopError::DecompressError).
+///
+/// # Example
+/// ```no_run
+/// # use std::path::Path;
+/// # use logboop::filesystem::rollup_months;
+/// // Bundle everything older than three months
+/// rollup_months(Path::new("/srv/archive"), 90)?;
+/// # Ok::<(), logboop::error::LogBoopError>(())
+/// ```
+pub fn rollup_months(outpath: &Path, older_than: u64) -> Result<(), LogBoopError> {
+    let limit = Local::now().date_naive() - Duration::days(older_than as i64);
+    // Archive path -> (daily file path, name in the archive)
+    let mut months: BTreeMap<PathBuf, Vec<(PathBuf, String)>> = BTreeMap::new();
+    walk(outpath, false, None, true)
+        .filter(|entry| entry.is_file())
+        .for_each(|entry| {
+            let caps = match entry.file_name()
+                .and_then(|f| f.to_str())
+                .and_then(daily_file) {
+                Some(caps) => caps,
+                None => return
+            };
+            let date = NaiveDate::parse_from_str(
+                &format!("{}-{}", &caps["month"], &caps["day"]), "%Y-%m-%d");
+            if date.is_ok_and(|date| date < limit) {
+                let archive = entry.with_file_name(
+                    format!("{}-{}.tar.gz", &caps["base"], &caps["month"]));
+                let name = format!("{}-{}-{}{}{}{}", &caps["base"], &caps["month"],
+                                   &caps["day"],
+                                   caps.name("hour").map_or("", |h| h.as_str()),
+                                   caps.name("offset").map_or("", |o| o.as_str()),
+                                   &caps["suffix"]);
+                months.entry(archive).or_default().push((entry.clone(), name));
+            }
+        });
+
+    months.into_iter().try_for_each(|(archive, mut dailies)| -> Result<_, LogBoopError> {
+        dailies.sort();
+        write_archive(&archive, &dailies)?;
+        info!("Rolled {} file(s) up into {} \u{2713}",
+              dailies.len(), archive.display());
+        Ok(())
+    })
+}
+
+/// Bundle the output files of every day into one archive per day
+///
+/// # Arguments
+/// This method needs a [`&Path`](std::path::Path) to the output directory.
+///
+/// # Behaviour
+///
+/// This method recursively iterates all files in the folder, and selects
+/// the daily (or hourly) output files, compressed or not, of every service.
+/// Those files are grouped by date, and every group is added to a
+/// `day-YYYY-MM-DD.tar.gz` archive at the root of the output directory,
+/// which is created if needed. Archives store the files decompressed,
+/// under their uncompressed path relative to the output directory, so that
+/// the service which produced every file is known once extracted. The
+/// archives themselves (and `.tmp` files left behind) are never bundled
+/// again, so that running it twice keeps what was bundled.
+///
+/// As for [`rollup_months`], every archive is first written to a `.tmp`
+/// file, and the files are only removed once it replaced the previous
+/// archive.
+///
+/// # Errors
+/// Any I/O error, when reading the output files or writing an archive,
+/// flows upwards. Compressed output files that cannot be decoded yield a
+/// [`DecompressError`](LogBoopError::DecompressError), and paths too long
+/// for a tar entry an `InvalidInput` [`IoError`](LogBoopError::IoError).
+///
+/// # Example
+/// ```no_run
+/// # use std::path::Path;
+/// # use logboop::filesystem::bundle_days;
+/// bundle_days(Path::new("/srv/archive"))?;
+/// # Ok::<(), logboop::error::LogBoopError>(())
+/// ```
+pub fn bundle_days(outpath: &Path) -> Result<(), LogBoopError> {
+    // Archive path -> (output file path, name in the archive)
+    let mut days: BTreeMap<PathBuf, Vec<(PathBuf, String)>> = BTreeMap::new();
+    walk(outpath, false, None, true)
+        .filter(|entry| entry.is_file())
+        .for_each(|entry| {
+            let caps = match entry.file_name()
+                .and_then(|f| f.to_str())
+                .and_then(daily_file) {
+                Some(caps) => caps,
+                None => return
+            };
+            let relative = match entry.parent()
+                .and_then(|parent| parent.strip_prefix(outpath).ok())
+                .map(|parent| parent.iter()
+                     .map(|component| component.to_str())
+                     .collect::<Option<Vec<&str>>>()) {
+                Some(Some(relative)) => relative,
+                _ => return
+            };
+            let archive = outpath.join(
+                format!("day-{}-{}.tar.gz", &caps["month"], &caps["day"]));
+            let name = format!("{}-{}-{}{}{}{}", &caps["base"], &caps["month"],
+                               &caps["day"],
+                               caps.name("hour").map_or("", |h| h.as_str()),
+                               caps.name("offset").map_or("", |o| o.as_str()),
+                               &caps["suffix"]);
+            let name = relative.into_iter().chain(std::iter::once(name.as_str()))
+                .collect::<Vec<&str>>().join("/");
+            days.entry(archive).or_default().push((entry.clone(), name));
+        });
+
+    days.into_iter().try_for_each(|(archive, mut files)| -> Result<_, LogBoopError> {
+        files.sort();
+        write_archive(&archive, &files)?;
+        info!("Bundled {} file(s) into {} \u{2713}",
+              files.len(), archive.display());
+        Ok(())
+    })
+}
+
+/// Merge the small daily output files of every month into one monthly file
+///
+/// # Arguments
+/// This method needs a [`&Path`](std::path::Path) to the output directory,
+/// and the size under which the daily files of a month are merged.
+///
+/// # Behaviour
+///
+/// This method recursively iterates all files in the folder, and selects
+/// the daily (or hourly) output files, compressed or not, of the months
+/// before the current one. Those files are grouped by directory, base name
+/// and month, and every group whose files take less than `below` bytes
+/// altogether is merged into a `{base}-YYYY-MM.gz` file next to them :
+/// their decompressed contents are written one after the other, in the
+/// order of their dates, so that lines stay sorted. A monthly file left by
+/// a previous run is kept at the beginning of the new one.
+///
+/// Every monthly file is first written to a `.tmp` file, which replaces the
+/// previous one once complete, and only then are the daily files removed.
+/// The archives of [`bundle_days`], and `.tmp` files left behind by an
+/// interrupted run, are left alone.
+///
+/// # Errors
+/// Any I/O error, when reading the daily files or writing a monthly file,
+/// flows upwards. Compressed daily files that cannot be decoded yield a
+/// [`DecompressError`](LogBoopError::DecompressError).
+///
+/// # Example
+/// ```no_run
+/// # use std::path::Path;
+/// # use logboop::filesystem::consolidate_months;
+/// // Merge months holding less than 64 KiB of logs
+/// consolidate_months(Path::new("/srv/archive"), 64 << 10)?;
+/// # Ok::<(), logboop::error::LogBoopError>(())
+/// ```
+pub fn consolidate_months(outpath: &Path, below: u64) -> Result<(), LogBoopError> {
+    let current = Local::now().format("%Y-%m").to_string();
+    // Monthly file path -> daily file paths
+    let mut months: BTreeMap<PathBuf, Vec<PathBuf>> = BTreeMap::new();
+    walk(outpath, false, None, true)
+        .filter(|entry| entry.is_file())
+        .for_each(|entry| {
+            let caps = match entry.file_name()
+                .and_then(|f| f.to_str())
+                .and_then(daily_file) {
+                Some(caps) => caps,
+                None => return
+            };
+            if caps["month"] >= *current {
+                return;
+            }
+            let monthly = entry.with_file_name(
+                format!("{}-{}{}.gz", &caps["base"], &caps["month"], &caps["suffix"]));
+            months.entry(monthly).or_default().push(entry.clone());
+        });
+
+    months.into_iter().try_for_each(|(monthly, mut dailies)| -> Result<_, LogBoopError> {
+        let size = dailies.iter()
+            .map(|daily| daily.metadata().map(|m| m.len()))
+            .sum::<std::io::Result<u64>>()?;
+        if size >= below {
+            return Ok(());
+        }
+        dailies.sort();
+        let mut temporary = monthly.clone();
+        add_extension(&mut temporary, "tmp");
+        let mut encoder = GzEncoder::new(File::create(&temporary)?,
+                                         Compression::default());
+        if monthly.exists() {
+            encoder.write_all(&read_inflated(&monthly)?)?;
+        }
+        dailies.iter().try_for_each(|daily| -> Result<_, LogBoopError> {
+            Ok(encoder.write_all(&read_inflated(daily)?)?)
+        })?;
+        encoder.finish()?;
+        rename(&temporary, &monthly)?;
+        dailies.iter().try_for_each(remove_file)?;
+        info!("Merged {} file(s) into {} \u{2713}",
+              dailies.len(), monthly.display());
+        Ok(())
+    })
+}
+
+/// Remove the empty directories of the input directory
+///
+/// # Arguments
+/// This method needs a [`&Path`](std::path::Path) to the input directory,
+/// and the [`Options`] of the run.
+///
+/// # Behaviour
+///
+/// The directory is walked bottom-up, so that directories are seen once
+/// their subdirectories are dealt with, and every directory holding nothing
+/// at all is removed : a directory left with a single file, or with a
+/// subdirectory that was not removed, is kept. The input directory itself
+/// is never removed, nor anything deeper than [`Options::max_depth`], and
+/// symbolic links are never followed.
+///
+/// Directories which were already empty before the run are removed as well.
+///
+/// The number of directories removed is returned.
+///
+/// # Errors
+/// Any I/O error, when listing or removing a directory, flows upwards.
+///
+/// # Example
+/// ```no_run
+/// # use std::path::Path;
+/// # use logboop::filesystem::delete_empty_dirs;
+/// # use logboop::options::Options;
+/// let removed = delete_empty_dirs(Path::new("/var/log"), &Options::default())?;
+/// # Ok::<(), logboop::error::LogBoopError>(())
+/// ```
+pub fn delete_empty_dirs(inpath: &Path, options: &Options) -> Result<usize, LogBoopError> {
+    let mut removed = 0;
+    for entry in WalkDir::new(inpath)
+        .contents_first(true)
+        .min_depth(1)
+        .max_depth(options.max_depth.unwrap_or(usize::MAX)) {
+        let entry = entry.map_err(std::io::Error::from)?;
+        if !entry.file_type().is_dir() || std::fs::read_dir(entry.path())?.next().is_some() {
+            continue;
+        }
+        std::fs::remove_dir(entry.path())?;
+        debug!("Removed empty directory {}", entry.path().display());
+        removed += 1;
+    }
+    Ok(removed)
+}
+
+/// Add files to a `.tar.gz` archive, created if needed, then remove them
+///
+/// Every file is given with its name in the archive, and is streamed into
+/// it decompressed, as are the entries of the previous archive. The archive
+/// is first written to a `.tmp` file, which replaces the previous archive
+/// once complete, and only then are the files removed.
+fn write_archive(archive: &Path, files: &[(PathBuf, String)]) -> Result<(), LogBoopError> {
+    let mut temporary = archive.to_path_buf();
+    add_extension(&mut temporary, "tmp");
+    let encoder = GzEncoder::new(File::create(&temporary)?,
+                                 Compression::default());
+    let mut writer = TarWriter::new(encoder);
+    if archive.exists() {
+        writer.copy_entries(GzDecoder::new(File::open(archive)?))?;
+    }
+    files.iter().try_for_each(|(file, name)| -> Result<_, LogBoopError> {
+        let metadata = file.metadata()?;
+        let (mut data, size) = open_inflated(file)?;
+        let mtime = metadata.modified().ok()
+            .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+            .map_or(0, |d| d.as_secs());
+        Ok(writer.append(name, size, 0o644, mtime, &mut data)?)
+    })?;
+    writer.finish()?.finish()?;
+    rename(&temporary, archive)?;
+    Ok(files.iter().try_for_each(|(file, _)| remove_file(file))?)
+}
+
+/// Match the name of a daily (or hourly) output file with
+/// [`DAILY_FILE_REGEX`], leaving out the archives of [`bundle_days`] (like
+/// `day-2023-10-11.tar.gz`, whose suffix is `.tar`) and the `.tmp` files left
+/// behind by an interrupted run
+fn daily_file(name: &str) -> Option<Captures<'_>> {
+    DAILY_FILE_REGEX.captures(name).filter(|caps| !caps["suffix"].starts_with(".tar")
+                                           && !caps["suffix"].ends_with(".tmp"))
+}
+
+/// Open an output file, decompressed according to its extension, along with
+/// the size of its decompressed contents
+///
+/// Compressed files are decoded once first to know that size (see
+/// [`inflated_len`](compress::inflated_len)), so that files which cannot be
+/// decoded are reported before anything is written from them.
+fn open_inflated(file: &Path) -> Result<(Box<dyn Read>, u64), LogBoopError> {
+    let fptr = File::open(file)?;
+    Ok(match file.extension().and_then(|ext| ext.to_str()) {
+        Some("gz") => (Box::new(MultiGzDecoder::new(fptr)), compress::inflated_len(file)?),
+        Some("zst") => (Box::new(ZstdDecoder::new(fptr)?), compress::inflated_len(file)?),
+        Some("bz2") => (Box::new(MultiBzDecoder::new(fptr)), compress::inflated_len(file)?),
+        _ => {
+            let size = fptr.metadata()?.len();
+            (Box::new(fptr), size)
+        }
+    })
+}
+
+/// Read a whole output file, decompressed according to its extension
+fn read_inflated(file: &Path) -> Result<Vec<u8>, LogBoopError> {
+    let mut data: Vec<u8> = Vec::new();
+    let decompress_error = |source| LogBoopError::DecompressError {
+        path: file.to_path_buf(), source };
+    match file.extension().and_then(|ext| ext.to_str()) {
+        Some("gz") => MultiGzDecoder::new(File::open(file)?)
+            .read_to_end(&mut data).map_err(decompress_error)?,
+        Some("zst") => ZstdDecoder::new(File::open(file)?)?
+            .read_to_end(&mut data).map_err(decompress_error)?,
+        Some("bz2") => MultiBzDecoder::new(File::open(file)?)
+            .read_to_end(&mut data).map_err(decompress_error)?,
+        _ => File::open(file)?.read_to_end(&mut data)?
+    };
+    Ok(data)
 }
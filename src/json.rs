@@ -0,0 +1,96 @@
+//! Module with the little JSON handling `LogBoop` needs
+//!
+//! ## Provided by this module
+//!
+//! `LogBoop` writes simple records, and looks at one line at a time. This
+//! module provides [`escape`], to write any text as a JSON string,
+//! [`timestamp`], to find the date of a JSON log line, and [`string_field`],
+//! to read what a container wrote from a Docker log line.
+//!
+//! ## Example
+//!
+//! ```rust
+//! # use logboop::json::escape;
+//! assert_eq!(escape("say \"hi\"\n"), r#""say \"hi\"\n""#);
+//! ```
+use std::fmt::Write;
+
+use chrono::{DateTime, FixedOffset};
+
+/// Fields tried, in order, to find the timestamp of a JSON log line by
+/// default (see [`Options::json_time_fields`](crate::options::Options::json_time_fields))
+pub const DEFAULT_TIME_FIELDS: [&str; 4] = ["time", "ts", "timestamp", "@timestamp"];
+
+/// Field holding the timestamp of a line written by the `json-file` logging
+/// driver of Docker (see [`LogType::DockerJson`](crate::process::LogType::DockerJson))
+pub const DOCKER_TIME_FIELD: &str = "time";
+
+/// Field holding what a container wrote, in a line written by the
+/// `json-file` logging driver of Docker
+pub const DOCKER_LOG_FIELD: &str = "log";
+
+/// Write a text as a JSON string, quotes included
+///
+/// Quotes, backslashes and control characters are escaped, everything
+/// else is kept as is.
+pub fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len() + 2);
+    escaped.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(escaped, "\\u{:04x}", c as u32);
+            },
+            c => escaped.push(c)
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+/// Read a string field of a line holding one JSON object
+///
+/// Lines which are not JSON objects, or whose field is missing or not a
+/// string, yield `None`.
+///
+/// ```
+/// # use logboop::json::string_field;
+/// let line = r#"{"log":"say \"hi\"\n","stream":"stdout"}"#;
+/// assert_eq!(string_field(line, "log").as_deref(), Some("say \"hi\"\n"));
+/// assert_eq!(string_field(line, "time"), None);
+/// ```
+pub fn string_field(line: &str, field: &str) -> Option<String> {
+    let mut value: serde_json::Value = serde_json::from_str(line).ok()?;
+    match value.get_mut(field)?.take() {
+        serde_json::Value::String(text) => Some(text),
+        _ => None
+    }
+}
+
+/// Find the timestamp of a line holding one JSON object
+///
+/// The line is parsed as JSON, and the first of `fields` holding an
+/// RFC 3339 timestamp gives it. Lines which are not JSON objects, or have
+/// no such field, yield `None`.
+///
+/// ```
+/// # use logboop::json::{timestamp, DEFAULT_TIME_FIELDS};
+/// let line = r#"{"level":30,"time":"2023-10-11T22:14:15.003+02:00","msg":"hi"}"#;
+/// let ts = timestamp(line, &DEFAULT_TIME_FIELDS).unwrap();
+/// assert_eq!(ts.to_rfc3339(), "2023-10-11T22:14:15.003+02:00");
+/// assert_eq!(timestamp(line, &["when"]), None);
+/// assert_eq!(timestamp("time=2023-10-11", &DEFAULT_TIME_FIELDS), None);
+/// ```
+pub fn timestamp<S: AsRef<str>>(line: &str, fields: &[S])
+    -> Option<DateTime<FixedOffset>> {
+    let value: serde_json::Value = serde_json::from_str(line).ok()?;
+    let object = value.as_object()?;
+    fields.iter()
+        .filter_map(|field| object.get(field.as_ref())?.as_str())
+        .find_map(|ts| DateTime::parse_from_rfc3339(ts).ok())
+}
@@ -0,0 +1,70 @@
+//! `LogBoop` library, the engine behind the `logboop` binary
+//!
+//! Author : Lux
+//!
+//! License : CC0
+//!
+//! The binary is a thin layer over the modules exposed here : one to
+//! (de)compress files, one for filesystem-wide operations, and one to
+//! recognize, date and split log files. Their errors are all described by
+//! the [`LogBoopError`](error::LogBoopError) enum.
+//!
+//! # Embedding the pipeline
+//! The phases run by the binary are re-exported at the root of the crate, so
+//! that a run of `logboop input output` boils down to
+//! ```no_run
+//! # use std::path::Path;
+//! # use logboop::options::Options;
+//! let (input, output) = (Path::new("/var/log"), Path::new("/srv/logs"));
+//! let options = Options::default();
+//! logboop::degunzip_all_the_files(input, &options)?;
+//! logboop::all_files(input, output, &options)?;
+//! logboop::gunzip_all_the_files(output, &options)?;
+//! # Ok::<(), logboop::error::LogBoopError>(())
+//! ```
+//! The same phases can also overlap, in a single walk of the input
+//! directory, with [`pipeline::run`].
+//!
+//! Finer control is available in the modules themselves, down to processing
+//! a single file with a [`FileProcessor`](process::FileProcessor).
+#![doc(issue_tracker_base_url = "https://github.com/Lymkwi/logboop/issues/")]
+
+/* Crates used by this crate */
+// Lazy static is used to define constant regexes at compile time
+#[macro_use] extern crate lazy_static;
+// Regexes are used to detect and match log types
+extern crate regex;
+// WalkDir is used to easily walk through a directory tree structure
+// in order to operate on files in the input/output directories
+extern crate walkdir;
+// Flate2 is used for anything related to GZ compression/deflation
+extern crate flate2;
+// Chrono is used to manage, infer and format dates from the logs
+extern crate chrono;
+// Zstd is used for Zstandard compression, an alternative to GZ
+extern crate zstd;
+// Bzip2 is used to inflate legacy `.bz2` archives, and compress to them
+extern crate bzip2;
+// Rayon is used to process several files at once
+extern crate rayon;
+// Serde JSON is used to read the timestamp of JSON log lines
+extern crate serde_json;
+// Globset is used to select the files to touch with glob patterns
+extern crate globset;
+// XZ is used to inflate `.xz` archives, the default of some distributions
+extern crate xz2;
+// Tar is used to bundle output files into archives
+extern crate tar;
+
+pub mod filesystem;
+pub mod compress;
+pub mod process;
+pub mod pipeline;
+pub mod options;
+pub mod archive;
+pub mod json;
+pub mod error;
+
+// The processing pipeline, as used by the binary
+pub use filesystem::{degunzip_all_the_files, gunzip_all_the_files};
+pub use process::{all_files, one_file};
@@ -17,97 +17,920 @@
 //! in the `input_root` directory, create directories and files in
 //! `output_root` (or create it as well if needed), and enough disk space to
 //! duplicate the contents of `input_root` (roughly).
+//!
+//! `output_root` must not be inside `input_root`, nor the other way around,
+//! even through `..` or symbolic links : the next run would split the output
+//! files again, so `LogBoop` refuses to start.
+//!
+//! To split a single file instead, give its path as `input_root` : it is
+//! processed whatever its name, inflated on the fly if compressed, and its
+//! output files are written right in `output_root`, named after it without
+//! its extension (`huge.log` gives `output_root/huge-2020-05-17.gz`, and so
+//! on).
+//!
+//! Files can also be told their log format by their name, as their last
+//! extension before the rotation number : `router.iso.1` is split as an `iso`
+//! log whatever its first lines look like.
+//!
+//! Besides rotated files (like `syslog.1`), compressed files are split
+//! whatever their name : `app.log.gz` is inflated to `app.log`, which is then
+//! split like the others, while a plain `app.log` is still being written to
+//! and left alone. GZ, Zstandard (`.zst`), bzip2 (`.bz2`) and XZ (`.xz`)
+//! files are all inflated, told apart by their first bytes rather than
+//! their name when both are known.
+//!
+//! To check how a single line would be recognized and dated, without
+//! touching any file, use
+//! ```bash
+//! logboop --test-line 'Oct 11 22:14:15 host message'
+//! ```
+//!
+//! To see which bucket every line of a single file would be written to,
+//! without writing nor deleting any file, use
+//! ```bash
+//! logboop --stdout huge.log | awk -F '\t' '{ print $1 }' | sort | uniq -c
+//! ```
+//! which prints every line prefixed with its bucket (like `2020-05-17`, or
+//! `unknown` for lines that cannot be dated) and a tab.
+//!
+//! To see which log type every file of a directory would be given, without
+//! inflating, processing nor deleting any file, use
+//! ```bash
+//! logboop --probe /var/log
+//! ```
+//! which prints, for every file, its path, its log type (or `?`), its size in
+//! bytes and its first line that is not blank, separated by tabs. Compressed
+//! files are inflated on the fly.
+//!
+//! To list the log formats it recognizes, with the regex recognizing their
+//! lines and the format of their dates, use
+//! ```bash
+//! logboop --list-formats
+//! ```
+//!
+//! The exit status is 0 when everything went well, 1 when the run was
+//! stopped by an error (including an invalid command line), and 2 when it
+//! went through but some files could not be processed, and were left as
+//! they were, or some `--postrotate` commands failed.
+//!
+//! A run can be interrupted with Ctrl-C : the files being handled are
+//! finished, but no other one is started, and the exit status is 130. The
+//! files left are handled by the next run. Pressing Ctrl-C again stops right
+//! away.
+//!
+//! # Options
+//! Options can be given anywhere on the command line :
+//!  - `-v`, `-q` : tell more (`-v` for the status of every file, `-vv` for
+//!    everything) or less (only errors) about the run, which is logged on
+//!    the standard error (the `RUST_LOG` variable can also be used)
+//!  - `--compress-only-newer` : do not compress output files again when
+//!    their `.gz` (or `.zst`, or `.bz2`) is already more recent than them
+//!  - `--codec CODEC` : `gz` (default), `zstd` or `bzip2`, the compression
+//!    used for output files
+//!  - `--compression-level N` : GZ compression level of output files, from
+//!    `1` (fastest) to `9` (smallest), default `6`
+//!  - `--max-dates-per-file N` : leave alone files yielding more than `N`
+//!    distinct dates, most likely misdetected (default 1000, 0 for no limit)
+//!  - `--max-open-files N` : keep at most `N` output files open per input
+//!    file being processed (default 16), for lines going back and forth
+//!    between dates without reopening their files every time
+//!  - `--buffer-size BYTES` : read every input file and write every output
+//!    file through a buffer of `BYTES` bytes (default 8192)
+//!  - `--mem-limit BYTES` : keep the buffers of the files being processed,
+//!    along with the encoders of output files written with
+//!    `--compress-directly`, under `BYTES` bytes in all (default 256 MiB), by
+//!    processing fewer files at once than `--jobs` and keeping fewer output
+//!    files open than `--max-open-files` when needed
+//!  - `--detection-lines N` : look for a known log format in the first `N`
+//!    lines of every input file (default 20)
+//!  - `--header-lines N` : leave the first `N` lines of every input file out
+//!    of log format detection and dating, and write them to a `.header` file
+//!    next to its outputs instead (default 0)
+//!  - `--mixed` : date every line by whichever known log format it follows,
+//!    instead of only the format of its file, for files interleaving several
+//!    formats (slower)
+//!  - `--strict` : leave a file untouched when one of its lines follows its
+//!    log format but holds an invalid date (like `2023-02-30`), instead of
+//!    filing that line with the ones around it
+//!  - `--continue-on-parse-error` : file lines holding an invalid date with
+//!    the ones around them (default), undoing an earlier `--strict`
+//!  - `--dedup` : write lines identical to the one before them in the same
+//!    output file only once, followed by a `... (repeated N times)` line
+//!  - `--resumable` : save how far every file was split to a `.progress`
+//!    file next to its outputs, so that an interrupted run goes on from there
+//!    (not with `--compress-directly`)
+//!  - `--json-time-fields FIELDS` : comma-separated fields holding the
+//!    timestamp of JSON log lines (default `time,ts,timestamp,@timestamp`)
+//!  - `--formats FILE` : also recognize the log formats of the JSON file
+//!    `FILE`, an array of objects with a `name`, a `regex` matching the
+//!    timestamp of their lines (or holding it in a `ts` group), and the
+//!    `strftime` `format` of that timestamp, tried before the built-in
+//!    formats (for example
+//!    `[{"name": "myapp", "regex": "^\\|[^|]*\\|", "format": "|%Y%m%d %H:%M|"}]`)
+//!  - `--only-type TYPES` : only process the files of the comma-separated
+//!    log types `TYPES` (like `grafana,iso`, or the name of a format of
+//!    `--formats`), leaving every other file untouched
+//!  - `--output-template TEMPLATE` : name output files after `TEMPLATE`,
+//!    relative to the output directory of their input file, where
+//!    `{base}` (or `{name}`, without extension) stands for the name of the
+//!    input file, `{date}`, `{year}`, `{month}`, `{day}` and `{hour}` for
+//!    the date of its lines, or `{date:FORMAT}` for that date written with
+//!    the `strftime` format `FORMAT`, like `{date:%Y%m%d}`, and `{vhost}` for
+//!    their virtual host with `--split-vhosts`
+//!    (default `{base}-{date}`, for example `{year}/{month}/{name}.{day}.log`)
+//!  - `--output-suffix SUFFIX` : append `SUFFIX` to the name of the input
+//!    file in the names of the output files, before their date (`.log` naming
+//!    those of `access.1` like `access.log-2023-10-11`), instead of
+//!    `--output-template`
+//!  - `--suffix-after-date` : append the suffix of `--output-suffix` after
+//!    the date instead (like `access-2023-10-11.log`), which must then be
+//!    made of extensions
+//!  - `--from DATE`, `--to DATE` : only keep lines dated from `DATE` and
+//!    up to `DATE` (both included, as `YYYY-MM-DD`) in the output files, and
+//!    set the others aside in `.excluded` files
+//!  - `--progress` : show a progress bar counting files and bytes in every
+//!    phase, instead of a line per file, when run in a terminal
+//!  - `--follow-links` : follow symbolic links when looking for files, for
+//!    log directories linking to other partitions
+//!  - `--max-depth N` : look for input files at most `N` directories deep
+//!    (default unlimited), `1` meaning only the files right in the input
+//!    directory, `2` those files and the files of its subdirectories, etc.
+//!  - `--unsorted` : handle files in the order the file system lists them,
+//!    instead of sorting them by name, to spare memory on huge directories
+//!    (runs on the same input are then logged in a different order)
+//!  - `--retries N` : try a file up to `N` more times (default 0) when
+//!    inflating, processing or compressing it fails with an error which may
+//!    go away (interrupted call, stale NFS handle, etc.), waiting a bit longer
+//!    every time, then report it and move on to the other files
+//!  - `--extensions REGEX` : process the files whose name matches `REGEX`
+//!    instead of those whose extension is a number, naming their outputs
+//!    after the rest of their name (for example `-\d{8}$` for the
+//!    `access.log-20231011` files of logrotate's `dateext`)
+//!  - `--flatten` : write all output files right in the output directory,
+//!    merging inputs of the same name found in different directories
+//!  - `--sanitize-names` : lowercase the names of the output files, and
+//!    replace any character other than `a-z`, `0-9`, `.`, `_` and `-` with
+//!    `_`, warning about inputs then merged into the same output files
+//!  - `--dir-mode MODE` : give the directories created for the output files
+//!    (and the output directory) the octal mode `MODE`, like `0750`, leaving
+//!    existing directories as they are
+//!  - `--json` : print the summary of the run (files processed per log type,
+//!    output files and lines written) as a line of JSON, for monitoring
+//!  - `--compress-directly` : write output files compressed right away,
+//!    instead of compressing them in a separate phase
+//!  - `--no-recompress` : skip the phase compressing output files, leaving
+//!    them in plain text for programs reading them right away
+//!  - `--no-degunzip` : skip the phase inflating compressed input files, for
+//!    inputs already plain, leaving compressed files as they are
+//!  - `--jobs N` : process at most `N` input files at once (default 0, one
+//!    per CPU), to spare spinning disks
+//!  - `--pipeline` : walk the input directory once, and inflate, process and
+//!    compress groups of files at the same time instead of one phase after
+//!    the other, on big trees (no progress bar is shown)
+//!  - `--threads-per-phase N` : run every phase of `--pipeline` with `N`
+//!    threads (default 0, one per CPU)
+//!  - `--min-size BYTES` : leave input files smaller than `BYTES` as they
+//!    are, and do not compress output files smaller than that either
+//!    (default 0)
+//!  - `--since AGE` : only inflate and process input files modified less
+//!    than `AGE` ago (like `25h`, with a unit among `s`, `m`, `h`, `d` and
+//!    `w`), leaving older ones as they are
+//!  - `--include PATTERN` : only inflate, process and compress the files
+//!    whose path below the input (or output) directory matches the glob
+//!    `PATTERN` (like `**/*.access.*`, where `*` stops at a `/` and `**`
+//!    crosses directories), which may be given several times ; input files
+//!    must still be rotated to be processed
+//!  - `--exclude PATTERN` : never touch the files whose path below the input
+//!    (or output) directory matches the glob `PATTERN` (like `**/journal/**`),
+//!    which may be given several times, and wins over `--include`
+//!  - `--no-touch-hardlinks` : leave alone the files with other hard links
+//!    (which are otherwise removed with a warning once done with), for a
+//!    program like a log shipper may still read them through another link
+//!  - `--postrotate COMMAND` : run `COMMAND` on every output file once
+//!    compressed, like `'rsync {} backup:/srv/logs/'`, where `{}` is the
+//!    path of the compressed file (the command is split on whitespace and run
+//!    without a shell), logging the commands which fail ; it cannot be used
+//!    with `--compress-directly`, `--bundle-days` or `--no-recompress`, which
+//!    skip the phase compressing output files
+//!  - `--keep-source` : never delete input files (nor plain output files once
+//!    compressed), for a trial run on a directory you have not backed up
+//!  - `--verify` : only delete an input file once the lines written from it
+//!    are counted, and a file once its compressed (or inflated) counterpart
+//!    is decoded again and checked, for irreplaceable logs
+//!  - `--manifest` : append to `logboop-manifest.ndjson`, in the output
+//!    directory, one JSON record per output file written from an input file,
+//!    giving its source, log type, date and number of lines
+//!  - `--read-compressed` : process compressed input files (like `syslog.2.gz`)
+//!    as they are, instead of inflating them to disk first, to spare space
+//!  - `--keep-line-endings` : write lines ending with `\r\n` (like logs
+//!    coming from Windows) with that ending, instead of a plain `\n`
+//!  - `--empty-files POLICY` : `delete` (default) to only delete input files
+//!    holding nothing but whitespace, or `mark` to move them to `.empty` files
+//!  - `--granularity GRANULARITY` : `day` (default) for one output file
+//!    per day, `hour` for one per hour, for log types giving the time of day,
+//!    or `week` for one per ISO week (named like `auth.log-2023-W41`)
+//!  - `--split-vhosts` : split Apache logs beginning with the virtual host
+//!    (`%v`) by virtual host on top of their date, into output files like
+//!    `access.log-example.com-2023-10-11` (or after `{vhost}` in
+//!    `--output-template`)
+//!  - `--timezone ZONE` : `log` (default) to split lines by the date written
+//!    in them, `utc` to split lines by their date in UTC, or `offset` to
+//!    split lines by the date written in them and its offset (named like
+//!    `access.log-2023-10-11+0200`), for log types writing the offset of
+//!    their timestamps (RFC 5424 syslog, MySQL slow queries, JSON, Apache
+//!    and Nginx access, and Grafana logs)
+//!  - `--rollup-after DAYS` : bundle daily output files older than `DAYS`
+//!    days into monthly `.tar.gz` archives
+//!  - `--consolidate-months BYTES` : merge the daily output files of every
+//!    past month taking less than `BYTES` altogether into one
+//!    `{base}-YYYY-MM.gz` file, lines kept in order
+//!  - `--bundle-days` : bundle the output files of every day, across all
+//!    services, into `day-YYYY-MM-DD.tar.gz` archives instead of compressing
+//!    them one by one
+//!  - `--delete-empty-dirs` : once everything is processed, remove the
+//!    directories of the input directory left empty (but never the input
+//!    directory itself)
+//!  - `--rename-only` : copy every input file whole to the output file of its
+//!    first dated line, without dating the others, for files already holding a
+//!    single day (with `--output-template '{year}/{month}/{day}/{base}'`,
+//!    files are sorted into dated directories)
+//!  - `--output-format FORMAT` : `raw` (default) to write lines as they
+//!    were read, or `ndjson` to write lines of JSON logs as normalized
+//!    records, and other lines as `{"date":...,"type":...,"line":...}`
+//!    records
+//!  - `--output-mtime WHEN` : `now` (default) to leave output files the
+//!    time they were written as their modification time, `bucket` to give
+//!    them the last second of their date (or hour) instead, or `source` the
+//!    modification time of their input file, kept once compressed, for
+//!    `find -mtime` clean ups (`--compress-only-newer` then misses lines
+//!    appended to plain output files)
+//!  - `--unwrap-docker` : write only what containers wrote in the lines of
+//!    Docker's `json-file` logs, without their JSON envelope
 #![doc(issue_tracker_base_url = "https://github.com/Lymkwi/logboop/issues/")]
 
-/* Crates used by this crate */
-// Lazy static is used to define constant regexes at compile time
-#[macro_use] extern crate lazy_static;
-// Regexes are used to detect and match log types
-extern crate regex;
-// WalkDir is used to easily walk through a directory tree structure
-// in order to operate on files in the input/output directories
-extern crate walkdir;
-// Flate2 is used for anything related to GZ compression/deflation
-extern crate flate2;
-// Chrono is used to manage, infer and format dates from the logs
-extern crate chrono;
-
-mod filesystem;
-mod compress;
-mod process;
+/* The library crate holds all of the actual logic */
+extern crate logboop;
+
+use logboop::{filesystem, pipeline, process};
+use logboop::options::{glob_set, Options};
+use logboop::process::{CustomFormat, FileProcessor, LogType};
+use flate2::Compression;
+use indicatif::{HumanBytes, ProgressBar, ProgressStyle};
+use log::{error, info, warn, LevelFilter};
 
 /* Needed imports for the main module */
-// We actually create the output directory here
-use std::fs::create_dir_all;
 // We manipulate paths
-use std::path::Path;
+use std::path::{Path, PathBuf};
 // Arguments are used to retrieve the input/output directories
 use std::env::{args, Args};
+// Failed runs are told apart by their exit status
+use std::process::exit;
+// Option values are parsed from strings
+use std::str::FromStr;
+// The progress bar is only shown on a terminal
+use std::io::IsTerminal;
+// The progress hook counts bytes from several threads
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+/// What the binary was asked to do
+enum Mode {
+    /// Degunzip, process and compress the files of a directory, showing
+    /// a progress bar instead of a line per file if asked to
+    Run {
+        /// Whether to show a progress bar
+        progress: bool,
+        /// Whether to print the summary of the run as JSON
+        json: bool,
+        /// Whether to run the phases as a pipeline
+        pipeline: bool,
+        /// How much of the run is logged
+        level: LevelFilter
+    },
+    /// Print every line of a single file along with its bucket, without
+    /// writing any file
+    Stdout {
+        /// How much is logged
+        level: LevelFilter
+    },
+    /// Print the log type of every file of a directory, without touching
+    /// any file
+    Probe {
+        /// How much is logged
+        level: LevelFilter
+    },
+    /// Print how a line would be recognized and dated
+    TestLine(String),
+    /// Print the log formats recognized, with their regex and date format
+    ListFormats
+}
+
+/// Separate the options from the positional arguments of the command line
+///
+/// Options are recognized anywhere on the command line, and fill an
+/// [`Options`] structure, or change the [`Mode`]. Everything else is
+/// returned, in order, as a positional argument.
+///
+/// # Errors
+/// An unknown option, or an option missing its value or given an invalid
+/// one, yields an `Err` describing it.
+fn parse_args(mut argv: Args) -> Result<(Options, Mode, Vec<String>), String> {
+    let mut options = Options::default();
+    let mut test_line = None;
+    let mut list_formats = false;
+    let mut only_types = None;
+    let mut include = Vec::new();
+    let mut exclude = Vec::new();
+    let mut progress = false;
+    let mut json = false;
+    let mut pipeline = false;
+    let mut stdout = false;
+    let mut probe = false;
+    let mut verbosity: i32 = 0;
+    let mut positionals = Vec::new();
+    while let Some(arg) = argv.next() {
+        match arg.as_str() {
+            "--test-line" => test_line = Some(parse_value(&arg, argv.next())?),
+            "--list-formats" => list_formats = true,
+            "-v" => verbosity += 1,
+            "-vv" => verbosity += 2,
+            "-q" => verbosity -= 1,
+            "--progress" => progress = true,
+            "--json" => json = true,
+            "--pipeline" => pipeline = true,
+            "--stdout" => stdout = true,
+            "--probe" => probe = true,
+            "--threads-per-phase" =>
+                options.threads_per_phase = parse_value(&arg, argv.next())?,
+            "--follow-links" => options.follow_links = true,
+            "--unsorted" => options.unsorted = true,
+            "--max-depth" => options.max_depth = Some(parse_value(&arg, argv.next())?),
+            "--retries" => options.retries = parse_value(&arg, argv.next())?,
+            "--flatten" => options.flatten = true,
+            "--sanitize-names" => options.sanitize_names = true,
+            "--dir-mode" => {
+                let mode: String = parse_value(&arg, argv.next())?;
+                let parsed = u32::from_str_radix(&mode, 8).ok().filter(|m| *m <= 0o7777);
+                options.dir_mode = Some(parsed.ok_or_else(|| format!(
+                    "invalid value \"{}\" for option \"{}\"", mode, arg))?);
+            },
+            "--extensions" =>
+                options.rotated_files = Some(parse_value(&arg, argv.next())?),
+            "--compress-only-newer" => options.compress_only_newer = true,
+            "--compress-directly" => options.compress_directly = true,
+            "--no-recompress" => options.no_recompress = true,
+            "--no-degunzip" => options.no_degunzip = true,
+            "--keep-source" => options.keep_source = true,
+            "--no-touch-hardlinks" => options.no_touch_hardlinks = true,
+            "--postrotate" => {
+                let command: String = parse_value(&arg, argv.next())?;
+                if command.trim().is_empty() {
+                    return Err(format!("invalid value \"{}\" for option \"{}\"",
+                                       command, arg));
+                }
+                options.postrotate = Some(command);
+            },
+            "--verify" => options.verify = true,
+            "--manifest" => options.manifest = true,
+            "--read-compressed" => options.read_compressed = true,
+            "--keep-line-endings" => options.keep_line_endings = true,
+            "--empty-files" =>
+                options.empty_files = parse_value(&arg, argv.next())?,
+            "--codec" => options.codec = parse_value(&arg, argv.next())?,
+            "--compression-level" => {
+                let level: u32 = parse_value(&arg, argv.next())?;
+                if level > 9 {
+                    return Err(format!("invalid value \"{}\" for option \"{}\"",
+                                       level, arg));
+                }
+                options.compression = Compression::new(level);
+            },
+            "--jobs" => options.jobs = parse_value(&arg, argv.next())?,
+            "--min-size" => options.min_size = parse_value(&arg, argv.next())?,
+            "--since" => options.since = Some(parse_value(&arg, argv.next())?),
+            "--include" => include.push(parse_value::<String>(&arg, argv.next())?),
+            "--exclude" => exclude.push(parse_value::<String>(&arg, argv.next())?),
+            "--timezone" => options.timezone = parse_value(&arg, argv.next())?,
+            "--granularity" =>
+                options.granularity = parse_value(&arg, argv.next())?,
+            "--split-vhosts" => options.split_vhosts = true,
+            "--output-format" =>
+                options.output_format = parse_value(&arg, argv.next())?,
+            "--output-mtime" =>
+                options.output_mtime = parse_value(&arg, argv.next())?,
+            "--unwrap-docker" => options.unwrap_docker = true,
+            "--bundle-days" => options.bundle_days = true,
+            "--consolidate-months" =>
+                options.consolidate_months = Some(parse_value(&arg, argv.next())?),
+            "--delete-empty-dirs" => options.delete_empty_dirs = true,
+            "--rename-only" => options.rename_only = true,
+            "--rollup-after" =>
+                options.rollup_after = Some(parse_value(&arg, argv.next())?),
+            "--max-dates-per-file" =>
+                options.max_dates_per_file = parse_value(&arg, argv.next())?,
+            "--max-open-files" =>
+                options.max_open_files = parse_value(&arg, argv.next())?,
+            "--buffer-size" => {
+                let size: usize = parse_value(&arg, argv.next())?;
+                if size == 0 {
+                    return Err(format!("invalid value \"{}\" for option \"{}\"",
+                                       size, arg));
+                }
+                options.buffer_size = size;
+            },
+            "--mem-limit" => options.mem_limit = parse_value(&arg, argv.next())?,
+            "--mixed" => options.mixed = true,
+            "--strict" => options.strict = true,
+            "--continue-on-parse-error" => options.strict = false,
+            "--dedup" => options.dedup = true,
+            "--resumable" => options.resumable = true,
+            "--detection-lines" =>
+                options.detection_lines = parse_value(&arg, argv.next())?,
+            "--header-lines" =>
+                options.header_lines = parse_value(&arg, argv.next())?,
+            "--from" => options.from_date = Some(parse_value(&arg, argv.next())?),
+            "--to" => options.to_date = Some(parse_value(&arg, argv.next())?),
+            "--output-template" =>
+                options.output_template = Some(parse_value(&arg, argv.next())?),
+            "--output-suffix" => {
+                let suffix: String = parse_value(&arg, argv.next())?;
+                if suffix.is_empty() || suffix.contains('/') {
+                    return Err(format!("invalid value \"{}\" for option \"{}\"",
+                                       suffix, arg));
+                }
+                options.output_suffix = suffix;
+            },
+            "--suffix-after-date" => options.suffix_after_date = true,
+            "--json-time-fields" => options.json_time_fields =
+                parse_value::<String>(&arg, argv.next())?
+                    .split(',').map(str::to_owned).collect(),
+            "--formats" => {
+                let path: String = parse_value(&arg, argv.next())?;
+                options.formats = CustomFormat::load(Path::new(&path))
+                    .map_err(|e| format!("invalid value \"{}\" for option \"{}\" : {}",
+                                         path, arg, e))?;
+            },
+            "--only-type" => only_types = Some(parse_value::<String>(&arg, argv.next())?),
+            flag if flag.starts_with("--") =>
+                return Err(format!("unknown option \"{}\"", flag)),
+            _ => positionals.push(arg)
+        }
+    }
+    // Custom log types are only known once their formats are loaded
+    if let Some(names) = only_types {
+        options.only_types = names.split(',').map(|name| options.find_type(name)
+            .map_err(|e| format!("invalid value \"{}\" for option \"--only-type\" : {}",
+                                 names, e)))
+            .collect::<Result<_, _>>()?;
+    }
+    if !include.is_empty() {
+        options.include = Some(glob_set(&include)
+            .map_err(|e| format!("invalid value for option \"--include\" : {}", e))?);
+    }
+    if !exclude.is_empty() {
+        options.exclude = Some(glob_set(&exclude)
+            .map_err(|e| format!("invalid value for option \"--exclude\" : {}", e))?);
+    }
+    // Only extensions can follow the date, for rollups to find the files again
+    let extensions = options.output_suffix.strip_prefix('.')
+        .is_some_and(|suffix| suffix.split('.').all(|extension| !extension.is_empty()
+            && extension.chars().all(|c| c.is_alphanumeric() || c == '_' || c == '-')));
+    if options.suffix_after_date && !extensions {
+        return Err(format!("invalid value \"{}\" for option \"--output-suffix\" : \
+                            only extensions can follow the date", options.output_suffix));
+    }
+    if options.output_template.is_some() && !options.output_suffix.is_empty() {
+        return Err("options \"--output-suffix\" and \"--output-template\" \
+                    cannot be used together".to_owned());
+    }
+    // The command is only run by the phase compressing output files
+    let skipping = [("--compress-directly", options.compress_directly),
+                    ("--bundle-days", options.bundle_days),
+                    ("--no-recompress", options.no_recompress)];
+    if let Some((flag, _)) = options.postrotate.as_ref()
+        .and(skipping.iter().find(|(_, skipped)| *skipped)) {
+        return Err(format!("options \"--postrotate\" and \"{}\" cannot be used together",
+                           flag));
+    }
+    if let (Some(from), Some(to)) = (options.from_date, options.to_date) {
+        if from > to {
+            return Err(format!("empty date range, from {} to {}", from, to));
+        }
+    }
+    let level = match verbosity {
+        i32::MIN..=-1 => LevelFilter::Error,
+        0 => LevelFilter::Info,
+        1 => LevelFilter::Debug,
+        2..=i32::MAX => LevelFilter::Trace
+    };
+    let mode = match test_line {
+        Some(line) => Mode::TestLine(line),
+        None if list_formats => Mode::ListFormats,
+        None if stdout => Mode::Stdout { level },
+        None if probe => Mode::Probe { level },
+        None => Mode::Run { progress, json, pipeline, level }
+    };
+    Ok((options, mode, positionals))
+}
+
+/// Parse the value given to an option
+///
+/// # Errors
+/// A missing value, or one that cannot be parsed, yields an `Err` naming
+/// the option.
+fn parse_value<T: FromStr>(option: &str, value: Option<String>)
+    -> Result<T, String> {
+    let value = value
+        .ok_or_else(|| format!("missing value for option \"{}\"", option))?;
+    value.parse()
+        .map_err(|_| format!("invalid value \"{}\" for option \"{}\"",
+                             value, option))
+}
+
+/// Count the files of a directory a phase of the run will handle
+fn count_files<F: Fn(&Path) -> bool>(root: &Path, follow_links: bool,
+                                     max_depth: Option<usize>, handled: F) -> u64 {
+    filesystem::walk(root, follow_links, max_depth, false)
+        .filter(|path| path.is_file() && handled(path))
+        .count() as u64
+}
+
+/// Show the progress of a phase of the run handling `total` files, if
+/// counted
+///
+/// The progress bar is installed as the hook of [`Options::progress`], and
+/// counts the files and bytes handled.
+fn track_progress(options: &mut Options, total: Option<u64>) -> Option<ProgressBar> {
+    let total = total?;
+    let bar = ProgressBar::new(total).with_style(
+        ProgressStyle::with_template("{bar:40} {pos}/{len} files, {msg} processed")
+            .unwrap());
+    let hook = bar.clone();
+    let bytes = AtomicU64::new(0);
+    options.progress = Some(Arc::new(move |_, size| {
+        let total = bytes.fetch_add(size, Ordering::Relaxed) + size;
+        hook.set_message(HumanBytes(total).to_string());
+        hook.inc(1);
+    }));
+    Some(bar)
+}
+
+/// Remove the progress bar of a phase, if any
+fn finish_progress(options: &mut Options, bar: Option<ProgressBar>) {
+    options.progress = None;
+    if let Some(bar) = bar {
+        bar.finish();
+    }
+}
+
+/// Print the log type, date and hour `LogBoop` would find for a line, custom
+/// formats first
+fn test_line(line: &str, options: &Options) {
+    let custom = options.formats.iter().find(|format| format.regex.is_match(line));
+    match (custom, process::detect_line(line)) {
+        (Some(format), _) => {
+            println!("type : {}", format.name);
+            println!("date : {}", format.date(line)
+                     .map_or_else(|| "?".to_owned(), |d| d.to_string()));
+            println!("hour : {}", format.hour(line)
+                     .map_or_else(|| "?".to_owned(), |h| format!("{:02}", h)));
+        },
+        (None, Some(logtype)) => {
+            println!("type : {}", logtype);
+            println!("date : {}", process::determine_date(&logtype, line)
+                     .unwrap_or_else(|_| "?".to_owned()));
+            println!("hour : {}", process::parse_hour(&logtype, line)
+                     .map_or_else(|| "?".to_owned(), |h| format!("{:02}", h)));
+        },
+        (None, None) => println!("type : ?")
+    }
+}
+
+/// Print every line of a single file along with its bucket
+///
+/// # Errors
+/// A path which is not a file, or an error while reading it, yields an
+/// `Err` describing it.
+fn print_file(path: &str, options: &Options) -> Result<(), String> {
+    let path = Path::new(path);
+    if !path.is_file() {
+        return Err(format!("input path (\"{}\") is not a file", path.display()));
+    }
+    let mut processor = FileProcessor::new(path.to_path_buf(), PathBuf::new(), options);
+    processor.determine_type()
+        .and_then(|_| processor.inspect(std::io::stdout().lock()))
+        .map(|_| ())
+        .map_err(|e| format!("error while reading {} : {}", path.display(), e))
+}
+
+/// Print the log type of every file found below a path, along with its size
+/// and first line that is not blank, separated by tabs
+///
+/// Files are only read, up to [`Options::detection_lines`] lines (see
+/// [`FileProcessor::head`]). Files which cannot be read are reported, and
+/// the others probed anyway.
+///
+/// # Errors
+/// A path which does not exist yields an `Err` describing it.
+fn probe(path: &str, options: &Options) -> Result<(), String> {
+    let path = Path::new(path);
+    if !path.exists() {
+        return Err(format!("input path (\"{}\") does not exist", path.display()));
+    }
+    filesystem::walk(path, options.follow_links, options.max_depth, true)
+        .filter(|entry| entry.is_file())
+        .for_each(|entry| {
+            let mut processor = FileProcessor::new(entry.clone(), PathBuf::new(), options);
+            let probed = processor.determine_type()
+                .and_then(|_| processor.head())
+                .and_then(|head| Ok((entry.metadata()?.len(), head)));
+            match probed {
+                Ok((bytes, head)) => println!("{}\t{}\t{}\t{}", entry.display(),
+                    processor.logtype().map_or_else(|| "?".to_owned(), |t| t.to_string()),
+                    bytes, head.iter().find(|line| !line.trim().is_empty())
+                        .map_or("", String::as_str)),
+                Err(e) => error!("error while reading {} : {}", entry.display(), e)
+            }
+        });
+    Ok(())
+}
+
+/// Print every log format `LogBoop` recognizes, custom formats first, in
+/// the order they are tried
+fn list_formats(options: &Options) {
+    options.formats.iter().for_each(|format| {
+        println!("type : {}", format.name);
+        println!("regex : {}", format.regex);
+        println!("date : {}", format.format);
+        println!();
+    });
+    LogType::builtins().iter().for_each(|logtype| {
+        println!("type : {}", logtype);
+        println!("regex : {}", logtype.regex_source().unwrap_or("?"));
+        println!("date : {}", logtype.date_format().unwrap_or("RFC 3339"));
+        println!();
+    });
+}
+
+/// Log to the standard error, up to `level` (unless `RUST_LOG` tells
+/// otherwise)
+fn init_logging(level: LevelFilter) {
+    env_logger::Builder::new()
+        .filter_level(level)
+        .format_timestamp(None)
+        .format_target(false)
+        .parse_default_env()
+        .init();
+}
 
 #[doc(hidden)]
 fn main() {
     // Check that we have all of the arguments
     let mut argv: Args = args();
     let progname = argv.next().unwrap();
+    let (mut options, mode, positionals) = match parse_args(argv) {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            eprintln!("{} : {}", progname, e);
+            exit(1);
+        }
+    };
+    let (show_progress, json, pipelined) = match mode {
+        Mode::TestLine(line) => {
+            test_line(&line, &options);
+            return;
+        },
+        Mode::ListFormats => {
+            list_formats(&options);
+            return;
+        },
+        Mode::Stdout { level } => {
+            init_logging(level);
+            let printed = match positionals.first() {
+                Some(path) => print_file(path, &options),
+                None => Err("missing argument (input file path)".to_owned())
+            };
+            if let Err(e) = printed {
+                error!("{} : {}", progname, e);
+                exit(1);
+            }
+            return;
+        },
+        Mode::Probe { level } => {
+            init_logging(level);
+            let probed = match positionals.first() {
+                Some(path) => probe(path, &options),
+                None => Err("missing argument (input folder or file path)".to_owned())
+            };
+            if let Err(e) = probed {
+                error!("{} : {}", progname, e);
+                exit(1);
+            }
+            return;
+        },
+        Mode::Run { progress, json, pipeline, level } => {
+            init_logging(level);
+            // Logs of unattended runs are left free of escape codes, and
+            // the phases of a pipeline cannot share a progress bar
+            (progress && !pipeline && std::io::stdout().is_terminal(), json, pipeline)
+        }
+    };
+    let mut positionals = positionals.into_iter();
+
+    // A first Ctrl-C lets the files being handled finish, a second one
+    // stops right away
+    let stop = Arc::new(AtomicBool::new(false));
+    let handler = stop.clone();
+    let installed = ctrlc::set_handler(move || {
+        if handler.swap(true, Ordering::SeqCst) {
+            exit(130);
+        }
+        warn!("Interrupted, finishing the files being handled");
+    });
+    if let Err(e) = installed {
+        warn!("{} : cannot handle interruptions : {}", progname, e);
+    }
+    options.stop = Some(stop);
 
     // Check that we have an input folder
-    let potential_path: Option<String> = argv.next();
+    let potential_path: Option<String> = positionals.next();
     if potential_path.is_none() {
-        eprintln!("{} : missing argument (input folder path)", progname);
-        return;
+        error!("{} : missing argument (input folder or file path)", progname);
+        exit(1);
     }
     
     let input_path_str: String = potential_path.unwrap();
 
     // Retrieve a potential second argument
-    let output_path_str: String = argv.next()
+    let output_path_str: String = positionals.next()
         .unwrap_or_else(|| "output".to_owned());
 
     // Now, assess the input path
     let input_path = Path::new(&input_path_str);
     let output_path = Path::new(&output_path_str);
 
-    // Input ok ?
-    if !input_path.is_dir() {
-        eprintln!("{} : input path (\"{}\") is not a directory", progname, input_path_str);
-        return;
+    // Input ok ? A single file is processed on its own
+    let single = input_path.is_file();
+    if !single && !input_path.is_dir() {
+        error!("{} : input path (\"{}\") is neither a file nor a directory",
+               progname, input_path_str);
+        exit(1);
+    }
+
+    // Outputs inside the input directory would be split again by the next
+    // run, so this is checked before anything is written
+    if !single {
+        match filesystem::overlap(input_path, output_path) {
+            Ok(false) => (),
+            Ok(true) => {
+                error!("{} : output path (\"{}\") and input path (\"{}\") are inside one \
+                        another", progname, output_path_str, input_path_str);
+                exit(1);
+            },
+            Err(e) => {
+                error!("{} : error while resolving the input and output paths : {}",
+                       progname, e);
+                exit(1);
+            }
+        }
     }
 
     // Output ok ?
     if !output_path.is_dir() {
         // If the output folder does not exist, we can try and create it...
         if output_path.exists() {
-            eprintln!("{} : output path (\"{}\") exists and is not a directory", progname, output_path_str);
-            return;
+            error!("{} : output path (\"{}\") exists and is not a directory", progname, output_path_str);
+            exit(1);
         }
-        if let Err(e) = create_dir_all(&output_path) {
-            eprintln!("{} : error while creating output folder : {}",
-                      progname, e);
-            return;
+        if let Err(e) = filesystem::create_dirs(output_path, options.dir_mode) {
+            error!("{} : error while creating output folder : {}",
+                   progname, e);
+            exit(1);
         }
     }
 
-    // Degunzip all the files
-    println!("--- Beginning Degunzipping procedure ---");
-    if let Err(e) = filesystem::degunzip_all_the_files(&input_path) {
-        eprintln!("{} : terrible : {}", progname, e);
-        return;
+    // A single file is not worth a pipeline
+    let pipelined = pipelined && !single;
+
+    // Degunzip all the files, unless they are read as they are
+    if !options.read_compressed && !options.no_degunzip && !single && !pipelined {
+        info!("--- Beginning Degunzipping procedure ---");
+        let total = show_progress.then(|| count_files(input_path, options.follow_links,
+                                                      options.max_depth,
+                                                      |path| options.is_recent(path)
+                                                      && options.is_selected(path, input_path)
+                                                      && options.may_touch(path)));
+        let bar = track_progress(&mut options, total);
+        let result = filesystem::degunzip_all_the_files(input_path, &options);
+        finish_progress(&mut options, bar);
+        if let Err(e) = result {
+            error!("{} : terrible : {}", progname, e);
+            exit(1);
+        }
+        info!("--- All compressed files degunzipped ---");
+    }
+    if options.stop_requested() {
+        exit(130);
     }
-    println!("--- All compressed files degunzipped ---");
 
     // Process all of the files
-    println!("--- Processing all of the files ---");
-    if let Err(e) = process::all_files(&input_path, &output_path) {
-        eprintln!("{} : Error during file processing : {}", progname, e);
-        return;
+    info!("--- Processing all of the files ---");
+    let total = show_progress.then(|| if single {
+        1
+    } else {
+        count_files(input_path, options.follow_links, options.max_depth,
+                    |path| options.rotated_base(path).is_some() && options.is_recent(path)
+                    && options.is_selected(path, input_path) && options.may_touch(path))
+    });
+    let bar = track_progress(&mut options, total);
+    let result = if single {
+        process::single_file(input_path, output_path, &options)
+    } else if pipelined {
+        pipeline::run(input_path, output_path, &options)
+    } else {
+        process::all_files(input_path, output_path, &options)
+    };
+    finish_progress(&mut options, bar);
+    let stats = match result {
+        Ok(stats) => stats,
+        Err(e) => {
+            error!("{} : Error during file processing : {}", progname, e);
+            exit(1);
+        }
+    };
+    info!("--- All files processed ---");
+
+    // Regunzip all the dated files, unless they already are compressed, are
+    // to be bundled or are to be left plain
+    if !options.compress_directly && !options.bundle_days && !options.no_recompress
+        && !options.stop_requested() && !pipelined {
+        info!("--- Compressing all of the output files ---");
+        let total = show_progress.then(|| count_files(
+            output_path, options.follow_links, None,
+            |path| options.is_output_file(path) && options.is_selected(path, output_path)));
+        let bar = track_progress(&mut options, total);
+        let result = filesystem::gunzip_all_the_files(output_path, &options);
+        finish_progress(&mut options, bar);
+        if let Err(e) = result {
+            error!("{} : Error during file compressing : {}", progname, e);
+            exit(1);
+        }
+        info!("--- All files successfully compressed ---");
+    }
+
+    // Merge the small files of every month together
+    if let Some(below) = options.consolidate_months.filter(|_| !options.stop_requested()) {
+        info!("--- Merging months of less than {} bytes ---", below);
+        if let Err(e) = filesystem::consolidate_months(output_path, below) {
+            error!("{} : Error during merging : {}", progname, e);
+            exit(1);
+        }
+        info!("--- All small months merged ---");
     }
-    println!("--- All files processed ---");
 
-    // Regunzip all the dated files
-    println!("--- Compressing all of the output files ---");
-    if let Err(e) = filesystem::gunzip_all_the_files(&output_path) {
-        eprintln!("{} : Error during file compressing : {}", progname, e);
-        return;
+    // Bundle the files of every day together
+    if options.bundle_days && !options.stop_requested() {
+        info!("--- Bundling the output files of every day ---");
+        if let Err(e) = filesystem::bundle_days(output_path) {
+            error!("{} : Error during bundling : {}", progname, e);
+            exit(1);
+        }
+        info!("--- All files bundled ---");
+    }
+
+    // Roll old files up into monthly archives
+    if let Some(days) = options.rollup_after.filter(|_| !options.stop_requested()) {
+        info!("--- Rolling up files older than {} days ---", days);
+        if let Err(e) = filesystem::rollup_months(output_path, days) {
+            error!("{} : Error during roll up : {}", progname, e);
+            exit(1);
+        }
+        info!("--- All old files rolled up ---");
+    }
+
+    // Tidy the input directory up
+    if options.delete_empty_dirs && !single && !options.stop_requested() {
+        info!("--- Removing empty input directories ---");
+        match filesystem::delete_empty_dirs(input_path, &options) {
+            Ok(removed) => info!("--- {} empty directories removed ---", removed),
+            Err(e) => {
+                error!("{} : Error while removing empty directories : {}", progname, e);
+                exit(1);
+            }
+        }
+    }
+
+    // Tell what was done
+    if json {
+        println!("{}", stats.to_json());
+    } else {
+        info!("--- Summary ---");
+        info!("{}", stats);
+    }
+    if options.stop_requested() {
+        exit(130);
+    }
+    if stats.failed > 0 || options.hook_failures.load(Ordering::SeqCst) > 0 {
+        exit(2);
     }
-    println!("--- All files successfully compressed ---");
 }
@@ -0,0 +1,1368 @@
+//! Module holding the settings of a `LogBoop` run
+//!
+//! Every knob that changes the behaviour of the processing or
+//! (de)compression phases lives in the [`Options`] structure, which is
+//! given by reference to the functions that need it. The binary fills it
+//! from its command line, while library users can build one directly,
+//! starting from [`Options::default`] which reproduces the historical
+//! behaviour of `LogBoop`.
+//!
+//! # Example
+//! ```
+//! # use logboop::options::Options;
+//! let options = Options {
+//!     compress_only_newer: true,
+//!     ..Options::default()
+//! };
+//! assert!(options.compress_only_newer);
+//! ```
+use std::collections::HashSet;
+use std::convert::TryFrom;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::thread::available_parallelism;
+use std::time::{Duration, SystemTime};
+
+use chrono::{NaiveDate, NaiveTime};
+use chrono::format::{Fixed, Item, Numeric, Pad, StrftimeItems};
+use flate2::Compression;
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use regex::Regex;
+
+use crate::filesystem::link_count;
+use crate::json;
+use crate::process::{is_rotated, Bucket, CustomFormat, LogType};
+
+lazy_static! {
+    /// Regex object used to match the ISO 8601 date format at the end of
+    /// a file name, with an optional hour, or an ISO 8601 week, and an
+    /// optional UTC offset
+    ///
+    /// Its exact regex is
+    /// `-(\d{4}-\d{2}-\d{2}(T\d{2})?|\d{4}-W\d{2})([+-]\d{4})?$` (a hyphen is
+    /// added before the date when we create the file)
+    static ref ISO_DATE_REGEX: Regex = Regex::new(r"-(\d{4}-\d{2}-\d{2}(T\d{2})?|\d{4}-W\d{2})([+-]\d{4})?$").unwrap();
+}
+
+/// Naming policy of the output files
+///
+/// Such a function receives a [`Bucket`] (the lines of one input file sharing
+/// a date, or an hour) and the base output path of that input file, and returns
+/// the path of the file in which that bucket is written. Parent directories
+/// of the returned path are created as needed.
+///
+/// Input files are processed concurrently, except for those sharing a base
+/// output path (see [`all_files`](crate::process::all_files)). Such a
+/// function must therefore never give the same path for two distinct base
+/// output paths.
+pub type BucketPathFn = dyn Fn(&Bucket, &Path) -> PathBuf + Send + Sync;
+
+/// Hook told about every file handled by a phase of the run
+///
+/// Such a function receives the path of the file, and its size in bytes
+/// before it was handled. Files are handled concurrently while processing
+/// (see [`all_files`](crate::process::all_files)), so it may be called from
+/// several threads at once.
+pub type ProgressFn = dyn Fn(&Path, u64) + Send + Sync;
+
+/// Settings of a `LogBoop` run
+#[derive(std::clone::Clone)]
+pub struct Options {
+    /// When compressing the output files, skip those for which a compressed
+    /// file (see [`Options::codec`]) already exists and is more recent than the plain file
+    pub compress_only_newer: bool,
+    /// Function giving the path of every output file, which defaults to
+    /// [`default_bucket_path`], unless [`Options::output_template`] is set
+    ///
+    /// ```
+    /// # use std::path::Path;
+    /// # use std::sync::Arc;
+    /// # use logboop::options::Options;
+    /// # use logboop::process::Bucket;
+    /// // Sort outputs in a directory per month instead
+    /// let options = Options {
+    ///     bucket_path: Arc::new(|bucket, base| {
+    ///         let month = bucket.date.format("%Y-%m").to_string();
+    ///         base.with_file_name(month).join(base.file_name().unwrap())
+    ///     }),
+    ///     ..Options::default()
+    /// };
+    /// let date = chrono::NaiveDate::from_ymd_opt(2020, 5, 17).unwrap();
+    /// let bucket = Bucket { date, hour: None, week: false, offset: None, vhost: None };
+    /// assert_eq!((options.bucket_path)(&bucket, Path::new("out/syslog")),
+    ///            Path::new("out/2020-05/syslog"));
+    /// ```
+    pub bucket_path: Arc<BucketPathFn>,
+    /// Maximum number of distinct dates (or hours, with hourly buckets) a
+    /// single input file may yield before it is aborted, `0` meaning no limit
+    ///
+    /// A file yielding thousands of dates is almost always a misdetected
+    /// format, which would otherwise create thousands of output files.
+    pub max_dates_per_file: usize,
+    /// Maximum number of output files kept open at once while processing a
+    /// single input file, the least recently used one being closed to open
+    /// another (see [`split_lines`](crate::process::split_lines))
+    ///
+    /// Up to [`Options::jobs`] input files are processed at once, each with
+    /// its own output files : keep their product under the limit of open
+    /// files of the process (`ulimit -n`). `0` is taken as `1`, closing
+    /// every output file as soon as lines go to another one.
+    pub max_open_files: usize,
+    /// Capacity in bytes of the buffer through which every input file is
+    /// read, and every output file written
+    ///
+    /// Bigger buffers mean fewer system calls, at the cost of memory (see
+    /// [`Options::mem_limit`]).
+    pub buffer_size: usize,
+    /// Memory in bytes the buffers of the files being processed, along with
+    /// the encoders of output files written compressed, may take at once
+    ///
+    /// Fewer input files than [`Options::jobs`] are processed at once, and
+    /// fewer output files than [`Options::max_open_files`] kept open, when
+    /// needed to stay under it (see [`Options::memory_bounds`]). One input
+    /// file is still processed, with one output file open, however low it
+    /// is.
+    pub mem_limit: u64,
+    /// Write the output files through compression directly (as
+    /// `{bucket}.gz`, or `{bucket}.zst` with [`Codec::Zstd`] and
+    /// `{bucket}.bz2` with [`Codec::Bzip2`]), instead of
+    /// writing them in plain text and compressing them in a separate phase
+    pub compress_directly: bool,
+    /// Leave the output files in plain text, skipping the phase compressing
+    /// them, for programs reading them right away
+    pub no_recompress: bool,
+    /// Once everything is processed and compressed, roll daily output files
+    /// older than this many days up into monthly archives (see
+    /// [`rollup_months`](crate::filesystem::rollup_months))
+    pub rollup_after: Option<u64>,
+    /// Once everything is processed, bundle the output files of every day
+    /// into one archive per day, instead of compressing them one by one (see
+    /// [`bundle_days`](crate::filesystem::bundle_days))
+    pub bundle_days: bool,
+    /// Once everything is processed and compressed, merge the daily output
+    /// files of every past month taking less than this many bytes altogether
+    /// into one monthly file (see
+    /// [`consolidate_months`](crate::filesystem::consolidate_months))
+    pub consolidate_months: Option<u64>,
+    /// Once everything is processed, remove the directories of the input
+    /// directory left empty (see
+    /// [`delete_empty_dirs`](crate::filesystem::delete_empty_dirs))
+    pub delete_empty_dirs: bool,
+    /// Copy every input file whole to the output file of the first dated
+    /// line among its first [`Options::detection_lines`], instead of dating
+    /// every line, for files already holding a single day (see
+    /// [`FileProcessor::process`](crate::process::FileProcessor::process))
+    ///
+    /// With an [`Options::output_template`] like `{year}/{month}/{day}/{base}`,
+    /// files are thus sorted into dated directories.
+    pub rename_only: bool,
+    /// How lines are written in the output files
+    pub output_format: OutputFormat,
+    /// Modification time given to the output files once written, and kept
+    /// by their compressed counterparts
+    pub output_mtime: OutputMtime,
+    /// Write only what containers wrote, without the JSON envelope of
+    /// [`LogType::DockerJson`] lines (see
+    /// [`render_line`](crate::process::render_line))
+    pub unwrap_docker: bool,
+    /// Write lines ending with `\r\n` (as logs coming from Windows do) with
+    /// that ending, instead of a plain `\n`, for byte-exact outputs
+    ///
+    /// Line endings never take part in recognizing or dating lines. Lines
+    /// ending with nothing (the last line of a file) still get a `\n`, so that
+    /// lines appended after them are kept apart.
+    pub keep_line_endings: bool,
+    /// Compression used for the output files, whether they are compressed
+    /// in a separate phase or directly
+    pub codec: Codec,
+    /// Level of GZ compression of the output files, from
+    /// [`Compression::fast`] for hot data to [`Compression::best`] for cold
+    /// archives
+    pub compression: Compression,
+    /// Never remove an input file once it is inflated or processed, nor an
+    /// output file once it is compressed, so that a trial run leaves the
+    /// input directory intact
+    ///
+    /// Lines of inputs processed again by a later run are appended to the
+    /// outputs once more.
+    pub keep_source: bool,
+    /// Before deleting an input file, check what was written from it : the
+    /// lines written to the outputs of a processed file are counted against
+    /// its lines, and compressed (or inflated) files are decoded again and
+    /// their size compared with the plain file
+    ///
+    /// Files failing this check are kept, with a
+    /// [`VerifyError`](crate::error::LogBoopError::VerifyError).
+    pub verify: bool,
+    /// Append a record of every output file written from every input file
+    /// to a manifest in the output directory, naming its source, log type,
+    /// date and number of lines (see
+    /// [`write_manifest`](crate::process::write_manifest))
+    pub manifest: bool,
+    /// Leave alone the files with other hard links, instead of inflating,
+    /// processing or compressing them and removing one of their links with
+    /// a warning, for a program reading them through another link (like a
+    /// log shipper) expects them as they are
+    pub no_touch_hardlinks: bool,
+    /// Command run on every file compressed by
+    /// [`gunzip_all_the_files`](crate::filesystem::gunzip_all_the_files),
+    /// like `rsync {} backup:/srv/logs/`, once compressed
+    ///
+    /// The command is split on whitespace, without any shell, and every
+    /// `{}` in it is replaced with the path of the compressed file. A
+    /// command failing is logged and counted in [`Options::hook_failures`],
+    /// and the run goes on. Output files compressed right away (see
+    /// [`Options::compress_directly`]) are not given to it.
+    pub postrotate: Option<String>,
+    /// What is done with input files holding nothing but whitespace
+    pub empty_files: EmptyFiles,
+    /// Process compressed input files as they are, inflating them on the fly,
+    /// instead of inflating them to disk first (see
+    /// [`FileProcessor`](crate::process::FileProcessor))
+    pub read_compressed: bool,
+    /// Skip the phase inflating compressed input files, for inputs already
+    /// plain : compressed files are then left as they are
+    pub no_degunzip: bool,
+    /// Period of time covered by every output file
+    pub granularity: Granularity,
+    /// Split [`LogType::ApacheVhost`] lines by virtual host on top of their
+    /// date, into output files like `access.log-example.com-2023-10-11`
+    ///
+    /// The virtual host is kept in the [`Bucket`] of every line (see
+    /// [`parse_vhost`]), and output templates name it `{vhost}` (see
+    /// [`OutputTemplate`]).
+    ///
+    /// [`parse_vhost`]: crate::process::parse_vhost
+    pub split_vhosts: bool,
+    /// Whether lines are bucketed by the date written in them, or by their
+    /// date in UTC when their timestamp gives an offset
+    pub timezone: Timezone,
+    /// Number of threads of every phase of a pipelined run, `0` meaning one
+    /// per CPU (see [`pipeline::run`](crate::pipeline::run))
+    pub threads_per_phase: usize,
+    /// Number of input files processed at once, `0` meaning one per CPU
+    pub jobs: usize,
+    /// Size in bytes under which input files are not processed, nor output
+    /// files compressed, and left as they are
+    ///
+    /// Tiny rotated stubs are not worth processing, and compressing a file
+    /// of a few bytes only makes it bigger.
+    pub min_size: u64,
+    /// Only inflate or process input files modified less than this long ago,
+    /// leaving older ones as they are (see [`Options::is_recent`])
+    pub since: Option<Age>,
+    /// Number of lines read at the start of an input file to determine its
+    /// log type (see [`FileProcessor::determine_type`](crate::process::FileProcessor::determine_type))
+    ///
+    /// Blank lines, banners and rotation headers are skipped until a line of
+    /// a known log type is found.
+    pub detection_lines: usize,
+    /// Number of lines at the beginning of every input file making up a
+    /// header (like the column names of CSV logs, or a startup banner),
+    /// which are neither looked at to detect its log type nor dated, but
+    /// kept aside (see
+    /// [`FileProcessor::process`](crate::process::FileProcessor::process))
+    pub header_lines: usize,
+    /// Date every line by whichever log type it matches, rather than only by
+    /// the log type of its file, for files interleaving several formats
+    /// (like the output of a log aggregator)
+    ///
+    /// Lines are still tried with the log type of their file first, so that
+    /// only the others are matched against every format, which is slower.
+    /// Files whose log type cannot be determined are still kept aside as a
+    /// whole.
+    pub mixed: bool,
+    /// Abort a file on its first line following the log type of that file
+    /// but holding no valid date (like `2023-02-30`), rather than filing it
+    /// with the lines around it
+    ///
+    /// The file is then left untouched, with a
+    /// [`ParseError`](crate::error::LogBoopError::ParseError) naming the line.
+    /// Lines not following the log type at all, like the continuation lines
+    /// of a stack trace, are still filed with the line before them.
+    pub strict: bool,
+    /// Write a line identical to the one before it in the same output file
+    /// only once, followed by a `... (repeated N times)` line counting the
+    /// copies dropped, like syslog does for hammering clients
+    ///
+    /// Lines are compared as they were read, per output file, so that lines
+    /// of other dates in between do not stop a run of repeats. With
+    /// [`Options::resumable`], pending repeats are written at every
+    /// checkpoint, so that none are lost, and the run starts over with the
+    /// line itself.
+    pub dedup: bool,
+    /// Save how far every input file was split to `{outroot}.progress` along
+    /// the way, so that a run interrupted in the middle of a huge file starts
+    /// again from there instead of from its first line (see
+    /// [`FileProcessor::process`](crate::process::FileProcessor::process))
+    ///
+    /// Ignored with [`Options::compress_directly`], since compressed output
+    /// files cannot be cut back to a checkpoint.
+    pub resumable: bool,
+    /// Fields tried, in order, to find the RFC 3339 timestamp of
+    /// [`LogType::Json`](crate::process::LogType::Json) lines, by default
+    /// those of [`json::DEFAULT_TIME_FIELDS`]
+    pub json_time_fields: Vec<String>,
+    /// Log formats recognized on top of the built-in ones, and tried before
+    /// them (see [`CustomFormat`])
+    pub formats: Vec<CustomFormat>,
+    /// Log types of the files to process, every other file being left
+    /// untouched, or every log type when empty (see
+    /// [`Options::accepts_type`])
+    pub only_types: Vec<LogType>,
+    /// Template naming the output files, overriding [`Options::bucket_path`]
+    ///
+    /// Unlike a naming function, a template also tells which files of the
+    /// output directory are outputs to compress (see
+    /// [`Options::is_output_file`]). Monthly rollups only gather files named
+    /// the historical way, though.
+    pub output_template: Option<OutputTemplate>,
+    /// Suffix appended to the base output path of every input file, before
+    /// the date (`access` giving `access.log-2023-10-11` with `.log`), or
+    /// after it with [`Options::suffix_after_date`]
+    ///
+    /// It is ignored with [`Options::output_template`], which holds any
+    /// suffix itself. After the date, monthly rollups and daily bundles only
+    /// gather files whose suffix is made of extensions, like `.log`.
+    pub output_suffix: String,
+    /// Append [`Options::output_suffix`] after the date rather than before
+    /// it (`access` giving `access-2023-10-11.log` with `.log`)
+    pub suffix_after_date: bool,
+    /// First date (included) of the lines kept in the output files, lines
+    /// dated before being set aside (see
+    /// [`FileProcessor::process`](crate::process::FileProcessor::process))
+    pub from_date: Option<NaiveDate>,
+    /// Last date (included) of the lines kept in the output files, lines
+    /// dated after being set aside
+    pub to_date: Option<NaiveDate>,
+    /// Hook told about every file handled while inflating, processing or
+    /// compressing, to show the progress of the run
+    ///
+    /// When set, it replaces the line printed for every file.
+    pub progress: Option<Arc<ProgressFn>>,
+    /// Flag which, once set (from a signal handler, for example), stops the
+    /// run between two files : the file being handled is finished, but no
+    /// other one is started (see [`Options::stop_requested`])
+    pub stop: Option<Arc<AtomicBool>>,
+    /// Files inflated so far by the run (see
+    /// [`degunzip_file`](crate::filesystem::degunzip_file)), shared by the
+    /// clones of these options
+    ///
+    /// They were rotated and compressed, so are processed whatever their
+    /// name (see [`Options::rotated_base`]), as long as the same options
+    /// are given to both phases.
+    pub inflated: Arc<Mutex<HashSet<PathBuf>>>,
+    /// Number of [`Options::postrotate`] commands which failed so far,
+    /// shared by the clones of these options
+    pub hook_failures: Arc<AtomicUsize>,
+    /// Follow symbolic links when looking for files to inflate, process or
+    /// compress (see [`walk`](crate::filesystem::walk))
+    pub follow_links: bool,
+    /// Only look for input files to inflate or process this many levels
+    /// below the input directory, `None` meaning no limit (see
+    /// [`walk`](crate::filesystem::walk))
+    ///
+    /// `Some(1)` only finds the files right in the input directory, and
+    /// `Some(2)` those of its subdirectories too. The output directory is
+    /// always walked whole.
+    pub max_depth: Option<usize>,
+    /// Handle files in the order the operating system lists them, instead
+    /// of sorting the entries of every directory by name (see
+    /// [`walk`](crate::filesystem::walk)), to spare the memory of listing
+    /// huge directories whole
+    ///
+    /// Runs on the same input are then no longer handled in the same order,
+    /// nor logged or written to the manifest in the same order.
+    pub unsorted: bool,
+    /// Try a file this many more times when inflating, processing or
+    /// compressing it fails with an error which may go away, like a stale
+    /// handle of a network file system (see
+    /// [`retry`](crate::filesystem::retry))
+    ///
+    /// A file still failing after its retries is reported and given up on,
+    /// and the other files are handled anyway.
+    pub retries: u32,
+    /// Write all output files right in the output directory, named after the
+    /// file name of their input only, instead of mirroring the input tree
+    ///
+    /// Inputs of the same name found in different directories then append
+    /// to the same output files.
+    pub flatten: bool,
+    /// Lowercase the names of the output files, and replace any character
+    /// other than `a` to `z`, `0` to `9`, `.`, `_` and `-` with `_` (see
+    /// [`sanitize_path`](crate::filesystem::sanitize_path)), for storage
+    /// choking on other names
+    ///
+    /// Only the part of the names coming from the input files is sanitized,
+    /// not their date. Inputs whose names are then the same append to the
+    /// same output files, which is warned about.
+    pub sanitize_names: bool,
+    /// Mode given to the directories created in the output directory (and
+    /// to the output directory itself, by the binary), like `0o750` to keep
+    /// other users out, instead of the default permissions
+    ///
+    /// Directories which already existed are left as they are (see
+    /// [`create_dirs`](crate::filesystem::create_dirs)).
+    pub dir_mode: Option<u32>,
+    /// Regex selecting the input files to process, searched in their file
+    /// name, whose match is removed to name their outputs (see
+    /// [`Options::rotated_base`])
+    ///
+    /// It defaults to `None`, for the files whose extension is a number
+    /// (see [`is_rotated`](crate::process::is_rotated)).
+    pub rotated_files: Option<Regex>,
+    /// Glob patterns (see [`glob_set`]) of the files to inflate, process or
+    /// compress, `None` meaning any file (see [`Options::is_selected`])
+    pub include: Option<GlobSet>,
+    /// Glob patterns (see [`glob_set`]) of the files never to inflate,
+    /// process or compress, whatever [`Options::include`] says
+    pub exclude: Option<GlobSet>
+}
+
+impl Options {
+    /// Report that a file was handled
+    ///
+    /// The status line of the file is logged at `level`, unless
+    /// [`Options::progress`] is set, in which case it is told about the file
+    /// instead.
+    pub fn report(&self, level: log::Level, path: &Path, bytes: u64,
+                  status: std::fmt::Arguments<'_>) {
+        match self.progress {
+            Some(ref progress) => progress(path, bytes),
+            None => log::log!(level, "{}", status)
+        }
+    }
+
+    /// Whether the run was asked to stop (see [`Options::stop`])
+    ///
+    /// ```
+    /// # use std::sync::Arc;
+    /// # use std::sync::atomic::{AtomicBool, Ordering};
+    /// # use logboop::options::Options;
+    /// let stop = Arc::new(AtomicBool::new(false));
+    /// let options = Options { stop: Some(stop.clone()), ..Options::default() };
+    /// assert!(!options.stop_requested());
+    /// stop.store(true, Ordering::SeqCst);
+    /// assert!(options.stop_requested());
+    /// ```
+    pub fn stop_requested(&self) -> bool {
+        self.stop.as_ref().is_some_and(|stop| stop.load(Ordering::SeqCst))
+    }
+
+    /// Whether a file was modified less than [`Options::since`] ago, which
+    /// every file is when it is not set
+    ///
+    /// Files modified in the future (as with a skewed clock), or whose
+    /// modification time cannot be read, are recent.
+    ///
+    /// ```no_run
+    /// # use std::path::Path;
+    /// # use logboop::options::Options;
+    /// let options = Options { since: Some("25h".parse().unwrap()), ..Options::default() };
+    /// assert!(options.is_recent(Path::new("/var/log/syslog.1")));
+    /// ```
+    pub fn is_recent(&self, path: &Path) -> bool {
+        let since = match self.since {
+            Some(Age(since)) => since,
+            None => return true
+        };
+        path.metadata().and_then(|meta| meta.modified())
+            .ok()
+            .and_then(|mtime| SystemTime::now().duration_since(mtime).ok())
+            .is_none_or(|age| age < since)
+    }
+
+    /// Number of input files processed at once out of `workers` (`0`
+    /// meaning one per CPU), and of output files each keeps open, for their
+    /// buffers to fit in [`Options::mem_limit`]
+    ///
+    /// Every input file processed is read through a buffer of
+    /// [`Options::buffer_size`] bytes, and every output file open is written
+    /// through another one, along with an encoder when
+    /// [`Options::compress_directly`] is set (see [`Codec::encoder_memory`]).
+    ///
+    /// ```
+    /// # use logboop::options::{Codec, Options};
+    /// let options = Options { compress_directly: true, codec: Codec::Bzip2,
+    ///                         mem_limit: 64 << 20, ..Options::default() };
+    /// assert_eq!(options.memory_bounds(16), (7, 1));
+    /// assert_eq!(options.memory_bounds(2), (2, 3));
+    /// assert_eq!(Options::default().memory_bounds(4), (4, 16));
+    /// ```
+    pub fn memory_bounds(&self, workers: usize) -> (usize, usize) {
+        let workers = match workers {
+            0 => available_parallelism().map_or(1, |n| n.get()),
+            n => n
+        };
+        let reader = self.buffer_size as u64;
+        let writer = self.buffer_size as u64
+            + if self.compress_directly { self.codec.encoder_memory() } else { 0 };
+        let count = |n: u64| usize::try_from(n).unwrap_or(usize::MAX).max(1);
+        let workers = workers.min(count(self.mem_limit / (reader + writer).max(1)));
+        let writers = count((self.mem_limit / workers as u64).saturating_sub(reader)
+                            / writer.max(1));
+        (workers, writers.min(self.max_open_files.max(1)))
+    }
+
+    /// Whether a file may be inflated, processed or compressed, which all
+    /// files may unless [`Options::no_touch_hardlinks`] is set and they have
+    /// other hard links
+    ///
+    /// Files whose number of links cannot be read may be touched.
+    ///
+    /// ```no_run
+    /// # use std::path::Path;
+    /// # use logboop::options::Options;
+    /// let options = Options { no_touch_hardlinks: true, ..Options::default() };
+    /// assert!(options.may_touch(Path::new("/var/log/syslog.1")));
+    /// ```
+    pub fn may_touch(&self, path: &Path) -> bool {
+        !self.no_touch_hardlinks || link_count(path).map_or(true, |links| links <= 1)
+    }
+
+    /// Whether files of a log type (`None` for files of unknown type, or
+    /// empty) are processed, according to [`Options::only_types`]
+    ///
+    /// ```
+    /// # use logboop::options::Options;
+    /// # use logboop::process::LogType;
+    /// let options = Options { only_types: vec![LogType::GrafanaLogs],
+    ///                         ..Options::default() };
+    /// assert!(options.accepts_type(Some(&LogType::GrafanaLogs)));
+    /// assert!(!options.accepts_type(Some(&LogType::Iso)));
+    /// assert!(!options.accepts_type(None));
+    /// assert!(Options::default().accepts_type(None));
+    /// ```
+    pub fn accepts_type(&self, logtype: Option<&LogType>) -> bool {
+        self.only_types.is_empty()
+            || logtype.is_some_and(|logtype| self.only_types.contains(logtype))
+    }
+
+    /// The log type of a name, either built-in (see the
+    /// [`FromStr`](std::str::FromStr) of [`LogType`]) or the name of one of
+    /// [`Options::formats`]
+    ///
+    /// ```
+    /// # use logboop::options::Options;
+    /// # use logboop::process::LogType;
+    /// let options = Options::default();
+    /// assert_eq!(options.find_type("nginx"), Ok(LogType::NginxAccess));
+    /// assert!(options.find_type("myapp").is_err());
+    /// ```
+    ///
+    /// # Errors
+    /// Names of no log type are an error, telling so.
+    pub fn find_type(&self, name: &str) -> Result<LogType, String> {
+        name.parse().or_else(|e| self.formats.iter()
+            .find(|format| format.name == name)
+            .map(|format| LogType::Custom(format.name.clone()))
+            .ok_or(e))
+    }
+
+    /// Whether a date is within [`Options::from_date`] and
+    /// [`Options::to_date`], both included
+    ///
+    /// ```
+    /// # use chrono::NaiveDate;
+    /// # use logboop::options::Options;
+    /// let options = Options {
+    ///     from_date: NaiveDate::from_ymd_opt(2023, 11, 1),
+    ///     to_date: NaiveDate::from_ymd_opt(2023, 11, 30),
+    ///     ..Options::default()
+    /// };
+    /// assert!(options.in_date_range(&NaiveDate::from_ymd_opt(2023, 11, 30).unwrap()));
+    /// assert!(!options.in_date_range(&NaiveDate::from_ymd_opt(2023, 12, 1).unwrap()));
+    /// assert!(Options::default().in_date_range(&NaiveDate::MIN));
+    /// ```
+    pub fn in_date_range(&self, date: &NaiveDate) -> bool {
+        self.from_date.is_none_or(|from| from <= *date)
+            && self.to_date.is_none_or(|to| *date <= to)
+    }
+
+    /// Path of the output file of a bucket, given the base output path of
+    /// its input file
+    ///
+    /// This is [`Options::output_template`] if set, and
+    /// [`Options::bucket_path`] otherwise, along with
+    /// [`Options::output_suffix`].
+    ///
+    /// ```
+    /// # use std::path::Path;
+    /// # use logboop::options::Options;
+    /// # use logboop::process::Bucket;
+    /// let date = chrono::NaiveDate::from_ymd_opt(2023, 10, 11).unwrap();
+    /// let bucket = Bucket { date, hour: None, week: false, offset: None, vhost: None };
+    /// let options = Options { output_suffix: ".log".to_owned(), ..Options::default() };
+    /// assert_eq!(options.output_path(&bucket, Path::new("out/access")),
+    ///            Path::new("out/access.log-2023-10-11"));
+    /// let options = Options { suffix_after_date: true, ..options };
+    /// assert_eq!(options.output_path(&bucket, Path::new("out/access")),
+    ///            Path::new("out/access-2023-10-11.log"));
+    /// ```
+    pub fn output_path(&self, bucket: &Bucket, base: &Path) -> PathBuf {
+        match self.output_template {
+            Some(ref template) => template.expand(bucket, base),
+            None if self.suffix_after_date => {
+                let mut path = (self.bucket_path)(bucket, base).into_os_string();
+                path.push(&self.output_suffix);
+                PathBuf::from(path)
+            },
+            None => {
+                let mut base = base.as_os_str().to_os_string();
+                base.push(&self.output_suffix);
+                (self.bucket_path)(bucket, Path::new(&base))
+            }
+        }
+    }
+
+    /// Whether a file of the output directory is an output file waiting to
+    /// be compressed
+    ///
+    /// Without [`Options::output_template`], those are the files whose name
+    /// ends with a date (and maybe an hour) or a week, as [`default_bucket_path`]
+    /// names them, followed by [`Options::output_suffix`] with
+    /// [`Options::suffix_after_date`].
+    ///
+    /// ```
+    /// # use std::path::Path;
+    /// # use logboop::options::Options;
+    /// let options = Options::default();
+    /// assert!(options.is_output_file(Path::new("out/syslog-2020-05-17")));
+    /// assert!(!options.is_output_file(Path::new("out/syslog-2020-05-17.gz")));
+    /// assert!(options.is_output_file(Path::new("out/auth.log-2023-W41")));
+    /// assert!(options.is_output_file(Path::new("out/access.log-2023-10-11+0200")));
+    /// let options = Options {
+    ///     output_template: Some("{year}/{month}/{base}".parse().unwrap()),
+    ///     ..Options::default()
+    /// };
+    /// assert!(options.is_output_file(Path::new("out/2020/05/syslog")));
+    /// assert!(!options.is_output_file(Path::new("out/2020/05/syslog.zst")));
+    /// let options = Options {
+    ///     output_suffix: ".log".to_owned(),
+    ///     suffix_after_date: true,
+    ///     ..Options::default()
+    /// };
+    /// assert!(options.is_output_file(Path::new("out/access-2023-10-11.log")));
+    /// assert!(!options.is_output_file(Path::new("out/access-2023-10-11.log.gz")));
+    /// ```
+    pub fn is_output_file(&self, path: &Path) -> bool {
+        let path = match path.to_str() {
+            Some(path) => path,
+            None => return false
+        };
+        match self.output_template {
+            Some(ref template) => template.regex.is_match(path)
+                && !path.ends_with(".gz") && !path.ends_with(".zst")
+                && !path.ends_with(".bz2"),
+            None if self.suffix_after_date => path.strip_suffix(self.output_suffix.as_str())
+                .is_some_and(|path| ISO_DATE_REGEX.is_match(path)),
+            None => ISO_DATE_REGEX.is_match(path)
+        }
+    }
+
+    /// Whether a file found while walking `root` is selected by
+    /// [`Options::exclude`] and [`Options::include`]
+    ///
+    /// Patterns are matched against the path of the file relative to `root`.
+    /// Excluded files are never selected. Otherwise, files are selected when
+    /// no [`Options::include`] is set, or when they match one of its
+    /// patterns. Being selected only narrows what a phase would handle : an
+    /// input file must still be rotated to be processed (see
+    /// [`Options::rotated_base`]), and an output file be named like one to be
+    /// compressed (see [`Options::is_output_file`]).
+    ///
+    /// ```
+    /// # use std::path::Path;
+    /// # use logboop::options::{glob_set, Options};
+    /// let options = Options {
+    ///     include: Some(glob_set(&["**/*.access.*"]).unwrap()),
+    ///     exclude: Some(glob_set(&["**/journal/**", "private/**"]).unwrap()),
+    ///     ..Options::default()
+    /// };
+    /// let root = Path::new("/var/log");
+    /// assert!(options.is_selected(Path::new("/var/log/web/site.access.log.1"), root));
+    /// assert!(!options.is_selected(Path::new("/var/log/web/site.error.log.1"), root));
+    /// assert!(!options.is_selected(Path::new("/var/log/private/a.access.log.1"), root));
+    /// assert!(!options.is_selected(Path::new("/var/log/x/journal/y.access.1"), root));
+    /// ```
+    pub fn is_selected(&self, path: &Path, root: &Path) -> bool {
+        let relative = path.strip_prefix(root).unwrap_or(path);
+        !self.exclude.as_ref().is_some_and(|globs| globs.is_match(relative))
+            && self.include.as_ref().is_none_or(|globs| globs.is_match(relative))
+    }
+
+    /// Base output path of an input file, or `None` if it is not to be
+    /// processed
+    ///
+    /// Without [`Options::rotated_files`], rotated files (see
+    /// [`is_rotated`](crate::process::is_rotated)) are processed, and lose
+    /// their numeric extension. Otherwise, files whose name matches the regex
+    /// are processed, and lose the part of their name it matched.
+    ///
+    /// With [`Options::read_compressed`], a `.gz`, `.zst`, `.bz2` or `.xz`
+    /// extension is removed first, so that compressed rotated files are
+    /// processed too.
+    ///
+    /// Files nobody writes to anymore, whose name is not rotated (like
+    /// `app.log` inflated from `app.log.gz`), are processed too, keeping their
+    /// whole name : compressed files with [`Options::read_compressed`], and
+    /// files in [`Options::inflated`]. Output files (see
+    /// [`Options::is_output_file`]) never are.
+    ///
+    /// ```
+    /// # use std::path::Path;
+    /// # use logboop::options::Options;
+    /// let options = Options::default();
+    /// assert_eq!(options.rotated_base(Path::new("in/access.log.1")),
+    ///            Some(Path::new("in/access.log").to_path_buf()));
+    /// assert_eq!(options.rotated_base(Path::new("in/access.log-20231011")), None);
+    /// let options = Options {
+    ///     rotated_files: Some(r"-\d{8}$".parse().unwrap()),
+    ///     ..Options::default()
+    /// };
+    /// assert_eq!(options.rotated_base(Path::new("in/access.log-20231011")),
+    ///            Some(Path::new("in/access.log").to_path_buf()));
+    /// let options = Options { read_compressed: true, ..Options::default() };
+    /// assert_eq!(options.rotated_base(Path::new("in/access.log.2.gz")),
+    ///            Some(Path::new("in/access.log").to_path_buf()));
+    /// assert_eq!(options.rotated_base(Path::new("in/app.log.gz")),
+    ///            Some(Path::new("in/app.log").to_path_buf()));
+    /// assert_eq!(options.rotated_base(Path::new("in/app.log")), None);
+    /// ```
+    pub fn rotated_base(&self, path: &Path) -> Option<PathBuf> {
+        let compressed = path.extension()
+            .is_some_and(|ext| ext == "gz" || ext == "zst" || ext == "bz2" || ext == "xz");
+        let plain;
+        let path = if self.read_compressed && compressed {
+            plain = path.with_extension("");
+            &plain
+        } else {
+            path
+        };
+        let rotated = match self.rotated_files {
+            Some(ref regex) => path.file_name().and_then(|name| name.to_str())
+                .and_then(|name| {
+                    let found = regex.find(name)?;
+                    let base = [&name[..found.start()], &name[found.end()..]].concat();
+                    (!base.is_empty()).then(|| path.with_file_name(base))
+                }),
+            None => is_rotated(path).then(|| path.with_extension(""))
+        };
+        // Compressed (or inflated) files are no longer written to
+        let finished = (self.read_compressed && compressed)
+            || self.inflated.lock().unwrap().contains(path);
+        rotated.or_else(|| (finished && !self.is_output_file(path))
+                        .then(|| path.to_path_buf()))
+    }
+}
+
+/// Template of the names of the output files
+///
+/// A template is a path, relative to the directory of the base output path
+/// of an input file, holding placeholders :
+///  - `{base}` : the name of the base output path (`access.log`)
+///  - `{name}` : that name without its extension (`access`)
+///  - `{date}` : the bucket, as `YYYY-MM-DD`, `YYYY-MM-DDTHH` or `YYYY-Www`,
+///    followed by its offset with [`Timezone::Offset`] (see [`Bucket`])
+///  - `{year}`, `{month}`, `{day}` : parts of the date of the bucket (the
+///    Monday of weekly buckets)
+///  - `{hour}` : the hour of the bucket, empty with daily buckets
+///  - `{vhost}` : the virtual host of the bucket, empty unless
+///    [`Options::split_vhosts`] is set (templates without it write the
+///    lines of every virtual host to the same files)
+///  - `{date:FORMAT}` : the date (and hour) of the bucket, formatted with
+///    the `strftime` specifiers of `FORMAT`, like `{date:%Y%m%d}`. Those
+///    naming the time zone or the half of the day are not supported, and
+///    `FORMAT` should hold `%H` with hourly buckets, and `%G-W%V` with weekly
+///    ones, for their files to be told apart.
+///
+/// Every template must hold `{base}` or `{name}`, so that two input files
+/// never share an output file. When the template holds a path separator,
+/// output files are sorted in subdirectories, which are created as needed.
+///
+/// Output files are then found again to be compressed by the regex the
+/// template is turned into (see [`Options::is_output_file`]), whatever
+/// the format of their dates.
+///
+/// The historical naming of `LogBoop` is `{base}-{date}`.
+///
+/// ```
+/// # use std::path::Path;
+/// # use logboop::options::OutputTemplate;
+/// # use logboop::process::Bucket;
+/// let date = chrono::NaiveDate::from_ymd_opt(2023, 10, 11).unwrap();
+/// let bucket = Bucket { date, hour: None, week: false, offset: None, vhost: None };
+/// let template: OutputTemplate = "{name}.{date}.log".parse().unwrap();
+/// assert_eq!(template.expand(&bucket, Path::new("out/access.log")),
+///            Path::new("out/access.2023-10-11.log"));
+/// let template: OutputTemplate = "{year}/{month}/{base}".parse().unwrap();
+/// assert_eq!(template.expand(&bucket, Path::new("out/access.log")),
+///            Path::new("out/2023/10/access.log"));
+/// let template: OutputTemplate = "{base}-{date:%Y%m%d}".parse().unwrap();
+/// assert_eq!(template.expand(&bucket, Path::new("out/access.log")),
+///            Path::new("out/access.log-20231011"));
+/// assert!("{date}.log".parse::<OutputTemplate>().is_err());
+/// assert!("{base}-{week}".parse::<OutputTemplate>().is_err());
+/// assert!("{base}-{date:%Y%m%d%z}".parse::<OutputTemplate>().is_err());
+/// ```
+#[derive(std::fmt::Debug, std::clone::Clone)]
+pub struct OutputTemplate {
+    /// The template, as given
+    template: String,
+    /// Formats of its `{date:FORMAT}` placeholders
+    formats: Vec<String>,
+    /// Regex matching the end of the paths of the files named by the
+    /// template
+    regex: Regex
+}
+
+impl OutputTemplate {
+    /// Expand the template for a bucket, given the base output path of its
+    /// input file
+    pub fn expand(&self, bucket: &Bucket, base: &Path) -> PathBuf {
+        let file_name = |p: Option<&std::ffi::OsStr>| p
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let start = bucket.date.and_time(NaiveTime::MIN)
+            + chrono::Duration::hours(bucket.hour.unwrap_or(0).into());
+        let name = self.formats.iter().fold(self.template.clone(), |name, format| name
+            .replace(&format!("{{date:{}}}", format), &start.format(format).to_string()));
+        let name = name
+            .replace("{base}", &file_name(base.file_name()))
+            .replace("{name}", &file_name(base.file_stem()))
+            .replace("{date}", &bucket.to_string())
+            .replace("{year}", &bucket.date.format("%Y").to_string())
+            .replace("{month}", &bucket.date.format("%m").to_string())
+            .replace("{day}", &bucket.date.format("%d").to_string())
+            .replace("{hour}", &bucket.hour.map(|h| format!("{:02}", h))
+                     .unwrap_or_default())
+            .replace("{vhost}", bucket.vhost.as_deref().unwrap_or_default());
+        base.with_file_name(name)
+    }
+}
+
+impl std::str::FromStr for OutputTemplate {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<OutputTemplate, String> {
+        let mut pieces = s.split('{');
+        // Everything before the first placeholder is a literal
+        let mut pattern = format!("(?:^|/){}", regex::escape(pieces.next().unwrap_or("")));
+        let mut named = false;
+        let mut formats = Vec::new();
+        for piece in pieces {
+            let (placeholder, literal) = piece.split_once('}')
+                .ok_or_else(|| format!("unclosed placeholder in template \"{}\"", s))?;
+            let format = placeholder.strip_prefix("date:");
+            pattern.push_str(&match (placeholder, format) {
+                ("base", _) | ("name", _) => {
+                    named = true;
+                    r"[^/]+".to_owned()
+                },
+                ("date", _) => r"\d{4}-(?:\d{2}-\d{2}(?:T\d{2})?|W\d{2})(?:[+-]\d{4})?".to_owned(),
+                ("year", _) => r"\d{4}".to_owned(),
+                ("month", _) | ("day", _) => r"\d{2}".to_owned(),
+                ("hour", _) => r"(?:\d{2})?".to_owned(),
+                ("vhost", _) => r"[^/]*".to_owned(),
+                (_, Some(format)) => {
+                    formats.push(format.to_owned());
+                    format_regex(format).map_err(|e| format!("{} in template \"{}\"", e, s))?
+                },
+                (_, None) => return Err(format!(
+                    "unknown placeholder \"{{{}}}\" in template \"{}\"", placeholder, s))
+            });
+            pattern.push_str(&regex::escape(literal));
+        }
+        if !named {
+            return Err(format!("template \"{}\" holds neither {{base}} nor {{name}}", s));
+        }
+        pattern.push('$');
+        Ok(OutputTemplate {
+            template: s.to_owned(),
+            formats,
+            regex: Regex::new(&pattern).map_err(|e| e.to_string())?
+        })
+    }
+}
+
+/// Regex matching what a `strftime` format writes for a date and hour
+///
+/// # Errors
+/// Formats holding an invalid specifier, or one naming the time zone or
+/// the half of the day, yield an error describing it.
+fn format_regex(format: &str) -> Result<String, String> {
+    StrftimeItems::new(format).map(|item| Ok(match item {
+        Item::Literal(literal) | Item::Space(literal) => regex::escape(literal),
+        Item::OwnedLiteral(ref literal) | Item::OwnedSpace(ref literal) =>
+            regex::escape(literal),
+        Item::Numeric(_, Pad::None) => r"\d+".to_owned(),
+        Item::Numeric(Numeric::Year, _) | Item::Numeric(Numeric::IsoYear, _) =>
+            r"\d{4}".to_owned(),
+        Item::Numeric(Numeric::Ordinal, _) => r"[\d ]{3}".to_owned(),
+        Item::Numeric(Numeric::NumDaysFromSun, _) | Item::Numeric(Numeric::WeekdayFromMon, _) =>
+            r"\d".to_owned(),
+        Item::Numeric(_, _) => r"[\d ]{2}".to_owned(),
+        Item::Fixed(Fixed::ShortMonthName) | Item::Fixed(Fixed::ShortWeekdayName) =>
+            "[[:alpha:]]{3}".to_owned(),
+        Item::Fixed(Fixed::LongMonthName) | Item::Fixed(Fixed::LongWeekdayName) =>
+            "[[:alpha:]]+".to_owned(),
+        _ => return Err(format!("unsupported date format \"{}\"", format))
+    })).collect()
+}
+
+/// Period of time covered by every output file
+#[derive(std::fmt::Debug, std::clone::Clone, std::marker::Copy,
+         std::cmp::PartialEq, std::cmp::Eq, std::default::Default)]
+pub enum Granularity {
+    /// One output file per day
+    #[default]
+    Day,
+    /// One output file per hour, for log types giving the time of day (see
+    /// [`parse_hour`](crate::process::parse_hour)). Lines of other log types
+    /// are still split by day.
+    Hour,
+    /// One output file per ISO 8601 week, for logs written too rarely to be
+    /// worth a file per day. Monthly rollups and daily bundles leave those
+    /// files alone.
+    Week
+}
+
+impl std::str::FromStr for Granularity {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Granularity, String> {
+        match s {
+            "day" => Ok(Granularity::Day),
+            "hour" => Ok(Granularity::Hour),
+            "week" => Ok(Granularity::Week),
+            _ => Err(format!("unknown granularity \"{}\"", s))
+        }
+    }
+}
+
+/// Time zone of the dates lines are bucketed by
+///
+/// Only some log types write the offset of their timestamps, and can be
+/// bucketed by their date in UTC, or by their offset : [`LogType::Syslog5424`],
+/// [`LogType::MysqlSlow`], [`LogType::EcsJson`], [`LogType::DockerJson`] and
+/// [`LogType::Json`] (RFC 3339 timestamps), [`LogType::ApacheAccess`],
+/// [`LogType::ApacheVhost`] and [`LogType::GrafanaLogs`] (`+0200`),
+/// [`LogType::NginxAccess`] when its offset is written, and
+/// [`LogType::IisW3c`], always written in UTC. The time zone of [`LogType::Syslog`],
+/// [`LogType::Iso`], [`LogType::IsoBasic`], [`LogType::DayMonthYear2`],
+/// [`LogType::HaproxyHttp`], [`LogType::ApacheError`] and
+/// [`LogType::CiscoSyslog`] lines is unknown, so
+/// those are always bucketed by the date written in them.
+///
+/// [`LogType::Syslog5424`]: crate::process::LogType::Syslog5424
+/// [`LogType::MysqlSlow`]: crate::process::LogType::MysqlSlow
+/// [`LogType::EcsJson`]: crate::process::LogType::EcsJson
+/// [`LogType::DockerJson`]: crate::process::LogType::DockerJson
+/// [`LogType::Json`]: crate::process::LogType::Json
+/// [`LogType::ApacheAccess`]: crate::process::LogType::ApacheAccess
+/// [`LogType::GrafanaLogs`]: crate::process::LogType::GrafanaLogs
+/// [`LogType::NginxAccess`]: crate::process::LogType::NginxAccess
+/// [`LogType::Syslog`]: crate::process::LogType::Syslog
+/// [`LogType::Iso`]: crate::process::LogType::Iso
+/// [`LogType::IsoBasic`]: crate::process::LogType::IsoBasic
+/// [`LogType::IisW3c`]: crate::process::LogType::IisW3c
+/// [`LogType::DayMonthYear2`]: crate::process::LogType::DayMonthYear2
+/// [`LogType::HaproxyHttp`]: crate::process::LogType::HaproxyHttp
+/// [`LogType::ApacheError`]: crate::process::LogType::ApacheError
+/// [`LogType::CiscoSyslog`]: crate::process::LogType::CiscoSyslog
+#[derive(std::fmt::Debug, std::clone::Clone, std::marker::Copy,
+         std::cmp::PartialEq, std::cmp::Eq, std::default::Default)]
+pub enum Timezone {
+    /// The date (and hour) as written in the line, in the time zone of the
+    /// log
+    #[default]
+    Log,
+    /// The date (and hour) in UTC, converted with the offset of the line
+    Utc,
+    /// The date (and hour) as written in the line, like [`Timezone::Log`],
+    /// followed by the offset of the line in the name of its output file
+    /// (like `access.log-2023-10-11+0200`), so that lines of distinct offsets
+    /// never share an output file
+    Offset
+}
+
+impl std::str::FromStr for Timezone {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Timezone, String> {
+        match s {
+            "log" => Ok(Timezone::Log),
+            "utc" => Ok(Timezone::Utc),
+            "offset" => Ok(Timezone::Offset),
+            _ => Err(format!("unknown time zone \"{}\"", s))
+        }
+    }
+}
+
+/// What is done with input files holding nothing but whitespace
+///
+/// Those files carry no data, and no log type can be found for them, so
+/// instead of ending up in `.unknown` files, they are handled on their own.
+#[derive(std::fmt::Debug, std::clone::Clone, std::marker::Copy,
+         std::cmp::PartialEq, std::cmp::Eq, std::default::Default)]
+pub enum EmptyFiles {
+    /// Delete them (unless [`Options::keep_source`] is set), writing nothing
+    #[default]
+    Delete,
+    /// Move them to a `.empty` file next to the other output files, to know
+    /// that they were found
+    Mark
+}
+
+impl std::str::FromStr for EmptyFiles {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<EmptyFiles, String> {
+        match s {
+            "delete" => Ok(EmptyFiles::Delete),
+            "mark" => Ok(EmptyFiles::Mark),
+            _ => Err(format!("unknown policy for empty files \"{}\"", s))
+        }
+    }
+}
+
+/// Compression used for the output files
+#[derive(std::fmt::Debug, std::clone::Clone, std::marker::Copy,
+         std::cmp::PartialEq, std::cmp::Eq, std::default::Default)]
+pub enum Codec {
+    /// GZ compression, producing `.gz` files
+    #[default]
+    Gzip,
+    /// Zstandard compression, producing `.zst` files
+    Zstd,
+    /// bzip2 compression, producing `.bz2` files
+    Bzip2
+}
+
+impl Codec {
+    /// Extension added to the files compressed with this codec
+    ///
+    /// ```
+    /// # use logboop::options::Codec;
+    /// assert_eq!(Codec::Gzip.extension(), "gz");
+    /// assert_eq!(Codec::Zstd.extension(), "zst");
+    /// assert_eq!(Codec::Bzip2.extension(), "bz2");
+    /// ```
+    pub fn extension(self) -> &'static str {
+        match self {
+            Codec::Gzip => "gz",
+            Codec::Zstd => "zst",
+            Codec::Bzip2 => "bz2"
+        }
+    }
+
+    /// Memory in bytes an encoder of this codec roughly takes, at the
+    /// default levels
+    ///
+    /// ```
+    /// # use logboop::options::Codec;
+    /// assert!(Codec::Gzip.encoder_memory() < Codec::Zstd.encoder_memory());
+    /// assert!(Codec::Zstd.encoder_memory() < Codec::Bzip2.encoder_memory());
+    /// ```
+    pub fn encoder_memory(self) -> u64 {
+        match self {
+            Codec::Gzip => 320 << 10,
+            Codec::Zstd => 2 << 20,
+            Codec::Bzip2 => 8 << 20
+        }
+    }
+}
+
+impl std::str::FromStr for Codec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Codec, String> {
+        match s {
+            "gz" | "gzip" => Ok(Codec::Gzip),
+            "zst" | "zstd" => Ok(Codec::Zstd),
+            "bz2" | "bzip2" => Ok(Codec::Bzip2),
+            _ => Err(format!("unknown codec \"{}\"", s))
+        }
+    }
+}
+
+/// Format in which lines are written in the output files
+#[derive(std::fmt::Debug, std::clone::Clone, std::marker::Copy,
+         std::cmp::PartialEq, std::cmp::Eq, std::default::Default)]
+pub enum OutputFormat {
+    /// Lines are written as they were read (see
+    /// [`Options::keep_line_endings`] for their line endings)
+    #[default]
+    Raw,
+    /// Lines of JSON log types are written as normalized JSON records, one
+    /// per line, so that outputs are date-partitioned NDJSON files. Lines of
+    /// other log types are wrapped into records giving their date and log
+    /// type along with them.
+    Ndjson
+}
+
+impl std::str::FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<OutputFormat, String> {
+        match s {
+            "raw" => Ok(OutputFormat::Raw),
+            "ndjson" => Ok(OutputFormat::Ndjson),
+            _ => Err(format!("unknown output format \"{}\"", s))
+        }
+    }
+}
+
+/// Modification time given to the output files once written
+///
+/// Anything but [`OutputMtime::Now`] lets jobs like `find -mtime +30
+/// -delete` tell old outputs apart. Compressed files then get the
+/// modification time of their plain counterpart, so that
+/// [`Options::compress_only_newer`] no longer notices lines appended to a
+/// plain file by a later run.
+#[derive(std::fmt::Debug, std::clone::Clone, std::marker::Copy,
+         std::cmp::PartialEq, std::cmp::Eq, std::default::Default)]
+pub enum OutputMtime {
+    /// The time the file was written, as any file gets
+    #[default]
+    Now,
+    /// The last second of the bucket of the file (see
+    /// [`Bucket::end`]), leaving the `.unknown`, `.excluded` and `.empty`
+    /// files as they are
+    Bucket,
+    /// The modification time of the input file written to the file last
+    /// (of the compressed file it was inflated from, if any)
+    Source
+}
+
+impl std::str::FromStr for OutputMtime {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<OutputMtime, String> {
+        match s {
+            "now" => Ok(OutputMtime::Now),
+            "bucket" => Ok(OutputMtime::Bucket),
+            "source" => Ok(OutputMtime::Source),
+            _ => Err(format!("unknown modification time \"{}\"", s))
+        }
+    }
+}
+
+/// Compile glob patterns into a set, for [`Options::include`] and
+/// [`Options::exclude`]
+///
+/// A `*` or a `?` never matches a `/`, while `**` matches any number of
+/// directories, so that `**/*.access.*` matches access logs anywhere, and
+/// `private/**` everything below `private`.
+///
+/// # Errors
+/// An invalid pattern yields an error naming it.
+pub fn glob_set<S: AsRef<str>>(patterns: &[S]) -> Result<GlobSet, String> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        let glob = GlobBuilder::new(pattern.as_ref())
+            .literal_separator(true)
+            .build()
+            .map_err(|e| format!("invalid pattern \"{}\" : {}", pattern.as_ref(), e))?;
+        builder.add(glob);
+    }
+    builder.build().map_err(|e| e.to_string())
+}
+
+/// Age of a file, read as a number followed by a unit : `s`, `m`, `h`, `d`
+/// or `w`, for seconds, minutes, hours, days or weeks
+///
+/// ```
+/// # use std::time::Duration;
+/// # use logboop::options::Age;
+/// assert_eq!("25h".parse::<Age>(), Ok(Age(Duration::from_secs(25 * 3600))));
+/// assert_eq!("2d".parse::<Age>(), Ok(Age(Duration::from_secs(2 * 86400))));
+/// assert!("25".parse::<Age>().is_err());
+/// ```
+#[derive(std::fmt::Debug, std::clone::Clone, std::marker::Copy,
+         std::cmp::PartialEq, std::cmp::Eq)]
+pub struct Age(pub Duration);
+
+impl std::str::FromStr for Age {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Age, String> {
+        let invalid = || format!("invalid age \"{}\"", s);
+        let unit = match s.chars().last() {
+            Some('s') => 1,
+            Some('m') => 60,
+            Some('h') => 3600,
+            Some('d') => 86400,
+            Some('w') => 7 * 86400,
+            _ => return Err(invalid())
+        };
+        let count: u64 = s[..s.len() - 1].parse().map_err(|_| invalid())?;
+        count.checked_mul(unit).map(|secs| Age(Duration::from_secs(secs))).ok_or_else(invalid)
+    }
+}
+
+impl std::default::Default for Options {
+    fn default() -> Options {
+        Options {
+            compress_only_newer: false,
+            bucket_path: Arc::new(default_bucket_path),
+            max_dates_per_file: 1000,
+            max_open_files: 16,
+            buffer_size: 8 << 10,
+            mem_limit: 256 << 20,
+            compress_directly: false,
+            no_recompress: false,
+            rollup_after: None,
+            bundle_days: false,
+            consolidate_months: None,
+            delete_empty_dirs: false,
+            rename_only: false,
+            output_format: OutputFormat::Raw,
+            output_mtime: OutputMtime::Now,
+            unwrap_docker: false,
+            keep_line_endings: false,
+            codec: Codec::Gzip,
+            compression: Compression::default(),
+            keep_source: false,
+            verify: false,
+            manifest: false,
+            no_touch_hardlinks: false,
+            postrotate: None,
+            empty_files: EmptyFiles::Delete,
+            read_compressed: false,
+            no_degunzip: false,
+            granularity: Granularity::Day,
+            split_vhosts: false,
+            timezone: Timezone::Log,
+            threads_per_phase: 0,
+            jobs: 0,
+            min_size: 0,
+            since: None,
+            detection_lines: 20,
+            header_lines: 0,
+            mixed: false,
+            strict: false,
+            dedup: false,
+            resumable: false,
+            json_time_fields: json::DEFAULT_TIME_FIELDS.iter()
+                .map(|field| (*field).to_owned()).collect(),
+            formats: Vec::new(),
+            only_types: Vec::new(),
+            output_template: None,
+            output_suffix: String::new(),
+            suffix_after_date: false,
+            from_date: None,
+            to_date: None,
+            progress: None,
+            stop: None,
+            inflated: Arc::default(),
+            hook_failures: Arc::default(),
+            follow_links: false,
+            max_depth: None,
+            unsorted: false,
+            retries: 0,
+            flatten: false,
+            sanitize_names: false,
+            dir_mode: None,
+            rotated_files: None,
+            include: None,
+            exclude: None
+        }
+    }
+}
+
+impl std::fmt::Debug for Options {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Options")
+            .field("compress_only_newer", &self.compress_only_newer)
+            .field("max_dates_per_file", &self.max_dates_per_file)
+            .field("max_open_files", &self.max_open_files)
+            .field("buffer_size", &self.buffer_size)
+            .field("mem_limit", &self.mem_limit)
+            .field("compress_directly", &self.compress_directly)
+            .field("no_recompress", &self.no_recompress)
+            .field("rollup_after", &self.rollup_after)
+            .field("bundle_days", &self.bundle_days)
+            .field("consolidate_months", &self.consolidate_months)
+            .field("delete_empty_dirs", &self.delete_empty_dirs)
+            .field("rename_only", &self.rename_only)
+            .field("output_format", &self.output_format)
+            .field("output_mtime", &self.output_mtime)
+            .field("unwrap_docker", &self.unwrap_docker)
+            .field("keep_line_endings", &self.keep_line_endings)
+            .field("codec", &self.codec)
+            .field("compression", &self.compression)
+            .field("keep_source", &self.keep_source)
+            .field("verify", &self.verify)
+            .field("manifest", &self.manifest)
+            .field("no_touch_hardlinks", &self.no_touch_hardlinks)
+            .field("postrotate", &self.postrotate)
+            .field("empty_files", &self.empty_files)
+            .field("read_compressed", &self.read_compressed)
+            .field("no_degunzip", &self.no_degunzip)
+            .field("granularity", &self.granularity)
+            .field("split_vhosts", &self.split_vhosts)
+            .field("timezone", &self.timezone)
+            .field("threads_per_phase", &self.threads_per_phase)
+            .field("jobs", &self.jobs)
+            .field("min_size", &self.min_size)
+            .field("since", &self.since)
+            .field("detection_lines", &self.detection_lines)
+            .field("header_lines", &self.header_lines)
+            .field("mixed", &self.mixed)
+            .field("strict", &self.strict)
+            .field("dedup", &self.dedup)
+            .field("resumable", &self.resumable)
+            .field("json_time_fields", &self.json_time_fields)
+            .field("formats", &self.formats)
+            .field("only_types", &self.only_types)
+            .field("output_template", &self.output_template)
+            .field("output_suffix", &self.output_suffix)
+            .field("suffix_after_date", &self.suffix_after_date)
+            .field("from_date", &self.from_date)
+            .field("to_date", &self.to_date)
+            .field("stop", &self.stop)
+            .field("follow_links", &self.follow_links)
+            .field("max_depth", &self.max_depth)
+            .field("unsorted", &self.unsorted)
+            .field("retries", &self.retries)
+            .field("flatten", &self.flatten)
+            .field("sanitize_names", &self.sanitize_names)
+            .field("dir_mode", &self.dir_mode)
+            .field("rotated_files", &self.rotated_files)
+            .field("include", &self.include)
+            .field("exclude", &self.exclude)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Historical naming policy of `LogBoop`, used by default
+///
+/// The bucket is appended to the base output path, after a hyphen, in
+/// ISO 8601 format (see [`Bucket`]), and after its virtual host if it has
+/// one.
+///
+/// ```
+/// # use std::path::Path;
+/// # use logboop::options::default_bucket_path;
+/// # use logboop::process::Bucket;
+/// let date = chrono::NaiveDate::from_ymd_opt(2020, 5, 17).unwrap();
+/// let bucket = Bucket { date, hour: None, week: false, offset: None, vhost: None };
+/// assert_eq!(default_bucket_path(&bucket, Path::new("out/syslog")),
+///            Path::new("out/syslog-2020-05-17"));
+/// let bucket = Bucket { hour: Some(2), ..bucket };
+/// assert_eq!(default_bucket_path(&bucket, Path::new("out/syslog")),
+///            Path::new("out/syslog-2020-05-17T02"));
+/// let bucket = Bucket { hour: None, vhost: Some("example.com".to_owned()), ..bucket };
+/// assert_eq!(default_bucket_path(&bucket, Path::new("out/access.log")),
+///            Path::new("out/access.log-example.com-2020-05-17"));
+/// ```
+pub fn default_bucket_path(bucket: &Bucket, base: &Path) -> PathBuf {
+    let mut path = base.as_os_str().to_os_string();
+    if let Some(ref vhost) = bucket.vhost {
+        path.push(format!("-{}", vhost));
+    }
+    path.push(format!("-{}", bucket));
+    PathBuf::from(path)
+}
@@ -0,0 +1,211 @@
+//! Module running the phases of `LogBoop` as a pipeline
+//!
+//! ## Provided by this module
+//!
+//! The binary usually runs three phases one after the other, each walking a
+//! whole directory : inflating the input files
+//! ([`degunzip_all_the_files`](crate::filesystem::degunzip_all_the_files)),
+//! processing them ([`all_files`](crate::process::all_files)), and
+//! compressing the output files
+//! ([`gunzip_all_the_files`](crate::filesystem::gunzip_all_the_files)).
+//!
+//! [`run`] walks the input directory once instead, and hands the input files,
+//! grouped by base output path, from one phase to the next through bounded
+//! channels, so that a group is compressed while others are still being
+//! inflated or processed. Every phase has its own threads (see
+//! [`Options::threads_per_phase`]).
+//!
+//! ## Example
+//!
+//! ```rust,no_run
+//! # use std::path::Path;
+//! # use logboop::options::Options;
+//! let options = Options { threads_per_phase: 2, ..Options::default() };
+//! let stats = logboop::pipeline::run(Path::new("/var/log"), Path::new("/srv/logs"),
+//!                                    &options)?;
+//! println!("{}", stats);
+//! # Ok::<(), logboop::error::LogBoopError>(())
+//! ```
+//!
+//! ## Details of imports
+//!
+//! Groups go from one phase to the next through a bounded
+//! [`sync_channel`], whose [`Receiver`] is shared by the threads of a phase
+//! behind a [`Mutex`], and the threads of every phase are [`scope`]d threads
+//! borrowing the [`Options`] of the run.
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::mpsc::{sync_channel, Receiver};
+use std::thread::scope;
+
+use log::error;
+
+use crate::error::LogBoopError;
+use crate::filesystem::{degunzip_file, give_up, gunzip_file, retry};
+use crate::options::Options;
+use crate::process::{group_files, one_file, tally, write_manifest, FileStats, ProcessStats};
+
+/// Input files sharing a base output path, along with that path
+type Group = (PathBuf, Vec<PathBuf>);
+
+/// Inflate, process and compress the files of a directory in a single walk
+///
+/// # Arguments
+/// This method takes the root of the input directory, the root of the output
+/// directory, and the [`Options`] of the run.
+///
+/// # Behaviour
+///
+/// The input files are found and grouped just like [`all_files`] does, with
+/// compressed rotated files (like `syslog.2.gz`) counted in their group, as
+/// with [`Options::read_compressed`]. Every group then goes through three
+/// phases, each run by [`Options::threads_per_phase`] threads :
+///  - its compressed files are inflated (see
+///    [`degunzip_file`](crate::filesystem::degunzip_file)), unless
+///    [`Options::read_compressed`] is set, or left out of the group with
+///    [`Options::no_degunzip`]
+///  - its files are processed, one after the other (see [`one_file`])
+///  - the output files written from it are compressed (see
+///    [`gunzip_file`](crate::filesystem::gunzip_file)), unless
+///    [`Options::compress_directly`], [`Options::bundle_days`] or
+///    [`Options::no_recompress`] is set
+///
+/// Phases hand groups to the next through channels holding at most as many
+/// groups as a phase has threads, so that a slow phase holds the others back
+/// instead of letting groups pile up.
+///
+/// Unlike the phases run one after the other, compressed files which are not
+/// input files are not inflated, and output files left uncompressed by an
+/// earlier run are only compressed once written to again.
+///
+/// Once [`Options::stop_requested`], no other group is inflated, and no
+/// other file processed, but what was written is still compressed.
+///
+/// Files failing with a transient error are tried again in every phase, up
+/// to [`Options::retries`] times (see [`retry`](crate::filesystem::retry)),
+/// and then given up on, the other files going on through the pipeline.
+///
+/// # Errors
+/// Errors while processing a file are reported and counted in the
+/// [`ProcessStats`] returned, like with [`all_files`]. An error while
+/// inflating or compressing a file stops the pipeline, and is returned once
+/// the groups under way are done with.
+///
+/// [`all_files`]: crate::process::all_files
+pub fn run(inpath: &Path, outpath: &Path, options: &Options)
+    -> Result<ProcessStats, LogBoopError> {
+    let (threads, max_open_files) = options.memory_bounds(options.threads_per_phase);
+    let options = &Options { max_open_files, ..options.clone() };
+    let compress = !options.compress_directly && !options.bundle_days
+        && !options.no_recompress;
+    let walk_options = Options { read_compressed: true, ..options.clone() };
+    let groups = group_files(inpath, outpath, &walk_options);
+
+    let failure: Mutex<Option<LogBoopError>> = Mutex::new(None);
+    let failed = || failure.lock().unwrap().is_some();
+    let fail = |e: LogBoopError| {
+        error!("Error in the pipeline : {}", e);
+        failure.lock().unwrap().get_or_insert(e);
+    };
+    let results: Mutex<Vec<(PathBuf, Result<FileStats, LogBoopError>)>> =
+        Mutex::new(Vec::new());
+
+    let (to_inflate, inflate_rx) = sync_channel::<Group>(threads);
+    let (to_process, process_rx) = sync_channel::<Group>(threads);
+    let (to_compress, compress_rx) = sync_channel::<Vec<PathBuf>>(threads);
+    let (inflate_rx, process_rx, compress_rx) =
+        (&Mutex::new(inflate_rx), &Mutex::new(process_rx), &Mutex::new(compress_rx));
+    let recorded = &results;
+
+    scope(|scope| {
+        scope.spawn(move || {
+            for group in groups {
+                if options.stop_requested() || failed() || to_inflate.send(group).is_err() {
+                    break;
+                }
+            }
+        });
+        for _ in 0..threads {
+            let to_process = to_process.clone();
+            scope.spawn(move || while let Some((base, entries)) = next(inflate_rx) {
+                let mut plain = Vec::with_capacity(entries.len());
+                for entry in entries {
+                    if options.stop_requested() || failed() {
+                        break;
+                    }
+                    if options.read_compressed {
+                        plain.push(entry);
+                        continue;
+                    }
+                    if options.no_degunzip {
+                        // Only what would be an input file once not inflated
+                        if options.rotated_base(&entry).is_some() {
+                            plain.push(entry);
+                        }
+                        continue;
+                    }
+                    let inflated = retry(&entry, options, || degunzip_file(&entry, options));
+                    match give_up(&entry, options, inflated) {
+                        Ok(Some(Some(path))) => plain.push(path),
+                        Ok(_) => (),
+                        Err(e) => {
+                            fail(e);
+                            break;
+                        }
+                    }
+                }
+                if to_process.send((base, plain)).is_err() {
+                    break;
+                }
+            });
+            let to_compress = to_compress.clone();
+            scope.spawn(move || while let Some((base, entries)) = next(process_rx) {
+                let mut outputs = Vec::new();
+                for entry in entries {
+                    if options.stop_requested() {
+                        break;
+                    }
+                    let result = retry(&entry, options, || one_file(&entry, base.clone(), options));
+                    match result {
+                        Ok(ref stats) => outputs.extend(stats.outputs.iter()
+                                                         .map(|output| output.path.clone())),
+                        Err(ref e) => error!("Error while processing {} : {}",
+                                             entry.display(), e)
+                    }
+                    recorded.lock().unwrap().push((entry, result));
+                }
+                if compress && to_compress.send(outputs).is_err() {
+                    break;
+                }
+            });
+            scope.spawn(move || while let Some(outputs) = next(compress_rx) {
+                let result = outputs.iter()
+                    .filter(|output| options.is_output_file(output) && output.is_file()
+                            && options.is_selected(output, outpath))
+                    .take_while(|_| !failed())
+                    .try_for_each(|output| give_up(output, options, retry(
+                        output, options, || gunzip_file(output, options))).map(|_| ()));
+                if let Err(e) = result {
+                    fail(e);
+                }
+            });
+        }
+        // Phases are over once all of the threads feeding them are
+        drop((to_process, to_compress));
+    });
+
+    let results = results.into_inner().unwrap();
+    if options.manifest {
+        write_manifest(outpath, &results)?;
+    }
+    match failure.into_inner().unwrap() {
+        Some(e) => Err(e),
+        None => Ok(tally(results))
+    }
+}
+
+/// Wait for the next item handed to a phase, or `None` once the phase before
+/// it is over
+fn next<T>(receiver: &Mutex<Receiver<T>>) -> Option<T> {
+    receiver.lock().unwrap().recv().ok()
+}
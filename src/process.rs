@@ -16,41 +16,154 @@
 //! We need to accomplish all sorts of I/O and file operations, so
 //!  - The entire [I/O prelude](std::io::prelude) is imported
 //!  - [`File`], [`OpenOptions`], [`remove_file`] and [`create_dir_all`] from
-//!  the [`std::fs`] module
+//!    the [`std::fs`] module
 //!  - [`BufReader`] and [`BufWriter`], buffered writers from the I/O module
 //!  - Both [`Path`] and [`PathBuf`] for path manipulation
 //!  - Finally, the [`HashMap`] collection to store regexes supposed to match
-//!  a given [`LogType`]
+//!    a given [`LogType`], the [`HashSet`] collection to count the dates
+//!    found in a file, and the [`BTreeMap`] collection to group input files
+//!    by base output path
+//!  - [`SystemTime`], to give output files the modification time of their
+//!    input file
 //!
 //! ## Crate imports
 //! In order to conduct our business, we import
 //!  - [`Regex`]
-//!  - [`WalkDir`]
+//!  - The [`rayon` prelude](rayon::prelude), to process files in parallel
 //!  - [`Datelike`], the trait needed to make [`NaiveDate`] format from dates
-//!  using [`StrftimeItems`]
+//!    using [`StrftimeItems`] (whose [`Item`]s also tell whether the format
+//!    of a [`CustomFormat`] is valid), and [`Timelike`] to read the hour of a
+//!    timestamp
+//!  - [`DateTime`], [`FixedOffset`], [`Local`], [`NaiveTime`] and
+//!    [`TimeZone`], to find when a [`Bucket`] ends
+//!  - [`GzEncoder`] from `flate2`, and the Zstandard
+//!    [`Encoder`](ZstdEncoder), to write compressed output files directly,
+//!    and their [`MultiGzDecoder`] and [`Decoder`](ZstdDecoder) counterparts (along
+//!    with [`is_gzip`]) to read compressed input files, as well as the bzip2
+//!    [`BzEncoder`] and [`MultiBzDecoder`], and the [`XzDecoder`]
+//!  - [`Options`], [`OutputFormat`], [`OutputMtime`], [`Codec`] and
+//!    [`Granularity`], the settings of the run
+//!  - [`json`], to write NDJSON records
+//!  - [`LogBoopError`], returned by every fallible function
+//!  - [`add_extension`], to name compressed output files,
+//!    [`copy_permissions`], to give output files the permissions of their
+//!    input file, and [`walk`], to find input files
+//!  - The [`log`] macros and their [`Level`], to tell how processing went
 use std::io::prelude::*;
 use std::fs::{File, OpenOptions, remove_file, create_dir_all};
 use std::io::{BufReader,BufWriter};
 use std::path::{Path, PathBuf};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::borrow::Cow;
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use std::time::SystemTime;
 
 use regex::Regex;
-use walkdir::WalkDir;
-use chrono::Datelike;
-use chrono::NaiveDate;
+use rayon::prelude::*;
+use chrono::{Datelike, Timelike};
+use chrono::{DateTime, FixedOffset, Local, NaiveDate, NaiveTime, TimeZone};
 use chrono::format::strftime::StrftimeItems;
+use chrono::format::Item;
+use flate2::write::GzEncoder;
+use zstd::stream::write::Encoder as ZstdEncoder;
+use flate2::read::MultiGzDecoder;
+use zstd::stream::read::Decoder as ZstdDecoder;
+use bzip2::read::MultiBzDecoder;
+use bzip2::write::BzEncoder;
+use xz2::read::XzDecoder;
+use log::{error, info, warn, Level};
 
-// Define the dictionary of matching regexes for data
+use crate::compress::is_gzip;
+use crate::filesystem::{add_extension, copy_permissions, create_dirs, remove_source, retry,
+                        sanitize_path, walk};
+use crate::error::LogBoopError;
+use crate::json;
+use crate::options::{Codec, EmptyFiles, Granularity, Options, OutputFormat, OutputMtime,
+                     Timezone};
+
+/// How the lines of a built-in log type are recognized, and dated
+///
+/// This is the single table both [`detect_line`] and the parsing functions
+/// (see [`parse_date`]) rely on, and that [`LogType::regex_source`] and
+/// [`LogType::date_format`] describe.
+struct BuiltinFormat {
+    /// Regex recognizing the lines of the log type, matching their timestamp
+    regex: Regex,
+    /// [`strftime`](chrono::format::strftime) format of the timestamp matched
+    /// by the regex, or `None` when it is an RFC 3339 timestamp
+    ///
+    /// Log types giving no year have the year they are assumed to be from
+    /// appended as `%Y` (see [`infer_year`]).
+    date_format: Option<&'static str>
+}
+
+// Define the dictionary of matching regexes and date formats for data
 lazy_static! {
     #[doc(hidden)]
-    static ref REGEXES: HashMap<LogType, Regex> = vec![
-        (LogType::Syslog, Regex::new(r"^(Jan|Feb|Mar|Apr|May|Jun|Jul|Aug|Sep|Oct|Nov|Dec) ([012 ]\d|3[01])").unwrap()),
-        (LogType::Iso, Regex::new(r"^\d{4}-\d{2}-\d{2}").unwrap()),
-        (LogType::ApacheAccess, Regex::new(r"\[\d{2}/(Jan|Feb|Mar|Apr|May|Jun|Jul|Aug|Sep|Oct|Nov|Dec)/\d{4}:").unwrap()),
-        (LogType::ApacheError, Regex::new(r"\[(Mon|Tue|Wed|Thu|Fri|Sat|Sun) (Jan|Feb||Mar|Apr|May|Jun|Jul|Aug|Sep|Oct|Nov|Dec) \d{2} \d{2}:\d{2}:\d{2}.\d{6} \d{4}]").unwrap()),
-        (LogType::GrafanaLogs, Regex::new(r"^t=\d{4}-\d{2}-\d{2}T\d{2}:\d{2}:\d{2}(\+|-)\d{4} lvl=").unwrap())
+    static ref FORMATS: HashMap<LogType, BuiltinFormat> = vec![
+        (LogType::Syslog, BuiltinFormat {
+            regex: Regex::new(r"^\s*(?i:Jan|Feb|Mar|Apr|May|Jun|Jul|Aug|Sep|Oct|Nov|Dec) ([012 ]\d|3[01])").unwrap(),
+            date_format: Some("%b %d") }),
+        (LogType::Syslog5424, BuiltinFormat {
+            regex: Regex::new(r"^\s*<\d{1,3}>\d{1,2} (?P<ts>\d{4}-\d{2}-\d{2}T\d{2}:\d{2}:\d{2}(?:\.\d{1,6})?(?:Z|[+-]\d{2}:\d{2}))").unwrap(),
+            date_format: None }),
+        (LogType::Iso, BuiltinFormat {
+            regex: Regex::new(r"^\s*\d{4}-\d{2}-\d{2}").unwrap(),
+            date_format: Some("%Y-%m-%d") }),
+        (LogType::IsoBasic, BuiltinFormat {
+            regex: Regex::new(r"^\s*(?P<date>\d{8})[ T](?:(?P<hour>\d{2}):?\d{2})?").unwrap(),
+            date_format: Some("%Y%m%d") }),
+        (LogType::IisW3c, BuiltinFormat {
+            regex: Regex::new(r"^#(?:Software: Microsoft Internet Information Services|Fields: date time )").unwrap(),
+            date_format: Some("%Y-%m-%d %H:%M:%S") }),
+        (LogType::DayMonthYear2, BuiltinFormat {
+            regex: Regex::new(r"^\s*(?P<date>\d{1,2}-(?i:Jan|Feb|Mar|Apr|May|Jun|Jul|Aug|Sep|Oct|Nov|Dec)-\d{2})\b(?: (?P<hour>\d{2}):\d{2}:\d{2})?").unwrap(),
+            date_format: Some("%d-%b-%y") }),
+        (LogType::ApacheAccess, BuiltinFormat {
+            regex: Regex::new(r"\[\d{2}/(?i:Jan|Feb|Mar|Apr|May|Jun|Jul|Aug|Sep|Oct|Nov|Dec)/\d{4}:(?P<hour>\d{2}):\d{2}:\d{2} [+-]\d{4}\]").unwrap(),
+            date_format: Some("[%d/%b/%Y:%H:%M:%S %z]") }),
+        (LogType::ApacheVhost, BuiltinFormat {
+            regex: Regex::new(r"^\s*(?P<vhost>[\w.-]*[^\W\d][\w.-]*)(?::\d+)? \S+ \S+ \S+ (?P<ts>\[\d{2}/(?i:Jan|Feb|Mar|Apr|May|Jun|Jul|Aug|Sep|Oct|Nov|Dec)/\d{4}:(?P<hour>\d{2}):\d{2}:\d{2} [+-]\d{4}\])").unwrap(),
+            date_format: Some("[%d/%b/%Y:%H:%M:%S %z]") }),
+        (LogType::HaproxyHttp, BuiltinFormat {
+            regex: Regex::new(r"\[\d{2}/(?i:Jan|Feb|Mar|Apr|May|Jun|Jul|Aug|Sep|Oct|Nov|Dec)/\d{4}:(?P<hour>\d{2}):\d{2}:\d{2}\.\d{3}\]").unwrap(),
+            date_format: Some("[%d/%b/%Y:%H:%M:%S%.3f]") }),
+        (LogType::NginxAccess, BuiltinFormat {
+            regex: Regex::new(r"\[\d{2}/(?i:Jan|Feb|Mar|Apr|May|Jun|Jul|Aug|Sep|Oct|Nov|Dec)/\d{4}:(?P<hour>\d{2}):\d{2}:\d{2}").unwrap(),
+            date_format: Some("[%d/%b/%Y:%H:%M:%S") }),
+        (LogType::ApacheError, BuiltinFormat {
+            regex: Regex::new(r"\[(?i:Mon|Tue|Wed|Thu|Fri|Sat|Sun) (?i:Jan|Feb|Mar|Apr|May|Jun|Jul|Aug|Sep|Oct|Nov|Dec) \d{2} (?P<hour>\d{2}):\d{2}:\d{2}.\d{6} \d{4}]").unwrap(),
+            date_format: Some("[%a %b %d %H:%M:%S%.6f %Y]") }),
+        (LogType::GrafanaLogs, BuiltinFormat {
+            regex: Regex::new(r"^\s*t=\d{4}-\d{2}-\d{2}T(?P<hour>\d{2}):\d{2}:\d{2}(\+|-)\d{4} lvl=").unwrap(),
+            date_format: Some("t=%Y-%m-%dT%H:%M:%S%z lvl=") }),
+        (LogType::MysqlSlow, BuiltinFormat {
+            regex: Regex::new(r"^\s*# Time: (?P<ts>\d{4}-\d{2}-\d{2}T\d{2}:\d{2}:\d{2}(?:\.\d{1,6})?(?:Z|[+-]\d{2}:\d{2}))").unwrap(),
+            date_format: None }),
+        (LogType::JournalExport, BuiltinFormat {
+            regex: Regex::new(r"(?m)^__REALTIME_TIMESTAMP=(?P<ts>\d{1,19})$").unwrap(),
+            date_format: None }),
+        (LogType::CiscoSyslog, BuiltinFormat {
+            regex: Regex::new(r"^\s*(?:<\d{1,3}>)?(?:\d+: )?(?:[\w.-]+: )?[*.]?(?P<month>(?i:Jan|Feb|Mar|Apr|May|Jun|Jul|Aug|Sep|Oct|Nov|Dec)) +(?P<day>\d{1,2})(?: (?P<year>\d{4}))? (?P<hour>\d{2}):\d{2}:\d{2}(?:\.\d+)?(?: [A-Za-z]{2,5})?: %").unwrap(),
+            date_format: Some("%b %d") }),
+        (LogType::EcsJson, BuiltinFormat {
+            regex: Regex::new(r#"^\s*\{(?:.*"@timestamp"\s*:\s*"[^"]*".*"(?:@version|ecs|ecs\.version)"\s*:|.*"(?:@version|ecs|ecs\.version)"\s*:.*"@timestamp"\s*:\s*"[^"]*")"#).unwrap(),
+            date_format: None }),
+        (LogType::DockerJson, BuiltinFormat {
+            regex: Regex::new(r#"^\s*\{\s*"log"\s*:\s*".*"stream"\s*:\s*"(?:stdout|stderr)".*"time"\s*:\s*"[^"]*""#).unwrap(),
+            date_format: None }),
+        (LogType::Json, BuiltinFormat {
+            regex: Regex::new(r"^\s*\{").unwrap(),
+            date_format: None })
     ]
-    .into_iter().collect::<HashMap<LogType, Regex>>();
+    .into_iter().collect::<HashMap<LogType, BuiltinFormat>>();
+    #[doc(hidden)]
+    static ref ECS_TIMESTAMP_REGEX: Regex = Regex::new(r#""@timestamp"\s*:\s*"(?P<ts>[^"]*)""#).unwrap();
+    #[doc(hidden)]
+    static ref ISO_HOUR_REGEX: Regex = Regex::new(r"^\s*\d{4}-\d{2}-\d{2}[T ](?P<hour>\d{2}):\d{2}").unwrap();
+    #[doc(hidden)]
+    static ref IIS_LINE_REGEX: Regex = Regex::new(r"^(?P<ts>\d{4}-\d{2}-\d{2} (?P<hour>\d{2}):\d{2}:\d{2})").unwrap();
     #[doc(hidden)]
     static ref NUMBER_REGEX: Regex = Regex::new(r"^\d+$").unwrap();
     // And this is the format (StrFtimeItems) for ISO 8601 dates
@@ -60,8 +173,9 @@ lazy_static! {
 ///
 /// This enum has different values, each one representing a different format
 /// of logs detected by the program while scanning a file.
-#[derive(std::hash::Hash, std::cmp::Eq, std::cmp::PartialEq, std::fmt::Debug)]
-enum LogType {
+#[derive(std::hash::Hash, std::cmp::Eq, std::cmp::PartialEq, std::fmt::Debug,
+         std::clone::Clone)]
+pub enum LogType {
     /// This format is commonly used by system logging utilities
     /// (`/var/log/messages`, `/var/log/debug`, etc...), and consists of the
     /// abbreviated month name, followed by the number of the day of the month,
@@ -69,19 +183,94 @@ enum LogType {
     ///
     /// The extreme disadvantage of this format is that it gives no information
     /// about the year those logs were written. Provided with no information,
-    /// we assume that the year those logs were taken is the current one
-    /// (even in cases where that would give dates in the future, although that
-    /// could be a check implemented in future versions).
+    /// we assume that those logs were written during the twelve months before
+    /// the file was last modified (see [`infer_year`]).
     Syslog,
+    /// Modern syslog daemons (rsyslog, systemd, ...) can follow RFC 5424,
+    /// where lines begin with a priority value, a version number, and a
+    /// complete RFC 3339 timestamp :
+    /// ```txt
+    /// <34>1 2023-10-11T22:14:15.003Z mymachine su - ID47 - ...
+    /// ```
+    /// No more guessing the year, we take the date as written in the
+    /// timestamp, whatever its offset (unless told otherwise by
+    /// [`Options::timezone`]).
+    Syslog5424,
     /// Some logging systems will have log lines begin with a calendar date
     /// following ISO 8601 standards (`YYYY-MM-DD`). For me, `fail2ban` is the
     /// main reason I need this format.
     Iso,
+    /// Embedded systems sometimes write the basic format of ISO 8601
+    /// instead, without separators in the date, followed by the time of day :
+    /// ```txt
+    /// 20231011 13:55:36 watchdog: ping
+    /// ```
+    /// The date must be followed by a space or a `T`, so that lines merely
+    /// beginning with a number are not taken for dates.
+    IsoBasic,
+    /// IIS writes the W3C extended log format, whose `#` directives declare
+    /// the software and the fields of the lines below them, and whose lines
+    /// begin with their date and time in UTC, whatever the other fields :
+    /// ```txt
+    /// #Software: Microsoft Internet Information Services 10.0
+    /// #Fields: date time s-ip cs-method cs-uri-stem sc-status
+    /// 2023-10-11 13:55:36 10.0.0.1 GET /index.html 200
+    /// ```
+    /// Files are recognized by their directives, and only their other lines
+    /// are dated. Directives are never continuations of the line before
+    /// them, and are kept aside in `{outroot}.meta` (see
+    /// [`FileProcessor::process`]).
+    IisW3c,
+    /// Embedded devices and some older appliances begin their lines with the
+    /// day, the abbreviated month name and a two-digit year, maybe followed
+    /// by the time of day :
+    /// ```txt
+    /// 11-Oct-23 14:02:11 eth0: link up
+    /// ```
+    /// Two-digit years follow the pivot of `%y` in
+    /// [`strftime`](chrono::format::strftime) : `00` to `69` are read as
+    /// 2000 to 2069, and `70` to `99` as 1970 to 1999. Lines from before
+    /// 1970 are thus dated a century later.
+    DayMonthYear2,
     /// Apache follows a particular standard for its log formats, where lines
     /// begin with a ton of information (IP of the client, codes, etc).
-    /// The date is present, but in the format `[%d/%b/%Y`..., for example
-    /// `[17/May/2020`.
+    /// The date is present, but in the format `[%d/%b/%Y:%H:%M:%S %z]`, for
+    /// example `[17/May/2020:02:07:16 +0200]`.
+    ///
+    /// Behind proxies, that date can be preceded by other bracketed content
+    /// (IPv6 addresses like `[2001:db8::1]`, `X-Forwarded-For` chains, ...).
+    /// We only consider complete timestamps, and keep the first one that is
+    /// an actual date.
     ApacheAccess,
+    /// Apache logs shared by several virtual hosts (`LogFormat "%v:%p %h
+    /// %l %u %t ..."`) begin with the name of the virtual host, maybe
+    /// followed by the port, before the client and its date :
+    /// ```txt
+    /// example.com:443 1.2.3.4 - - [11/Oct/2023:13:55:36 +0000] "GET / HTTP/1.1" 200 42
+    /// ```
+    /// Lines are dated like [`LogType::ApacheAccess`] lines, which they
+    /// would otherwise be recognized as, and can be split by virtual host on
+    /// top of their date (see [`Options::split_vhosts`] and [`parse_vhost`]).
+    /// Lines whose first word holds no letter, like the address of a proxy,
+    /// are still [`LogType::ApacheAccess`] lines.
+    ApacheVhost,
+    /// Nginx writes the same bracketed `$time_local` date as Apache, but
+    /// reverse proxies and custom `log_format`s move it around, add fields
+    /// before it, or drop the offset :
+    /// ```txt
+    /// 10.0.0.2 203.0.113.9 - - [10/Oct/2023:13:55:36] "GET / HTTP/1.1" ...
+    /// ```
+    /// We only look for the bracketed date and time, anywhere in the line.
+    /// Lines also matching [`LogType::ApacheAccess`] are recognized as such.
+    NginxAccess,
+    /// HAProxy HTTP logs give the date the request was accepted, with
+    /// milliseconds and without offset, after the client address :
+    /// ```txt
+    /// 10.0.1.2:33317 [11/Oct/2023:13:55:36.123] http-in static/srv1 10/0/30/69/109 200 ...
+    /// ```
+    /// HAProxy logs sent through syslog are recognized as
+    /// [`LogType::Syslog`] lines instead, since they begin with its date.
+    HaproxyHttp,
     /// Since apache couldn't follow one standard, error logs follow another
     /// format.
     /// This one puts the date at the beginning of the lines, but sadly
@@ -98,11 +287,701 @@ enum LogType {
     ///
     /// Every line begins with the precise time formatted according to ISO 8601,
     /// prefixed with `t=`, and followed by `lvl=` showing the log level.
-    /// ```
+    /// ```txt
     /// t=2020-05-12T18:14:21+0200 lvl=...
     /// ```
     /// So we can analyze those easily.
-    GrafanaLogs
+    GrafanaLogs,
+    /// The slow query log of MySQL (5.7 and later) describes every query
+    /// over several lines, the first of which gives its RFC 3339 time :
+    /// ```txt
+    /// # Time: 2023-10-11T13:55:36.123456Z
+    /// # User@Host: app[app] @ localhost []  Id:     8
+    /// SELECT ...
+    /// ```
+    /// Only the `# Time:` lines are dated, the others follow them. Like
+    /// with [`LogType::Syslog5424`], we take the date as written.
+    MysqlSlow,
+    /// The export format of the systemd journal (`journalctl -o export`)
+    /// writes every entry as a record of `FIELD=value` lines, ended by an
+    /// empty line, and dated by its `__REALTIME_TIMESTAMP`, in microseconds
+    /// since the epoch :
+    /// ```txt
+    /// __CURSOR=s=739ad463348b4ceca5a9e69c95a3c93f;i=4ece7;...
+    /// __REALTIME_TIMESTAMP=1697032536123456
+    /// MESSAGE=request done
+    ///
+    /// ```
+    /// Fields whose value is not printable are written as their name, the
+    /// length of the value as a 64-bit little-endian integer, and the value
+    /// itself. Whole records are read and written at once, dated by their
+    /// UTC timestamp (see [`split_lines_at`]).
+    JournalExport,
+    /// Cisco devices (and a few other network appliances) send a syslog
+    /// flavour of their own, where the date can be preceded by a priority
+    /// value, a sequence number, the name of the host and a `*` (meaning that
+    /// the clock of the device is not synchronized). The message itself
+    /// always begins with a `%` mnemonic.
+    /// ```txt
+    /// <189>123: host: *Oct 11 22:14:15.003: %SYS-5-CONFIG_I: ...
+    /// Oct 11 2023 22:14:15: %ASA-6-302013: ...
+    /// ```
+    /// When the year is present, we use it. Otherwise, we are in the same
+    /// situation as with [`LogType::Syslog`].
+    CiscoSyslog,
+    /// The Elastic ecosystem (Logstash, Filebeat, and anything logging in
+    /// the Elastic Common Schema) writes one JSON object per line, dated
+    /// by an RFC 3339 `@timestamp` field.
+    /// ```txt
+    /// {"@timestamp":"2023-10-11T22:14:15.003Z","@version":"1","message":...}
+    /// ```
+    /// Since other JSON logs may also have a `@timestamp`, we only recognize
+    /// lines that also have a `@version` or an `ecs` field.
+    EcsJson,
+    /// The `json-file` logging driver of Docker (and containerd, through
+    /// Docker) writes every line of a container in a JSON envelope, along
+    /// with the stream it was written to and its RFC 3339 `time`, with
+    /// nanoseconds :
+    /// ```txt
+    /// {"log":"request done\n","stream":"stdout","time":"2023-10-11T13:55:36.123456789Z"}
+    /// ```
+    /// Lines are dated by their `time`, and can be written without their
+    /// envelope (see [`Options::unwrap_docker`]).
+    DockerJson,
+    /// Structured loggers (bunyan, pino, and many others) write one JSON
+    /// object per line, dated by an RFC 3339 timestamp in a field such as
+    /// `time` or `ts`.
+    /// ```txt
+    /// {"level":30,"time":"2023-10-11T22:14:15.003Z","msg":"request done"}
+    /// ```
+    /// Lines are parsed as JSON, and the timestamp is read from the first
+    /// of [`Options::json_time_fields`] holding one. Lines of such files
+    /// which are not JSON are never dated.
+    Json,
+    /// Any other format, described by the [`CustomFormat`] of that name in
+    /// [`Options::formats`]
+    ///
+    /// Only the functions given the [`Options`] of the run know those
+    /// formats : the others never date such lines.
+    Custom(String)
+}
+
+impl LogType {
+    /// Built-in log types, in the order they are tried when detecting the
+    /// type of a line
+    ///
+    /// ```
+    /// # use logboop::process::LogType;
+    /// assert_eq!(LogType::builtins().first(), Some(&LogType::Syslog5424));
+    /// assert!(LogType::builtins().iter().all(|lt| lt.regex_source().is_some()));
+    /// ```
+    pub fn builtins() -> &'static [LogType] {
+        &DETECTION_ORDER
+    }
+
+    /// Name of the log type, which is the name of the variant for built-in
+    /// ones, and the name of the format for custom ones
+    pub fn name(&self) -> &str {
+        match self {
+            LogType::Syslog => "Syslog",
+            LogType::Syslog5424 => "Syslog5424",
+            LogType::Iso => "Iso",
+            LogType::IsoBasic => "IsoBasic",
+            LogType::IisW3c => "IisW3c",
+            LogType::DayMonthYear2 => "DayMonthYear2",
+            LogType::ApacheAccess => "ApacheAccess",
+            LogType::ApacheVhost => "ApacheVhost",
+            LogType::HaproxyHttp => "HaproxyHttp",
+            LogType::NginxAccess => "NginxAccess",
+            LogType::ApacheError => "ApacheError",
+            LogType::GrafanaLogs => "GrafanaLogs",
+            LogType::MysqlSlow => "MysqlSlow",
+            LogType::JournalExport => "JournalExport",
+            LogType::CiscoSyslog => "CiscoSyslog",
+            LogType::EcsJson => "EcsJson",
+            LogType::DockerJson => "DockerJson",
+            LogType::Json => "Json",
+            LogType::Custom(name) => name
+        }
+    }
+
+    /// Source of the regex recognizing the lines of a built-in log type, or
+    /// `None` for custom ones (see [`CustomFormat::regex`])
+    ///
+    /// ```
+    /// # use logboop::process::LogType;
+    /// assert_eq!(LogType::Iso.regex_source(), Some(r"^\s*\d{4}-\d{2}-\d{2}"));
+    /// ```
+    pub fn regex_source(&self) -> Option<&'static str> {
+        FORMATS.get(self).map(|format| format.regex.as_str())
+    }
+
+    /// [`strftime`](chrono::format::strftime) format of the timestamp matched
+    /// by the regex of a built-in log type, or `None` when it is an RFC 3339
+    /// timestamp, or for custom log types (see [`CustomFormat::format`])
+    ///
+    /// Log types giving no year ([`LogType::Syslog`], and
+    /// [`LogType::CiscoSyslog`] most of the time) have their year inferred
+    /// (see [`infer_year`]).
+    ///
+    /// ```
+    /// # use logboop::process::LogType;
+    /// assert_eq!(LogType::NginxAccess.date_format(), Some("[%d/%b/%Y:%H:%M:%S"));
+    /// assert_eq!(LogType::Syslog5424.date_format(), None);
+    /// ```
+    pub fn date_format(&self) -> Option<&'static str> {
+        FORMATS.get(self).and_then(|format| format.date_format)
+    }
+
+    /// Whether lines of this log type are JSON objects
+    pub fn is_json(&self) -> bool {
+        matches!(self, LogType::EcsJson | LogType::DockerJson | LogType::Json)
+    }
+}
+
+/// Log types are displayed by their name, which is the name of the variant
+/// for built-in ones
+///
+/// ```
+/// # use logboop::process::LogType;
+/// assert_eq!(LogType::ApacheAccess.to_string(), "ApacheAccess");
+/// assert_eq!(LogType::Custom("myapp".to_owned()).to_string(), "myapp");
+/// ```
+impl std::fmt::Display for LogType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// Log types are parsed from their name, in any case, and ignoring dashes
+/// and underscores, or from a shorter alias (`rfc5424`, `cisco`, `haproxy`,
+/// `nginx`, `grafana`, `mysql`, `journal`, `ecs` or `docker`)
+///
+/// Custom log types cannot be parsed, since their names are only known from
+/// [`Options::formats`].
+///
+/// ```
+/// # use logboop::process::LogType;
+/// assert_eq!("grafana".parse(), Ok(LogType::GrafanaLogs));
+/// assert_eq!("apache-access".parse(), Ok(LogType::ApacheAccess));
+/// assert_eq!("Syslog5424".parse(), Ok(LogType::Syslog5424));
+/// assert!("myapp".parse::<LogType>().is_err());
+/// ```
+impl std::str::FromStr for LogType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<LogType, String> {
+        let name: String = s.chars()
+            .filter(|c| *c != '-' && *c != '_')
+            .map(|c| c.to_ascii_lowercase())
+            .collect();
+        match name.as_str() {
+            "rfc5424" => Ok(LogType::Syslog5424),
+            "cisco" => Ok(LogType::CiscoSyslog),
+            "haproxy" => Ok(LogType::HaproxyHttp),
+            "nginx" => Ok(LogType::NginxAccess),
+            "grafana" => Ok(LogType::GrafanaLogs),
+            "mysql" => Ok(LogType::MysqlSlow),
+            "journal" => Ok(LogType::JournalExport),
+            "ecs" => Ok(LogType::EcsJson),
+            "docker" => Ok(LogType::DockerJson),
+            "iis" => Ok(LogType::IisW3c),
+            _ => DETECTION_ORDER.iter()
+                .find(|logtype| logtype.name().to_ascii_lowercase() == name)
+                .cloned()
+                .ok_or_else(|| format!("unknown log type \"{}\"", s))
+        }
+    }
+}
+
+/// A log format defined at run time, rather than built into `LogBoop`
+///
+/// Lines are recognized by the regex of the format, and the region it
+/// matches (or only its `ts` group, if it has one) is parsed with the
+/// [`strftime`](chrono::format::strftime) format of the format. That format
+/// must give at least a complete date, and may give the time of day and the
+/// offset, for hourly buckets (see [`Granularity`]) and [`Timezone::Utc`].
+///
+/// A two-digit year (`%y`) is read as chrono reads it : `00` to `69` are
+/// 2000 to 2069, and `70` to `99` are 1970 to 1999, as with
+/// [`LogType::DayMonthYear2`].
+///
+/// Formats are given to a run in [`Options::formats`], and are tried before
+/// the built-in ones, in order, when detecting the log type of a file. Their
+/// lines have the log type [`LogType::Custom`], holding the name of the
+/// format.
+///
+/// ```
+/// # use logboop::process::CustomFormat;
+/// let format = CustomFormat {
+///     name: "myapp".to_owned(),
+///     regex: r"^\|(?P<ts>\d{8} \d{2}h\d{2})\|".parse().unwrap(),
+///     format: "%Y%m%d %Hh%M".to_owned()
+/// };
+/// let line = "|20231011 22h14| request done";
+/// assert_eq!(format.date(line).map(|date| date.to_string()),
+///            Some("2023-10-11".to_owned()));
+/// assert_eq!(format.hour(line), Some(22));
+/// ```
+#[derive(std::fmt::Debug, std::clone::Clone)]
+pub struct CustomFormat {
+    /// Name of the format, naming its log type (see [`LogType::Custom`])
+    pub name: String,
+    /// Regex recognizing the lines of the format, and locating their
+    /// timestamp
+    pub regex: Regex,
+    /// Format of the timestamp, as understood by
+    /// [`NaiveDate::parse_from_str`]
+    pub format: String
+}
+
+impl CustomFormat {
+    /// Load log formats from a JSON file
+    ///
+    /// The file holds an array of formats, each one being an object with a
+    /// `name`, a `regex` and a `format`, as described by [`CustomFormat`].
+    /// ```json
+    /// [{"name": "myapp", "regex": "^\\|(?P<ts>[^|]*)\\|", "format": "%Y%m%d %Hh%M"}]
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// The file not being readable is an
+    /// [`IoError`](LogBoopError::IoError). Its content not being such an
+    /// array, a regex or a format being invalid, or a name being empty,
+    /// given twice or already used by a built-in log type, is a
+    /// [`FormatError`](LogBoopError::FormatError).
+    pub fn load(path: &Path) -> Result<Vec<CustomFormat>, LogBoopError> {
+        let invalid = |reason: String| LogBoopError::FormatError {
+            path: path.to_path_buf(), reason };
+        let text = std::fs::read_to_string(path)?;
+        let value: serde_json::Value = serde_json::from_str(&text)
+            .map_err(|e| invalid(e.to_string()))?;
+        let entries = value.as_array()
+            .ok_or_else(|| invalid("not an array of formats".to_owned()))?;
+        let mut formats: Vec<CustomFormat> = Vec::with_capacity(entries.len());
+        for (index, entry) in entries.iter().enumerate() {
+            let field = |key: &str| entry.get(key).and_then(|v| v.as_str())
+                .ok_or_else(|| invalid(format!("format {} has no \"{}\"", index, key)));
+            let name = field("name")?;
+            if name.is_empty() || DETECTION_ORDER.iter().any(|lt| lt.to_string() == name)
+                || formats.iter().any(|format| format.name == name) {
+                return Err(invalid(format!("format {} cannot be named \"{}\"",
+                                           index, name)));
+            }
+            let regex = Regex::new(field("regex")?)
+                .map_err(|e| invalid(format!("format {} : {}", index, e)))?;
+            let format = field("format")?;
+            if StrftimeItems::new(format).any(|item| item == Item::Error) {
+                return Err(invalid(format!("format {} has an invalid format \"{}\"",
+                                           index, format)));
+            }
+            formats.push(CustomFormat { name: name.to_owned(), regex,
+                                        format: format.to_owned() });
+        }
+        Ok(formats)
+    }
+
+    /// The timestamp of a line, as written, if it matches the regex
+    fn timestamp_text<'l>(&self, line: &'l str) -> Option<&'l str> {
+        let caps = self.regex.captures(line)?;
+        caps.name("ts").or_else(|| caps.get(0)).map(|found| found.as_str())
+    }
+
+    /// The date of a line, or `None` when it holds no valid one
+    pub fn date(&self, line: &str) -> Option<NaiveDate> {
+        NaiveDate::parse_from_str(self.timestamp_text(line)?, &self.format).ok()
+    }
+
+    /// The hour of a line, or `None` when its format gives no time of day
+    pub fn hour(&self, line: &str) -> Option<u32> {
+        chrono::NaiveDateTime::parse_from_str(self.timestamp_text(line)?, &self.format)
+            .ok().map(|datetime| datetime.hour())
+    }
+
+    /// The complete timestamp of a line, or `None` when its format gives
+    /// no offset
+    fn timestamp(&self, line: &str) -> Option<chrono::DateTime<chrono::FixedOffset>> {
+        chrono::DateTime::parse_from_str(self.timestamp_text(line)?, &self.format).ok()
+    }
+}
+
+/// The custom format of a name, if any
+fn custom_format<'f>(formats: &'f [CustomFormat], name: &str) -> Option<&'f CustomFormat> {
+    formats.iter().find(|format| format.name == name)
+}
+
+/// Abbreviated names of the months, as written in logs
+const MONTHS: [&str; 12] = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul",
+    "Aug", "Sep", "Oct", "Nov", "Dec"];
+
+/// Order in which log types are tried when detecting the type of a line
+///
+/// [`LogType::CiscoSyslog`] comes before [`LogType::Syslog`] since some
+/// Cisco lines would also be recognized as regular syslog lines, but not
+/// the other way around. The same goes for [`LogType::HaproxyHttp`] (whose
+/// timestamps have milliseconds), [`LogType::ApacheAccess`] (whose
+/// timestamps have an offset) and [`LogType::NginxAccess`], and for
+/// [`LogType::ApacheVhost`], whose lines are [`LogType::ApacheAccess`] lines
+/// preceded by a virtual host. [`LogType::IsoBasic`] comes after
+/// [`LogType::Iso`], whose lines it never matches anyway, and
+/// [`LogType::IisW3c`] is only recognized by its `#` directives, its lines
+/// being [`LogType::Iso`] lines.
+/// [`LogType::Syslog5424`] is tried first, so that its lines are never
+/// mistaken for anything else, and [`LogType::Json`] last, since it would
+/// also recognize [`LogType::DockerJson`] and [`LogType::EcsJson`] lines.
+const DETECTION_ORDER: [LogType; 18] = [LogType::Syslog5424,
+    LogType::CiscoSyslog, LogType::Syslog,
+    LogType::Iso, LogType::IsoBasic, LogType::IisW3c, LogType::DayMonthYear2,
+    LogType::HaproxyHttp, LogType::ApacheVhost, LogType::ApacheAccess, LogType::NginxAccess,
+    LogType::ApacheError, LogType::GrafanaLogs, LogType::MysqlSlow, LogType::JournalExport,
+    LogType::DockerJson, LogType::EcsJson,
+    LogType::Json];
+
+/// Period of time whose lines are written in the same output file
+///
+/// A bucket is a calendar date, and with [`Granularity::Hour`] an hour of
+/// that date too (for log types giving the time of day, see [`parse_hour`]).
+/// It is displayed as `YYYY-MM-DD`, or `YYYY-MM-DDTHH` when it has an hour.
+///
+/// With [`Granularity::Week`], a bucket is an ISO 8601 week, whose date is
+/// the Monday, displayed as `YYYY-Www` (the year being the one the week
+/// belongs to, which may not be the year of all of its days).
+///
+/// With [`Timezone::Offset`], a bucket also holds the offset of the
+/// timestamps of its lines, displayed after the rest like `+0200`.
+///
+/// With [`Options::split_vhosts`], the buckets of [`LogType::ApacheVhost`]
+/// lines also hold their virtual host, which is not displayed, but names
+/// their output files (see
+/// [`default_bucket_path`](crate::options::default_bucket_path)).
+///
+/// ```
+/// # use logboop::process::Bucket;
+/// let date = chrono::NaiveDate::from_ymd_opt(2020, 5, 17).unwrap();
+/// let day = Bucket { date, hour: None, week: false, offset: None, vhost: None };
+/// assert_eq!(day.to_string(), "2020-05-17");
+/// assert_eq!(Bucket { hour: Some(2), ..day.clone() }.to_string(), "2020-05-17T02");
+/// let date = chrono::NaiveDate::from_ymd_opt(2020, 12, 28).unwrap();
+/// assert_eq!(Bucket { date, week: true, ..day.clone() }.to_string(), "2020-W53");
+/// let offset = chrono::FixedOffset::east_opt(-5 * 3600);
+/// assert_eq!(Bucket { date, offset, ..day.clone() }.to_string(), "2020-12-28-0500");
+/// let vhost = Some("example.com".to_owned());
+/// assert_eq!(Bucket { date, vhost, ..day }.to_string(), "2020-12-28");
+/// ```
+#[derive(std::hash::Hash, std::cmp::Eq, std::cmp::PartialEq, std::fmt::Debug,
+         std::clone::Clone)]
+pub struct Bucket {
+    /// Calendar date of the lines
+    pub date: NaiveDate,
+    /// Hour of the lines, from 0 to 23, if buckets are hourly
+    pub hour: Option<u32>,
+    /// Whether the bucket is the whole ISO 8601 week starting on `date`
+    pub week: bool,
+    /// Offset of the timestamps of the lines, with [`Timezone::Offset`]
+    pub offset: Option<chrono::FixedOffset>,
+    /// Virtual host of the lines, with [`Options::split_vhosts`]
+    pub vhost: Option<String>
+}
+
+impl Bucket {
+    /// Last second of the bucket
+    ///
+    /// Its date (and hour) are read in its offset when it has one, in UTC
+    /// with [`Timezone::Utc`], and otherwise in the local time zone, where
+    /// logs are usually written.
+    ///
+    /// ```
+    /// # use logboop::process::Bucket;
+    /// # use logboop::options::Timezone;
+    /// let date = chrono::NaiveDate::from_ymd_opt(2020, 5, 17).unwrap();
+    /// let bucket = Bucket { date, hour: Some(2), week: false, offset: None, vhost: None };
+    /// assert_eq!(bucket.end(Timezone::Utc).to_rfc3339(), "2020-05-17T02:59:59+00:00");
+    /// let offset = chrono::FixedOffset::east_opt(2 * 3600);
+    /// let bucket = Bucket { date, hour: None, week: false, offset, vhost: None };
+    /// assert_eq!(bucket.end(Timezone::Offset).to_rfc3339(), "2020-05-17T23:59:59+02:00");
+    /// ```
+    pub fn end(&self, timezone: Timezone) -> DateTime<FixedOffset> {
+        let length = match (self.week, self.hour) {
+            (true, _) => chrono::Duration::days(7),
+            (false, Some(_)) => chrono::Duration::hours(1),
+            (false, None) => chrono::Duration::days(1)
+        };
+        let last = self.date.and_time(NaiveTime::MIN)
+            + chrono::Duration::hours(self.hour.unwrap_or(0).into())
+            + length - chrono::Duration::seconds(1);
+        let end = match (self.offset, timezone) {
+            (Some(offset), _) => offset.from_local_datetime(&last).single(),
+            (None, Timezone::Utc) => None,
+            // Skipped by a change of offset, or repeated
+            (None, _) => Local.from_local_datetime(&last).earliest()
+                .map(|end| end.fixed_offset())
+        };
+        end.unwrap_or_else(|| last.and_utc().fixed_offset())
+    }
+}
+
+impl std::fmt::Display for Bucket {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.week {
+            let week = self.date.iso_week();
+            write!(f, "{}-W{:02}", week.year(), week.week())?;
+        } else {
+            write!(f, "{}", self.date.format("%Y-%m-%d"))?;
+        }
+        if let Some(hour) = self.hour {
+            write!(f, "T{:02}", hour)?;
+        }
+        match self.offset {
+            Some(offset) => {
+                let seconds = offset.local_minus_utc();
+                let sign = if seconds < 0 { '-' } else { '+' };
+                let minutes = seconds.abs() / 60;
+                write!(f, "{}{:02}{:02}", sign, minutes / 60, minutes % 60)
+            },
+            None => Ok(())
+        }
+    }
+}
+
+/// Writers in which the lines of a bucket are written by [`split_lines`]
+///
+/// On top of being [`Write`], such a writer can be finished once all of
+/// the lines of its bucket have been written, which by default simply
+/// flushes it.
+pub trait BucketWrite: Write {
+    /// Complete the bucket, once all of its lines are written
+    ///
+    /// # Errors
+    /// Any I/O error while completing the bucket flows upwards.
+    fn finish_bucket(mut self) -> std::io::Result<()> where Self: Sized {
+        self.flush()
+    }
+}
+
+impl BucketWrite for std::io::Sink {}
+impl BucketWrite for Vec<u8> {}
+impl<W: Write> BucketWrite for BufWriter<W> {}
+
+impl<W: Write> BucketWrite for GzEncoder<W> {
+    /// The GZ trailer is written, and the inner writer flushed
+    fn finish_bucket(self) -> std::io::Result<()> {
+        self.finish()?.flush()
+    }
+}
+
+impl<W: Write> BucketWrite for ZstdEncoder<'static, W> {
+    /// The Zstandard frame is ended, and the inner writer flushed
+    fn finish_bucket(self) -> std::io::Result<()> {
+        self.finish()?.flush()
+    }
+}
+
+impl<W: Write> BucketWrite for BzEncoder<W> {
+    /// The bzip2 stream is ended, and the inner writer flushed
+    fn finish_bucket(self) -> std::io::Result<()> {
+        self.finish()?.flush()
+    }
+}
+
+/// Writer of an output file, written either plainly or through compression
+/// (see [`Options::compress_directly`])
+enum OutputWriter {
+    /// Plain text output
+    Plain(BufWriter<File>),
+    /// GZ compressed output
+    Gzip(GzEncoder<BufWriter<File>>),
+    /// Zstandard compressed output
+    Zstd(ZstdEncoder<'static, BufWriter<File>>),
+    /// bzip2 compressed output
+    Bzip2(BzEncoder<BufWriter<File>>)
+}
+
+impl Write for OutputWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            OutputWriter::Plain(w) => w.write(buf),
+            OutputWriter::Gzip(w) => w.write(buf),
+            OutputWriter::Zstd(w) => w.write(buf),
+            OutputWriter::Bzip2(w) => w.write(buf)
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            OutputWriter::Plain(w) => w.flush(),
+            OutputWriter::Gzip(w) => w.flush(),
+            OutputWriter::Zstd(w) => w.flush(),
+            OutputWriter::Bzip2(w) => w.flush()
+        }
+    }
+}
+
+impl BucketWrite for OutputWriter {
+    fn finish_bucket(self) -> std::io::Result<()> {
+        match self {
+            OutputWriter::Plain(w) => w.finish_bucket(),
+            OutputWriter::Gzip(w) => w.finish_bucket(),
+            OutputWriter::Zstd(w) => w.finish_bucket(),
+            OutputWriter::Bzip2(w) => w.finish_bucket()
+        }
+    }
+}
+
+/// Output writer counting the lines written to it, which are added to the
+/// [`OutputStats`] of its file once its bucket is finished
+struct CountingWriter<'w> {
+    /// Where the lines are actually written
+    inner: OutputWriter,
+    /// The output file
+    path: PathBuf,
+    /// Bucket of the lines written
+    bucket: Option<Bucket>,
+    /// Line feeds written so far
+    lines: u64,
+    /// What was written to every output file of the file being processed
+    written: &'w RefCell<HashMap<PathBuf, OutputStats>>
+}
+
+impl Write for CountingWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.lines += buf[..n].iter().filter(|&&b| b == b'\n').count() as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl BucketWrite for CountingWriter<'_> {
+    fn finish_bucket(self) -> std::io::Result<()> {
+        self.inner.finish_bucket()?;
+        let path = self.path;
+        let bucket = self.bucket;
+        self.written.borrow_mut().entry(path.clone())
+            .or_insert_with(|| OutputStats { path, bucket, lines: 0 })
+            .lines += self.lines;
+        Ok(())
+    }
+}
+
+/// What processing a single file did (see [`FileProcessor::process`])
+#[derive(std::fmt::Debug, std::clone::Clone, std::cmp::PartialEq, std::cmp::Eq)]
+pub struct FileStats {
+    /// Log type of the file, `None` when it was unknown
+    pub logtype: Option<LogType>,
+    /// Whether the file held nothing but whitespace (see
+    /// [`EmptyFiles`](crate::options::EmptyFiles))
+    pub empty: bool,
+    /// Whether the file was left untouched, its log type not being one of
+    /// [`Options::only_types`]
+    pub filtered: bool,
+    /// Number of lines written to the output files
+    pub lines: u64,
+    /// Number of bytes read from the file, once inflated, which is its
+    /// whole content unless processing stopped early
+    pub bytes: u64,
+    /// Output files written to, sorted by path
+    pub outputs: Vec<OutputStats>
+}
+
+/// What was written to one output file while processing a file (see
+/// [`FileStats::outputs`])
+#[derive(std::fmt::Debug, std::clone::Clone, std::cmp::PartialEq, std::cmp::Eq)]
+pub struct OutputStats {
+    /// The output file, with the extension of [`Options::codec`] when
+    /// [`Options::compress_directly`] is set
+    pub path: PathBuf,
+    /// Bucket of the lines written, `None` for the `.unknown`, `.excluded`
+    /// and `.empty` files
+    pub bucket: Option<Bucket>,
+    /// Number of lines written
+    pub lines: u64
+}
+
+/// What processing a directory did (see [`all_files`])
+///
+/// Its [`Display`](std::fmt::Display) is a summary meant for people, and
+/// [`ProcessStats::to_json`] the same summary meant for monitoring tools.
+#[derive(std::fmt::Debug, std::clone::Clone, std::default::Default,
+         std::cmp::PartialEq, std::cmp::Eq)]
+pub struct ProcessStats {
+    /// Number of files processed, whether their log type was known or not
+    ///
+    /// Files left untouched because of their log type are not counted.
+    pub files: usize,
+    /// Number of files processed for every log type
+    pub per_type: HashMap<LogType, usize>,
+    /// Number of files of unknown log type, written to `.unknown` files
+    pub unknown: usize,
+    /// Number of files holding nothing but whitespace
+    pub empty: usize,
+    /// Number of files left untouched because of an error
+    pub failed: usize,
+    /// Number of files left untouched because of their log type (see
+    /// [`Options::only_types`])
+    pub filtered: usize,
+    /// Number of distinct output files written to
+    pub outputs: usize,
+    /// Number of lines written to the output files
+    pub lines: u64
+}
+
+impl ProcessStats {
+    /// The summary as a single line of JSON
+    ///
+    /// ```
+    /// # use logboop::process::{LogType, ProcessStats};
+    /// let mut stats = ProcessStats { files: 2, unknown: 1, outputs: 3, lines: 42,
+    ///                                ..ProcessStats::default() };
+    /// stats.per_type.insert(LogType::Iso, 1);
+    /// assert_eq!(stats.to_json(),
+    ///            r#"{"empty":0,"failed":0,"files":2,"filtered":0,"lines":42,"outputs":3,"per_type":{"Iso":1},"unknown":1}"#);
+    /// ```
+    pub fn to_json(&self) -> String {
+        let per_type: serde_json::Map<String, serde_json::Value> = self.counts()
+            .map(|(lt, count)| (lt.to_string(), count.into()))
+            .collect();
+        serde_json::json!({
+            "files": self.files,
+            "per_type": per_type,
+            "unknown": self.unknown,
+            "empty": self.empty,
+            "failed": self.failed,
+            "filtered": self.filtered,
+            "outputs": self.outputs,
+            "lines": self.lines
+        }).to_string()
+    }
+
+    /// Number of files of every log type processed, built-in log types
+    /// first, in the order they are detected, and then custom ones, by name
+    fn counts(&self) -> impl Iterator<Item = (&LogType, usize)> {
+        let mut custom: Vec<(&LogType, usize)> = self.per_type.iter()
+            .filter(|(lt, _)| matches!(lt, LogType::Custom(_)))
+            .map(|(lt, count)| (lt, *count))
+            .collect();
+        custom.sort_by_key(|(lt, _)| lt.to_string());
+        DETECTION_ORDER.iter()
+            .filter_map(move |lt| self.per_type.get(lt).map(|count| (lt, *count)))
+            .chain(custom)
+    }
+}
+
+impl std::fmt::Display for ProcessStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "files processed : {} ({} of unknown type, {} empty, {} failed)",
+                 self.files, self.unknown, self.empty, self.failed)?;
+        if self.filtered != 0 {
+            writeln!(f, "files skipped (filtered) : {}", self.filtered)?;
+        }
+        self.counts()
+            .try_for_each(|(lt, count)| writeln!(f, "  {} : {}", lt, count))?;
+        writeln!(f, "output files : {}", self.outputs)?;
+        write!(f, "lines written : {}", self.lines)
+    }
 }
 
 /// File processing data structure
@@ -115,72 +994,143 @@ enum LogType {
 ///
 /// This is how a `FileProcessor` is used in `LogBoop`.
 ///
-/// ```
+/// ```no_run
+/// # use std::path::PathBuf;
+/// # use logboop::process::FileProcessor;
+/// # use logboop::options::Options;
+/// # let options = Options::default();
 /// // Building file processor
-/// // We need to have two PathBuf, and here `path` isn't one
-/// let mut proco = FileProcessor::new(path.to_path_buf(), outroot);
+/// let mut proco = FileProcessor::new(PathBuf::from("var/log/syslog.1"),
+///                                    PathBuf::from("out/syslog"), &options);
 /// // Second, we need to determine the type of the file we process
 /// proco.determine_type()?;
 /// // It could very well fail, and it could find no compatible type
-/// // Meaning that it'll keep the `logtype` field at `None`,
-/// // Then, process if there was a compatible log type found.
-/// proco.process()
-/// // That method returns an io Result, so you can just return from it
+/// // Meaning that it'll keep the log type at `None`,
+/// println!("{:?}", proco.logtype());
+/// // Then, process the file, which tells what was written
+/// let stats = proco.process()?;
+/// println!("{} lines written", stats.lines);
+/// # Ok::<(), logboop::error::LogBoopError>(())
 /// ```
 /// # Creating one
 ///
 /// A `FileProcessor` is created from the combination of an input path
-/// (a [`PathBuf`] pointing to the file being processed) and an output
-/// path (another [`PathBuf`] giving the root path to which dates will
-/// be added while extracting).
-struct FileProcessor {
+/// (a [`PathBuf`] pointing to the file being processed), an output
+/// path (another [`PathBuf`] giving the root path from which the output
+/// files are named, see [`Options::bucket_path`]) and the [`Options`] of
+/// the run.
+pub struct FileProcessor<'a> {
     /// An owned path to the file being processed
     path: PathBuf,
     /// An owned path to the root path of the output data
     outroot: PathBuf,
     /// An optional log type, if one has been determined
-    logtype: Option<LogType>
+    logtype: Option<LogType>,
+    /// The settings of the run
+    options: &'a Options
 }
 
-impl FileProcessor {
+impl<'a> FileProcessor<'a> {
     /// Constructor for the `FileProcessor`
-    fn new(path: PathBuf, outroot: PathBuf) -> FileProcessor {
-        FileProcessor { path, outroot, logtype: None }
+    pub fn new(path: PathBuf, outroot: PathBuf, options: &'a Options)
+        -> FileProcessor<'a> {
+        FileProcessor { path, outroot, logtype: None, options }
+    }
+
+    /// Path to the file being processed
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Root path of the output files
+    pub fn outroot(&self) -> &Path {
+        &self.outroot
+    }
+
+    /// Log type of the file, if one has been determined
+    pub fn logtype(&self) -> Option<LogType> {
+        self.logtype.clone()
     }
 
     /// Determine a type for the current file.
     ///
-    /// This method opens the file, reads the first line, and tries to
-    /// match it with known types using regular expressions.
+    /// This method opens the file (inflating it on the fly if it is
+    /// compressed, like [`FileProcessor::process`]), reads up to
+    /// [`Options::detection_lines`] lines, and tries to match them with
+    /// known types using regular expressions, those of
+    /// [`Options::formats`] first. The first line matching a
+    /// type decides it, so that leading blank lines or banners are skipped.
+    /// If none does, the log type stays unknown.
+    ///
+    /// Files whose name tells their log type (see [`type_hint`]), like
+    /// `app.syslog.1`, get that log type instead. Should none of the lines
+    /// read match it, a warning is given, but the name is trusted all the
+    /// same.
     ///
     /// # Errors
     ///
     /// Of course, if any I/O operation fails for some reason (file not
     /// existing, no data in the file, permissions, disk failure, etc...),
-    /// `determine_type` will throw an I/O Error. Otherwise, it will return
-    /// the `Ok` variant of [`std::io::Result<()>`](std::io::Result).
-    fn determine_type(&mut self) -> std::io::Result<()> {
-        // We need to open the file and get the first line
-        let fptr = File::open(self.path.to_str().unwrap())?;
-        let mut bufr = BufReader::new(fptr);
-        let mut first_line = String::new();
-
-        // Read the first line
-        let _ = bufr.read_line(&mut first_line)?;
-        // Match it
-        let types = vec![LogType::Syslog,
-            LogType::Iso, LogType::ApacheAccess,
-            LogType::ApacheError, LogType::GrafanaLogs
-        ].into_iter();
-        for logtype in types {
-            if REGEXES[&logtype].is_match(&first_line) {
-                self.logtype = Some(logtype);
-                return Ok(());
+    /// `determine_type` will throw an [`IoError`](LogBoopError::IoError).
+    /// Otherwise, it will return `Ok(())`.
+    pub fn determine_type(&mut self) -> Result<(), LogBoopError> {
+        self.logtype = None;
+        let hint = type_hint(&self.path, self.options);
+        // Whether the lines read confirm the hint, if there was anything to read
+        let mut confirmed = None;
+        for line in self.head()? {
+            if let Some(ref hint) = hint {
+                if !line.trim().is_empty() {
+                    confirmed = Some(matches_type(hint, &line, self.options));
+                }
+                if confirmed == Some(true) {
+                    break;
+                }
+                continue;
+            }
+            self.logtype = detect_line_with(&line, &self.options.json_time_fields,
+                                            &self.options.formats);
+            if self.logtype.is_some() {
+                break;
+            }
+        }
+        if let Some(hint) = hint {
+            if confirmed == Some(false) {
+                warn!("{} is named as a {} log, but does not look like one, trusting its name",
+                      self.path.display(), hint);
             }
+            self.logtype = Some(hint);
         }
         Ok(())
     }
 
+    /// Read the first lines of the file, up to [`Options::detection_lines`],
+    /// without their line ending, past its [`Options::header_lines`]
+    ///
+    /// This is what [`FileProcessor::determine_type`] looks at, and where
+    /// [`Options::rename_only`] looks for the date of the whole file.
+    ///
+    /// # Errors
+    ///
+    /// Any error while opening or reading the file flows upwards.
+    pub fn head(&self) -> Result<Vec<String>, LogBoopError> {
+        // Compressed files are inflated on the fly
+        let mut bufr = open_input(&self.path, self.options.buffer_size)?;
+        read_header(&mut bufr, self.options.header_lines)?;
+        let mut lines = Vec::new();
+        let mut line = String::new();
+        for _ in 0..self.options.detection_lines {
+            line.clear();
+            // Stop at the end of a short file
+            if bufr.read_line(&mut line)? == 0 {
+                break;
+            }
+            // Lines are matched whatever their line ending
+            lines.push(line.trim_end_matches(['\r', '\n']).to_owned());
+        }
+        Ok(lines)
+    }
+
     /// Perform the processing, line by line, of the file.
     ///
     /// Once the log type is determined, process the file and
@@ -189,137 +1139,1720 @@ impl FileProcessor {
     /// 
     /// Every line is read, matched with the regex, and a method
     /// determines the date using a Date format string (using `determine_date`).
+    /// Compressed files (GZ, whatever their name, or Zstandard, bzip2 and
+    /// XZ, named so) are inflated on the fly, and deleted like any other
+    /// input file.
+    /// Lines giving no year are dated relative to the modification date of
+    /// the file (see [`split_lines_at`]).
+    ///
+    /// Lines need not be in date order : every line is appended to the output
+    /// file of its date, in the order it is read, whichever output files were
+    /// written to in between (see [`Options::max_open_files`]).
+    ///
+    /// The first [`Options::header_lines`] lines of a file whose log type is
+    /// known are never dated, but written as they are to `{outroot}.header`.
+    ///
+    /// Lines which cannot be dated before the first dated line (or anywhere
+    /// in JSON logs), and all of the lines of a file whose log type is
+    /// unknown, are written as they are to `{outroot}.unknown`, so that
+    /// nothing is lost. The directives of [`LogType::IisW3c`] files, their
+    /// only lines without a date, are written to `{outroot}.meta` instead.
+    /// In the same way, lines dated outside of [`Options::from_date`] and
+    /// [`Options::to_date`] are written to `{outroot}.excluded`.
     ///
-    /// If everything is successful, the file is deleted.
+    /// With [`Options::rename_only`], the whole file is copied as it is to
+    /// the output file of its first dated line among those
+    /// [`FileProcessor::determine_type`] looks at, without dating the other
+    /// lines. Files with no such line, or whose first date is outside of
+    /// [`Options::from_date`] and [`Options::to_date`], are split anyway.
+    ///
+    /// With [`Options::resumable`], how far the file was split, and the size
+    /// of every output file at that point, are saved to `{outroot}.progress`
+    /// along the way. Should the run be interrupted, the next one cuts the
+    /// output files back to those sizes, and goes on from there, instead of
+    /// writing the same lines twice. The `.progress` file is removed once
+    /// the file is processed. A `.progress` file left by another input file
+    /// of the same `outroot` is left alone, and the file processed without
+    /// one.
+    ///
+    /// Files holding nothing but whitespace have no log type either, but are
+    /// handled as told by [`Options::empty_files`] : either only deleted, or
+    /// written to `{outroot}.empty`.
+    ///
+    /// Files whose log type is not one of [`Options::only_types`] (when set),
+    /// including files of unknown type and empty files, are left untouched,
+    /// and reported as skipped.
+    ///
+    /// If everything is successful, the file is deleted, unless
+    /// [`Options::keep_source`] is set. With [`Options::verify`], the lines
+    /// written are first counted against the lines of the file, and
+    /// processing is aborted when they differ.
     ///
     /// # Errors
     ///
     /// If at any point, any I/O operation fails, the error will flow upwards.
-    /// Otherwise, the `Ok` variant of a
-    /// [`std::io::Result<()>`](std::io::Result).
-    fn process(&mut self) -> std::io::Result<()> {
+    /// Otherwise, it will return what was written, as [`FileStats`].
+    ///
+    /// The file is also aborted with a
+    /// [`TooManyDates`](LogBoopError::TooManyDates) error as soon as it
+    /// yields more than [`Options::max_dates_per_file`] distinct dates.
+    ///
+    /// Whenever processing is aborted, the output files are rolled back to
+    /// the state they were in before (see `rollback`), and the file is
+    /// kept, so that processing it again later does not duplicate lines.
+    /// Should rolling back fail too, that is logged, and the error which
+    /// aborted processing is still the one returned.
+    pub fn process(&mut self) -> Result<FileStats, LogBoopError> {
         // Redo the opening procedure, and read line by line
-        print!("{} ", self.path.to_str().unwrap());
-        std::io::stdout().flush()?;
-        if self.logtype.is_none() {
-            println!("?");
-            return Ok(());
-        }
+        // Progress is printed in one go, since other files may be processed
+        // at the same time
+        let path_in = self.path.to_str().unwrap();
         let prepared_path_out = self.outroot.to_str().unwrap();
-        // Ensure that the directory containing that output exists
-        create_dir_all(self.outroot.parent().unwrap())?;
-        let logtype = self.logtype.as_ref().unwrap();
-        let fptr = File::open(self.path.to_str().unwrap())?;
-        let bufr = BufReader::new(fptr);
-        bufr.lines()
-            .filter_map(|line|
-                        line.map(|l|
-                              (determine_date(&logtype, &l), l)
-                        ).ok()
-            )
-            .try_fold(
-                (String::new(), None),
-                |(mut odp, mut nbufw), (date_postfix, line)| -> std::io::Result<_> {
-                    if let Some(date_postfix) = date_postfix {
-                        if date_postfix != odp {
-                            let new_fname = format!("{}-{}",
-                                                    prepared_path_out,
-                                                    date_postfix);
-                            nbufw = Some(BufWriter::new(
-                                OpenOptions::new()
-                                    .append(true)
-                                    .create(true)
-                                    .open(new_fname)?));
-                            odp = date_postfix;
+        let metadata = self.path.metadata()?;
+        let bytes = metadata.len();
+        // Bytes read from the file, however it is read
+        let consumed = Rc::new(Cell::new(0));
+        let bufr = CountingReader { inner: open_input(&self.path, self.options.buffer_size)?,
+                                    consumed: Rc::clone(&consumed) };
+        let limit = self.options.max_dates_per_file;
+        let mut dates: HashSet<Bucket> = HashSet::new();
+        // Size of every output file before we touched it (if it existed)
+        let touched: RefCell<HashMap<PathBuf, Option<u64>>> = RefCell::new(HashMap::new());
+        // Lines written to every output file
+        let written: RefCell<HashMap<PathBuf, OutputStats>> = RefCell::new(HashMap::new());
+        // The only lines of IIS logs without a date are their directives
+        let mut unknown = self.outroot.clone();
+        add_extension(&mut unknown, match self.logtype {
+            Some(LogType::IisW3c) => "meta",
+            _ => "unknown"
+        });
+        let mut excluded = self.outroot.clone();
+        add_extension(&mut excluded, "excluded");
+        let mut marker = self.outroot.clone();
+        add_extension(&mut marker, "empty");
+        let mut header = self.outroot.clone();
+        add_extension(&mut header, "header");
+        let empty = self.logtype.is_none() && is_blank(&self.path, self.options.buffer_size)?;
+        let progress_path = self.progress_path();
+        // Where an interrupted run left this file, if it did
+        let (checkpoints, progress) = if self.options.resumable
+            && !self.options.compress_directly {
+            match Progress::load(&progress_path)? {
+                Some(progress) if progress.input != self.path => {
+                    warn!("{} belongs to {}, not checkpointing {}", progress_path.display(),
+                          progress.input.display(), path_in);
+                    (false, None)
+                },
+                progress => (true, progress)
+            }
+        } else {
+            (false, None)
+        };
+        // Files of other log types are not ours to touch
+        if !self.options.accepts_type(self.logtype.as_ref()) {
+            self.options.report(Level::Info, &self.path, bytes, format_args!(
+                "{} skipped (filtered)", path_in));
+            return Ok(FileStats { logtype: self.logtype.clone(), empty,
+                                  filtered: true, lines: 0, bytes: 0, outputs: Vec::new() });
+        }
+        let result = match &self.logtype {
+            // Empty files carry no data, nor lines to count
+            None if empty => match self.options.empty_files {
+                EmptyFiles::Delete => Ok(0),
+                EmptyFiles::Mark => self.open_output(marker.clone(), false, None, &written,
+                                                   &touched)
+                    .and_then(|mut writer| {
+                        let mut bufr = bufr;
+                        std::io::copy(&mut bufr, &mut writer)?;
+                        writer.finish_bucket()?;
+                        Ok(0)
+                    })
+            },
+            // Without a log type, the whole file is kept aside
+            None => self.open_output(unknown.clone(), false, None, &written, &touched)
+                .and_then(|writer| copy_lines(bufr, writer, self.options)),
+            Some(logtype) => {
+                // Header lines are kept aside, undated, unless an interrupted
+                // run already did
+                let mut bufr = bufr;
+                let header_lines = read_header(&mut bufr, self.options.header_lines)
+                    .map_err(LogBoopError::from)
+                    .and_then(|(text, lines)| match lines {
+                        0 => Ok(0),
+                        _ if progress.is_some() => Ok(lines),
+                        _ => self.open_output(header.clone(), false, None, &written, &touched)
+                            .and_then(|writer| copy_lines(&text[..], writer, self.options))
+                    });
+                header_lines.and_then(|header_lines| {
+                    let reference = modification_date(&self.path);
+                    // The whole file may go where its first dated line would
+                    let whole = if self.options.rename_only {
+                        self.head()?.iter().find_map(|line| find_bucket(
+                            logtype, line, self.options.granularity, self.options.timezone,
+                            &reference, &self.options.json_time_fields, &self.options.formats))
+                    } else {
+                        None
+                    }.filter(|bucket| self.options.in_date_range(&bucket.date));
+                    if let Some(bucket) = whole {
+                        dates.insert(bucket.clone());
+                        let fname = self.options.output_path(&bucket, &self.outroot);
+                        self.open_output(fname, self.options.compress_directly, Some(bucket),
+                                         &written, &touched)
+                            .and_then(|writer| copy_lines(bufr, writer, self.options))
+                    } else {
+                        let open = |bucket: Option<&Bucket>| {
+                            let bucket = match bucket {
+                                Some(bucket) if self.options.in_date_range(&bucket.date) =>
+                                    bucket,
+                                Some(_) => return self.open_output(excluded.clone(), false, None,
+                                                                   &written, &touched),
+                                None => return self.open_output(unknown.clone(), false, None,
+                                                                &written, &touched)
+                            };
+                            dates.insert(bucket.clone());
+                            if limit != 0 && dates.len() > limit {
+                                return Err(LogBoopError::TooManyDates { limit });
+                            }
+                            let fname = self.options.output_path(bucket, &self.outroot);
+                            self.open_output(fname, self.options.compress_directly,
+                                             Some(bucket.clone()), &written, &touched)
+                        };
+                        if checkpoints {
+                            self.split_resumable(logtype, &reference, progress.as_ref(),
+                                                 &consumed, &touched, open)
+                        } else {
+                            split_lines_at(logtype, &reference, bufr, self.options, open)
                         }
                     }
-                    // Write
-                    if let Some(ref mut writer) = nbufw {
-                        writeln!(writer, "{}", line)?;
-                    }
-                    Ok((odp, nbufw))
+                }.map(|lines| header_lines + lines))
+            }
+        };
+        // Every line read must have been written somewhere
+        let result = result.and_then(|lines| {
+            if self.options.verify && !empty {
+                let expected = count_lines(&self.path, self.options.buffer_size)?;
+                if expected != lines {
+                    return Err(LogBoopError::VerifyError {
+                        path: self.path.clone(), expected, found: lines });
+                }
+            }
+            Ok(lines)
+        });
+        // The checkpoint is either done with, or back to where it was
+        if checkpoints {
+            match (&result, &progress) {
+                (Err(_), Some(progress)) => progress.save(&progress_path)?,
+                _ => match remove_file(&progress_path) {
+                    Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+                    _ => ()
+                }
+            }
+        }
+        let lines = match result {
+            Ok(lines) => lines,
+            Err(e) => {
+                self.options.report(Level::Warn, &self.path, bytes,
+                                    format_args!("{} \u{2717}", path_in));
+                // Failing to roll back must not hide why the file failed
+                if let Err(rollback_error) = rollback(&touched.borrow()) {
+                    error!("Could not roll the outputs of {} back : {}",
+                           self.path.display(), rollback_error);
                 }
-            )?;
-        println!("\u{2713} -> {}", prepared_path_out);
-        remove_file(&self.path)
+                return Err(e);
+            }
+        };
+        if empty {
+            let status = match self.options.empty_files {
+                EmptyFiles::Delete => "deleted".to_owned(),
+                EmptyFiles::Mark => marker.display().to_string()
+            };
+            self.options.report(Level::Info, &self.path, bytes, format_args!(
+                "{} (empty) -> {}", path_in, status));
+        } else if self.logtype.is_none() {
+            self.options.report(Level::Info, &self.path, bytes, format_args!(
+                "{} ? -> {}", path_in, unknown.display()));
+        } else {
+            self.options.report(Level::Debug, &self.path, bytes, format_args!(
+                "{} \u{2713} -> {} ({} bytes read)", path_in, prepared_path_out,
+                consumed.get()));
+        }
+        let mut written = written.into_inner();
+        let mut outputs: Vec<OutputStats> = touched.into_inner().into_keys()
+            .map(|path| written.remove(&path)
+                 .unwrap_or(OutputStats { path, bucket: None, lines: 0 }))
+            .collect();
+        outputs.sort_by(|a, b| a.path.cmp(&b.path));
+        set_output_mtimes(&outputs, metadata.modified().ok(), self.options);
+        if !self.options.keep_source {
+            remove_source(&self.path)?;
+        }
+        Ok(FileStats { logtype: self.logtype.clone(), empty, filtered: false, lines,
+                       bytes: consumed.get(), outputs })
     }
-}
-
-/// Process exactly one file using the `FileProcessor` structure
-///
-/// # Arguments
-///
-/// This method takes a [`&Path`](std::path::Path) and a
-/// [`PathBuf`](std::path::PathBuf). The former is a reference to the file
-/// path that will be turned into a `PathBuf` for the `FileProcessor`. The
-/// latter is simply the output path prefix for the processor.
-///
-/// # Errors
-///
-/// If anything fails during processing, the error will flow upwards.
-pub fn one_file(path: &Path, outroot: PathBuf) -> std::io::Result<()> {
-    // Building file processor
-    let mut proco = FileProcessor::new(path.to_path_buf(), outroot);
-    proco.determine_type()?;
-    proco.process()
-}
 
-/// Recursively process all of the files in an input directory
-///
-/// # Arguments
-/// This method takes two arguments :
-///  - a [`&Path`](std::path::Path) which is the root of the input directory
+    /// Print every line of the file along with its bucket, instead of
+    /// writing it to an output file
+    ///
+    /// Lines are bucketed just like [`FileProcessor::process`] would, and
+    /// written to `out` as `{bucket}\t{line}`, without their line ending.
+    /// Lines [`FileProcessor::process`] would write to `{outroot}.unknown`
+    /// are given `unknown` as their bucket, those it would write to
+    /// `{outroot}.excluded`, `excluded`, and those it would write to
+    /// `{outroot}.header` or `{outroot}.meta`, `header` or `meta`. The number
+    /// of lines is returned.
+    ///
+    /// Every line of a [`LogType::JournalExport`] record is given the bucket
+    /// of the record, its binary fields being printed as best as they can.
+    ///
+    /// The file is only read : neither it nor any output file is touched.
+    ///
+    /// # Errors
+    ///
+    /// Any error while reading the file or writing to `out` flows upwards.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use std::path::{Path, PathBuf};
+    /// # use logboop::process::FileProcessor;
+    /// # use logboop::options::Options;
+    /// let options = Options::default();
+    /// let mut processor = FileProcessor::new(PathBuf::from("huge.log"), PathBuf::new(),
+    ///                                        &options);
+    /// processor.determine_type()?;
+    /// processor.inspect(std::io::stdout().lock())?;
+    /// # Ok::<(), logboop::error::LogBoopError>(())
+    /// ```
+    pub fn inspect<W: Write>(&self, mut out: W) -> Result<u64, LogBoopError> {
+        let reference = modification_date(&self.path);
+        if let Some(logtype @ LogType::JournalExport) = &self.logtype {
+            let mut lines = 0;
+            for record in journal_records(open_input(&self.path, self.options.buffer_size)?) {
+                let record = record?;
+                let bucket = match line_bucket(logtype, &record.fields, &reference,
+                                               self.options) {
+                    Some(bucket) if self.options.in_date_range(&bucket.date) =>
+                        bucket.to_string(),
+                    Some(_) => "excluded".to_owned(),
+                    None => "unknown".to_owned()
+                };
+                for line in String::from_utf8_lossy(&record.raw).lines()
+                    .filter(|line| !line.is_empty()) {
+                    writeln!(out, "{}\t{}", bucket, line)?;
+                }
+                lines += record.lines;
+            }
+            out.flush()?;
+            return Ok(lines);
+        }
+        // Undated lines follow the last dated one, except in JSON logs and
+        // IIS directives
+        let continued = self.logtype.as_ref()
+            .is_some_and(|logtype| !(logtype.is_json() || *logtype == LogType::IisW3c));
+        let undated = match self.logtype {
+            Some(LogType::IisW3c) => "meta",
+            _ => "unknown"
+        };
+        let mut last: Option<Bucket> = None;
+        let mut bufr = open_input(&self.path, self.options.buffer_size)?;
+        let mut lines = 0;
+        if self.logtype.is_some() {
+            let (text, header_lines) = read_header(&mut bufr, self.options.header_lines)?;
+            for line in String::from_utf8_lossy(&text).lines() {
+                writeln!(out, "header\t{}", line)?;
+            }
+            lines += header_lines;
+        }
+        for line in lines_with_endings(bufr) {
+            let line = match line {
+                Ok((line, _)) => line,
+                Err(e) if e.kind() == std::io::ErrorKind::InvalidData => continue,
+                Err(e) => return Err(e.into())
+            };
+            let found = self.logtype.as_ref()
+                .and_then(|logtype| line_bucket(logtype, &line, &reference, self.options));
+            if found.is_some() {
+                last.clone_from(&found);
+            }
+            match found.or_else(|| last.clone().filter(|_| continued)) {
+                Some(bucket) if self.options.in_date_range(&bucket.date) =>
+                    writeln!(out, "{}\t{}", bucket, line)?,
+                Some(_) => writeln!(out, "excluded\t{}", line)?,
+                None => writeln!(out, "{}\t{}", undated, line)?
+            }
+            lines += 1;
+        }
+        out.flush()?;
+        Ok(lines)
+    }
+
+    /// Path of the `.progress` file of the file (see [`Options::resumable`])
+    fn progress_path(&self) -> PathBuf {
+        let mut path = self.outroot.clone();
+        add_extension(&mut path, "progress");
+        path
+    }
+
+    /// Split the lines of the file like [`split_lines_at`], saving how far
+    /// they were written to its `.progress` file at every checkpoint (see
+    /// [`split_lines_checkpointed`])
+    ///
+    /// Given the `progress` an interrupted run saved, the output files are
+    /// first cut back to the size they had then (see [`Progress::restore`]),
+    /// and the file is read from there on. The lines returned count those
+    /// written before the interruption, for [`Options::verify`], while the
+    /// bytes counted in `consumed` are only those read now.
+    fn split_resumable<W, F>(&self, logtype: &LogType, reference: &NaiveDate,
+                             progress: Option<&Progress>, consumed: &Rc<Cell<u64>>,
+                             touched: &RefCell<HashMap<PathBuf, Option<u64>>>, open: F)
+        -> Result<u64, LogBoopError>
+        where W: BucketWrite, F: FnMut(Option<&Bucket>) -> Result<W, LogBoopError> {
+        let (offset, skipped) = match progress {
+            Some(progress) => {
+                progress.restore()?;
+                info!("Resuming {} from byte {} (line {})", self.path.display(),
+                      progress.offset, progress.lines);
+                (progress.offset, progress.lines)
+            },
+            // Past the header lines, already read
+            None => (consumed.get(), 0)
+        };
+        let bufr = CountingReader { inner: open_input_at(&self.path, offset, self.options.buffer_size)?,
+                                    consumed: Rc::clone(consumed) };
+        let path = &self.progress_path();
+        let mut checkpoint = |read: u64, lines: u64| -> Result<(), LogBoopError> {
+            let outputs = touched.borrow().keys()
+                .map(|output| Ok((output.metadata()?.len(), output.clone())))
+                .collect::<std::io::Result<Vec<_>>>()?;
+            Progress { input: self.path.clone(), offset: offset + read,
+                       lines: skipped + lines, outputs }.save(path)
+        };
+        let lines = split_lines_checkpointed(logtype, reference, bufr, self.options, open,
+                                             Some(&mut checkpoint))?;
+        Ok(skipped + lines)
+    }
+
+    /// Open an output file in append mode, compressed or not
+    ///
+    /// The parent directories of the file are created as needed (see
+    /// [`Options::dir_mode`]), and the
+    /// size the file had before is recorded in `touched` (see [`rollback`]).
+    /// A file created here gets the permissions of the input file (see
+    /// [`copy_permissions`]). The lines written to it, for `bucket`, are
+    /// counted in `written` once its bucket is finished.
+    fn open_output<'w>(&self, mut fname: PathBuf, compressed: bool, bucket: Option<Bucket>,
+                       written: &'w RefCell<HashMap<PathBuf, OutputStats>>,
+                       touched: &RefCell<HashMap<PathBuf, Option<u64>>>)
+        -> Result<CountingWriter<'w>, LogBoopError> {
+        if compressed {
+            add_extension(&mut fname, self.options.codec.extension());
+        }
+        // Ensure that the directory containing that output exists
+        if let Some(parent) = fname.parent() {
+            create_dirs(parent, self.options.dir_mode)?;
+        }
+        let created = !fname.exists();
+        touched.borrow_mut().entry(fname.clone())
+            .or_insert_with(|| fname.metadata().ok().map(|m| m.len()));
+        let writer = BufWriter::with_capacity(
+            self.options.buffer_size,
+            OpenOptions::new()
+                .append(true)
+                .create(true)
+                .open(&fname)?);
+        // New outputs are no more readable than the file they come from
+        if created {
+            copy_permissions(&self.path, &fname)?;
+        }
+        // Appending to an existing compressed file adds a GZ member
+        // (or a Zstandard frame, or a bzip2 stream)
+        let inner = match (compressed, self.options.codec) {
+            (false, _) => OutputWriter::Plain(writer),
+            (true, Codec::Gzip) =>
+                OutputWriter::Gzip(GzEncoder::new(writer, self.options.compression)),
+            (true, Codec::Zstd) =>
+                OutputWriter::Zstd(ZstdEncoder::new(writer, 0)?),
+            (true, Codec::Bzip2) =>
+                OutputWriter::Bzip2(BzEncoder::new(writer, bzip2::Compression::default()))
+        };
+        Ok(CountingWriter { inner, path: fname, bucket, lines: 0, written })
+    }
+}
+
+/// Read the first `count` lines of a reader, as they are, leaving the reader
+/// right after them
+///
+/// The lines read are returned along with their number, which is lower than
+/// `count` for shorter readers.
+fn read_header<R: BufRead>(reader: &mut R, count: usize) -> std::io::Result<(Vec<u8>, u64)> {
+    let mut text = Vec::new();
+    let mut lines = 0;
+    while lines < count as u64 && reader.read_until(b'\n', &mut text)? != 0 {
+        lines += 1;
+    }
+    Ok((text, lines))
+}
+
+/// Copy the lines of a reader as they are, ending them with `\n` unless
+/// [`Options::keep_line_endings`] is set, and finish the bucket of the writer
+///
+/// The number of lines copied is returned.
+fn copy_lines<R: BufRead, W: BucketWrite>(reader: R, mut writer: W, options: &Options)
+    -> Result<u64, LogBoopError> {
+    let lines = reader.split(b'\n').try_fold(0, |lines, line|
+        -> Result<u64, LogBoopError> {
+        let line = line?;
+        let line = match line.strip_suffix(b"\r") {
+            Some(stripped) if !options.keep_line_endings => stripped,
+            _ => &line[..]
+        };
+        writer.write_all(line)?;
+        writer.write_all(b"\n")?;
+        Ok(lines + 1)
+    })?;
+    writer.finish_bucket()?;
+    Ok(lines)
+}
+
+/// Give output files the modification time of [`Options::output_mtime`]
+///
+/// `source` is the modification time of the input file written to them,
+/// if known. Failing to change it is only warned about, since the lines are
+/// written all the same.
+fn set_output_mtimes(outputs: &[OutputStats], source: Option<SystemTime>, options: &Options) {
+    for output in outputs {
+        let mtime = match (options.output_mtime, &output.bucket) {
+            (OutputMtime::Now, _) | (OutputMtime::Bucket, None) => None,
+            (OutputMtime::Bucket, Some(bucket)) => Some(bucket.end(options.timezone).into()),
+            (OutputMtime::Source, _) => source
+        };
+        let result = match mtime {
+            Some(mtime) => OpenOptions::new().write(true).open(&output.path)
+                .and_then(|file| file.set_modified(mtime)),
+            None => Ok(())
+        };
+        if let Err(e) = result {
+            warn!("Could not date {} : {}", output.path.display(), e);
+        }
+    }
+}
+
+/// Restore output files to the size they had before processing a file
+///
+/// # Arguments
+///
+/// This method receives a map from the paths of output files to their size
+/// before they were touched, or `None` if they did not exist.
+///
+/// # Behaviour
+///
+/// Files which existed are truncated back to their former size (lines are
+/// only ever appended to them), and files which did not exist are removed.
+///
+/// # Errors
+///
+/// Any I/O error while truncating or removing flows upwards. Files which
+/// have disappeared in the meantime are not an error.
+fn rollback(touched: &HashMap<PathBuf, Option<u64>>) -> std::io::Result<()> {
+    touched.iter().try_for_each(|(path, size)| {
+        let result = match size {
+            Some(len) => OpenOptions::new().write(true).open(path)
+                .and_then(|f| f.set_len(*len)),
+            None => remove_file(path)
+        };
+        match result {
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            other => other
+        }
+    })
+}
+
+/// How far an input file was split, as saved in its `{outroot}.progress`
+/// file with [`Options::resumable`]
+///
+/// The file holds the path of the input file on its first line, then the
+/// number of bytes of it which were read and of lines which were written,
+/// separated by a space, and then the size and path of every output file,
+/// one per line.
+#[derive(Debug)]
+struct Progress {
+    /// The input file being split
+    input: PathBuf,
+    /// Bytes of the input file read (once inflated), up to the end of the
+    /// last line written
+    offset: u64,
+    /// Lines of the input file written
+    lines: u64,
+    /// Size of every output file written to, once those lines were
+    outputs: Vec<(u64, PathBuf)>
+}
+
+impl Progress {
+    /// Read a `.progress` file, if there is one
+    ///
+    /// # Errors
+    /// I/O errors flow upwards, and a file which cannot be read as progress
+    /// is an [`InvalidData`](std::io::ErrorKind::InvalidData) error.
+    fn load(path: &Path) -> std::io::Result<Option<Progress>> {
+        let content = match std::fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e)
+        };
+        let invalid = || std::io::Error::new(std::io::ErrorKind::InvalidData,
+                                             format!("invalid progress in {}", path.display()));
+        let mut lines = content.lines();
+        let input = PathBuf::from(lines.next().ok_or_else(invalid)?);
+        let (offset, read) = lines.next().and_then(|line| line.split_once(' '))
+            .ok_or_else(invalid)?;
+        let outputs = lines.map(|line| line.split_once(' ')
+                                .and_then(|(size, output)| Some((size.parse().ok()?,
+                                                                 PathBuf::from(output))))
+                                .ok_or_else(invalid))
+            .collect::<std::io::Result<_>>()?;
+        Ok(Some(Progress {
+            input,
+            offset: offset.parse().map_err(|_| invalid())?,
+            lines: read.parse().map_err(|_| invalid())?,
+            outputs
+        }))
+    }
+
+    /// Write a `.progress` file, through a `.tmp` file renamed into place, so
+    /// that an interruption never leaves half of it
+    fn save(&self, path: &Path) -> Result<(), LogBoopError> {
+        let mut temporary = path.to_path_buf();
+        add_extension(&mut temporary, "tmp");
+        let mut out = BufWriter::new(File::create(&temporary)?);
+        writeln!(out, "{}\n{} {}", self.input.display(), self.offset, self.lines)?;
+        for (size, output) in &self.outputs {
+            writeln!(out, "{} {}", size, output.display())?;
+        }
+        out.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+        std::fs::rename(&temporary, path)?;
+        Ok(())
+    }
+
+    /// Cut the output files back to their size at the checkpoint, removing
+    /// what was written to them after it
+    ///
+    /// # Errors
+    /// An output file which is missing, or smaller than it was at the
+    /// checkpoint, means the outputs were changed since, and resuming would
+    /// lose lines : this is an [`InvalidData`](std::io::ErrorKind::InvalidData)
+    /// error, and the output files are left as they are.
+    fn restore(&self) -> std::io::Result<()> {
+        for (size, output) in &self.outputs {
+            if output.metadata()?.len() < *size {
+                return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, format!(
+                    "{} is smaller than when its progress was saved, not resuming",
+                    output.display())));
+            }
+        }
+        self.outputs.iter().try_for_each(|(size, output)|
+            OpenOptions::new().write(true).open(output)?.set_len(*size))
+    }
+}
+
+/// Determine the log type of a single line
+///
+/// Every known [`LogType`] is tried in turn, and the first one whose regex
+/// matches the line is returned. If none match, `None` is returned.
+///
+/// [`LogType::Json`] lines must also hold a timestamp in one of the
+/// default fields (see [`json::DEFAULT_TIME_FIELDS`]).
+///
+/// Names of months and days are recognized whatever their case, and lines
+/// may be indented.
+///
+/// # Example
+/// ```
+/// # use logboop::process::{detect_line, LogType};
+/// assert_eq!(detect_line("2020-05-17 02:07:16 some message"),
+///            Some(LogType::Iso));
+/// assert_eq!(detect_line(r#"{"ts":"2020-05-17T02:07:16Z","msg":"hi"}"#),
+///            Some(LogType::Json));
+/// assert_eq!(detect_line(r#"{"msg":"no date"}"#), None);
+/// assert_eq!(detect_line("=== log rotated ==="), None);
+/// assert_eq!(detect_line("  OCT 11 22:14:15 host message"), Some(LogType::Syslog));
+/// ```
+pub fn detect_line(line: &str) -> Option<LogType> {
+    detect_line_with(line, &json::DEFAULT_TIME_FIELDS, &[])
+}
+
+/// The log type a file name tells, if any
+///
+/// Once its compression extension (`.gz`, `.zst`, `.bz2` or `.xz`) and its
+/// rotation number (like `.1`) are set aside, the last extension of the
+/// name is taken as a log type (see [`Options::find_type`]), so that files
+/// of formats which cannot be recognized can be renamed to be handled.
+///
+/// ```
+/// # use std::path::Path;
+/// # use logboop::options::Options;
+/// # use logboop::process::{type_hint, LogType};
+/// let options = Options::default();
+/// assert_eq!(type_hint(Path::new("in/app.syslog.1"), &options), Some(LogType::Syslog));
+/// assert_eq!(type_hint(Path::new("in/app.iso.3.gz"), &options), Some(LogType::Iso));
+/// assert_eq!(type_hint(Path::new("in/app.log.1"), &options), None);
+/// // A name made of a log type only is no hint
+/// assert_eq!(type_hint(Path::new("in/syslog.1"), &options), None);
+/// ```
+pub fn type_hint(path: &Path, options: &Options) -> Option<LogType> {
+    let mut parts: Vec<&str> = path.file_name()?.to_str()?.split('.').collect();
+    if parts.last().is_some_and(|ext| matches!(*ext, "gz" | "zst" | "bz2" | "xz")) {
+        parts.pop();
+    }
+    if parts.last().is_some_and(|ext| NUMBER_REGEX.is_match(ext)) {
+        parts.pop();
+    }
+    match parts.as_slice() {
+        [_, .., hint] => options.find_type(hint).ok(),
+        _ => None
+    }
+}
+
+/// Whether a line matches a given log type, whatever the other log types
+/// it matches
+fn matches_type(lt: &LogType, line: &str, options: &Options) -> bool {
+    match lt {
+        LogType::Custom(name) => custom_format(&options.formats, name)
+            .is_some_and(|format| format.regex.is_match(line)),
+        LogType::Json => FORMATS[lt].regex.is_match(line)
+            && json::timestamp(line, &options.json_time_fields).is_some(),
+        _ => FORMATS[lt].regex.is_match(line)
+    }
+}
+
+/// Determine the log type of a single line, given the fields holding the
+/// timestamp of [`LogType::Json`] lines and the custom formats, tried first
+fn detect_line_with<S: AsRef<str>>(line: &str, fields: &[S],
+                                   formats: &[CustomFormat]) -> Option<LogType> {
+    if let Some(format) = formats.iter().find(|format| format.regex.is_match(line)) {
+        return Some(LogType::Custom(format.name.clone()));
+    }
+    DETECTION_ORDER.iter()
+        .find(|logtype| FORMATS[logtype].regex.is_match(line)
+              && (**logtype != LogType::Json
+                  || json::timestamp(line, fields).is_some()))
+        .cloned()
+}
+
+/// Split lines of a given log type into writers keyed by bucket
+///
+/// # Arguments
+///
+/// This method takes :
+///  - a reference to the [`LogType`] of the lines
+///  - a [`BufRead`] source from which the lines are read
+///  - the [`Options`] of the run
+///  - a closure that, given a [`Bucket`], opens the writer in which
+///    the lines of that bucket must be written, or given `None`, the writer
+///    in which lines that cannot be dated must be written
+///
+/// # Behaviour
+///
+/// Every line is dated like [`parse_bucket`] does, at the
+/// [`Options::granularity`] of the run, and written to the writer of its
+/// bucket. Writers are kept open, so that lines going back and forth between
+/// buckets (like those of multi-threaded programs) do not reopen them all the
+/// time : the closure is only called for a bucket without one. Once
+/// [`Options::max_open_files`] writers are open, the least recently used one
+/// is finished (see [`BucketWrite`]) to make room, and its bucket gets a new
+/// writer from the closure if it comes back.
+///
+/// Lines for which no date can be determined (those not matching the log
+/// type, or holding an invalid date) are written to the writer of the last
+/// dated line. Until the first dated line, there is none, and those lines
+/// are written to the writer opened by the closure with `None`. Lines of
+/// JSON log types (see [`LogType::is_json`]) are whole records, never
+/// continuations : those without a date always go to that writer, and so do
+/// the directives of [`LogType::IisW3c`] files.
+///
+/// [`LogType::JournalExport`] files are read record by record instead of
+/// line by line : every record is dated by its `__REALTIME_TIMESTAMP`, and
+/// written whole (binary fields included) to the writer of its bucket, or
+/// to the writer opened with `None` when it has none. Its lines are counted
+/// as read.
+///
+/// [`LogType::Json`] lines are dated with [`Options::json_time_fields`], and
+/// [`LogType::Custom`] lines with the format of that name in
+/// [`Options::formats`]. With [`Options::mixed`], lines not matching
+/// `logtype` are dated by whichever log type they match (see [`detect_line`]).
+///
+/// Lines are written as rendered by [`render_line`], followed by `\n`
+/// whatever their line ending was (unless [`Options::keep_line_endings`] is
+/// set), and their number is returned. Line endings are never part of what
+/// is matched and dated.
+///
+/// This is the in-memory core of [`FileProcessor::process`], which only
+/// provides a closure opening files in append mode. Nothing stops you from
+/// giving it `Vec<u8>`s or [`std::io::sink`] instead.
+///
+/// # Errors
+///
+/// Any error returned by the closure or by a writer flows upwards. Lines
+/// that cannot be read (invalid UTF-8, for example) are skipped.
+///
+/// Lines giving no year are dated relative to today (see [`infer_year`]), use
+/// [`split_lines_at`] to choose another reference date.
+///
+/// # Example
+/// ```
+/// # use logboop::process::{split_lines, LogType};
+/// # use logboop::options::Options;
+/// let input = "2020-05-17 a\n2020-05-18 b\n".as_bytes();
+/// let mut dates = Vec::new();
+/// split_lines(&LogType::Iso, input, &Options::default(), |bucket| {
+///     dates.extend(bucket.map(ToString::to_string));
+///     Ok(std::io::sink())
+/// })?;
+/// assert_eq!(dates, vec!["2020-05-17", "2020-05-18"]);
+/// # Ok::<(), logboop::error::LogBoopError>(())
+/// ```
+pub fn split_lines<R, W, F>(logtype: &LogType, reader: R, options: &Options,
+                            open: F) -> Result<u64, LogBoopError>
+    where R: BufRead, W: BucketWrite,
+          F: FnMut(Option<&Bucket>) -> Result<W, LogBoopError>
+{
+    split_lines_at(logtype, &today(), reader, options, open)
+}
+
+/// Split lines of a given log type into writers keyed by bucket, relative to
+/// a reference date
+///
+/// This is [`split_lines`], where lines giving no year are assumed to be
+/// from the twelve months up to `reference` (see [`infer_year`]).
+/// [`FileProcessor::process`] gives the modification date of the file.
+///
+/// # Example
+/// ```
+/// # use logboop::process::{split_lines_at, LogType};
+/// # use logboop::options::Options;
+/// let input = "Dec 31 23:59:59 host a\nJan  1 00:00:01 host b\n".as_bytes();
+/// let reference = chrono::NaiveDate::from_ymd_opt(2021, 1, 2).unwrap();
+/// let mut dates = Vec::new();
+/// split_lines_at(&LogType::Syslog, &reference, input, &Options::default(), |bucket| {
+///     dates.extend(bucket.map(ToString::to_string));
+///     Ok(std::io::sink())
+/// })?;
+/// assert_eq!(dates, vec!["2020-12-31", "2021-01-01"]);
+/// # Ok::<(), logboop::error::LogBoopError>(())
+/// ```
+pub fn split_lines_at<R, W, F>(logtype: &LogType, reference: &NaiveDate,
+                               reader: R, options: &Options, open: F)
+    -> Result<u64, LogBoopError>
+    where R: BufRead, W: BucketWrite,
+          F: FnMut(Option<&Bucket>) -> Result<W, LogBoopError>
+{
+    split_lines_checkpointed(logtype, reference, reader, options, open, None)
+}
+
+/// Number of bytes of input read between two checkpoints of a resumable file
+/// (see [`Options::resumable`])
+const CHECKPOINT_BYTES: u64 = 64 << 20;
+
+/// Callback told how many bytes of input were read, up to the end of the last
+/// line written, and how many lines were written, once all of them are
+/// flushed to their writers
+type Checkpoint<'c> = &'c mut dyn FnMut(u64, u64) -> Result<(), LogBoopError>;
+
+/// Output files of [`split_lines_checkpointed`] along the way : those of
+/// buckets, from the least to the most recently used, the one of undated
+/// lines, and the number of lines read
+type SplitState<'o, W> = (Vec<(Bucket, LineOutput<'o, W>)>, Option<LineOutput<'o, W>>, u64);
+
+/// Split lines like [`split_lines_at`], checkpointing how far they were
+/// written if asked to
+///
+/// `checkpoint` is called, once every open writer is flushed, right after a
+/// writer is opened (before anything is written to it), and then every
+/// [`CHECKPOINT_BYTES`] of input, so that what was written up to the last
+/// checkpoint is always known.
+fn split_lines_checkpointed<'o, R, W, F>(logtype: &'o LogType, reference: &NaiveDate,
+                                         reader: R, options: &'o Options, mut open: F,
+                                         mut checkpoint: Option<Checkpoint<'_>>)
+    -> Result<u64, LogBoopError>
+    where R: BufRead, W: BucketWrite,
+          F: FnMut(Option<&Bucket>) -> Result<W, LogBoopError>
+{
+    let capacity = options.max_open_files.max(1);
+    let consumed = Rc::new(Cell::new(0));
+    let reader = CountingReader { inner: reader, consumed: Rc::clone(&consumed) };
+    // Bytes read up to the end of the last line written, and at the last
+    // checkpoint
+    let (mut done, mut saved) = (0, 0);
+    // Open writers, from the least to the most recently used
+    let mut step = |(mut writers, mut unknown, lines): SplitState<'o, W>,
+                    bucket: Option<Bucket>, chunk: Chunk|
+        -> Result<_, LogBoopError> {
+        if let Some(ref bucket) = bucket {
+            match writers.iter().position(|(open, _)| open == bucket) {
+                Some(index) if index + 1 == writers.len() => (),
+                Some(index) => {
+                    let used = writers.remove(index);
+                    writers.push(used);
+                },
+                None => {
+                    if writers.len() == capacity {
+                        writers.remove(0).1.finish_bucket()?;
+                    }
+                    let writer = LineOutput::new(open(Some(bucket))?, Some(bucket.date),
+                                                 logtype, options);
+                    writers.push((bucket.clone(), writer));
+                    if let Some(save) = checkpoint.as_mut() {
+                        flush_then_save(writers.iter_mut().map(|(_, w)| w)
+                                        .chain(unknown.as_mut()),
+                                        &mut **save, done, lines)?;
+                        saved = done;
+                    }
+                }
+            }
+        }
+        // Write
+        let dated = bucket.is_some()
+            || !(logtype.is_json()
+                 || matches!(logtype, LogType::JournalExport | LogType::IisW3c));
+        if (writers.is_empty() || !dated) && unknown.is_none() {
+            unknown = Some(LineOutput::new(open(None)?, None, logtype, options));
+            if let Some(save) = checkpoint.as_mut() {
+                flush_then_save(writers.iter_mut().map(|(_, w)| w)
+                                .chain(unknown.as_mut()),
+                                &mut **save, done, lines)?;
+                saved = done;
+            }
+        }
+        let writer = match (writers.last_mut(), unknown.as_mut()) {
+            (Some((_, writer)), _) if dated => writer,
+            (_, Some(writer)) => writer,
+            (Some(_), None) | (None, None) =>
+                unreachable!("lines always have a writer opened above")
+        };
+        let lines = match chunk {
+            Chunk::Line(line, ending) => {
+                writer.write_line(line, ending)?;
+                lines + 1
+            },
+            Chunk::Record(record) => {
+                writer.write_all(&record.raw)?;
+                lines + record.lines
+            }
+        };
+        done = consumed.get();
+        if let Some(save) = checkpoint.as_mut() {
+            if done - saved >= CHECKPOINT_BYTES {
+                flush_then_save(writers.iter_mut().map(|(_, w)| w)
+                                .chain(unknown.as_mut()),
+                                &mut **save, done, lines)?;
+                saved = done;
+            }
+        }
+        Ok((writers, unknown, lines))
+    };
+    let start = (Vec::with_capacity(capacity), None, 0);
+    let (writers, unknown, lines) = if *logtype == LogType::JournalExport {
+        // Records are dated by their own timestamp, whichever line it is on
+        journal_records(reader).try_fold(start, |state, record| {
+            let record = record?;
+            let bucket = checked_bucket(logtype, &record.fields, reference, options)?;
+            step(state, bucket, Chunk::Record(record))
+        })?
+    } else {
+        lines_with_endings(reader)
+            .filter_map(|line| match line {
+                Ok((l, ending)) => Some(checked_bucket(logtype, &l, reference, options)
+                                        .map(|bucket| (bucket, l, ending))),
+                // Lines which are not UTF-8 are skipped, but a failing read
+                // (like a corrupted compressed input) stops everything
+                Err(e) if e.kind() == std::io::ErrorKind::InvalidData => None,
+                Err(e) => Some(Err(e.into()))
+            })
+            .try_fold(start, |state, read| {
+                let (bucket, line, ending) = read?;
+                step(state, bucket, Chunk::Line(line, ending))
+            })?
+    };
+    if let Some(w) = unknown {
+        w.finish_bucket()?;
+    }
+    writers.into_iter().try_for_each(|(_, w)| w.finish_bucket())?;
+    Ok(lines)
+}
+
+/// An output file of [`split_lines_checkpointed`], writing lines as rendered
+/// by [`render_line`]
+///
+/// With [`Options::dedup`], a line identical to the one written before it
+/// is not written again : the number of such repeats is written instead,
+/// as a `... (repeated N times)` line, once a different line comes, and
+/// whenever the writer is flushed or finished.
+struct LineOutput<'o, W> {
+    /// Where the lines are written
+    inner: W,
+    /// Date of the bucket of the file, if it has one
+    date: Option<NaiveDate>,
+    /// Log type of the lines
+    logtype: &'o LogType,
+    /// Settings of the run
+    options: &'o Options,
+    /// Last line written, with [`Options::dedup`], along with its line ending
+    last: Option<(String, &'static str)>,
+    /// Number of times the last line was repeated since it was written
+    repeats: u64
+}
+
+impl<'o, W: BucketWrite> LineOutput<'o, W> {
+    fn new(inner: W, date: Option<NaiveDate>, logtype: &'o LogType, options: &'o Options)
+        -> LineOutput<'o, W> {
+        LineOutput { inner, date, logtype, options, last: None, repeats: 0 }
+    }
+
+    /// Write a line, without its ending, and that ending
+    fn write_line(&mut self, line: String, ending: &'static str) -> std::io::Result<()> {
+        let ending = match ending {
+            "\r\n" if self.options.keep_line_endings => ending,
+            _ => "\n"
+        };
+        if self.options.dedup {
+            if self.last.as_ref().is_some_and(|(last, _)| *last == line) {
+                self.repeats += 1;
+                return Ok(());
+            }
+            self.write_repeats()?;
+        }
+        write!(self.inner, "{}{}",
+               render_line(self.logtype, &line, self.date.as_ref(), self.options), ending)?;
+        if self.options.dedup {
+            self.last = Some((line, ending));
+        }
+        Ok(())
+    }
+
+    /// Write how many times the last line was repeated, if it was
+    ///
+    /// The line is then forgotten, so that a run of repeats cut here (by a
+    /// checkpoint flushing the writer) starts again with the line itself,
+    /// rather than with a second marker.
+    fn write_repeats(&mut self) -> std::io::Result<()> {
+        if let (Some((_, ending)), 1..) = (&self.last, self.repeats) {
+            let marker = format!("... (repeated {} times)", self.repeats);
+            write!(self.inner, "{}{}",
+                   render_line(self.logtype, &marker, self.date.as_ref(), self.options),
+                   ending)?;
+            self.last = None;
+        }
+        self.repeats = 0;
+        Ok(())
+    }
+}
+
+impl<W: BucketWrite> Write for LineOutput<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.inner.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.write_repeats()?;
+        self.inner.flush()
+    }
+}
+
+impl<W: BucketWrite> BucketWrite for LineOutput<'_, W> {
+    fn finish_bucket(mut self) -> std::io::Result<()> {
+        self.write_repeats()?;
+        self.inner.finish_bucket()
+    }
+}
+
+/// What [`split_lines_checkpointed`] writes at once to an output file
+enum Chunk {
+    /// A line, without its ending, and that ending
+    Line(String, &'static str),
+    /// A whole [`LogType::JournalExport`] record
+    Record(JournalRecord)
+}
+
+/// A record of the export format of the systemd journal (see
+/// [`LogType::JournalExport`])
+#[derive(Default)]
+struct JournalRecord {
+    /// The record as it was read, binary fields included, ending with an
+    /// empty line
+    raw: Vec<u8>,
+    /// The text fields of the record, one per line, which is what it is
+    /// dated by
+    fields: String,
+    /// Number of lines the record was read from
+    lines: u64
+}
+
+/// Read the records of a reader in the export format of the systemd journal
+///
+/// Records end with an empty line, or with the reader. Binary fields are
+/// read whole, according to their length, so that their value may hold
+/// line feeds or bytes which are not UTF-8. A record ended by the reader is
+/// given the empty line it lacks, so that records appended after it are
+/// kept apart.
+fn journal_records<R: BufRead>(mut reader: R)
+    -> impl Iterator<Item = std::io::Result<JournalRecord>> {
+    std::iter::from_fn(move || {
+        let mut record = JournalRecord::default();
+        loop {
+            let start = record.raw.len();
+            match reader.read_until(b'\n', &mut record.raw) {
+                Ok(0) if record.raw.is_empty() => return None,
+                Ok(0) => {
+                    if !record.raw.ends_with(b"\n") {
+                        record.raw.push(b'\n');
+                    }
+                    record.raw.push(b'\n');
+                    return Some(Ok(record));
+                },
+                Ok(_) => record.lines += 1,
+                Err(e) => return Some(Err(e))
+            }
+            let field = &record.raw[start..];
+            let field = field.strip_suffix(b"\n").unwrap_or(field);
+            if field.is_empty() {
+                return Some(Ok(record));
+            }
+            if field.contains(&b'=') {
+                if let Ok(field) = std::str::from_utf8(field) {
+                    record.fields.push_str(field);
+                    record.fields.push('\n');
+                }
+                continue;
+            }
+            // A binary field : its length, its value, and a line feed
+            let value = record.raw.len();
+            let mut size = [0; 8];
+            if let Err(e) = reader.read_exact(&mut size) {
+                return Some(Err(e));
+            }
+            record.raw.extend_from_slice(&size);
+            let size = u64::from_le_bytes(size).saturating_add(1);
+            match reader.by_ref().take(size).read_to_end(&mut record.raw) {
+                Ok(read) if read as u64 == size => (),
+                Ok(_) => return Some(Err(std::io::ErrorKind::UnexpectedEof.into())),
+                Err(e) => return Some(Err(e))
+            }
+            record.lines += record.raw[value..].iter().filter(|b| **b == b'\n').count() as u64;
+        }
+    })
+}
+
+/// Flush writers, then call a checkpoint with how far lines were read and
+/// written (see [`split_lines_checkpointed`])
+fn flush_then_save<'w, W: Write + 'w>(writers: impl Iterator<Item = &'w mut W>,
+                                      checkpoint: &mut dyn FnMut(u64, u64)
+                                          -> Result<(), LogBoopError>,
+                                      read: u64, lines: u64)
+    -> Result<(), LogBoopError> {
+    for writer in writers {
+        writer.flush()?;
+    }
+    checkpoint(read, lines)
+}
+
+/// Reader counting the bytes consumed from it, in a counter shared with
+/// whoever wants to know how far it went
+///
+/// It wraps any reader, so that what is counted is what was actually read,
+/// whether the file behind it is compressed or not.
+struct CountingReader<R> {
+    /// Where the bytes are actually read from
+    inner: R,
+    /// Bytes read or consumed so far
+    consumed: Rc<Cell<u64>>
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.consumed.set(self.consumed.get() + n as u64);
+        Ok(n)
+    }
+}
+
+impl<R: BufRead> BufRead for CountingReader<R> {
+    fn fill_buf(&mut self) -> std::io::Result<&[u8]> {
+        self.inner.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.inner.consume(amt);
+        self.consumed.set(self.consumed.get() + amt as u64);
+    }
+}
+
+/// Find the bucket of a line of a given log type, at the granularity and in
+/// the time zone of the run
+///
+/// With [`Options::mixed`], a line holding no date of that log type is dated
+/// by the first log type it matches instead, custom formats first. With
+/// [`Options::split_vhosts`], [`LogType::ApacheVhost`] lines are also
+/// bucketed by virtual host.
+fn line_bucket(logtype: &LogType, line: &str, reference: &NaiveDate, options: &Options)
+    -> Option<Bucket> {
+    let bucket = |logtype: &LogType| find_bucket(
+        logtype, line, options.granularity, options.timezone, reference,
+        &options.json_time_fields, &options.formats)
+        .map(|bucket| match logtype {
+            LogType::ApacheVhost if options.split_vhosts =>
+                Bucket { vhost: parse_vhost(line).map(str::to_owned), ..bucket },
+            _ => bucket
+        });
+    bucket(logtype).or_else(|| {
+        if !options.mixed {
+            return None;
+        }
+        detect_line_with(line, &options.json_time_fields, &options.formats)
+            .filter(|detected| detected != logtype)
+            .and_then(|detected| bucket(&detected))
+    })
+}
+
+/// Find the bucket of a line like [`line_bucket`], failing under
+/// [`Options::strict`] when the line follows `logtype` but yields no bucket
+///
+/// Lines not following `logtype` at all are left to the caller, which files
+/// them with the line before them.
+fn checked_bucket(logtype: &LogType, line: &str, reference: &NaiveDate, options: &Options)
+    -> Result<Option<Bucket>, LogBoopError> {
+    let bucket = line_bucket(logtype, line, reference, options);
+    if bucket.is_none() && options.strict && matches_type(logtype, line, options) {
+        return Err(parse_error(logtype, line));
+    }
+    Ok(bucket)
+}
+
+/// Read the lines of a reader, along with their line ending
+///
+/// Lines are given without their ending (`\r\n`, `\n`, or nothing for the
+/// last line of a file not ending with one), which is given next to them.
+/// Just like [`BufRead::lines`], lines which are not UTF-8 yield an
+/// [`InvalidData`](std::io::ErrorKind::InvalidData) error, and reading goes on
+/// after them.
+fn lines_with_endings<R: BufRead>(mut reader: R)
+    -> impl Iterator<Item = std::io::Result<(String, &'static str)>> {
+    std::iter::from_fn(move || {
+        let mut line = String::new();
+        match reader.read_line(&mut line) {
+            Ok(0) => None,
+            Ok(_) => {
+                let ending = if line.ends_with("\r\n") {
+                    "\r\n"
+                } else if line.ends_with('\n') {
+                    "\n"
+                } else {
+                    ""
+                };
+                line.truncate(line.len() - ending.len());
+                Some(Ok((line, ending)))
+            },
+            Err(e) => Some(Err(e))
+        }
+    })
+}
+
+/// Render a line the way it must be written in its output file
+///
+/// # Behaviour
+///
+/// Depending on [`Options::output_format`] :
+///  - with [`OutputFormat::Raw`], the line is kept as is
+///  - with [`OutputFormat::Ndjson`], lines of JSON log types (see
+///    [`LogType::is_json`]) are normalized into one JSON record : objects are
+///    stripped of surrounding whitespace, and anything else is wrapped into a
+///    `{"line": ...}` record. Lines of other log types are wrapped into a
+///    `{"date": ..., "type": ..., "line": ...}` record, with the `date` they
+///    were filed under (`null` for lines kept aside as unknown) and the
+///    name of `logtype`.
+///
+/// With [`Options::unwrap_docker`], [`LogType::DockerJson`] lines are
+/// stripped of their envelope instead, whatever the output format : only
+/// their `log`, without its trailing line feed, is kept. Lines without a
+/// `log` are kept as is.
+///
+/// # Example
+/// ```
+/// # use logboop::process::{render_line, LogType};
+/// # use logboop::options::{Options, OutputFormat};
+/// let options = Options { output_format: OutputFormat::Ndjson, ..Options::default() };
+/// assert_eq!(render_line(&LogType::EcsJson, "  {\"a\":1}  ", None, &options), "{\"a\":1}");
+/// assert_eq!(render_line(&LogType::EcsJson, "oops", None, &options), "{\"line\":\"oops\"}");
+/// let date = chrono::NaiveDate::from_ymd_opt(2023, 10, 11);
+/// assert_eq!(render_line(&LogType::Iso, "2023-10-11 \"up\"", date.as_ref(), &options),
+///            r#"{"date":"2023-10-11","type":"Iso","line":"2023-10-11 \"up\""}"#);
+///
+/// let options = Options { unwrap_docker: true, ..Options::default() };
+/// let line = r#"{"log":"done\n","stream":"stdout","time":"2023-10-11T13:55:36.123456789Z"}"#;
+/// assert_eq!(render_line(&LogType::DockerJson, line, None, &options), "done");
+/// ```
+pub fn render_line<'l>(logtype: &LogType, line: &'l str, date: Option<&NaiveDate>,
+                       options: &Options) -> Cow<'l, str> {
+    if options.unwrap_docker && *logtype == LogType::DockerJson {
+        if let Some(log) = json::string_field(line, json::DOCKER_LOG_FIELD) {
+            let mut log = log;
+            if log.ends_with('\n') {
+                log.pop();
+            }
+            return Cow::Owned(log);
+        }
+    }
+    match options.output_format {
+        OutputFormat::Ndjson if logtype.is_json() => {
+            let trimmed = line.trim();
+            if trimmed.starts_with('{') && trimmed.ends_with('}') {
+                Cow::Borrowed(trimmed)
+            } else {
+                Cow::Owned(format!("{{\"line\":{}}}", json::escape(line)))
+            }
+        },
+        OutputFormat::Ndjson => Cow::Owned(format!(
+            "{{\"date\":{},\"type\":{},\"line\":{}}}",
+            date.map_or_else(|| "null".to_owned(), |date| format!("\"{}\"", date)),
+            json::escape(logtype.name()), json::escape(line))),
+        OutputFormat::Raw => Cow::Borrowed(line)
+    }
+}
+
+/// Process exactly one file using the `FileProcessor` structure
+///
+/// # Arguments
+///
+/// This method takes a [`&Path`](std::path::Path), a
+/// [`PathBuf`](std::path::PathBuf) and the [`Options`] of the run.
+/// The first is a reference to the file path that will be turned into a
+/// `PathBuf` for the `FileProcessor`. The second is simply the output path
+/// prefix for the processor.
+///
+/// # Errors
+///
+/// If anything fails during processing, the error will flow upwards.
+/// Otherwise, what was written is returned (see [`FileProcessor::process`]).
+pub fn one_file(path: &Path, outroot: PathBuf, options: &Options)
+    -> Result<FileStats, LogBoopError> {
+    // Building file processor
+    let mut proco = FileProcessor::new(path.to_path_buf(), outroot, options);
+    proco.determine_type()?;
+    proco.process()
+}
+
+/// Recursively process all of the files in an input directory
+///
+/// # Arguments
+/// This method takes two arguments :
+///  - a [`&Path`](std::path::Path) which is the root of the input directory
 ///  - another [`&Path`](std::path::Path) which is the root of the output
-///  directory
+///    directory
+///  - the [`Options`] of the run
 ///
 /// # Behaviour
 ///
 /// When given a path, this method recursively iterates all files in the
 /// folder (and at this point in the program it must be a folder),
 /// checks their extension (if any) with a regex matching for digits (in the
-/// style of ".1", ".3", ".12" and so on). When a file matching this regex
-/// is found, the [`one_file`] method is called.
+/// style of ".1", ".3", ".12" and so on), or their name with
+/// [`Options::rotated_files`] if set. When a file matching this regex
+/// is found, the [`one_file`] method is called (see [`Options::rotated_base`]),
+/// unless it is smaller than [`Options::min_size`], in which case it is left
+/// as it is, or modified longer than [`Options::since`] ago (see
+/// [`Options::is_recent`]). Files inflated by the same run, like `app.log`
+/// from `app.log.gz`, are processed whatever their name (see
+/// [`Options::inflated`]). Only files selected by [`Options::exclude`] and
+/// [`Options::include`] are processed (see [`Options::is_selected`]), and,
+/// with [`Options::no_touch_hardlinks`], only files without other hard
+/// links.
+///
+/// Output files are written at the same place in the output directory as
+/// their input in the input directory, or right in the output directory when
+/// [`Options::flatten`] is set. Files deeper than [`Options::max_depth`]
+/// below the input directory are left alone. With
+/// [`Options::sanitize_names`], that place is sanitized (see
+/// [`sanitize_path`]), and inputs then sharing it are warned about.
+///
+/// Files are processed in parallel, by up to [`Options::jobs`] threads.
+/// Once [`Options::stop_requested`], no other file is started, and the
+/// files left are not counted.
+/// Files sharing a base output path (like `syslog.1` and `syslog.2`, or
+/// `a/syslog.1` and `b/syslog.1` when flattening) would append to the same
+/// output files, so those are processed one after the other, in the order
+/// they were found : by name, unless [`Options::unsorted`] is set (see
+/// [`walk`]). Files with distinct base output paths
+/// never write to the same output file (see
+/// [`BucketPathFn`](crate::options::BucketPathFn)).
+///
+/// Files aborted because they yielded too many dates (see
+/// [`TooManyDates`](LogBoopError::TooManyDates)) are listed at the end,
+/// since they most likely need a closer look.
+///
+/// What was done is counted in a [`ProcessStats`], which is returned. With
+/// [`Options::manifest`], what was written from every file is also appended
+/// to the manifest of the output directory (see [`write_manifest`]).
 ///
 /// # Errors
-/// This method will return a `std::io::Result<()>`, and can be invoked
-/// with the `?` syntax sugar. When an internal error occurs
-/// (with [`one_file`]), that error will flow upwards.
+/// This method will return a `Result<ProcessStats, LogBoopError>`, and can be
+/// invoked with the `?` syntax sugar. Errors while processing a file (with
+/// [`one_file`]) are reported, counted, and the other files processed anyway.
+/// Files failing with a transient error are tried again first, up to
+/// [`Options::retries`] times (see [`retry`]).
+/// An error is only returned when the threads cannot be started, or the
+/// manifest cannot be written.
 ///
 /// # Example
 /// This method can be used thusly.
-/// ```
+/// ```no_run
+/// # use std::path::Path;
+/// # use logboop::process::all_files;
+/// # use logboop::options::Options;
 /// let my_files_path = Path::new("var/log");
 /// let output_path = Path::new("/tmp/processed/var/log");
-/// all_files(&my_files_path, &output_path)?;
-/// ```
-pub fn all_files(inpath: &Path, outpath: &Path) -> std::io::Result<()> {
-    WalkDir::new(inpath)
-        .into_iter()
-        .filter_map(|entry| entry.map(walkdir::DirEntry::into_path).ok())
-        .filter(|ent| match ent.extension() {
-            Some(ext) => ext
-                .to_str()
-                .map_or(false, |e| NUMBER_REGEX.is_match(e)),
-            None => false
+/// let stats = all_files(&my_files_path, &output_path, &Options::default())?;
+/// println!("{}", stats);
+/// # Ok::<(), logboop::error::LogBoopError>(())
+/// ```
+pub fn all_files(inpath: &Path, outpath: &Path, options: &Options)
+    -> Result<ProcessStats, LogBoopError> {
+    let groups = group_files(inpath, outpath, options);
+
+    let (jobs, max_open_files) = options.memory_bounds(options.jobs);
+    let options = &Options { max_open_files, ..options.clone() };
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(jobs)
+        .build()
+        .map_err(|e| LogBoopError::IoError(std::io::Error::other(e)))?;
+    let results: Vec<(PathBuf, Result<FileStats, LogBoopError>)> = pool.install(|| groups
+        .into_par_iter()
+        .flat_map_iter(|(base_output_path, entries)| entries
+            .into_iter()
+            // Once asked to stop, files being processed are finished, but
+            // no other one is started
+            .take_while(move |_| !options.stop_requested())
+            .map(move |entry| {
+                let result = retry(&entry, options,
+                                   || one_file(&entry, base_output_path.clone(), options));
+                if let Err(ref e) = result {
+                    error!("Error while processing {} : {}",
+                              entry.display(), e);
+                }
+                (entry, result)
+            }))
+        .collect());
+    if options.manifest {
+        write_manifest(outpath, &results)?;
+    }
+    Ok(tally(results))
+}
+
+/// Find the input files of a directory, grouped by base output path
+///
+/// Input files sharing a base output path are listed in the order they are
+/// found. See [`all_files`] for the files selected, and their base output
+/// path.
+pub(crate) fn group_files(inpath: &Path, outpath: &Path, options: &Options)
+    -> BTreeMap<PathBuf, Vec<PathBuf>> {
+    // Input files sharing a base output path, in the order they are found
+    let mut groups: BTreeMap<PathBuf, Vec<PathBuf>> = BTreeMap::new();
+    // First base output path found for every sanitized one, and the others
+    // merged into it
+    let mut unsanitized: HashMap<PathBuf, PathBuf> = HashMap::new();
+    let mut merged: HashSet<PathBuf> = HashSet::new();
+    walk(inpath, options.follow_links, options.max_depth, !options.unsorted)
+        .filter(|entry| options.is_selected(entry, inpath))
+        .filter_map(|entry| options.rotated_base(&entry).map(|base| (entry, base)))
+        .filter(|(entry, _)| match entry.metadata() {
+            Ok(meta) if meta.len() < options.min_size => {
+                options.report(Level::Debug, entry, meta.len(), format_args!(
+                    "{} too small, skipped", entry.display()));
+                false
+            },
+            Ok(meta) if !options.is_recent(entry) => {
+                options.report(Level::Debug, entry, meta.len(), format_args!(
+                    "{} too old, skipped", entry.display()));
+                false
+            },
+            Ok(meta) if !options.may_touch(entry) => {
+                options.report(Level::Debug, entry, meta.len(), format_args!(
+                    "{} hard linked, skipped", entry.display()));
+                false
+            },
+            _ => true
         })
-        .try_for_each(|entry| -> std::io::Result<_> {
-            match entry.strip_prefix(inpath) {
-                Ok(suffix) => {
-                    // First, join the outpath root and suffix
-                    // Second, remove the extension (i.e. the digit)
-                    let base_output_path = outpath.join(suffix)
-                        .with_extension("");
-                    if let Err(e) = one_file(entry.as_path(), base_output_path) {
-                        eprintln!("Error while processing {} : {}",
-                                  entry.display(), e);
+        .for_each(|(entry, base)| match base.strip_prefix(inpath) {
+            Ok(suffix) => {
+                // Join the outpath root and suffix, whose rotation mark (i.e.
+                // the digit) is already removed (or only the file name when
+                // flattening)
+                let suffix = match (options.flatten, suffix.file_name()) {
+                    (true, Some(name)) => Path::new(name),
+                    _ => suffix
+                };
+                let mut base_output_path = outpath.join(suffix);
+                if options.sanitize_names {
+                    let sanitized = outpath.join(sanitize_path(suffix));
+                    let first = unsanitized.entry(sanitized.clone())
+                        .or_insert_with(|| base_output_path.clone());
+                    if *first != base_output_path && merged.insert(base_output_path.clone()) {
+                        warn!("{} and {} are both written to {} once sanitized",
+                              first.display(), base_output_path.display(),
+                              sanitized.display());
                     }
-                },
-                Err(e) => {
-                    eprintln!("Error in suffix determination : {}", e);
+                    base_output_path = sanitized;
                 }
+                groups.entry(base_output_path).or_default().push(entry.clone());
+            },
+            Err(e) => {
+                error!("Error in suffix determination : {}", e);
             }
-            Ok(())
-        })
+        });
+    groups
+}
+
+/// Process a single file, named like in a directory
+///
+/// # Arguments
+/// This method takes the path to the file, the output directory, and the
+/// [`Options`] of the run.
+///
+/// # Behaviour
+///
+/// The file is processed with [`one_file`], whatever its name, and even if
+/// it is smaller than [`Options::min_size`]. Its output files are written
+/// right in the output directory, named after the file without its
+/// extension (or its last two, for a compressed file), as [`all_files`]
+/// would name the outputs of a rotated file : `huge.log` and `huge.log.gz`
+/// write to `huge-YYYY-MM-DD`. That name is sanitized too with
+/// [`Options::sanitize_names`].
+///
+/// # Errors
+/// Just like [`all_files`], an error while processing the file is reported
+/// and counted in the [`ProcessStats`] returned, whose
+/// [`failed`](ProcessStats::failed) is then `1`.
+///
+/// # Example
+/// ```no_run
+/// # use std::path::Path;
+/// # use logboop::process::single_file;
+/// # use logboop::options::Options;
+/// let stats = single_file(Path::new("huge.log"), Path::new("out"), &Options::default())?;
+/// # Ok::<(), logboop::error::LogBoopError>(())
+/// ```
+pub fn single_file(path: &Path, outpath: &Path, options: &Options)
+    -> Result<ProcessStats, LogBoopError> {
+    let mut name = PathBuf::from(path.file_name().unwrap_or(path.as_os_str()));
+    if name.extension()
+        .is_some_and(|ext| ext == "gz" || ext == "zst" || ext == "bz2" || ext == "xz") {
+        name.set_extension("");
+    }
+    let mut name = name.with_extension("");
+    if options.sanitize_names {
+        name = sanitize_path(&name);
+    }
+    let (_, max_open_files) = options.memory_bounds(1);
+    let options = &Options { max_open_files, ..options.clone() };
+    let result = one_file(path, outpath.join(name), options);
+    if let Err(ref e) = result {
+        error!("Error while processing {} : {}", path.display(), e);
+    }
+    let results = vec![(path.to_path_buf(), result)];
+    if options.manifest {
+        write_manifest(outpath, &results)?;
+    }
+    Ok(tally(results))
+}
+
+/// Count what processing files did, warning about those which yielded too
+/// many dates
+pub(crate) fn tally(results: Vec<(PathBuf, Result<FileStats, LogBoopError>)>)
+    -> ProcessStats {
+    let mut stats = ProcessStats::default();
+    let mut outputs: HashSet<PathBuf> = HashSet::new();
+    let mut too_many_dates: Vec<PathBuf> = Vec::new();
+    results.into_iter().for_each(|(entry, result)| match result {
+        Ok(file) if file.filtered => stats.filtered += 1,
+        Ok(file) => {
+            stats.files += 1;
+            match file.logtype {
+                Some(logtype) => *stats.per_type.entry(logtype).or_default() += 1,
+                None if file.empty => stats.empty += 1,
+                None => stats.unknown += 1
+            }
+            stats.lines += file.lines;
+            outputs.extend(file.outputs.into_iter().map(|output| output.path));
+        },
+        Err(e) => {
+            stats.failed += 1;
+            if let LogBoopError::TooManyDates { .. } = e {
+                too_many_dates.push(entry);
+            }
+        }
+    });
+    stats.outputs = outputs.len();
+    if !too_many_dates.is_empty() {
+        warn!("{} file(s) yielded too many dates and were left \
+              untouched :", too_many_dates.len());
+        too_many_dates.iter()
+            .for_each(|path| warn!("  {}", path.display()));
+    }
+    stats
+}
+
+/// Name of the manifest of an output directory (see [`write_manifest`])
+pub const MANIFEST_NAME: &str = "logboop-manifest.ndjson";
+
+/// Append what was written from every file processed to the manifest of an
+/// output directory
+///
+/// # Arguments
+/// This method takes the output directory, and every file processed along
+/// with what processing it did, as [`all_files`] collects them.
+///
+/// # Behaviour
+///
+/// One line of JSON is appended to the [`MANIFEST_NAME`] file of the output
+/// directory for every output file written from every input file, such as
+/// `{"date":"2020-05-17","lines":12,"output":"out/syslog-2020-05-17","source":"in/syslog.1","type":"Syslog"}`.
+/// The `date` is the bucket of the lines, and is `null` for the `.unknown`,
+/// `.excluded` and `.empty` files, as is the `type` of files of unknown
+/// type. An output file written from several input files is listed once for
+/// each of them.
+///
+/// Output files are named as they were written, that is before being
+/// compressed by a later phase (unless [`Options::compress_directly`] is
+/// set). Files which failed, or were skipped, wrote nothing, and are not
+/// listed.
+///
+/// All of the records are appended in one go, so that runs sharing an
+/// output directory do not mix their lines up.
+///
+/// # Errors
+/// Any I/O error while writing the manifest flows upwards.
+pub fn write_manifest(outpath: &Path, results: &[(PathBuf, Result<FileStats, LogBoopError>)])
+    -> std::io::Result<()> {
+    let mut records = String::new();
+    results.iter()
+        .filter_map(|(source, result)| result.as_ref().ok().map(|file| (source, file)))
+        .flat_map(|(source, file)| file.outputs.iter().map(move |output| (source, file, output)))
+        .for_each(|(source, file, output)| {
+            records.push_str(&serde_json::json!({
+                "source": source.to_string_lossy(),
+                "type": file.logtype.as_ref().map(LogType::to_string),
+                "output": output.path.to_string_lossy(),
+                "date": output.bucket.as_ref().map(Bucket::to_string),
+                "lines": output.lines
+            }).to_string());
+            records.push('\n');
+        });
+    if records.is_empty() {
+        return Ok(());
+    }
+    create_dir_all(outpath)?;
+    OpenOptions::new()
+        .append(true)
+        .create(true)
+        .open(outpath.join(MANIFEST_NAME))?
+        .write_all(records.as_bytes())
+}
+
+/// Whether a file is a rotated log file, processed by [`all_files`] unless
+/// [`Options::rotated_files`] is set
+///
+/// Rotated files are those whose extension is a number, such as
+/// `syslog.1`.
+///
+/// ```
+/// # use std::path::Path;
+/// # use logboop::process::is_rotated;
+/// assert!(is_rotated(Path::new("/var/log/syslog.12")));
+/// assert!(!is_rotated(Path::new("/var/log/syslog")));
+/// assert!(!is_rotated(Path::new("/var/log/syslog.1.gz")));
+/// ```
+pub fn is_rotated(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| NUMBER_REGEX.is_match(ext))
+}
+
+/// Date of the last modification of a file, or today if it cannot be told
+///
+/// Lines giving no year were written before the file was last modified, so
+/// this is the reference date of those lines (see [`split_lines_at`]).
+fn modification_date(path: &Path) -> NaiveDate {
+    path.metadata().and_then(|m| m.modified())
+        .map_or_else(|_| today(),
+                     |mtime| chrono::DateTime::<chrono::Utc>::from(mtime).date_naive())
+}
+
+/// Open an input file for reading, inflating it on the fly if it is
+/// compressed, through a buffer of `capacity` bytes
+///
+/// Files whose name ends with `.zst` are read as Zstandard data, those
+/// whose name ends with `.bz2` as bzip2 data, those whose name ends with
+/// `.xz` as XZ data, and files whose name ends with `.gz`, or which start
+/// like GZ data (see [`is_gzip`]), as GZ data. Other files are read as they
+/// are.
+fn open_input(path: &Path, capacity: usize) -> std::io::Result<Box<dyn BufRead>> {
+    let fptr = File::open(path)?;
+    Ok(match path.extension().and_then(|ext| ext.to_str()) {
+        Some("zst") => Box::new(BufReader::with_capacity(capacity, ZstdDecoder::new(fptr)?)),
+        Some("bz2") => Box::new(BufReader::with_capacity(capacity, MultiBzDecoder::new(fptr))),
+        Some("xz") => Box::new(BufReader::with_capacity(capacity,
+                                                        XzDecoder::new_multi_decoder(fptr))),
+        Some("gz") => Box::new(BufReader::with_capacity(capacity, MultiGzDecoder::new(fptr))),
+        _ if is_gzip(path)? =>
+            Box::new(BufReader::with_capacity(capacity, MultiGzDecoder::new(fptr))),
+        _ => Box::new(BufReader::with_capacity(capacity, fptr))
+    })
+}
+
+/// Open an input file like [`open_input`], past its first `offset` bytes
+/// (once inflated)
+///
+/// Plain files are sought into, while compressed files are inflated up to
+/// there.
+fn open_input_at(path: &Path, offset: u64, capacity: usize)
+    -> std::io::Result<Box<dyn BufRead>> {
+    if offset == 0 {
+        return open_input(path, capacity);
+    }
+    let compressed = matches!(path.extension().and_then(|ext| ext.to_str()),
+                              Some("zst") | Some("bz2") | Some("xz") | Some("gz"))
+        || is_gzip(path)?;
+    if !compressed {
+        let mut fptr = File::open(path)?;
+        fptr.seek(std::io::SeekFrom::Start(offset))?;
+        return Ok(Box::new(BufReader::with_capacity(capacity, fptr)));
+    }
+    let mut bufr = open_input(path, capacity)?;
+    if std::io::copy(&mut bufr.by_ref().take(offset), &mut std::io::sink())? < offset {
+        return Err(std::io::ErrorKind::UnexpectedEof.into());
+    }
+    Ok(bufr)
+}
+
+/// Count the lines of a file, the last one being counted even without a
+/// line feed, as [`BufRead::lines`] does
+fn count_lines(path: &Path, capacity: usize) -> std::io::Result<u64> {
+    open_input(path, capacity)?.split(b'\n')
+        .try_fold(0, |lines, line| line.map(|_| lines + 1))
+}
+
+/// Whether a file holds nothing but whitespace, if anything
+///
+/// Reading stops at the first byte that is not whitespace.
+fn is_blank(path: &Path, capacity: usize) -> std::io::Result<bool> {
+    let mut bufr = open_input(path, capacity)?;
+    loop {
+        let buf = bufr.fill_buf()?;
+        if buf.is_empty() {
+            return Ok(true);
+        }
+        if !buf.iter().all(u8::is_ascii_whitespace) {
+            return Ok(false);
+        }
+        let len = buf.len();
+        bufr.consume(len);
+    }
 }
 
 /// Given a line and assumed log type, determine the date of that log line
@@ -332,11 +2865,41 @@ pub fn all_files(inpath: &Path, outpath: &Path) -> std::io::Result<()> {
 ///
 /// # Return value
 ///
-/// This method returns an [`Option<String>`](Option) with the determined date
-/// in [simple ISO 8601 calendar date
-/// format](https://en.wikipedia.org/wiki/ISO_8601#Calendar_dates).
-/// It literally cannot return anything else. Panicking behaviours are not
-/// exactly handled just yet.
+/// This method returns the determined date in [simple ISO 8601 calendar date
+/// format](https://en.wikipedia.org/wiki/ISO_8601#Calendar_dates), or a
+/// [`ParseError`](LogBoopError::ParseError) when the line holds no valid date.
+///
+/// # Behaviour
+///
+/// The date itself is built by [`parse_date`], and then formatted.
+pub fn determine_date(lt: &LogType, line: &str) -> Result<String, LogBoopError> {
+    determine_date_at(lt, line, &today())
+}
+
+/// Given a line and assumed log type, determine the date of that log line,
+/// relative to a reference date
+///
+/// This is [`determine_date`], where the date is built by [`parse_date_at`].
+pub fn determine_date_at(lt: &LogType, line: &str, reference: &NaiveDate)
+    -> Result<String, LogBoopError> {
+    let iso_8601_fmt: StrftimeItems = StrftimeItems::new("%Y-%m-%d");
+    parse_date_at(lt, line, reference)
+        .map(|date| date.format_with_items(iso_8601_fmt).to_string())
+}
+
+/// Given a line and assumed log type, build the calendar date of that line
+///
+/// # Arguments
+///
+/// Just like [`determine_date`], this requires :
+///  - a reference to a [`LogType`] assumed to be valid for our line
+///  - the line as a [`&str`]
+///
+/// # Return value
+///
+/// This method returns the [`NaiveDate`] of the line, or a
+/// [`ParseError`](LogBoopError::ParseError) holding the line when it does not
+/// match the regex of the log type or when its date is invalid.
 ///
 /// # Behaviour
 ///
@@ -347,50 +2910,429 @@ pub fn all_files(inpath: &Path, outpath: &Path) -> std::io::Result<()> {
 /// That exact portion is parsed, depending on the type, to build a
 /// [`NaiveDate`].
 ///
-/// There is technically a fallback if the parsing fails (for example, logs
-/// that have been tampered with contain an impossible date) that assigns
-/// the day "0001-01-01" in case of failure.
-///
-/// Once that [`NaiveDate`] is built, it is converted to the format we want,
-/// and returned in the [`Option`].
+/// If the parsing fails (for example, logs that have been tampered with
+/// contain an impossible date), no date is made up : the error is returned.
 ///
 /// ## A note on the `Syslog` format
 ///
 /// The default format used by system logs ([`LogType::Syslog`]) commonly does
 /// not indicate the year. This is a huge issue, because we cannot infer an
-/// exact date. As such, **we assume that the year of the logs is the current
-/// one**, and append it to the portion of the line we extracted before trying
-/// to build our [`NaiveDate`].
-fn determine_date(lt: &LogType, line: &str) -> Option<String> {
+/// exact date. As such, **we assume that the logs were written during the
+/// last twelve months** (see [`infer_year`]), and append that year to the
+/// portion of the line we extracted before trying to build our [`NaiveDate`].
+/// When the date the file was written is known, use [`parse_date_at`]
+/// instead.
+///
+/// # Example
+/// ```
+/// # use logboop::process::{parse_date, LogType};
+/// assert!(parse_date(&LogType::Iso, "2020-05-17 fine").is_ok());
+/// assert!(parse_date(&LogType::Iso, "2020-02-31 tampered").is_err());
+/// ```
+pub fn parse_date(lt: &LogType, line: &str) -> Result<NaiveDate, LogBoopError> {
+    parse_date_at(lt, line, &today())
+}
+
+/// Given a line and assumed log type, build the calendar date of that line,
+/// relative to a reference date
+///
+/// This is [`parse_date`], where lines giving no year are assumed to be from
+/// the twelve months up to `reference`, typically the date their file was
+/// last modified (see [`infer_year`]).
+///
+/// # Example
+/// ```
+/// # use logboop::process::{parse_date_at, LogType};
+/// // A file rotated early in 2021 still holds logs of December 2020
+/// let reference = chrono::NaiveDate::from_ymd_opt(2021, 1, 2).unwrap();
+/// let date = parse_date_at(&LogType::Syslog, "Dec 31 23:59:59 host a", &reference)?;
+/// assert_eq!(date.to_string(), "2020-12-31");
+/// # Ok::<(), logboop::error::LogBoopError>(())
+/// ```
+pub fn parse_date_at(lt: &LogType, line: &str, reference: &NaiveDate)
+    -> Result<NaiveDate, LogBoopError> {
+    find_date(lt, line, reference, &json::DEFAULT_TIME_FIELDS, &[])
+        .ok_or_else(|| parse_error(lt, line))
+}
+
+/// Today's date, the reference date of lines whose file is unknown
+fn today() -> NaiveDate {
+    chrono::Utc::now().date_naive()
+}
+
+/// Guess the year of a month written without one
+///
+/// The month (an abbreviated English name, whatever its case) is assumed
+/// to be at most eleven months before the reference date : a month coming
+/// after the one of the reference date belongs to the year before, like
+/// December logs in a file last modified in January.
+///
+/// ```
+/// # use logboop::process::infer_year;
+/// let reference = chrono::NaiveDate::from_ymd_opt(2021, 1, 2).unwrap();
+/// assert_eq!(infer_year("Jan", &reference), 2021);
+/// assert_eq!(infer_year("Dec", &reference), 2020);
+/// assert_eq!(infer_year("DEC", &reference), 2020);
+/// ```
+pub fn infer_year(month: &str, reference: &NaiveDate) -> i32 {
+    let month = MONTHS.iter().position(|m| m.eq_ignore_ascii_case(month))
+        .map_or(0, |m| m as u32 + 1);
+    if month > reference.month() {
+        reference.year() - 1
+    } else {
+        reference.year()
+    }
+}
+
+/// Build the error of a line holding no valid date
+fn parse_error(lt: &LogType, line: &str) -> LogBoopError {
+    LogBoopError::ParseError { line: line.to_owned(), logtype: lt.clone() }
+}
+
+/// Build the calendar date of a line, or `None` when it holds no valid one
+///
+/// This is the core of [`parse_date_at`], used as is by [`split_lines_at`] so
+/// that no error is built for every line without a date. [`LogType::Json`]
+/// lines are dated by the first of `fields` holding a timestamp, and
+/// [`LogType::Custom`] lines by the format of that name in `formats`.
+fn find_date<S: AsRef<str>>(lt: &LogType, line: &str, reference: &NaiveDate,
+                            fields: &[S], formats: &[CustomFormat]) -> Option<NaiveDate> {
     // Create the moment
-    let matched_part = REGEXES[lt].find(line)?;
+    let full_line = line;
+    let matched_part = match lt {
+        LogType::Custom(name) => return custom_format(formats, name)?.date(line),
+        LogType::IisW3c => IIS_LINE_REGEX.find(line)?,
+        _ => FORMATS[lt].regex.find(line)?
+    };
     let match_start = matched_part.start();
     let match_end = matched_part.end();
-    let line = &line[match_start..match_end];
-    let iso_8601_fmt: StrftimeItems = StrftimeItems::new("%Y-%m-%d");
+    // Lines may be indented
+    let line = line[match_start..match_end].trim_start();
 
     // Depending on the type, parse into a Date
-    Some(match lt {
+    match lt {
         LogType::Syslog => { 
-            // What is the current year?
-            let year = chrono::Utc::now().year();
+            // Which year was it?
+            let year = infer_year(&line[..3], reference);
             let line = &format!("{} {}", line, year);
-            NaiveDate::parse_from_str(line, "%b %d %Y")
+            NaiveDate::parse_from_str(line, &format!("{} %Y", lt.date_format()?))
         },
-        LogType::Iso => {
-            NaiveDate::parse_from_str(line, "%Y-%m-%d")
+        LogType::Syslog5424 | LogType::MysqlSlow => {
+            let caps = FORMATS[lt].regex.captures(line)?;
+            chrono::DateTime::parse_from_rfc3339(&caps["ts"])
+                .map(|datetime| datetime.date_naive())
         },
         LogType::ApacheAccess => {
-            NaiveDate::parse_from_str(line, "[%d/%b/%Y:")
+            // Bracketed content can come before the actual date, so keep the
+            // first complete timestamp which is a valid date
+            let format = lt.date_format()?;
+            FORMATS[lt].regex.find_iter(full_line)
+                .map(|candidate| NaiveDate::parse_from_str(candidate.as_str(), format))
+                .find(Result::is_ok)
+                .unwrap_or_else(|| NaiveDate::parse_from_str(line, format))
         },
-        LogType::ApacheError => {
-            NaiveDate::parse_from_str(line, "[%a %b %d %H:%M:%s%.6f %Y]")
+        LogType::ApacheVhost => {
+            // The virtual host and client come first
+            let caps = FORMATS[lt].regex.captures(line)?;
+            NaiveDate::parse_from_str(&caps["ts"], lt.date_format()?)
         },
-        LogType::GrafanaLogs => {
-            NaiveDate::parse_from_str(line, "t=%Y-%m-%dT%H:%M:%S%z lvl=")
+        LogType::Iso | LogType::NginxAccess | LogType::HaproxyHttp
+            | LogType::ApacheError | LogType::GrafanaLogs => {
+            NaiveDate::parse_from_str(line, lt.date_format()?)
+        },
+        LogType::IsoBasic | LogType::DayMonthYear2 => {
+            // The time of day is not part of the date format
+            let caps = FORMATS[lt].regex.captures(line)?;
+            NaiveDate::parse_from_str(&caps["date"], lt.date_format()?)
+        },
+        LogType::IisW3c => {
+            // Directives are what the file is recognized by, not dated lines
+            chrono::NaiveDateTime::parse_from_str(line, lt.date_format()?)
+                .map(|datetime| datetime.date())
+        },
+        LogType::CiscoSyslog => {
+            // Only infer the year if the device did not give one
+            let caps = FORMATS[lt].regex.captures(line)?;
+            let year = caps.name("year")
+                .map_or_else(|| infer_year(&caps["month"], reference).to_string(),
+                             |y| y.as_str().to_owned());
+            let line = &format!("{} {} {}", &caps["month"], &caps["day"], year);
+            NaiveDate::parse_from_str(line, &format!("{} %Y", lt.date_format()?))
+        },
+        LogType::EcsJson => {
+            // The date is the one written in the timestamp, whatever its offset
+            let caps = ECS_TIMESTAMP_REGEX.captures(line)?;
+            chrono::DateTime::parse_from_rfc3339(&caps["ts"])
+                .map(|datetime| datetime.date_naive())
+        },
+        LogType::Json => {
+            // The whole line is parsed, not only the opening brace we matched
+            return json::timestamp(full_line, fields)
+                .map(|datetime| datetime.date_naive());
+        },
+        LogType::DockerJson => {
+            // The log itself may hold other timestamps
+            return json::timestamp(full_line, &[json::DOCKER_TIME_FIELD])
+                .map(|datetime| datetime.date_naive());
+        },
+        LogType::JournalExport => {
+            // Only the UTC date is known
+            return journal_timestamp(line).map(|datetime| datetime.date_naive());
+        },
+        LogType::Custom(_) => unreachable!("custom formats are dated above")
+    }.ok()
+}
+
+/// Given a line and assumed log type, build the bucket of that line
+///
+/// # Arguments
+///
+/// On top of what [`parse_date`] requires, this takes the [`Granularity`] of
+/// the buckets.
+///
+/// # Return value
+///
+/// This method returns the same errors as [`parse_date`]. Otherwise, the
+/// [`Bucket`] holds the date of the line, and with
+/// [`Granularity::Hour`] its hour as found by [`parse_hour`]. Log types
+/// giving no time of day fall back to daily buckets. With
+/// [`Granularity::Week`], it is the week of the date, whatever the log type.
+///
+/// # Example
+/// ```
+/// # use logboop::process::{parse_bucket, LogType};
+/// # use logboop::options::Granularity;
+/// let line = "2020-05-17 02:07:16,656 fail2ban.filter [1234]: INFO";
+/// let bucket = parse_bucket(&LogType::Iso, line, Granularity::Hour).unwrap();
+/// assert_eq!(bucket.to_string(), "2020-05-17T02");
+/// let bucket = parse_bucket(&LogType::Iso, line, Granularity::Day).unwrap();
+/// assert_eq!(bucket.to_string(), "2020-05-17");
+/// let bucket = parse_bucket(&LogType::Iso, line, Granularity::Week).unwrap();
+/// assert_eq!(bucket.to_string(), "2020-W20");
+/// ```
+pub fn parse_bucket(lt: &LogType, line: &str, granularity: Granularity)
+    -> Result<Bucket, LogBoopError> {
+    find_bucket(lt, line, granularity, Timezone::Log, &today(),
+                &json::DEFAULT_TIME_FIELDS, &[])
+        .ok_or_else(|| parse_error(lt, line))
+}
+
+/// Build the bucket of a line, or `None` when it holds no valid date
+///
+/// Just like [`find_date`], this is the core of [`parse_bucket`]. With
+/// [`Timezone::Utc`], lines whose timestamp gives an offset are bucketed by
+/// their date (and hour) in UTC instead, and with [`Timezone::Offset`],
+/// their bucket holds that offset.
+fn find_bucket<S: AsRef<str>>(lt: &LogType, line: &str, granularity: Granularity,
+                              timezone: Timezone, reference: &NaiveDate,
+                              fields: &[S], formats: &[CustomFormat]) -> Option<Bucket> {
+    if timezone == Timezone::Utc {
+        if let Some(timestamp) = find_timestamp(lt, line, fields, formats) {
+            let utc = timestamp.naive_utc();
+            return Some(bucket_of(utc.date(), Some(utc.hour()), granularity));
+        }
+    }
+    let date = find_date(lt, line, reference, fields, formats)?;
+    let hour = match granularity {
+        Granularity::Hour => find_hour(lt, line, fields, formats),
+        Granularity::Day | Granularity::Week => None
+    };
+    let offset = match timezone {
+        Timezone::Offset => find_timestamp(lt, line, fields, formats)
+            .map(|timestamp| *timestamp.offset()),
+        Timezone::Log | Timezone::Utc => None
+    };
+    Some(Bucket { offset, ..bucket_of(date, hour, granularity) })
+}
+
+/// Build the bucket of a date and hour, at a given [`Granularity`]
+///
+/// Weekly buckets are dated by the Monday of their ISO 8601 week.
+fn bucket_of(date: NaiveDate, hour: Option<u32>, granularity: Granularity) -> Bucket {
+    match granularity {
+        Granularity::Day => Bucket { date, hour: None, week: false, offset: None,
+                                     vhost: None },
+        Granularity::Hour => Bucket { date, hour, week: false, offset: None, vhost: None },
+        Granularity::Week => {
+            let week = date.iso_week();
+            let monday = NaiveDate::from_isoywd_opt(week.year(), week.week(),
+                                                    chrono::Weekday::Mon)
+                .unwrap_or(date);
+            Bucket { date: monday, hour: None, week: true, offset: None, vhost: None }
         }
-    }.unwrap_or_else(|_| chrono::NaiveDate::from_ymd(0, 1, 1))
-        .format_with_items(iso_8601_fmt)
-        .to_string())
+    }
+}
+
+/// Given a line and assumed log type, find the offset of its timestamp
+///
+/// # Return value
+///
+/// The offset is the one written in the line, or `None` when its log type
+/// gives none (see [`Timezone`]) or its timestamp is invalid.
+///
+/// # Example
+/// ```
+/// # use logboop::process::{parse_offset, LogType};
+/// let line = r#"127.0.0.1 - - [11/Oct/2023:13:55:36 +0200] "GET / HTTP/1.1" 200 42"#;
+/// assert_eq!(parse_offset(&LogType::ApacheAccess, line),
+///            chrono::FixedOffset::east_opt(2 * 3600));
+/// assert_eq!(parse_offset(&LogType::Iso, "2023-10-11 13:55:36 a"), None);
+/// ```
+pub fn parse_offset(lt: &LogType, line: &str) -> Option<chrono::FixedOffset> {
+    find_timestamp(lt, line, &json::DEFAULT_TIME_FIELDS, &[])
+        .map(|timestamp| *timestamp.offset())
+}
+
+/// Given a [`LogType::ApacheVhost`] line, find its virtual host
+///
+/// # Return value
+///
+/// The virtual host is the first word of the line, without its port, or
+/// `None` when the line is not a [`LogType::ApacheVhost`] line. It holds
+/// nothing but letters, digits, underscores, dots and dashes, so that it
+/// can name a file, and at least one letter (or underscore), so that the
+/// address of a proxy logged before the client is never taken for one.
+///
+/// # Example
+/// ```
+/// # use logboop::process::parse_vhost;
+/// let line = r#"example.com:443 1.2.3.4 - - [11/Oct/2023:13:55:36 +0000] "GET / HTTP/1.1" 200 42"#;
+/// assert_eq!(parse_vhost(line), Some("example.com"));
+/// assert_eq!(parse_vhost(r#"1.2.3.4 - - [11/Oct/2023:13:55:36 +0000] "GET /""#), None);
+/// assert_eq!(parse_vhost(r#"10.0.0.2 1.2.3.4 - - [11/Oct/2023:13:55:36 +0000] "GET /""#), None);
+/// ```
+pub fn parse_vhost(line: &str) -> Option<&str> {
+    FORMATS[&LogType::ApacheVhost].regex.captures(line)
+        .and_then(|caps| caps.name("vhost"))
+        .map(|vhost| vhost.as_str())
 }
 
+/// Find the complete timestamp of a line, with its offset, or `None` when
+/// its log type gives no offset (see [`Timezone`])
+fn find_timestamp<S: AsRef<str>>(lt: &LogType, line: &str, fields: &[S],
+                                 formats: &[CustomFormat])
+    -> Option<chrono::DateTime<chrono::FixedOffset>> {
+    match lt {
+        LogType::Syslog5424 | LogType::MysqlSlow => {
+            let caps = FORMATS[lt].regex.captures(line)?;
+            chrono::DateTime::parse_from_rfc3339(&caps["ts"]).ok()
+        },
+        LogType::EcsJson => {
+            let caps = ECS_TIMESTAMP_REGEX.captures(line)?;
+            chrono::DateTime::parse_from_rfc3339(&caps["ts"]).ok()
+        },
+        LogType::Json => json::timestamp(line, fields),
+        LogType::DockerJson => json::timestamp(line, &[json::DOCKER_TIME_FIELD]),
+        LogType::JournalExport => journal_timestamp(line),
+        LogType::ApacheAccess => {
+            // The timestamp chosen by `find_date`
+            let format = lt.date_format()?;
+            FORMATS[lt].regex.find_iter(line)
+                .find_map(|candidate| chrono::DateTime::parse_from_str(
+                    candidate.as_str(), format).ok())
+        },
+        LogType::ApacheVhost => {
+            let caps = FORMATS[lt].regex.captures(line)?;
+            chrono::DateTime::parse_from_str(&caps["ts"], lt.date_format()?).ok()
+        },
+        LogType::IisW3c => {
+            // Times are always written in UTC
+            let caps = IIS_LINE_REGEX.captures(line)?;
+            chrono::NaiveDateTime::parse_from_str(&caps["ts"], lt.date_format()?).ok()
+                .map(|datetime| datetime.and_utc().fixed_offset())
+        },
+        LogType::NginxAccess => {
+            // The offset may follow the time, or be missing altogether
+            let start = FORMATS[lt].regex.find(line)?.start();
+            chrono::DateTime::parse_and_remainder(&line[start..],
+                                                  &format!("{} %z]", lt.date_format()?))
+                .ok().map(|(timestamp, _)| timestamp)
+        },
+        LogType::GrafanaLogs => {
+            let found = FORMATS[lt].regex.find(line)?;
+            chrono::DateTime::parse_from_str(found.as_str().trim_start(),
+                                             lt.date_format()?).ok()
+        },
+        LogType::Custom(name) => custom_format(formats, name)?.timestamp(line),
+        LogType::Syslog | LogType::Iso | LogType::IsoBasic | LogType::DayMonthYear2
+            | LogType::HaproxyHttp | LogType::ApacheError | LogType::CiscoSyslog => None
+    }
+}
+
+/// Timestamp of the `__REALTIME_TIMESTAMP` field of a
+/// [`LogType::JournalExport`] record, in UTC
+fn journal_timestamp(record: &str) -> Option<chrono::DateTime<chrono::FixedOffset>> {
+    let caps = FORMATS[&LogType::JournalExport].regex.captures(record)?;
+    chrono::DateTime::from_timestamp_micros(caps["ts"].parse().ok()?)
+        .map(|datetime| datetime.fixed_offset())
+}
+
+/// Given a line and assumed log type, determine the bucket of that line
+///
+/// This is the sibling of [`determine_date`] for a given [`Granularity`] :
+/// the bucket built by [`parse_bucket`] is formatted as `YYYY-MM-DD`, or
+/// `YYYY-MM-DDTHH` when it has an hour.
+pub fn determine_bucket(lt: &LogType, line: &str, granularity: Granularity)
+    -> Result<String, LogBoopError> {
+    parse_bucket(lt, line, granularity).map(|bucket| bucket.to_string())
+}
+
+/// Given a line and assumed log type, find the hour of that line
+///
+/// # Return value
+///
+/// The hour (from 0 to 23) is the one written in the line, whatever the
+/// offset of the timestamp, just like the date built by [`parse_date`].
+///
+/// `None` is returned for [`LogType::Syslog`], where the portion of the line
+/// we recognize holds no time of day, for [`LogType::Iso`] lines giving a
+/// date only, and whenever no valid hour can be found.
+pub fn parse_hour(lt: &LogType, line: &str) -> Option<u32> {
+    find_hour(lt, line, &json::DEFAULT_TIME_FIELDS, &[])
+}
+
+/// Find the hour of a line, [`LogType::Json`] lines being dated by the first
+/// of `fields` holding a timestamp, and [`LogType::Custom`] lines by the
+/// format of that name in `formats`
+///
+/// This is the core of [`parse_hour`].
+fn find_hour<S: AsRef<str>>(lt: &LogType, line: &str, fields: &[S],
+                            formats: &[CustomFormat]) -> Option<u32> {
+    let hour = match lt {
+        LogType::Syslog => None,
+        LogType::Custom(name) => custom_format(formats, name)?.hour(line),
+        LogType::Syslog5424 | LogType::MysqlSlow => {
+            let caps = FORMATS[lt].regex.captures(line)?;
+            chrono::DateTime::parse_from_rfc3339(&caps["ts"]).ok()
+                .map(|datetime| datetime.hour())
+        },
+        LogType::EcsJson => {
+            let caps = ECS_TIMESTAMP_REGEX.captures(line)?;
+            chrono::DateTime::parse_from_rfc3339(&caps["ts"]).ok()
+                .map(|datetime| datetime.hour())
+        },
+        LogType::Json => json::timestamp(line, fields)
+            .map(|datetime| datetime.hour()),
+        LogType::DockerJson => json::timestamp(line, &[json::DOCKER_TIME_FIELD])
+            .map(|datetime| datetime.hour()),
+        LogType::JournalExport => journal_timestamp(line).map(|datetime| datetime.hour()),
+        LogType::Iso => ISO_HOUR_REGEX.captures(line)
+            .and_then(|caps| caps["hour"].parse().ok()),
+        LogType::IsoBasic => FORMATS[lt].regex.captures(line)
+            .and_then(|caps| caps.name("hour")?.as_str().parse().ok()),
+        LogType::IisW3c => IIS_LINE_REGEX.captures(line)
+            .and_then(|caps| caps["hour"].parse().ok()),
+        LogType::ApacheAccess => {
+            // The hour of the timestamp chosen by `find_date`
+            let format = lt.date_format()?;
+            FORMATS[lt].regex.captures_iter(line)
+                .find(|caps| NaiveDate::parse_from_str(&caps[0], format).is_ok())
+                .and_then(|caps| caps["hour"].parse().ok())
+        },
+        LogType::NginxAccess | LogType::HaproxyHttp | LogType::ApacheError
+            | LogType::ApacheVhost | LogType::GrafanaLogs | LogType::DayMonthYear2
+            | LogType::CiscoSyslog => FORMATS[lt].regex.captures(line)
+            .and_then(|caps| caps["hour"].parse().ok())
+    };
+    hour.filter(|hour| *hour < 24)
+}
@@ -0,0 +1,111 @@
+//! Helpers shared by the integration tests
+#![allow(dead_code)]
+use std::io::Read;
+use std::path::Path;
+
+use logboop::process::LogType;
+use tempfile::TempDir;
+
+/// Create a fresh, empty scratch directory for a test, removed once the
+/// returned handle is dropped (even when an assertion fails)
+pub fn scratch_dir(name: &str) -> TempDir {
+    tempfile::Builder::new()
+        .prefix(&format!("logboop-{}-", name))
+        .tempdir()
+        .unwrap()
+}
+
+/// Write a file, creating its parent directories
+pub fn write_file(path: &Path, contents: &str) {
+    std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+    std::fs::write(path, contents).unwrap();
+}
+
+/// Sorted list of the files under a directory, relative to it
+pub fn list_files(dir: &Path) -> Vec<String> {
+    let mut files: Vec<String> = walkdir::WalkDir::new(dir)
+        .into_iter()
+        .filter_map(Result::ok)
+        .filter(|e| e.file_type().is_file())
+        .map(|e| e.path().strip_prefix(dir).unwrap().to_string_lossy().into_owned())
+        .collect();
+    files.sort();
+    files
+}
+
+/// Contents of a GZ compressed file, through all its members
+pub fn read_gz(path: &Path) -> String {
+    let mut contents = String::new();
+    flate2::read::MultiGzDecoder::new(std::fs::File::open(path).unwrap())
+        .read_to_string(&mut contents).unwrap();
+    contents
+}
+
+/// The undated lines a file of a built-in log type begins with, if any, and
+/// which are recognized as that log type by themselves
+///
+/// [`LogType::IisW3c`] files begin with their directives, without which
+/// their lines are [`LogType::Iso`] lines.
+pub fn preamble(lt: &LogType) -> &'static str {
+    match lt {
+        LogType::IisW3c => "#Software: Microsoft Internet Information Services 10.0\n\
+                            #Fields: date time s-ip cs-method cs-uri-stem sc-status\n",
+        _ => ""
+    }
+}
+
+/// A line of a built-in log type, dated the given day of May 2020 (or of
+/// the May it is taken for, for log types writing no year)
+///
+/// A [`LogType::JournalExport`] line is a whole record, ending with its
+/// blank line.
+pub fn fixture(lt: &LogType, day: u32) -> String {
+    match lt {
+        LogType::Syslog =>
+            format!("May {:2} 02:07:16 host sshd[1234]: Connection closed\n", day),
+        LogType::Syslog5424 =>
+            format!("<34>1 2020-05-{:02}T02:07:16.003Z host su - ID47 - 'su root' failed\n", day),
+        LogType::Iso =>
+            format!("2020-05-{:02} 02:07:16,656 fail2ban.filter [1234]: INFO\n", day),
+        LogType::IsoBasic =>
+            format!("202005{:02} 02:07:16 watchdog: ping\n", day),
+        LogType::IisW3c =>
+            format!("2020-05-{:02} 02:07:16 10.0.0.1 GET /index.html 200\n", day),
+        LogType::DayMonthYear2 =>
+            format!("{:02}-May-20 02:07:16 eth0: link up\n", day),
+        LogType::ApacheAccess =>
+            format!("127.0.0.1 - - [{:02}/May/2020:02:07:16 +0200] \"GET / HTTP/1.1\" 200 42\n",
+                    day),
+        LogType::ApacheVhost =>
+            format!("example.com:443 127.0.0.1 - - [{:02}/May/2020:02:07:16 +0200] \"GET / \
+                     HTTP/1.1\" 200 42\n", day),
+        LogType::NginxAccess =>
+            format!("10.0.0.2 127.0.0.1 - - [{:02}/May/2020:02:07:16] \"GET / HTTP/1.1\" 200 42\n",
+                    day),
+        LogType::HaproxyHttp =>
+            format!("10.0.1.2:33317 [{:02}/May/2020:02:07:16.656] http-in static/srv1 \
+                     10/0/30/69/109 200 2750\n", day),
+        LogType::ApacheError =>
+            format!("[{} May {:02} 02:07:16.656808 2020] [core:error] [pid 1234] oops\n",
+                    chrono::NaiveDate::from_ymd_opt(2020, 5, day).unwrap().format("%a"), day),
+        LogType::GrafanaLogs =>
+            format!("t=2020-05-{:02}T02:07:16+0200 lvl=info msg=\"Request Completed\"\n", day),
+        LogType::MysqlSlow =>
+            format!("# Time: 2020-05-{:02}T02:07:16.656808Z\n", day),
+        LogType::JournalExport =>
+            format!("__REALTIME_TIMESTAMP={}000000\nMESSAGE=hi\n\n",
+                    1_588_291_200 + (day - 1) * 86400),
+        LogType::CiscoSyslog =>
+            format!("<189>123: host: *May {:2} 02:07:16.003: %SYS-5-CONFIG_I: Configured\n", day),
+        LogType::EcsJson =>
+            format!("{{\"@timestamp\":\"2020-05-{:02}T02:07:16.656Z\",\"@version\":\"1\",\
+                     \"message\":\"hi\"}}\n", day),
+        LogType::DockerJson =>
+            format!("{{\"log\":\"hi\\n\",\"stream\":\"stdout\",\
+                     \"time\":\"2020-05-{:02}T02:07:16.656808123Z\"}}\n", day),
+        LogType::Json =>
+            format!("{{\"level\":30,\"time\":\"2020-05-{:02}T02:07:16.656Z\",\"msg\":\"hi\"}}\n",
+                    day),
+        LogType::Custom(name) => panic!("no fixture for the custom log type {}", name)
+    }
+}
@@ -0,0 +1,161 @@
+//! Compression and inflation of individual files
+mod common;
+
+use std::io::Write;
+
+use flate2::Compression;
+use logboop::compress::{bzip2, debzip2, degunzip, dexz, dezstd, format_of, gunzip, inflated_len,
+                        zstd};
+
+#[test]
+fn zstd_round_trip() {
+    let scratch = common::scratch_dir("zstd");
+    let dir = scratch.path();
+    let path = dir.join("syslog-2020-05-17");
+    let data: Vec<u8> = (0..100_000u32).flat_map(|n| n.to_le_bytes()).collect();
+    std::fs::write(&path, &data).unwrap();
+
+    zstd(&path, false).unwrap();
+    assert_eq!(common::list_files(dir), vec!["syslog-2020-05-17.zst"]);
+    dezstd(&dir.join("syslog-2020-05-17.zst"), false).unwrap();
+    assert_eq!(common::list_files(dir), vec!["syslog-2020-05-17"]);
+    assert_eq!(std::fs::read(&path).unwrap(), data);
+}
+
+#[test]
+fn gunzip_honours_the_compression_level() {
+    let scratch = common::scratch_dir("gunzip-level");
+    let dir = scratch.path();
+    let data = "2020-05-17 02:07:16 fail2ban.filter [1234]: INFO found 10.0.0.1\n"
+        .repeat(10_000);
+    let sizes: Vec<u64> = [Compression::none(), Compression::best()].iter()
+        .map(|&level| {
+            let path = dir.join("syslog-2020-05-17");
+            std::fs::write(&path, &data).unwrap();
+            gunzip(&path, level, false).unwrap();
+            let compressed = dir.join("syslog-2020-05-17.gz");
+            let size = compressed.metadata().unwrap().len();
+            degunzip(&compressed, false).unwrap();
+            assert_eq!(std::fs::read_to_string(&path).unwrap(), data);
+            size
+        })
+        .collect();
+    assert!(sizes[1] < sizes[0] / 10);
+}
+
+#[test]
+fn bzip2_round_trip_across_streams() {
+    let scratch = common::scratch_dir("bzip2");
+    let dir = scratch.path();
+    let path = dir.join("syslog-2020-05-17");
+    let compressed = dir.join("syslog-2020-05-17.bz2");
+    let data: Vec<u8> = (0..100_000u32).flat_map(|n| n.to_le_bytes()).collect();
+    std::fs::write(&path, &data).unwrap();
+
+    bzip2(&path, false).unwrap();
+    assert_eq!(common::list_files(dir), vec!["syslog-2020-05-17.bz2"]);
+    // A second stream, like the ones appended when compressing directly
+    let mut encoder = ::bzip2::write::BzEncoder::new(Vec::new(), ::bzip2::Compression::default());
+    encoder.write_all(b"more\n").unwrap();
+    let mut file = std::fs::OpenOptions::new().append(true).open(&compressed).unwrap();
+    file.write_all(&encoder.finish().unwrap()).unwrap();
+
+    assert_eq!(inflated_len(&compressed).unwrap(), data.len() as u64 + 5);
+    debzip2(&compressed, false).unwrap();
+    assert_eq!(common::list_files(dir), vec!["syslog-2020-05-17"]);
+    let inflated = std::fs::read(&path).unwrap();
+    assert_eq!(&inflated[..data.len()], &data[..]);
+    assert_eq!(&inflated[data.len()..], b"more\n");
+
+    // What was inflated of a corrupted file is not left behind
+    std::fs::write(&compressed, b"BZh9 definitely not bzip2").unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert!(debzip2(&compressed, false).is_err());
+    assert_eq!(common::list_files(dir), vec!["syslog-2020-05-17.bz2"]);
+}
+
+#[test]
+fn xz_inflates_across_streams() {
+    let scratch = common::scratch_dir("xz");
+    let dir = scratch.path();
+    let path = dir.join("syslog.2");
+    let compressed = dir.join("syslog.2.xz");
+    let xz = |data: &[u8]| {
+        let mut encoder = ::xz2::write::XzEncoder::new(Vec::new(), 6);
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    };
+    // Two streams, like those of a file appended to
+    std::fs::write(&compressed, [xz(b"first\n"), xz(b"second\n")].concat()).unwrap();
+
+    assert_eq!(format_of(&compressed).unwrap(), Some("xz"));
+    assert_eq!(inflated_len(&compressed).unwrap(), 13);
+    dexz(&compressed, false).unwrap();
+    assert_eq!(common::list_files(dir), vec!["syslog.2"]);
+    assert_eq!(std::fs::read(&path).unwrap(), b"first\nsecond\n");
+    assert_eq!(format_of(&path).unwrap(), None);
+
+    // What was inflated of a corrupted file is not left behind
+    std::fs::write(&compressed, b"\xfd7zXZ\x00 definitely not xz").unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert!(dexz(&compressed, false).is_err());
+    assert_eq!(common::list_files(dir), vec!["syslog.2.xz"]);
+}
+
+#[test]
+fn gzip_inflates_across_members() {
+    let scratch = common::scratch_dir("gzip-members");
+    let dir = scratch.path();
+    let path = dir.join("syslog.2");
+    let compressed = dir.join("syslog.2.gz");
+    let gz = |data: &[u8]| {
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    };
+    // Two members, like those of `cat a.gz b.gz > c.gz`
+    std::fs::write(&compressed, [gz(b"first\n"), gz(b"second\n")].concat()).unwrap();
+
+    assert_eq!(inflated_len(&compressed).unwrap(), 13);
+    degunzip(&compressed, false).unwrap();
+    assert_eq!(common::list_files(dir), vec!["syslog.2"]);
+    assert_eq!(std::fs::read(&path).unwrap(), b"first\nsecond\n");
+}
+
+#[test]
+fn outputs_only_appear_once_complete() {
+    let scratch = common::scratch_dir("write-then-rename");
+    let dir = scratch.path();
+    let path = dir.join("syslog-2020-05-17");
+    let compressed = dir.join("syslog-2020-05-17.gz");
+    // Left behind by a run that died while compressing
+    std::fs::write(dir.join("syslog-2020-05-17.gz.tmp"), b"\x1f\x8b truncated").unwrap();
+    std::fs::write(&path, b"2020-05-17 a\n").unwrap();
+
+    gunzip(&path, Compression::default(), false).unwrap();
+    assert_eq!(common::list_files(dir), vec!["syslog-2020-05-17.gz"]);
+    assert_eq!(inflated_len(&compressed).unwrap(), 13);
+
+    // Failing to inflate leaves neither the output nor its temporary file
+    std::fs::write(&compressed, b"\x1f\x8b definitely not gzip").unwrap();
+    assert!(degunzip(&compressed, false).is_err());
+    assert_eq!(common::list_files(dir), vec!["syslog-2020-05-17.gz"]);
+}
+
+#[test]
+fn gunzip_records_the_original_name_and_mtime() {
+    let scratch = common::scratch_dir("gunzip-header");
+    let dir = scratch.path();
+    let path = dir.join("syslog-2020-05-17");
+    std::fs::write(&path, "2020-05-17 a\n").unwrap();
+    let mtime = std::time::UNIX_EPOCH + std::time::Duration::from_secs(1_589_680_036);
+    std::fs::File::options().write(true).open(&path).unwrap().set_modified(mtime).unwrap();
+
+    gunzip(&path, Compression::default(), false).unwrap();
+    let compressed = std::fs::File::open(dir.join("syslog-2020-05-17.gz")).unwrap();
+    let mut decoder = flate2::read::GzDecoder::new(compressed);
+    std::io::copy(&mut decoder, &mut std::io::sink()).unwrap();
+    let header = decoder.header().unwrap();
+    assert_eq!(header.filename(), Some(&b"syslog-2020-05-17"[..]));
+    assert_eq!(header.mtime(), 1_589_680_036);
+}
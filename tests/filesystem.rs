@@ -0,0 +1,1734 @@
+//! Directory-wide operations on scratch directories
+mod common;
+
+use std::fs::File;
+
+use flate2::read::GzDecoder;
+use flate2::Compression;
+use logboop::archive::{read_entries, TarWriter};
+use logboop::compress::{bzip2, gunzip, inflated_len, zstd};
+use logboop::filesystem::{bundle_days, consolidate_months, degunzip_all_the_files,
+                          delete_empty_dirs, gunzip_all_the_files, overlap, retry,
+                          rollup_months, walk};
+use logboop::error::LogBoopError;
+use logboop::process::{all_files, infer_year, one_file, single_file, Bucket, CustomFormat,
+                       FileProcessor, LogType, OutputStats, MANIFEST_NAME};
+use logboop::options::{glob_set, Codec, EmptyFiles, Granularity, Options, OutputFormat,
+                       Timezone};
+
+#[test]
+fn every_log_type_goes_through_a_compressed_round_trip() {
+    let scratch = common::scratch_dir("round-trip");
+    let dir = scratch.path();
+    let name = |lt: &LogType| lt.to_string().to_lowercase();
+    for lt in LogType::builtins() {
+        let input = dir.join("in").join(name(lt)).join("app.log.1");
+        common::write_file(&input, &(common::preamble(lt).to_owned() + &common::fixture(lt, 17)
+                                     + &common::fixture(lt, 18)));
+        gunzip(&input, Compression::default(), false).unwrap();
+    }
+    let options = Options::default();
+
+    degunzip_all_the_files(&dir.join("in"), &options).unwrap();
+    let stats = all_files(&dir.join("in"), &dir.join("out"), &options).unwrap();
+    gunzip_all_the_files(&dir.join("out"), &options).unwrap();
+    assert!(common::list_files(&dir.join("in")).is_empty());
+    assert_eq!(stats.files, LogType::builtins().len());
+    let may = infer_year("May", &chrono::Utc::now().date_naive());
+    for lt in LogType::builtins() {
+        assert_eq!(stats.per_type.get(lt), Some(&1), "{}", lt);
+        let year = match lt {
+            LogType::Syslog | LogType::CiscoSyslog => may,
+            _ => 2020
+        };
+        for day in [17, 18] {
+            let output = dir.join("out").join(name(lt))
+                .join(format!("app.log-{}-05-{}.gz", year, day));
+            assert_eq!(common::read_gz(&output), common::fixture(lt, day), "{}", lt);
+        }
+    }
+}
+
+#[test]
+fn rollup_bundles_old_dailies_by_month() {
+    let scratch = common::scratch_dir("rollup");
+    let dir = scratch.path();
+    common::write_file(&dir.join("app/syslog-2020-05-17"), "a\n");
+    common::write_file(&dir.join("app/syslog-2020-05-18"), "b\n");
+    common::write_file(&dir.join("app/syslog-2020-06-01"), "c\n");
+    let today = chrono::Local::now().date_naive();
+    let recent = format!("app/syslog-{}", today.format("%Y-%m-%d"));
+    common::write_file(&dir.join(&recent), "d\n");
+    // Neither daily archives nor files left behind are daily files
+    common::write_file(&dir.join("app/syslog-2020-05-20.gz.tmp"), "partial\n");
+    common::write_file(&dir.join("day-2020-05-17.tar.gz"), "bundled\n");
+
+    rollup_months(dir, 90).unwrap();
+    assert_eq!(common::list_files(dir), vec![
+        "app/syslog-2020-05-20.gz.tmp".to_owned(),
+        "app/syslog-2020-05.tar.gz".to_owned(),
+        "app/syslog-2020-06.tar.gz".to_owned(),
+        recent,
+        "day-2020-05-17.tar.gz".to_owned()
+    ]);
+    let may = File::open(dir.join("app/syslog-2020-05.tar.gz")).unwrap();
+    assert_eq!(read_entries(GzDecoder::new(may)).unwrap(), vec![
+        ("syslog-2020-05-17".to_owned(), b"a\n".to_vec()),
+        ("syslog-2020-05-18".to_owned(), b"b\n".to_vec())
+    ]);
+
+    // A second roll up extends the existing archive
+    common::write_file(&dir.join("app/syslog-2020-05-19"), "e\n");
+    rollup_months(dir, 90).unwrap();
+    let may = File::open(dir.join("app/syslog-2020-05.tar.gz")).unwrap();
+    let names: Vec<String> = read_entries(GzDecoder::new(may)).unwrap()
+        .into_iter().map(|(name, _)| name).collect();
+    assert_eq!(names, vec!["syslog-2020-05-17", "syslog-2020-05-18",
+                           "syslog-2020-05-19"]);
+}
+
+#[test]
+fn daily_bundles_keep_the_tree_of_their_files() {
+    let scratch = common::scratch_dir("bundle");
+    let dir = scratch.path();
+    common::write_file(&dir.join("nginx/access.log-2023-10-11"), "a\n");
+    common::write_file(&dir.join("sshd/auth.log-2023-10-11"), "b\n");
+    common::write_file(&dir.join("sshd/auth.log-2023-10-12"), "c\n");
+    // Compressed files are stored decompressed
+    let gz = dir.join("auth.log-2023-10-11");
+    common::write_file(&gz, "d\n");
+    gunzip(&gz, Compression::default(), false).unwrap();
+
+    bundle_days(dir).unwrap();
+    assert_eq!(common::list_files(dir), vec![
+        "day-2023-10-11.tar.gz", "day-2023-10-12.tar.gz"
+    ]);
+    let day = File::open(dir.join("day-2023-10-11.tar.gz")).unwrap();
+    assert_eq!(read_entries(GzDecoder::new(day)).unwrap(), vec![
+        ("auth.log-2023-10-11".to_owned(), b"d\n".to_vec()),
+        ("nginx/access.log-2023-10-11".to_owned(), b"a\n".to_vec()),
+        ("sshd/auth.log-2023-10-11".to_owned(), b"b\n".to_vec())
+    ]);
+
+    // Bundling again only adds the new files to the existing archives
+    common::write_file(&dir.join("sshd/auth.log-2023-10-11T05"), "e\n");
+    common::write_file(&dir.join("day-2023-10-12.tar.gz.tmp"), "left behind\n");
+    bundle_days(dir).unwrap();
+    assert_eq!(common::list_files(dir), vec![
+        "day-2023-10-11.tar.gz", "day-2023-10-12.tar.gz", "day-2023-10-12.tar.gz.tmp"
+    ]);
+    let day = File::open(dir.join("day-2023-10-11.tar.gz")).unwrap();
+    assert_eq!(read_entries(GzDecoder::new(day)).unwrap().len(), 4);
+    let day = File::open(dir.join("day-2023-10-12.tar.gz")).unwrap();
+    assert_eq!(read_entries(GzDecoder::new(day)).unwrap(), vec![
+        ("sshd/auth.log-2023-10-12".to_owned(), b"c\n".to_vec())
+    ]);
+}
+
+#[test]
+fn tar_entries_hold_their_whole_size_and_keep_their_type() {
+    // Sizes of 8 GiB or more fit in the header, but the data must be there
+    let mut writer = TarWriter::new(Vec::new());
+    let err = writer.append("huge", 8 << 30, 0o644, 0, &mut std::io::empty()).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::UnexpectedEof);
+
+    // Only regular files are read back, but every entry is copied
+    let mut builder = tar::Builder::new(Vec::new());
+    let mut header = tar::Header::new_gnu();
+    header.set_entry_type(tar::EntryType::Directory);
+    header.set_size(0);
+    builder.append_data(&mut header, "app", std::io::empty()).unwrap();
+    let mut header = tar::Header::new_gnu();
+    header.set_entry_type(tar::EntryType::Symlink);
+    header.set_size(0);
+    builder.append_link(&mut header, "app/latest", "app.log").unwrap();
+    let mut writer = TarWriter::new(Vec::new());
+    writer.copy_entries(&builder.into_inner().unwrap()[..]).unwrap();
+    writer.append("app/app.log", 2, 0o644, 0, &mut "a\n".as_bytes()).unwrap();
+    let archive = writer.finish().unwrap();
+    assert_eq!(read_entries(&archive[..]).unwrap(),
+               vec![("app/app.log".to_owned(), b"a\n".to_vec())]);
+    let types: Vec<tar::EntryType> = tar::Archive::new(&archive[..]).entries().unwrap()
+        .map(|entry| entry.unwrap().header().entry_type())
+        .collect();
+    assert_eq!(types, vec![tar::EntryType::Directory, tar::EntryType::Symlink,
+                           tar::EntryType::Regular]);
+}
+
+#[test]
+fn every_compression_format_is_inflated_by_its_first_bytes() {
+    let scratch = common::scratch_dir("degunzip-formats");
+    let dir = scratch.path();
+    for (name, content) in [("gz.log.1", "from gz\n"), ("zst.log.1", "from zst\n"),
+                            ("bz2.log.1", "from bz2\n"), ("misnamed.log.1", "misnamed\n"),
+                            ("renamed.log.1", "renamed\n")] {
+        common::write_file(&dir.join(name), content);
+    }
+    gunzip(&dir.join("gz.log.1"), Compression::default(), false).unwrap();
+    zstd(&dir.join("zst.log.1"), false).unwrap();
+    bzip2(&dir.join("bz2.log.1"), false).unwrap();
+    // Zstandard data named like GZ data
+    zstd(&dir.join("misnamed.log.1"), false).unwrap();
+    std::fs::rename(dir.join("misnamed.log.1.zst"), dir.join("misnamed.log.1.gz")).unwrap();
+    // XZ data compressed without being renamed
+    let mut encoder = ::xz2::write::XzEncoder::new(Vec::new(), 6);
+    std::io::Write::write_all(&mut encoder, b"renamed\n").unwrap();
+    std::fs::write(dir.join("renamed.log.1"), encoder.finish().unwrap()).unwrap();
+    std::fs::write(dir.join("xz.log.1.xz"), std::fs::read(dir.join("renamed.log.1")).unwrap())
+        .unwrap();
+
+    degunzip_all_the_files(dir, &Options::default()).unwrap();
+    assert_eq!(common::list_files(dir), ["bz2.log.1", "gz.log.1", "misnamed.log.1",
+                                          "renamed.log.1", "xz.log.1", "zst.log.1"]);
+    for (name, content) in [("gz.log.1", "from gz\n"), ("zst.log.1", "from zst\n"),
+                            ("bz2.log.1", "from bz2\n"), ("misnamed.log.1", "misnamed\n"),
+                            ("renamed.log.1", "renamed\n"), ("xz.log.1", "renamed\n")] {
+        assert_eq!(std::fs::read_to_string(dir.join(name)).unwrap(), content);
+    }
+}
+
+#[test]
+fn degunzip_keeps_gz_and_zst_of_the_same_base() {
+    let scratch = common::scratch_dir("degunzip");
+    let dir = scratch.path();
+    common::write_file(&dir.join("gz/syslog.1"), "from gz\n");
+    common::write_file(&dir.join("zst/syslog.1"), "from zst\n");
+    common::write_file(&dir.join("zst/syslog.2"), "only zst\n");
+    gunzip(&dir.join("gz/syslog.1"), Compression::default(), false).unwrap();
+    zstd(&dir.join("zst/syslog.1"), false).unwrap();
+    zstd(&dir.join("zst/syslog.2"), false).unwrap();
+    std::fs::rename(dir.join("gz/syslog.1.gz"), dir.join("syslog.1.gz")).unwrap();
+    std::fs::rename(dir.join("zst/syslog.1.zst"), dir.join("syslog.1.zst")).unwrap();
+    std::fs::rename(dir.join("zst/syslog.2.zst"), dir.join("syslog.2.zst")).unwrap();
+
+    // Only one of the two is inflated, the other one is kept for later
+    degunzip_all_the_files(dir, &Options::default()).unwrap();
+    let files = common::list_files(dir);
+    assert!(files.contains(&"syslog.1".to_owned()));
+    assert!(files.contains(&"syslog.2".to_owned()));
+    let kept: Vec<&String> = files.iter()
+        .filter(|f| *f == "syslog.1.gz" || *f == "syslog.1.zst").collect();
+    assert_eq!(kept.len(), 1);
+    let inflated = std::fs::read_to_string(dir.join("syslog.1")).unwrap();
+    let expected = if kept[0].ends_with(".gz") { "from zst\n" } else { "from gz\n" };
+    assert_eq!(inflated, expected);
+
+    // Once the plain file is gone, the other one is inflated as well
+    std::fs::remove_file(dir.join("syslog.1")).unwrap();
+    degunzip_all_the_files(dir, &Options::default()).unwrap();
+    assert!(!dir.join(kept[0]).exists());
+    assert!(dir.join("syslog.1").exists());
+}
+
+#[test]
+fn keep_source_leaves_inputs_and_reruns_cleanly() {
+    let scratch = common::scratch_dir("keep-source");
+    let dir = scratch.path();
+    let (input, output) = (dir.join("in"), dir.join("out"));
+    common::write_file(&input.join("app/fail2ban.log.1"),
+                       "2020-05-17 a\n2020-05-18 b\n");
+    common::write_file(&input.join("app/fail2ban.log.2"), "2020-05-16 c\n");
+    gunzip(&input.join("app/fail2ban.log.2"), Compression::default(), false).unwrap();
+    let options = Options { keep_source: true, ..Options::default() };
+
+    for _ in 0..2 {
+        degunzip_all_the_files(&input, &options).unwrap();
+        all_files(&input, &output, &options).unwrap();
+        gunzip_all_the_files(&output, &options).unwrap();
+    }
+    assert_eq!(common::list_files(&input), vec![
+        "app/fail2ban.log.1", "app/fail2ban.log.2", "app/fail2ban.log.2.gz"
+    ]);
+    assert_eq!(common::list_files(&output), vec![
+        "app/fail2ban.log-2020-05-16", "app/fail2ban.log-2020-05-16.gz",
+        "app/fail2ban.log-2020-05-17", "app/fail2ban.log-2020-05-17.gz",
+        "app/fail2ban.log-2020-05-18", "app/fail2ban.log-2020-05-18.gz"
+    ]);
+}
+
+#[test]
+fn hourly_outputs_are_compressed_and_rolled_up() {
+    let scratch = common::scratch_dir("hourly");
+    let dir = scratch.path();
+    let (input, output) = (dir.join("in"), dir.join("out"));
+    common::write_file(&input.join("fail2ban.log.1"),
+                       "2020-05-17 01:00:00 a\n2020-05-17 01:30:00 b\n2020-05-17 02:00:00 c\n");
+    let options = Options { granularity: Granularity::Hour, ..Options::default() };
+
+    all_files(&input, &output, &options).unwrap();
+    gunzip_all_the_files(&output, &options).unwrap();
+    assert_eq!(common::list_files(&output), vec![
+        "fail2ban.log-2020-05-17T01.gz", "fail2ban.log-2020-05-17T02.gz"
+    ]);
+    rollup_months(&output, 90).unwrap();
+    let may = File::open(output.join("fail2ban.log-2020-05.tar.gz")).unwrap();
+    assert_eq!(read_entries(GzDecoder::new(may)).unwrap(), vec![
+        ("fail2ban.log-2020-05-17T01".to_owned(), b"2020-05-17 01:00:00 a\n2020-05-17 01:30:00 b\n".to_vec()),
+        ("fail2ban.log-2020-05-17T02".to_owned(), b"2020-05-17 02:00:00 c\n".to_vec())
+    ]);
+}
+
+#[test]
+fn parallel_processing_keeps_rotated_files_apart() {
+    let scratch = common::scratch_dir("jobs");
+    let dir = scratch.path();
+    let (input, output) = (dir.join("in"), dir.join("out"));
+    // Every rotated file of every log appends to the same dated outputs
+    for log in 0..8 {
+        for rotation in 1..=4 {
+            let lines: String = (0..500)
+                .map(|n| format!("2020-05-{:02} {}.{}.{}\n", 17 + n % 2, log, rotation, n))
+                .collect();
+            common::write_file(&input.join(format!("log{}.{}", log, rotation)), &lines);
+        }
+    }
+    let options = Options { jobs: 4, ..Options::default() };
+
+    all_files(&input, &output, &options).unwrap();
+    assert!(common::list_files(&input).is_empty());
+    for log in 0..8 {
+        let out = std::fs::read_to_string(
+            output.join(format!("log{}-2020-05-17", log))).unwrap();
+        // Whole lines, every one of them, and rotated files one after the other
+        let mut rotations: Vec<&str> = out.lines()
+            .map(|line| line.split(' ').nth(1).unwrap().split('.').nth(1).unwrap())
+            .collect();
+        assert_eq!(rotations.len(), 4 * 250);
+        rotations.dedup();
+        assert_eq!(rotations.len(), 4);
+    }
+}
+
+#[test]
+fn dated_rotations_are_selected_by_regex() {
+    let scratch = common::scratch_dir("extensions");
+    let dir = scratch.path();
+    let (input, output) = (dir.join("in"), dir.join("out"));
+    common::write_file(&input.join("access.log-20231011"), "2023-10-11 a\n");
+    common::write_file(&input.join("access.log-20231012"), "2023-10-12 b\n");
+    common::write_file(&input.join("access.log"), "2023-10-13 c\n");
+
+    // Only numeric extensions by default
+    all_files(&input, &output, &Options::default()).unwrap();
+    assert!(common::list_files(&output).is_empty());
+
+    let options = Options {
+        rotated_files: Some(r"-\d{8}$".parse().unwrap()),
+        ..Options::default()
+    };
+    all_files(&input, &output, &options).unwrap();
+    assert_eq!(common::list_files(&input), vec!["access.log"]);
+    assert_eq!(common::list_files(&output), vec![
+        "access.log-2023-10-11", "access.log-2023-10-12"
+    ]);
+}
+
+#[test]
+fn verified_runs_keep_inputs_that_were_not_fully_written() {
+    let scratch = common::scratch_dir("verify");
+    let dir = scratch.path();
+    let (input, output) = (dir.join("in"), dir.join("out"));
+    common::write_file(&input.join("ok.log.1"), "2020-05-17 a\n2020-05-18 b");
+    // The second line is not UTF-8, so it cannot be read as a line
+    std::fs::write(input.join("ko.log.1"), b"2020-05-17 a\n2020-05-17 \xff\n").unwrap();
+    let gz = input.join("old.log.2");
+    common::write_file(&gz, "2020-05-16 c\n");
+    gunzip(&gz, Compression::default(), false).unwrap();
+    let options = Options { verify: true, ..Options::default() };
+
+    degunzip_all_the_files(&input, &options).unwrap();
+    let stats = all_files(&input, &output, &options).unwrap();
+    assert_eq!((stats.files, stats.failed), (2, 1));
+    assert_eq!(common::list_files(&input), vec!["ko.log.1"]);
+    // Nothing of the kept file was written
+    assert_eq!(std::fs::read_to_string(output.join("ok.log-2020-05-17")).unwrap(),
+               "2020-05-17 a\n");
+    assert!(!output.join("ko.log-2020-05-17").exists());
+    gunzip_all_the_files(&output, &options).unwrap();
+    assert_eq!(common::list_files(&output), vec![
+        "ok.log-2020-05-17.gz", "ok.log-2020-05-18.gz", "old.log-2020-05-16.gz"
+    ]);
+}
+
+#[test]
+fn mixed_compressed_inputs_are_inflated_in_one_pass() {
+    let scratch = common::scratch_dir("mixed-codecs");
+    let dir = scratch.path();
+    let (input, output) = (dir.join("in"), dir.join("out"));
+    common::write_file(&input.join("app.log.1"), "2020-05-18 a\n");
+    common::write_file(&input.join("app.log.2"), "2020-05-17 b\n");
+    gunzip(&input.join("app.log.2"), Compression::default(), false).unwrap();
+    common::write_file(&input.join("app.log.3"), "2020-05-16 c\n");
+    bzip2(&input.join("app.log.3"), false).unwrap();
+    common::write_file(&input.join("app.log.4"), "2020-05-15 d\n");
+    zstd(&input.join("app.log.4"), false).unwrap();
+    let options = Options { codec: Codec::Bzip2, ..Options::default() };
+
+    degunzip_all_the_files(&input, &options).unwrap();
+    assert_eq!(common::list_files(&input),
+               vec!["app.log.1", "app.log.2", "app.log.3", "app.log.4"]);
+    all_files(&input, &output, &options).unwrap();
+    gunzip_all_the_files(&output, &options).unwrap();
+    assert_eq!(common::list_files(&output),
+               vec!["app.log-2020-05-15.bz2", "app.log-2020-05-16.bz2",
+                    "app.log-2020-05-17.bz2", "app.log-2020-05-18.bz2"]);
+
+    // Read as they are, and written compressed right away
+    common::write_file(&input.join("app.log.5"), "2020-05-16 e\n");
+    bzip2(&input.join("app.log.5"), false).unwrap();
+    let options = Options { read_compressed: true, compress_directly: true, ..options };
+    all_files(&input, &output, &options).unwrap();
+    assert_eq!(inflated_len(&output.join("app.log-2020-05-16.bz2")).unwrap(), 26);
+}
+
+#[test]
+fn nothing_is_started_once_asked_to_stop() {
+    let scratch = common::scratch_dir("stop");
+    let dir = scratch.path();
+    let (input, output) = (dir.join("in"), dir.join("out"));
+    common::write_file(&input.join("app.log.1"), "2020-05-17 a\n");
+    common::write_file(&input.join("app.log.2"), "2020-05-16 b\n");
+    gunzip(&input.join("app.log.2"), Compression::default(), false).unwrap();
+    common::write_file(&output.join("app.log-2020-05-15"), "2020-05-15 c\n");
+    let stop = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(true));
+    let options = Options { stop: Some(stop), ..Options::default() };
+
+    degunzip_all_the_files(&input, &options).unwrap();
+    let stats = all_files(&input, &output, &options).unwrap();
+    gunzip_all_the_files(&output, &options).unwrap();
+    assert_eq!(stats.files, 0);
+    assert_eq!(common::list_files(&input), vec!["app.log.1", "app.log.2.gz"]);
+    assert_eq!(common::list_files(&output), vec!["app.log-2020-05-15"]);
+}
+
+#[test]
+fn weekly_outputs_are_compressed() {
+    let scratch = common::scratch_dir("weekly");
+    let dir = scratch.path();
+    let (input, output) = (dir.join("in"), dir.join("out"));
+    common::write_file(&input.join("auth.log.1"),
+                       "2023-10-15 a\n2023-10-16 b\n2023-10-17 c\n");
+    let options = Options { granularity: Granularity::Week, ..Options::default() };
+
+    all_files(&input, &output, &options).unwrap();
+    assert_eq!(std::fs::read_to_string(output.join("auth.log-2023-W42")).unwrap(),
+               "2023-10-16 b\n2023-10-17 c\n");
+    gunzip_all_the_files(&output, &options).unwrap();
+    assert_eq!(common::list_files(&output),
+               vec!["auth.log-2023-W41.gz", "auth.log-2023-W42.gz"]);
+}
+
+#[test]
+fn files_under_the_minimum_size_are_left_alone() {
+    let scratch = common::scratch_dir("min-size");
+    let dir = scratch.path();
+    let (input, output) = (dir.join("in"), dir.join("out"));
+    common::write_file(&input.join("stub.log.1"), "2020-05-17 a\n");
+    common::write_file(&input.join("app.log.1"),
+                       "2020-05-17 a rather long line\n2020-05-18 b\n");
+    let options = Options { min_size: 20, ..Options::default() };
+
+    let stats = all_files(&input, &output, &options).unwrap();
+    assert_eq!(stats.files, 1);
+    assert_eq!(common::list_files(&input), vec!["stub.log.1"]);
+    gunzip_all_the_files(&output, &options).unwrap();
+    assert_eq!(common::list_files(&output),
+               vec!["app.log-2020-05-17.gz", "app.log-2020-05-18"]);
+}
+
+#[test]
+fn custom_formats_are_loaded_and_detected_first() {
+    let scratch = common::scratch_dir("custom-formats");
+    let dir = scratch.path();
+    let formats = dir.join("formats.json");
+    common::write_file(&formats, r#"[
+        {"name": "myapp", "regex": "^\\|(?P<ts>\\d{8} \\d{2}h\\d{2})\\|", "format": "%Y%m%d %Hh%M"},
+        {"name": "isoish", "regex": "^\\d{4}-\\d{2}-\\d{2}", "format": "%Y-%m-%d"}
+    ]"#);
+    let options = Options {
+        formats: CustomFormat::load(&formats).unwrap(),
+        granularity: Granularity::Hour,
+        ..Options::default()
+    };
+    let (input, output) = (dir.join("in"), dir.join("out"));
+    common::write_file(&input.join("myapp.log.1"),
+                       "|20231011 22h14| a\n  continued\n|20231011 23h01| b\n");
+    common::write_file(&input.join("other.log.1"), "2023-10-11 22:14:15 c\n");
+
+    let stats = all_files(&input, &output, &options).unwrap();
+    assert_eq!(stats.per_type.get(&LogType::Custom("myapp".to_owned())), Some(&1));
+    // Tried before the built-in formats, and falling back to daily buckets
+    assert_eq!(stats.per_type.get(&LogType::Custom("isoish".to_owned())), Some(&1));
+    assert_eq!(common::list_files(&output),
+               vec!["myapp.log-2023-10-11T22", "myapp.log-2023-10-11T23",
+                    "other.log-2023-10-11"]);
+    assert_eq!(std::fs::read_to_string(output.join("myapp.log-2023-10-11T22")).unwrap(),
+               "|20231011 22h14| a\n  continued\n");
+    assert!(stats.to_string().contains("  isoish : 1\n  myapp : 1\n"));
+
+    // Invalid files of formats are told apart from unreadable ones
+    let invalid = [r#"{"name": "a"}"#,
+                   r#"[{"name": "Iso", "regex": "^", "format": "%Y"}]"#,
+                   r#"[{"name": "a", "regex": "(", "format": "%Y"}]"#,
+                   r#"[{"name": "a", "regex": "^", "format": "%Q"}]"#,
+                   r#"[{"name": "a", "regex": "^"}]"#];
+    for text in invalid.iter() {
+        common::write_file(&formats, text);
+        assert!(matches!(CustomFormat::load(&formats),
+                         Err(LogBoopError::FormatError { .. })), "{}", text);
+    }
+    assert!(matches!(CustomFormat::load(&dir.join("missing.json")),
+                     Err(LogBoopError::IoError(_))));
+}
+
+#[test]
+fn crlf_line_endings_are_normalized_unless_kept() {
+    let scratch = common::scratch_dir("crlf");
+    let dir = scratch.path();
+    let formats = dir.join("formats.json");
+    // Only matches once the line ending is out of the way
+    common::write_file(&formats, r#"[
+        {"name": "stamped", "regex": "at (?P<ts>\\d{8})$", "format": "%Y%m%d"}
+    ]"#);
+    let (input, output) = (dir.join("in"), dir.join("out"));
+    let write_inputs = || {
+        common::write_file(&input.join("app.log.1"), "2020-05-17 a\r\ncontinued\r\n");
+        common::write_file(&input.join("job.log.1"), "done at 20200518\r\n");
+        common::write_file(&input.join("notes.txt.1"), "no date\r\n");
+    };
+    let read = |name: &str| std::fs::read_to_string(output.join(name)).unwrap();
+    let options = Options { formats: CustomFormat::load(&formats).unwrap(),
+                            ..Options::default() };
+
+    write_inputs();
+    let stats = all_files(&input, &output, &options).unwrap();
+    assert_eq!(stats.per_type.get(&LogType::Iso), Some(&1));
+    assert_eq!(stats.per_type.get(&LogType::Custom("stamped".to_owned())), Some(&1));
+    assert_eq!(read("app.log-2020-05-17"), "2020-05-17 a\ncontinued\n");
+    assert_eq!(read("job.log-2020-05-18"), "done at 20200518\n");
+    assert_eq!(read("notes.txt.unknown"), "no date\n");
+
+    std::fs::remove_dir_all(&output).unwrap();
+    write_inputs();
+    let options = Options { keep_line_endings: true, ..options };
+    all_files(&input, &output, &options).unwrap();
+    assert_eq!(read("app.log-2020-05-17"), "2020-05-17 a\r\ncontinued\r\n");
+    assert_eq!(read("job.log-2020-05-18"), "done at 20200518\r\n");
+    assert_eq!(read("notes.txt.unknown"), "no date\r\n");
+}
+
+#[test]
+fn manifest_maps_outputs_back_to_their_sources() {
+    let scratch = common::scratch_dir("manifest");
+    let dir = scratch.path();
+    let (input, output) = (dir.join("in"), dir.join("out"));
+    common::write_file(&input.join("app.log.1"), "2020-05-17 a\n2020-05-18 b\n2020-05-18 c\n");
+    common::write_file(&input.join("app.log.2"), "2020-05-17 d\n");
+    common::write_file(&input.join("notes.txt.1"), "no date\nat all\n");
+    let options = Options { manifest: true, ..Options::default() };
+    all_files(&input, &output, &options).unwrap();
+
+    let manifest = std::fs::read_to_string(output.join(MANIFEST_NAME)).unwrap();
+    let mut records: Vec<serde_json::Value> = manifest.lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    records.sort_by_key(|record| (record["source"].to_string(), record["output"].to_string()));
+    let record = |source: &str, logtype: Option<&str>, name: &str, date: Option<&str>,
+                  lines: u64| serde_json::json!({
+        "source": input.join(source).to_str().unwrap(),
+        "type": logtype,
+        "output": output.join(name).to_str().unwrap(),
+        "date": date,
+        "lines": lines
+    });
+    assert_eq!(records, vec![
+        record("app.log.1", Some("Iso"), "app.log-2020-05-17", Some("2020-05-17"), 1),
+        record("app.log.1", Some("Iso"), "app.log-2020-05-18", Some("2020-05-18"), 2),
+        record("app.log.2", Some("Iso"), "app.log-2020-05-17", Some("2020-05-17"), 1),
+        record("notes.txt.1", None, "notes.txt.unknown", None, 2)
+    ]);
+
+    // Later runs add their own records
+    common::write_file(&input.join("app.log.1"), "2020-05-19 e\n");
+    all_files(&input, &output, &options).unwrap();
+    let manifest = std::fs::read_to_string(output.join(MANIFEST_NAME)).unwrap();
+    assert_eq!(manifest.lines().count(), 5);
+}
+
+#[test]
+fn file_names_can_tell_the_log_type() {
+    let scratch = common::scratch_dir("type-hints");
+    let dir = scratch.path();
+    let (input, output) = (dir.join("in"), dir.join("out"));
+    // Banners longer than the detection lines would leave these unknown
+    let banner = "### device export ###\n".repeat(30);
+    common::write_file(&input.join("router.iso.1"),
+                       &format!("{}2020-05-17 a\n2020-05-18 b\n", banner));
+    // Names are trusted even when the lines do not look like their type
+    common::write_file(&input.join("odd.syslog.1"), "no date here\n");
+    common::write_file(&input.join("other.log.1"), &format!("{}2020-05-17 c\n", banner));
+    let stats = all_files(&input, &output, &Options::default()).unwrap();
+    assert_eq!(stats.per_type.get(&LogType::Iso), Some(&1));
+    assert_eq!(stats.per_type.get(&LogType::Syslog), Some(&1));
+    assert_eq!(stats.unknown, 1);
+    assert_eq!(common::list_files(&output), vec!["odd.syslog.unknown",
+                                                 "other.log.unknown",
+                                                 "router.iso-2020-05-17",
+                                                 "router.iso-2020-05-18",
+                                                 "router.iso.unknown"]);
+    assert_eq!(std::fs::read_to_string(output.join("router.iso.unknown")).unwrap(), banner);
+}
+
+#[test]
+fn only_empty_input_directories_are_removed() {
+    let scratch = common::scratch_dir("empty-dirs");
+    let dir = scratch.path();
+    let (input, output) = (dir.join("in"), dir.join("out"));
+    common::write_file(&input.join("a/b/app.log.1"), "2020-05-17 a\n");
+    common::write_file(&input.join("c/app.log.1"), "2020-05-17 b\n");
+    common::write_file(&input.join("c/notes.txt"), "kept\n");
+    common::write_file(&input.join("d/e/app.log.1"), "2020-05-17 c\n");
+    common::write_file(&input.join("d/app.log.2"), "2020-05-16 d\n");
+    all_files(&input, &output, &Options::default()).unwrap();
+
+    let removed = delete_empty_dirs(&input, &Options::default()).unwrap();
+    // `a/b`, then `a`, then `d/e`, then `d`
+    assert_eq!(removed, 4);
+    assert!(input.is_dir());
+    let mut left: Vec<String> = std::fs::read_dir(&input).unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+        .collect();
+    left.sort();
+    assert_eq!(left, vec!["c"]);
+    assert_eq!(common::list_files(&input), vec!["c/notes.txt"]);
+
+    // The input directory stays, even once empty
+    std::fs::remove_file(input.join("c/notes.txt")).unwrap();
+    assert_eq!(delete_empty_dirs(&input, &Options::default()).unwrap(), 1);
+    assert!(input.is_dir());
+    assert_eq!(std::fs::read_dir(&input).unwrap().count(), 0);
+}
+
+#[test]
+fn offsets_are_kept_in_output_names() {
+    let scratch = common::scratch_dir("offset-names");
+    let dir = scratch.path();
+    let (input, output) = (dir.join("in"), dir.join("out"));
+    common::write_file(&input.join("access.log.1"), concat!(
+        "10.0.0.1 - - [11/Oct/2023:13:55:36 +0200] \"GET / HTTP/1.1\" 200 42\n",
+        "10.0.0.2 - - [11/Oct/2023:07:55:37 -0500] \"GET / HTTP/1.1\" 200 42\n",
+        "10.0.0.3 - - [12/Oct/2023:00:10:00 +0200] \"GET / HTTP/1.1\" 200 42\n"));
+    let options = Options { timezone: Timezone::Offset, granularity: Granularity::Hour,
+                            ..Options::default() };
+    let stats = all_files(&input, &output, &options).unwrap();
+    assert_eq!(stats.outputs, 3);
+    assert_eq!(common::list_files(&output), vec!["access.log-2023-10-11T07-0500",
+                                                 "access.log-2023-10-11T13+0200",
+                                                 "access.log-2023-10-12T00+0200"]);
+    // Offset-suffixed names are output files all the same
+    gunzip_all_the_files(&output, &options).unwrap();
+    assert_eq!(common::list_files(&output), vec!["access.log-2023-10-11T07-0500.gz",
+                                                 "access.log-2023-10-11T13+0200.gz",
+                                                 "access.log-2023-10-12T00+0200.gz"]);
+}
+
+#[test]
+fn max_depth_limits_the_input_walk() {
+    let scratch = common::scratch_dir("max-depth");
+    let dir = scratch.path();
+    let (input, output) = (dir.join("in"), dir.join("out"));
+    let write_inputs = || {
+        common::write_file(&input.join("top.log.1"), "2020-05-17 a\n");
+        common::write_file(&input.join("app/app.log.1"), "2020-05-17 b\n");
+        common::write_file(&input.join("containers/abc/ctr.log.1"), "2020-05-17 c\n");
+    };
+
+    // Depth 1 is the files right in the input directory
+    write_inputs();
+    let options = Options { max_depth: Some(1), ..Options::default() };
+    all_files(&input, &output, &options).unwrap();
+    assert_eq!(common::list_files(&output), vec!["top.log-2020-05-17"]);
+    assert_eq!(common::list_files(&input), vec!["app/app.log.1",
+                                                "containers/abc/ctr.log.1"]);
+    std::fs::remove_dir_all(dir).unwrap();
+
+    write_inputs();
+    let options = Options { max_depth: Some(2), ..Options::default() };
+    all_files(&input, &output, &options).unwrap();
+    assert_eq!(common::list_files(&output), vec!["app/app.log-2020-05-17",
+                                                 "top.log-2020-05-17"]);
+    assert_eq!(common::list_files(&input), vec!["containers/abc/ctr.log.1"]);
+    std::fs::remove_dir_all(dir).unwrap();
+
+    // Compressed files are only inflated within the same depth
+    common::write_file(&input.join("top.log.1"), "2020-05-17 a\n");
+    common::write_file(&input.join("app/app.log.1"), "2020-05-17 b\n");
+    gunzip(&input.join("top.log.1"), Compression::default(), false).unwrap();
+    gunzip(&input.join("app/app.log.1"), Compression::default(), false).unwrap();
+    let options = Options { max_depth: Some(1), ..Options::default() };
+    degunzip_all_the_files(&input, &options).unwrap();
+    assert_eq!(common::list_files(&input), vec!["app/app.log.1.gz", "top.log.1"]);
+}
+
+#[test]
+fn shuffled_dates_land_in_their_own_buckets() {
+    let scratch = common::scratch_dir("shuffled");
+    let dir = scratch.path();
+    let (input, output) = (dir.join("in"), dir.join("out"));
+    let lines = ["2023-10-11 a", "2023-10-10 b", "2023-10-11 c", "2023-10-12 d",
+                 "2023-10-10 e", "2023-10-11 f", "2023-10-12 g", "2023-10-10 h"];
+    let expected = |date: &str| lines.iter()
+        .filter(|line| line.starts_with(date))
+        .map(|line| format!("{}\n", line))
+        .collect::<String>();
+    let read = |date: &str| std::fs::read_to_string(
+        output.join(format!("app.log-{}", date))).unwrap();
+    // Whether writers stay open or are closed after every line, each
+    // bucket gets its lines, in order, appended to what it already held
+    for max_open_files in [16, 1] {
+        common::write_file(&output.join("app.log-2023-10-10"), "earlier\n");
+        common::write_file(&input.join("app.log.1"), &(lines.join("\n") + "\n"));
+        let options = Options { max_open_files, ..Options::default() };
+        let stats = all_files(&input, &output, &options).unwrap();
+        assert_eq!((stats.lines, stats.outputs), (8, 3));
+        assert_eq!(read("2023-10-10"), format!("earlier\n{}", expected("2023-10-10")));
+        assert_eq!(read("2023-10-11"), expected("2023-10-11"));
+        assert_eq!(read("2023-10-12"), expected("2023-10-12"));
+        std::fs::remove_dir_all(&output).unwrap();
+    }
+}
+
+#[test]
+fn sanitized_output_names_keep_their_dates() {
+    let scratch = common::scratch_dir("sanitize");
+    let dir = scratch.path();
+    let (input, output) = (dir.join("in"), dir.join("out"));
+    common::write_file(&input.join("Web Server/Access Log.1"), "2020-05-17 a\n");
+    common::write_file(&input.join("Web Server/access+log.1"), "2020-05-17 b\n");
+    common::write_file(&input.join("Web Server/App.log.1"), "2020-05-18 c\n");
+    let options = Options { sanitize_names: true, ..Options::default() };
+    let stats = all_files(&input, &output, &options).unwrap();
+    assert_eq!((stats.files, stats.outputs), (3, 2));
+    assert_eq!(common::list_files(&output), vec!["web_server/access_log-2020-05-17",
+                                                 "web_server/app.log-2020-05-18"]);
+    // Both inputs were appended to the same output
+    let merged = std::fs::read_to_string(output.join("web_server/access_log-2020-05-17"))
+        .unwrap();
+    assert_eq!(merged.lines().count(), 2);
+}
+
+#[test]
+fn compressed_inputs_are_read_without_inflating_them() {
+    let scratch = common::scratch_dir("read-compressed");
+    let dir = scratch.path();
+    let (input, output) = (dir.join("in"), dir.join("out"));
+    common::write_file(&input.join("app.log.1"), "2020-05-18 a\n");
+    common::write_file(&input.join("app.log.2"), "2020-05-17 b\n");
+    gunzip(&input.join("app.log.2"), Compression::default(), false).unwrap();
+    common::write_file(&input.join("app.log.3"), "2020-05-16 c\n");
+    zstd(&input.join("app.log.3"), false).unwrap();
+    // Compressed, but not named so
+    common::write_file(&input.join("other.log.2"), "2020-05-17 d\n");
+    gunzip(&input.join("other.log.2"), Compression::default(), false).unwrap();
+    std::fs::rename(input.join("other.log.2.gz"), input.join("other.log.1")).unwrap();
+    let options = Options { read_compressed: true, verify: true, ..Options::default() };
+
+    let stats = all_files(&input, &output, &options).unwrap();
+    assert_eq!((stats.files, stats.lines), (4, 4));
+    assert!(common::list_files(&input).is_empty());
+    let read = |name: &str| std::fs::read_to_string(output.join(name)).unwrap();
+    assert_eq!(read("app.log-2020-05-16"), "2020-05-16 c\n");
+    assert_eq!(read("app.log-2020-05-17"), "2020-05-17 b\n");
+    assert_eq!(read("other.log-2020-05-17"), "2020-05-17 d\n");
+
+    // A corrupted input is kept
+    common::write_file(&input.join("app.log.4.gz"), "\x1f\u{8b}garbage");
+    let stats = all_files(&input, &output, &options).unwrap();
+    assert_eq!(stats.failed, 1);
+    assert_eq!(common::list_files(&input), vec!["app.log.4.gz"]);
+}
+
+#[test]
+fn flattened_outputs_merge_inputs_of_the_same_name() {
+    let scratch = common::scratch_dir("flatten");
+    let dir = scratch.path();
+    let (input, output) = (dir.join("in"), dir.join("out"));
+    for host in 0..8 {
+        let lines: String = (0..500)
+            .map(|n| format!("2020-05-17 {}.{}\n", host, n))
+            .collect();
+        common::write_file(&input.join(format!("host{}/syslog.1", host)), &lines);
+    }
+    common::write_file(&input.join("host0/auth.log.1"), "2020-05-18 a\n");
+    let options = Options { flatten: true, jobs: 4, ..Options::default() };
+
+    all_files(&input, &output, &options).unwrap();
+    assert_eq!(common::list_files(&output), vec![
+        "auth.log-2020-05-18", "syslog-2020-05-17"
+    ]);
+    // Whole lines, every one of them, and inputs one after the other
+    let out = std::fs::read_to_string(output.join("syslog-2020-05-17")).unwrap();
+    let mut hosts: Vec<&str> = out.lines()
+        .map(|line| line.split(' ').nth(1).unwrap().split('.').next().unwrap())
+        .collect();
+    assert_eq!(hosts.len(), 8 * 500);
+    hosts.dedup();
+    assert_eq!(hosts.len(), 8);
+}
+
+#[test]
+fn file_processor_is_usable_from_the_library() {
+    let scratch = common::scratch_dir("file-processor");
+    let dir = scratch.path();
+    let input = dir.join("in/fail2ban.log.1");
+    common::write_file(&input, "2020-05-17 02:07:16 a\n");
+    let options = Options::default();
+
+    let mut proco = FileProcessor::new(input.clone(), dir.join("out/fail2ban.log"),
+                                       &options);
+    assert_eq!(proco.logtype(), None);
+    proco.determine_type().unwrap();
+    assert_eq!(proco.logtype(), Some(LogType::Iso));
+    assert_eq!(proco.path(), input.as_path());
+    let stats = proco.process().unwrap();
+    assert_eq!((stats.logtype, stats.lines), (Some(LogType::Iso), 1));
+    let date = chrono::NaiveDate::from_ymd_opt(2020, 5, 17).unwrap();
+    assert_eq!(stats.outputs, vec![OutputStats {
+        path: dir.join("out/fail2ban.log-2020-05-17"),
+        bucket: Some(Bucket { date, hour: None, week: false, offset: None, vhost: None }),
+        lines: 1
+    }]);
+    assert_eq!(common::list_files(dir), vec!["out/fail2ban.log-2020-05-17"]);
+
+    // The whole pipeline is reachable from the root of the crate
+    common::write_file(&dir.join("in/fail2ban.log.2"), "2020-05-16 b\n");
+    logboop::degunzip_all_the_files(&dir.join("in"), &options).unwrap();
+    logboop::all_files(&dir.join("in"), &dir.join("out"), &options).unwrap();
+    logboop::gunzip_all_the_files(&dir.join("out"), &options).unwrap();
+    assert_eq!(common::list_files(dir), vec![
+        "out/fail2ban.log-2020-05-16.gz", "out/fail2ban.log-2020-05-17.gz"
+    ]);
+}
+
+#[test]
+fn syslog_year_comes_from_the_file_modification_time() {
+    let scratch = common::scratch_dir("syslog-mtime");
+    let dir = scratch.path();
+    // Last modified on 2021-01-02, and kept once inflated
+    let mtime = std::time::UNIX_EPOCH + std::time::Duration::from_secs(1_609_545_600);
+    for name in ["syslog.1", "auth.log.1"] {
+        let input = dir.join("in").join(name);
+        common::write_file(&input, "Dec 31 23:59:59 host a\nJan  1 00:00:01 host b\n");
+        File::options().write(true).open(&input).unwrap().set_modified(mtime).unwrap();
+    }
+    let gz = dir.join("in/auth.log.1.gz");
+    gunzip(&dir.join("in/auth.log.1"), Compression::default(), false).unwrap();
+    File::options().write(true).open(&gz).unwrap().set_modified(mtime).unwrap();
+
+    degunzip_all_the_files(&dir.join("in"), &Options::default()).unwrap();
+    all_files(&dir.join("in"), &dir.join("out"), &Options::default()).unwrap();
+    assert_eq!(common::list_files(&dir.join("out")), vec![
+        "auth.log-2020-12-31", "auth.log-2021-01-01",
+        "syslog-2020-12-31", "syslog-2021-01-01"
+    ]);
+}
+
+#[test]
+fn unmatched_lines_go_to_the_unknown_file() {
+    let scratch = common::scratch_dir("unknown");
+    let dir = scratch.path();
+    common::write_file(&dir.join("in/app.log.1"),
+                       "2020-02-31 tampered\n2020-05-17 a\nnot dated\n");
+    common::write_file(&dir.join("in/notes.txt.1"), "no date here\nnor here\n");
+
+    all_files(&dir.join("in"), &dir.join("out"), &Options::default()).unwrap();
+    assert_eq!(common::list_files(&dir.join("out")), vec![
+        "app.log-2020-05-17", "app.log.unknown", "notes.txt.unknown"
+    ]);
+    let read = |name: &str| std::fs::read_to_string(dir.join("out").join(name)).unwrap();
+    assert_eq!(read("app.log.unknown"), "2020-02-31 tampered\n");
+    assert_eq!(read("app.log-2020-05-17"), "2020-05-17 a\nnot dated\n");
+    assert_eq!(read("notes.txt.unknown"), "no date here\nnor here\n");
+    // Nothing was lost, so the inputs are gone
+    assert!(common::list_files(&dir.join("in")).is_empty());
+}
+
+#[test]
+fn files_of_other_log_types_are_left_untouched() {
+    let scratch = common::scratch_dir("only-types");
+    let dir = scratch.path();
+    common::write_file(&dir.join("in/grafana.log.1"),
+                       "t=2020-05-17T18:14:21+0200 lvl=info msg=a\n");
+    common::write_file(&dir.join("in/app.log.1"), "2020-05-17 a\n");
+    common::write_file(&dir.join("in/notes.txt.1"), "no date here\n");
+    common::write_file(&dir.join("in/blank.log.1"), "\n");
+    let options = Options { only_types: vec![LogType::GrafanaLogs], ..Options::default() };
+
+    let stats = all_files(&dir.join("in"), &dir.join("out"), &options).unwrap();
+    assert_eq!((stats.files, stats.filtered), (1, 3));
+    assert_eq!(stats.per_type.get(&LogType::GrafanaLogs), Some(&1));
+    assert!(stats.to_string().contains("files skipped (filtered) : 3"));
+    assert_eq!(common::list_files(&dir.join("out")), vec!["grafana.log-2020-05-17"]);
+    assert_eq!(common::list_files(&dir.join("in")),
+               vec!["app.log.1", "blank.log.1", "notes.txt.1"]);
+}
+
+#[test]
+fn single_files_are_split_right_in_the_output_directory() {
+    let scratch = common::scratch_dir("single-file");
+    let dir = scratch.path();
+    let out = dir.join("out");
+    common::write_file(&dir.join("huge.log"), "2020-05-17 a\n2020-05-18 b\n");
+    common::write_file(&dir.join("other.log"), "2020-05-17 c\n");
+    gunzip(&dir.join("other.log"), Compression::default(), false).unwrap();
+
+    let stats = single_file(&dir.join("huge.log"), &out, &Options::default()).unwrap();
+    assert_eq!((stats.files, stats.lines, stats.outputs), (1, 2, 2));
+    let stats = single_file(&dir.join("other.log.gz"), &out, &Options::default()).unwrap();
+    assert_eq!((stats.files, stats.lines), (1, 1));
+    assert_eq!(common::list_files(&out),
+               vec!["huge-2020-05-17", "huge-2020-05-18", "other-2020-05-17"]);
+    assert!(!dir.join("huge.log").exists());
+
+    // A file that cannot be read is counted as failed
+    let stats = single_file(&dir.join("missing.log"), &out, &Options::default()).unwrap();
+    assert_eq!((stats.files, stats.failed), (0, 1));
+}
+
+#[test]
+fn pipelined_runs_inflate_process_and_compress_in_one_walk() {
+    let scratch = common::scratch_dir("pipeline");
+    let dir = scratch.path();
+    let (input, output) = (dir.join("in"), dir.join("out"));
+    for n in 0..20 {
+        common::write_file(&input.join(format!("app{}.log.1", n)), "2020-05-18 a\n");
+        common::write_file(&input.join(format!("app{}.log.2", n)), "2020-05-17 b\n");
+        gunzip(&input.join(format!("app{}.log.2", n)), Compression::default(), false).unwrap();
+    }
+    common::write_file(&input.join("notes.txt"), "left alone\n");
+    // One thread per phase, so that groups queue up between phases
+    let options = Options { threads_per_phase: 1, ..Options::default() };
+
+    let stats = logboop::pipeline::run(&input, &output, &options).unwrap();
+    assert_eq!((stats.files, stats.failed, stats.lines, stats.outputs), (40, 0, 40, 40));
+    assert_eq!(common::list_files(&input), vec!["notes.txt"]);
+    let outputs = common::list_files(&output);
+    assert_eq!(outputs.len(), 40);
+    assert!(outputs.iter().all(|name| name.ends_with(".gz")));
+    assert_eq!(inflated_len(&output.join("app7.log-2020-05-17.gz")).unwrap(), 13);
+}
+
+#[test]
+fn pipelines_may_skip_inflating_and_compressing() {
+    let scratch = common::scratch_dir("pipeline-phases");
+    let dir = scratch.path();
+    let (input, output) = (dir.join("in"), dir.join("out"));
+    common::write_file(&input.join("app.log.1"), "2020-05-18 a\n");
+    common::write_file(&input.join("app.log.2"), "2020-05-17 b\n");
+    gunzip(&input.join("app.log.2"), Compression::default(), false).unwrap();
+    let options = Options { no_degunzip: true, no_recompress: true, ..Options::default() };
+
+    let stats = logboop::pipeline::run(&input, &output, &options).unwrap();
+    assert_eq!((stats.files, stats.failed, stats.lines), (1, 0, 1));
+    // The compressed file is left as it is, and the output file plain
+    assert_eq!(common::list_files(&input), vec!["app.log.2.gz"]);
+    assert_eq!(common::list_files(&output), vec!["app.log-2020-05-18"]);
+}
+
+#[test]
+fn run_summary_counts_files_outputs_and_lines() {
+    let scratch = common::scratch_dir("summary");
+    let dir = scratch.path();
+    common::write_file(&dir.join("in/app.log.1"), "2020-05-17 a\n2020-05-18 b\n");
+    common::write_file(&dir.join("in/app.log.2"), "2020-05-17 c\n");
+    common::write_file(&dir.join("in/notes.txt.1"), "no date here\nnor here\n");
+
+    let stats = all_files(&dir.join("in"), &dir.join("out"), &Options::default()).unwrap();
+    assert_eq!((stats.files, stats.unknown, stats.failed), (3, 1, 0));
+    assert_eq!(stats.per_type.get(&LogType::Iso), Some(&2));
+    assert_eq!(stats.per_type.len(), 1);
+    // Both rotated files write to the same output of the 17th
+    assert_eq!((stats.outputs, stats.lines), (3, 5));
+    assert_eq!(stats.to_json(), r#"{"empty":0,"failed":0,"files":3,"filtered":0,"lines":5,"outputs":3,"per_type":{"Iso":2},"unknown":1}"#);
+    assert!(stats.to_string().contains("Iso : 2"));
+}
+
+#[test]
+fn empty_files_are_deleted_or_marked() {
+    let scratch = common::scratch_dir("empty");
+    let dir = scratch.path();
+    let (input, output) = (dir.join("in"), dir.join("out"));
+    common::write_file(&input.join("app.log.1"), "");
+    common::write_file(&input.join("app.log.2"), " \n\t\n");
+    common::write_file(&input.join("app.log.3"), "2020-05-17 a\n");
+
+    let stats = all_files(&input, &output, &Options::default()).unwrap();
+    assert_eq!((stats.files, stats.empty, stats.unknown), (3, 2, 0));
+    assert!(common::list_files(&input).is_empty());
+    assert_eq!(common::list_files(&output), vec!["app.log-2020-05-17"]);
+
+    common::write_file(&input.join("app.log.1"), "");
+    common::write_file(&input.join("app.log.2"), " \n\t\n");
+    let options = Options { empty_files: EmptyFiles::Mark, ..Options::default() };
+    all_files(&input, &output, &options).unwrap();
+    assert!(common::list_files(&input).is_empty());
+    assert_eq!(common::list_files(&output), vec!["app.log-2020-05-17", "app.log.empty"]);
+    assert_eq!(std::fs::read_to_string(output.join("app.log.empty")).unwrap(), " \n\t\n");
+}
+
+#[test]
+fn log_type_is_found_past_leading_banners() {
+    let scratch = common::scratch_dir("detection-lines");
+    let dir = scratch.path();
+    let input = dir.join("in/app.log.1");
+    common::write_file(&input, "\n=== log rotated ===\n2020-05-17 02:07:16 a\n");
+    let short = dir.join("in/short.log.1");
+    common::write_file(&short, "\n");
+
+    let mut options = Options::default();
+    let mut proco = FileProcessor::new(input.clone(), dir.join("out/app.log"), &options);
+    proco.determine_type().unwrap();
+    assert_eq!(proco.logtype(), Some(LogType::Iso));
+    let mut proco = FileProcessor::new(short, dir.join("out/short.log"), &options);
+    proco.determine_type().unwrap();
+    assert_eq!(proco.logtype(), None);
+
+    // Beyond the window, the type stays unknown
+    options.detection_lines = 2;
+    let mut proco = FileProcessor::new(input, dir.join("out/app.log"), &options);
+    proco.determine_type().unwrap();
+    assert_eq!(proco.logtype(), None);
+}
+
+#[test]
+fn output_template_names_and_compresses_outputs() {
+    let scratch = common::scratch_dir("output-template");
+    let dir = scratch.path();
+    common::write_file(&dir.join("in/web/access.log.1"), "2023-10-11 a\n2023-11-02 b\n");
+    let options = Options {
+        output_template: Some("{year}/{month}/{name}.{day}.log".parse().unwrap()),
+        ..Options::default()
+    };
+
+    all_files(&dir.join("in"), &dir.join("out"), &options).unwrap();
+    assert_eq!(common::list_files(&dir.join("out")), vec![
+        "web/2023/10/access.11.log", "web/2023/11/access.02.log"
+    ]);
+    // Compressing twice finds the outputs, but never the compressed files
+    gunzip_all_the_files(&dir.join("out"), &options).unwrap();
+    gunzip_all_the_files(&dir.join("out"), &options).unwrap();
+    assert_eq!(common::list_files(&dir.join("out")), vec![
+        "web/2023/10/access.11.log.gz", "web/2023/11/access.02.log.gz"
+    ]);
+}
+
+#[test]
+fn strftime_dates_name_outputs_found_again_to_compress() {
+    let scratch = common::scratch_dir("date-format");
+    let dir = scratch.path();
+    for (template, names) in [
+        ("{base}-{date:%Y%m%d}", ["access.log-20231011", "access.log-20231102"]),
+        ("{date:%Y/%b/%d}/{name}", ["2023/Nov/02/access", "2023/Oct/11/access"])
+    ] {
+        common::write_file(&dir.join("in/access.log.1"), "2023-10-11 a\n2023-11-02 b\n");
+        let options = Options { output_template: Some(template.parse().unwrap()),
+                                ..Options::default() };
+        all_files(&dir.join("in"), &dir.join("out"), &options).unwrap();
+        assert_eq!(common::list_files(&dir.join("out")), names);
+        gunzip_all_the_files(&dir.join("out"), &options).unwrap();
+        assert_eq!(common::list_files(&dir.join("out")),
+                   names.map(|name| format!("{}.gz", name)));
+        std::fs::remove_dir_all(dir.join("out")).unwrap();
+    }
+}
+
+#[test]
+fn lines_outside_the_date_range_are_set_aside() {
+    let scratch = common::scratch_dir("date-range");
+    let dir = scratch.path();
+    common::write_file(&dir.join("in/app.log.1"), concat!(
+        "2023-10-31 before\n", "2023-11-01 first\n", "continued\n",
+        "2023-11-30 last\n", "2023-12-01 after\n", "continued\n"));
+    let options = Options {
+        from_date: chrono::NaiveDate::from_ymd_opt(2023, 11, 1),
+        to_date: chrono::NaiveDate::from_ymd_opt(2023, 11, 30),
+        ..Options::default()
+    };
+
+    all_files(&dir.join("in"), &dir.join("out"), &options).unwrap();
+    assert_eq!(common::list_files(&dir.join("out")), vec![
+        "app.log-2023-11-01", "app.log-2023-11-30", "app.log.excluded"
+    ]);
+    let read = |name: &str| std::fs::read_to_string(dir.join("out").join(name)).unwrap();
+    assert_eq!(read("app.log-2023-11-01"), "2023-11-01 first\ncontinued\n");
+    assert_eq!(read("app.log.excluded"),
+               "2023-10-31 before\n2023-12-01 after\ncontinued\n");
+}
+
+#[cfg(unix)]
+#[test]
+fn outputs_keep_the_permissions_of_their_input() {
+    use std::os::unix::fs::PermissionsExt;
+    let scratch = common::scratch_dir("permissions");
+    let dir = scratch.path();
+    let input = dir.join("in/auth.log.1");
+    common::write_file(&input, "oops\n2020-05-17 a\n");
+    std::fs::set_permissions(&input, std::fs::Permissions::from_mode(0o640)).unwrap();
+    let mode = |name: &str| dir.join("out").join(name).metadata().unwrap()
+        .permissions().mode() & 0o777;
+
+    all_files(&dir.join("in"), &dir.join("out"), &Options::default()).unwrap();
+    assert_eq!(mode("auth.log-2020-05-17"), 0o640);
+    assert_eq!(mode("auth.log.unknown"), 0o640);
+    gunzip_all_the_files(&dir.join("out"), &Options::default()).unwrap();
+    assert_eq!(mode("auth.log-2020-05-17.gz"), 0o640);
+}
+
+#[test]
+fn gzip_files_are_recognized_by_their_magic_number() {
+    let scratch = common::scratch_dir("gzip-magic");
+    let dir = scratch.path();
+    common::write_file(&dir.join("messages.1"), "compressed anyway\n");
+    gunzip(&dir.join("messages.1"), Compression::default(), false).unwrap();
+    std::fs::rename(dir.join("messages.1.gz"), dir.join("messages.1")).unwrap();
+    std::fs::write(dir.join("odd.1"), b"\x1f\x8bplain after all\n").unwrap();
+
+    degunzip_all_the_files(dir, &Options::default()).unwrap();
+    assert_eq!(common::list_files(dir), vec!["messages.1", "odd.1"]);
+    assert_eq!(std::fs::read_to_string(dir.join("messages.1")).unwrap(),
+               "compressed anyway\n");
+    assert_eq!(std::fs::read(dir.join("odd.1")).unwrap(),
+               b"\x1f\x8bplain after all\n");
+}
+
+#[test]
+fn progress_hook_is_told_about_every_file() {
+    let scratch = common::scratch_dir("progress");
+    let dir = scratch.path();
+    common::write_file(&dir.join("in/a.log.1"), "2020-05-17 a\n");
+    common::write_file(&dir.join("in/b.log.1"), "2020-05-17 bb\n2020-05-18 bb\n");
+    let seen = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+    let hook = std::sync::Arc::clone(&seen);
+    let options = Options {
+        progress: Some(std::sync::Arc::new(move |path: &std::path::Path, bytes| {
+            let name = path.file_name().unwrap().to_string_lossy().into_owned();
+            hook.lock().unwrap().push((name, bytes));
+        })),
+        ..Options::default()
+    };
+
+    all_files(&dir.join("in"), &dir.join("out"), &options).unwrap();
+    let mut seen_files = seen.lock().unwrap().drain(..).collect::<Vec<_>>();
+    seen_files.sort();
+    assert_eq!(seen_files, vec![("a.log.1".to_owned(), 13), ("b.log.1".to_owned(), 28)]);
+    gunzip_all_the_files(&dir.join("out"), &options).unwrap();
+    assert_eq!(seen.lock().unwrap().len(), 3);
+}
+
+#[cfg(unix)]
+#[test]
+fn symbolic_links_are_only_followed_when_asked() {
+    let scratch = common::scratch_dir("follow-links");
+    let dir = scratch.path();
+    common::write_file(&dir.join("elsewhere/app.log.1"), "2020-05-17 a\n");
+    std::fs::create_dir_all(dir.join("in")).unwrap();
+    std::os::unix::fs::symlink(dir.join("elsewhere"), dir.join("in/app")).unwrap();
+    // A loop, which must not make the walk endless
+    std::os::unix::fs::symlink(dir.join("in"), dir.join("in/loop")).unwrap();
+
+    let options = Options { keep_source: true, ..Options::default() };
+    all_files(&dir.join("in"), &dir.join("out"), &options).unwrap();
+    assert!(!dir.join("out").exists());
+
+    let options = Options { follow_links: true, ..options };
+    all_files(&dir.join("in"), &dir.join("out"), &options).unwrap();
+    assert_eq!(common::list_files(&dir.join("out")), vec!["app/app.log-2020-05-17"]);
+}
+
+#[test]
+fn failed_files_give_a_non_zero_exit_status() {
+    let scratch = common::scratch_dir("exit-status");
+    let dir = scratch.path();
+    common::write_file(&dir.join("in/ok.log.1"), "2020-05-17 a\n");
+    let run = |args: &[&str]| std::process::Command::new(env!("CARGO_BIN_EXE_logboop"))
+        .args(args)
+        .arg(dir.join("in"))
+        .arg(dir.join("out"))
+        .output().unwrap().status.code();
+    assert_eq!(run(&["-q"]), Some(0));
+
+    // Too many dates for a single file
+    common::write_file(&dir.join("in/ko.log.1"), "2020-05-17 a\n2020-05-18 b\n");
+    assert_eq!(run(&["-q", "--max-dates-per-file", "1"]), Some(2));
+    assert_eq!(common::list_files(&dir.join("in")), vec!["ko.log.1"]);
+    assert_eq!(run(&["--no-such-option"]), Some(1));
+}
+
+#[test]
+fn nested_input_and_output_directories_are_refused() {
+    let scratch = common::scratch_dir("nested");
+    let dir = scratch.path();
+    common::write_file(&dir.join("in/app.log.1"), "2020-05-17 a\n");
+    let run = |output: &str| std::process::Command::new(env!("CARGO_BIN_EXE_logboop"))
+        .arg("-q").arg(dir.join("in")).arg(dir.join(output))
+        .output().unwrap().status.code();
+
+    assert_eq!(run("in/processed"), Some(1));
+    assert_eq!(run("out/../in/./processed"), Some(1));
+    assert_eq!(run("."), Some(1));
+    assert_eq!(common::list_files(dir), vec!["in/app.log.1"]);
+    #[cfg(unix)]
+    {
+        std::os::unix::fs::symlink(dir.join("in"), dir.join("link")).unwrap();
+        assert!(overlap(&dir.join("in"), &dir.join("link/processed")).unwrap());
+    }
+    assert!(!overlap(&dir.join("in"), &dir.join("input")).unwrap());
+    assert_eq!(run("out"), Some(0));
+}
+
+#[test]
+fn only_transient_errors_are_tried_again() {
+    use std::io::{Error, ErrorKind};
+    let options = Options { retries: 2, ..Options::default() };
+    let failing = |kind: ErrorKind, failures: usize| {
+        let mut tries = 0;
+        let result = retry(std::path::Path::new("nfs/syslog.1"), &options, || {
+            tries += 1;
+            match tries > failures {
+                true => Ok(tries),
+                false => Err(LogBoopError::from(Error::from(kind)))
+            }
+        });
+        (result.ok(), tries)
+    };
+    assert_eq!(failing(ErrorKind::Interrupted, 2), (Some(3), 3));
+    assert_eq!(failing(ErrorKind::StaleNetworkFileHandle, 3), (None, 3));
+    assert_eq!(failing(ErrorKind::NotFound, 1), (None, 1));
+    assert_eq!(failing(ErrorKind::PermissionDenied, 1), (None, 1));
+}
+
+#[test]
+fn inspecting_a_file_prints_buckets_and_touches_nothing() {
+    let scratch = common::scratch_dir("inspect");
+    let dir = scratch.path();
+    let input = dir.join("app.log");
+    common::write_file(&input, "banner\n2020-05-17 a\ncontinued\n2020-05-18 b\n");
+    let options = Options { to_date: chrono::NaiveDate::from_ymd_opt(2020, 5, 17),
+                            ..Options::default() };
+    let mut processor = FileProcessor::new(input.clone(), dir.join("out/app"), &options);
+    processor.determine_type().unwrap();
+    let mut printed = Vec::new();
+    assert_eq!(processor.inspect(&mut printed).unwrap(), 4);
+    assert_eq!(String::from_utf8(printed).unwrap(),
+               "unknown\tbanner\n2020-05-17\t2020-05-17 a\n2020-05-17\tcontinued\n\
+                excluded\t2020-05-18 b\n");
+    assert_eq!(common::list_files(dir), vec!["app.log"]);
+
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_logboop"))
+        .arg("--stdout").arg(&input)
+        .output().unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(String::from_utf8(output.stdout).unwrap().lines().last(),
+               Some("2020-05-18\t2020-05-18 b"));
+    assert_eq!(common::list_files(dir), vec!["app.log"]);
+}
+
+#[test]
+fn probing_classifies_files_without_touching_them() {
+    let scratch = common::scratch_dir("probe");
+    let dir = scratch.path();
+    common::write_file(&dir.join("web/access.log.1"), "\n2020-05-17 a\tb\n");
+    common::write_file(&dir.join("app.log"), "nothing dated\n");
+    gunzip(&dir.join("web/access.log.1"), Compression::default(), false).unwrap();
+
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_logboop"))
+        .arg("--probe").arg(dir)
+        .output().unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    let size = dir.join("web/access.log.1.gz").metadata().unwrap().len();
+    assert_eq!(String::from_utf8(output.stdout).unwrap(),
+               format!("{}\t?\t14\tnothing dated\n{}\tIso\t{}\t2020-05-17 a\tb\n",
+                       dir.join("app.log").display(),
+                       dir.join("web/access.log.1.gz").display(), size));
+    assert_eq!(common::list_files(dir), vec!["app.log", "web/access.log.1.gz"]);
+}
+
+#[test]
+fn rename_only_copies_whole_files_to_their_first_date() {
+    let scratch = common::scratch_dir("rename-only");
+    let dir = scratch.path();
+    common::write_file(&dir.join("in/web/access.log.1"),
+                       "banner\n2023-10-11 a\n2023-10-12 b\n");
+    common::write_file(&dir.join("in/web/error.log.1"), "no date at all\n");
+    let options = Options {
+        rename_only: true,
+        output_template: Some("{year}/{month}/{day}/{base}".parse().unwrap()),
+        ..Options::default()
+    };
+
+    let stats = all_files(&dir.join("in"), &dir.join("out"), &options).unwrap();
+    assert_eq!(stats.lines, 4);
+    assert_eq!(common::list_files(&dir.join("out")), vec![
+        "web/2023/10/11/access.log", "web/error.log.unknown"
+    ]);
+    assert_eq!(std::fs::read_to_string(dir.join("out/web/2023/10/11/access.log")).unwrap(),
+               "banner\n2023-10-11 a\n2023-10-12 b\n");
+    assert!(common::list_files(&dir.join("in")).is_empty());
+    gunzip_all_the_files(&dir.join("out"), &options).unwrap();
+    assert_eq!(common::list_files(&dir.join("out")), vec![
+        "web/2023/10/11/access.log.gz", "web/error.log.unknown"
+    ]);
+
+    // Outside of the dates kept, files are split as usual
+    common::write_file(&dir.join("in/web/access.log.1"), "2023-10-12 c\n2023-10-13 d\n");
+    let options = Options { from_date: chrono::NaiveDate::from_ymd_opt(2023, 10, 13),
+                            ..options };
+    all_files(&dir.join("in"), &dir.join("out"), &options).unwrap();
+    assert_eq!(std::fs::read_to_string(dir.join("out/web/2023/10/13/access.log")).unwrap(),
+               "2023-10-13 d\n");
+}
+
+#[test]
+fn files_are_walked_and_processed_in_name_order() {
+    let scratch = common::scratch_dir("sorted-walk");
+    let dir = scratch.path();
+    for name in ["app.log.3", "app.log.1", "b/app.log.1", "app.log.2", "a/app.log.1"] {
+        common::write_file(&dir.join("in").join(name), &format!("2020-05-17 {}\n", name));
+    }
+    let walked: Vec<String> = walk(&dir.join("in"), false, None, true)
+        .filter(|path| path.is_file())
+        .map(|path| path.strip_prefix(dir.join("in")).unwrap().display().to_string())
+        .collect();
+    assert_eq!(walked, vec!["a/app.log.1", "app.log.1", "app.log.2", "app.log.3",
+                            "b/app.log.1"]);
+
+    let options = Options { flatten: true, ..Options::default() };
+    all_files(&dir.join("in"), &dir.join("out"), &options).unwrap();
+    assert_eq!(std::fs::read_to_string(dir.join("out/app.log-2020-05-17")).unwrap(),
+               "2020-05-17 a/app.log.1\n2020-05-17 app.log.1\n2020-05-17 app.log.2\n\
+                2020-05-17 app.log.3\n2020-05-17 b/app.log.1\n");
+}
+
+#[test]
+fn resumable_runs_go_on_from_their_last_checkpoint() {
+    let scratch = common::scratch_dir("resumable");
+    let dir = scratch.path();
+    let input = dir.join("in/web/access.log.1");
+    let output = dir.join("out/web/access.log-2023-10-11");
+    let progress = dir.join("out/web/access.log.progress");
+    common::write_file(&input, "2023-10-11 a\n2023-10-11 b\n2023-10-12 c\n");
+    // Interrupted after the checkpoint of the first line, while writing the second
+    common::write_file(&output, "2023-10-11 a\n2023-10-11 b\n2023-10");
+    common::write_file(&progress, &format!("{}\n13 1\n13 {}\n", input.display(),
+                                           output.display()));
+    let options = Options { resumable: true, verify: true, ..Options::default() };
+
+    let stats = all_files(&dir.join("in"), &dir.join("out"), &options).unwrap();
+    assert_eq!((stats.lines, stats.failed), (3, 0));
+    assert_eq!(std::fs::read_to_string(&output).unwrap(), "2023-10-11 a\n2023-10-11 b\n");
+    assert_eq!(std::fs::read_to_string(dir.join("out/web/access.log-2023-10-12")).unwrap(),
+               "2023-10-12 c\n");
+    assert!(!progress.exists());
+
+    // Outputs changed since the checkpoint are not cut back
+    common::write_file(&input, "2023-10-11 d\n");
+    common::write_file(&progress, &format!("{}\n13 1\n99 {}\n", input.display(),
+                                           output.display()));
+    let stats = all_files(&dir.join("in"), &dir.join("out"), &options).unwrap();
+    assert_eq!(stats.failed, 1);
+    assert!(input.exists() && progress.exists());
+    assert_eq!(std::fs::read_to_string(&output).unwrap(), "2023-10-11 a\n2023-10-11 b\n");
+}
+
+#[test]
+fn inflated_files_are_processed_whatever_their_name() {
+    let scratch = common::scratch_dir("inflated-names");
+    let dir = scratch.path();
+    common::write_file(&dir.join("in/app.log"), "2020-05-17 archived\n");
+    gunzip(&dir.join("in/app.log"), Compression::default(), false).unwrap();
+    common::write_file(&dir.join("in/live.log"), "2020-05-18 still written to\n");
+    let options = Options::default();
+
+    degunzip_all_the_files(&dir.join("in"), &options).unwrap();
+    let stats = all_files(&dir.join("in"), &dir.join("out"), &options).unwrap();
+    assert_eq!(stats.files, 1);
+    assert_eq!(common::list_files(&dir.join("out")), vec!["app.log-2020-05-17"]);
+    assert_eq!(common::list_files(&dir.join("in")), vec!["live.log"]);
+
+    // Other runs know nothing of what was inflated before them
+    common::write_file(&dir.join("in/app.log"), "2020-05-19 archived\n");
+    let stats = all_files(&dir.join("in"), &dir.join("out"), &Options::default()).unwrap();
+    assert_eq!(stats.files, 0);
+}
+
+#[test]
+fn files_older_than_since_are_left_alone() {
+    let scratch = common::scratch_dir("since");
+    let dir = scratch.path();
+    let day = std::time::Duration::from_secs(86400);
+    let now = std::time::SystemTime::now();
+    for (name, mtime) in [("app.log.1", now - 2 * day), ("app.log.2", now),
+                          ("app.log.3", now + day), ("old.log.1", now - 2 * day)] {
+        let input = dir.join("in").join(name);
+        common::write_file(&input, &format!("2020-05-17 {}\n", name));
+        if name.starts_with("old") {
+            gunzip(&input, Compression::default(), false).unwrap();
+            let gz = dir.join("in/old.log.1.gz");
+            File::options().write(true).open(gz).unwrap().set_modified(mtime).unwrap();
+        } else {
+            File::options().write(true).open(&input).unwrap().set_modified(mtime).unwrap();
+        }
+    }
+    let options = Options { since: Some("25h".parse().unwrap()), ..Options::default() };
+
+    degunzip_all_the_files(&dir.join("in"), &options).unwrap();
+    let stats = all_files(&dir.join("in"), &dir.join("out"), &options).unwrap();
+    // Files from the future are recent, whatever their clock says
+    assert_eq!(stats.lines, 2);
+    assert_eq!(std::fs::read_to_string(dir.join("out/app.log-2020-05-17")).unwrap(),
+               "2020-05-17 app.log.2\n2020-05-17 app.log.3\n");
+    assert_eq!(common::list_files(&dir.join("in")), vec!["app.log.1", "old.log.1.gz"]);
+}
+
+#[test]
+fn outputs_are_dated_after_their_bucket_or_source() {
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+    let scratch = common::scratch_dir("output-mtime");
+    let dir = scratch.path();
+    let mtime = |path: &str| dir.join(path).metadata().unwrap().modified().unwrap();
+    common::write_file(&dir.join("in/app.log.1"), "2020-05-17 a\n");
+    common::write_file(&dir.join("in/odd.log.1"), "not dated\n");
+    let options = Options { output_mtime: "bucket".parse().unwrap(),
+                            timezone: Timezone::Utc, ..Options::default() };
+
+    all_files(&dir.join("in"), &dir.join("out"), &options).unwrap();
+    let end_of_day = UNIX_EPOCH + Duration::from_secs(1_589_759_999);
+    assert_eq!(mtime("out/app.log-2020-05-17"), end_of_day);
+    assert!(mtime("out/odd.log.unknown") > SystemTime::now() - Duration::from_secs(3600));
+    gunzip_all_the_files(&dir.join("out"), &options).unwrap();
+    assert_eq!(mtime("out/app.log-2020-05-17.gz"), end_of_day);
+
+    let source = UNIX_EPOCH + Duration::from_secs(1_600_000_000);
+    common::write_file(&dir.join("in/web.log.1"), "2020-05-17 b\n");
+    File::options().write(true).open(dir.join("in/web.log.1")).unwrap()
+        .set_modified(source).unwrap();
+    // Compressed sources keep their time once inflated
+    common::write_file(&dir.join("in/db.log.1"), "2020-05-18 c\n");
+    gunzip(&dir.join("in/db.log.1"), Compression::default(), false).unwrap();
+    File::options().write(true).open(dir.join("in/db.log.1.gz")).unwrap()
+        .set_modified(source).unwrap();
+    let options = Options { output_mtime: "source".parse().unwrap(), ..Options::default() };
+    degunzip_all_the_files(&dir.join("in"), &options).unwrap();
+    all_files(&dir.join("in"), &dir.join("out"), &options).unwrap();
+    assert_eq!(mtime("out/web.log-2020-05-17"), source);
+    assert_eq!(mtime("out/db.log-2020-05-18"), source);
+}
+
+#[test]
+fn excluded_files_win_over_included_ones() {
+    let scratch = common::scratch_dir("globs");
+    let dir = scratch.path();
+    for name in ["web/site.access.log.1", "web/site.error.log.1", "web/site.access.log",
+                 "journal/web.access.log.1"] {
+        common::write_file(&dir.join("in").join(name), &format!("2020-05-17 {}\n", name));
+    }
+    gunzip(&dir.join("in/journal/web.access.log.1"), Compression::default(), false).unwrap();
+    let options = Options {
+        include: Some(glob_set(&["**/*.access.*"]).unwrap()),
+        exclude: Some(glob_set(&["journal/**"]).unwrap()),
+        ..Options::default()
+    };
+
+    degunzip_all_the_files(&dir.join("in"), &options).unwrap();
+    let stats = all_files(&dir.join("in"), &dir.join("out"), &options).unwrap();
+    // Live files stay unprocessed, included or not
+    assert_eq!(stats.lines, 1);
+    assert_eq!(common::list_files(&dir.join("in")),
+               vec!["journal/web.access.log.1.gz", "web/site.access.log",
+                    "web/site.error.log.1"]);
+    common::write_file(&dir.join("out/web/site.error.log-2020-05-18"), "b\n");
+    gunzip_all_the_files(&dir.join("out"), &options).unwrap();
+    assert_eq!(common::list_files(&dir.join("out")),
+               vec!["web/site.access.log-2020-05-17.gz", "web/site.error.log-2020-05-18"]);
+    assert!(glob_set(&["web/[z-a]"]).is_err());
+}
+
+#[cfg(unix)]
+#[test]
+fn hard_linked_files_are_left_alone_on_demand() {
+    let scratch = common::scratch_dir("hardlinks");
+    let dir = scratch.path();
+    common::write_file(&dir.join("in/app.log.1"), "2020-05-17 a\n");
+    common::write_file(&dir.join("in/other.log.1"), "2020-05-17 b\n");
+    std::fs::create_dir_all(dir.join("shipped")).unwrap();
+    std::fs::hard_link(dir.join("in/app.log.1"), dir.join("shipped/app.log.1")).unwrap();
+    let options = Options { no_touch_hardlinks: true, ..Options::default() };
+
+    let stats = all_files(&dir.join("in"), &dir.join("out"), &options).unwrap();
+    assert_eq!(stats.lines, 1);
+    assert_eq!(common::list_files(&dir.join("in")), vec!["app.log.1"]);
+    // Without the option, the other link keeps the content
+    all_files(&dir.join("in"), &dir.join("out"), &Options::default()).unwrap();
+    assert!(common::list_files(&dir.join("in")).is_empty());
+    assert_eq!(std::fs::read_to_string(dir.join("shipped/app.log.1")).unwrap(),
+               "2020-05-17 a\n");
+    assert_eq!(std::fs::read_to_string(dir.join("out/app.log-2020-05-17")).unwrap(),
+               "2020-05-17 a\n");
+}
+
+#[cfg(unix)]
+#[test]
+fn postrotate_commands_run_on_every_compressed_file() {
+    use std::sync::atomic::Ordering;
+    let scratch = common::scratch_dir("postrotate");
+    let dir = scratch.path();
+    common::write_file(&dir.join("out/web/access.log-2020-05-17"), "a\n");
+    common::write_file(&dir.join("out/syslog-2020-05-17"), "b\n");
+    std::fs::create_dir_all(dir.join("shipped")).unwrap();
+    let options = Options {
+        postrotate: Some(format!("cp {{}} {}", dir.join("shipped").display())),
+        ..Options::default()
+    };
+
+    gunzip_all_the_files(&dir.join("out"), &options).unwrap();
+    assert_eq!(common::list_files(&dir.join("shipped")),
+               vec!["access.log-2020-05-17.gz", "syslog-2020-05-17.gz"]);
+    assert_eq!(options.hook_failures.load(Ordering::SeqCst), 0);
+    // Failures are counted, and the other files compressed anyway
+    common::write_file(&dir.join("out/syslog-2020-05-18"), "c\n");
+    common::write_file(&dir.join("out/syslog-2020-05-19"), "d\n");
+    let options = Options { postrotate: Some("false {}".to_owned()), ..Options::default() };
+    gunzip_all_the_files(&dir.join("out"), &options).unwrap();
+    assert_eq!(options.hook_failures.load(Ordering::SeqCst), 2);
+    assert!(dir.join("out/syslog-2020-05-19.gz").exists());
+}
+
+#[cfg(unix)]
+#[test]
+fn created_directories_get_the_dir_mode() {
+    use std::os::unix::fs::PermissionsExt;
+    let scratch = common::scratch_dir("dir-mode");
+    let dir = scratch.path();
+    common::write_file(&dir.join("in/web/deep/access.log.1"), "2020-05-17 a\n");
+    std::fs::create_dir_all(dir.join("out/web")).unwrap();
+    std::fs::set_permissions(dir.join("out/web"), std::fs::Permissions::from_mode(0o755))
+        .unwrap();
+    let mode = |name: &str| dir.join("out").join(name).metadata().unwrap()
+        .permissions().mode() & 0o7777;
+    let options = Options { dir_mode: Some(0o750), ..Options::default() };
+
+    all_files(&dir.join("in"), &dir.join("out"), &options).unwrap();
+    assert_eq!(mode("web/deep"), 0o750);
+    // Directories which existed are never widened, nor narrowed
+    assert_eq!(mode("web"), 0o755);
+}
+
+#[test]
+fn bytes_read_are_counted_once_inflated() {
+    let scratch = common::scratch_dir("bytes-read");
+    let dir = scratch.path();
+    let content = "2020-05-17 a\n2020-05-18 b\n";
+    common::write_file(&dir.join("in/app.log.1"), content);
+    common::write_file(&dir.join("in/app.log.2"), content);
+    gunzip(&dir.join("in/app.log.2"), Compression::default(), false).unwrap();
+
+    let plain = one_file(&dir.join("in/app.log.1"), dir.join("out/app.log"),
+                         &Options::default()).unwrap();
+    assert_eq!(plain.bytes, content.len() as u64);
+    let compressed = one_file(&dir.join("in/app.log.2.gz"), dir.join("out/app.log"),
+                              &Options::default()).unwrap();
+    assert_eq!(compressed.bytes, content.len() as u64);
+}
+
+#[test]
+fn a_tight_memory_limit_still_sorts_every_line() {
+    let scratch = common::scratch_dir("mem-limit");
+    let dir = scratch.path();
+    let content = "2020-05-17 a\n2020-05-18 b\n2020-05-19 c\n2020-05-17 d\n2020-05-18 e\n";
+    for name in ["app.log.1", "db.log.1", "web.log.1"] {
+        common::write_file(&dir.join("in").join(name), content);
+    }
+    let options = Options { compress_directly: true, buffer_size: 16, mem_limit: 1,
+                            jobs: 4, ..Options::default() };
+    assert_eq!(options.memory_bounds(options.jobs), (1, 1));
+
+    all_files(&dir.join("in"), &dir.join("out"), &options).unwrap();
+    for name in ["app", "db", "web"] {
+        assert_eq!(common::read_gz(&dir.join(format!("out/{}.log-2020-05-17.gz", name))),
+                   "2020-05-17 a\n2020-05-17 d\n");
+        assert_eq!(common::read_gz(&dir.join(format!("out/{}.log-2020-05-19.gz", name))),
+                   "2020-05-19 c\n");
+    }
+}
+
+#[test]
+fn vhosts_are_split_on_demand() {
+    let scratch = common::scratch_dir("vhosts");
+    let dir = scratch.path();
+    let line = |vhost: &str, day: u32| format!(
+        "{}:443 1.2.3.4 - - [{:02}/Oct/2023:13:55:36 +0000] \"GET / HTTP/1.1\" 200 42\n",
+        vhost, day);
+    let content = [line("example.com", 11), line("example.org", 11),
+                   line("example.com", 12)].concat();
+    common::write_file(&dir.join("in/access.log.1"), &content);
+    common::write_file(&dir.join("in/other_vhosts_access.log.1"), &content);
+    let options = Options { split_vhosts: true, ..Options::default() };
+
+    one_file(&dir.join("in/access.log.1"), dir.join("out/access.log"), &options).unwrap();
+    assert_eq!(common::list_files(&dir.join("out")),
+               ["access.log-example.com-2023-10-11", "access.log-example.com-2023-10-12",
+                "access.log-example.org-2023-10-11"]);
+    assert_eq!(std::fs::read_to_string(dir.join("out/access.log-example.org-2023-10-11"))
+                   .unwrap(),
+               line("example.org", 11));
+    // Without it, virtual hosts share their files
+    one_file(&dir.join("in/other_vhosts_access.log.1"), dir.join("all/access.log"),
+             &Options::default()).unwrap();
+    assert_eq!(common::list_files(&dir.join("all")),
+               ["access.log-2023-10-11", "access.log-2023-10-12"]);
+}
+
+#[test]
+fn output_suffixes_survive_compression_and_bundling() {
+    let scratch = common::scratch_dir("output-suffix");
+    let dir = scratch.path();
+    let content = "2023-10-11 a\n2023-10-12 b\n";
+    common::write_file(&dir.join("in/before/access.1"), content);
+    common::write_file(&dir.join("in/after/access.1"), content);
+    let before = Options { output_suffix: ".log".to_owned(), ..Options::default() };
+    let after = Options { suffix_after_date: true, ..before.clone() };
+
+    all_files(&dir.join("in/before"), &dir.join("out"), &before).unwrap();
+    gunzip_all_the_files(&dir.join("out"), &before).unwrap();
+    all_files(&dir.join("in/after"), &dir.join("out"), &after).unwrap();
+    gunzip_all_the_files(&dir.join("out"), &after).unwrap();
+    assert_eq!(common::list_files(&dir.join("out")), [
+        "access-2023-10-11.log.gz", "access-2023-10-12.log.gz",
+        "access.log-2023-10-11.gz", "access.log-2023-10-12.gz"
+    ]);
+    bundle_days(&dir.join("out")).unwrap();
+    let day = File::open(dir.join("out/day-2023-10-11.tar.gz")).unwrap();
+    assert_eq!(read_entries(GzDecoder::new(day)).unwrap(), vec![
+        ("access-2023-10-11.log".to_owned(), b"2023-10-11 a\n".to_vec()),
+        ("access.log-2023-10-11".to_owned(), b"2023-10-11 a\n".to_vec())
+    ]);
+}
+
+#[test]
+fn invalid_dates_abort_a_file_only_when_strict() {
+    let scratch = common::scratch_dir("strict");
+    let dir = scratch.path();
+    let input = dir.join("in/app.log.1");
+    common::write_file(&input, "2023-02-27 a\n  at frame\n2023-02-30 b\n2023-02-28 c\n");
+    let strict = Options { strict: true, ..Options::default() };
+
+    match one_file(&input, dir.join("out/app.log"), &strict) {
+        Err(LogBoopError::ParseError { line, logtype }) =>
+            assert_eq!((line.as_str(), logtype), ("2023-02-30 b", LogType::Iso)),
+        other => panic!("unexpected {:?}", other.map(|file| file.lines))
+    }
+    assert!(input.exists());
+    assert!(common::list_files(&dir.join("out")).is_empty());
+    // By default, the line goes with the one before it
+    one_file(&input, dir.join("out/app.log"), &Options::default()).unwrap();
+    assert_eq!(std::fs::read_to_string(dir.join("out/app.log-2023-02-27")).unwrap(),
+               "2023-02-27 a\n  at frame\n2023-02-30 b\n");
+}
+
+#[test]
+fn ndjson_outputs_give_every_line_its_date_and_type() {
+    let scratch = common::scratch_dir("ndjson-records");
+    let dir = scratch.path();
+    let input = dir.join("in/app.log.1");
+    common::write_file(&input, "2023-10-11 a\n\tat \"frame\"\n2023-10-12 b\n");
+    let options = Options { output_format: OutputFormat::Ndjson, ..Options::default() };
+
+    one_file(&input, dir.join("out/app.log"), &options).unwrap();
+    assert_eq!(std::fs::read_to_string(dir.join("out/app.log-2023-10-11")).unwrap(),
+               "{\"date\":\"2023-10-11\",\"type\":\"Iso\",\"line\":\"2023-10-11 a\"}\n\
+                {\"date\":\"2023-10-11\",\"type\":\"Iso\",\"line\":\"\\tat \\\"frame\\\"\"}\n");
+    assert_eq!(std::fs::read_to_string(dir.join("out/app.log-2023-10-12")).unwrap(),
+               "{\"date\":\"2023-10-12\",\"type\":\"Iso\",\"line\":\"2023-10-12 b\"}\n");
+}
+
+#[test]
+fn small_months_are_merged_in_order() {
+    let scratch = common::scratch_dir("consolidate");
+    let dir = scratch.path();
+    let out = dir.join("out");
+    common::write_file(&out.join("app.log-2020-05-18"), "2020-05-18 b\n");
+    common::write_file(&out.join("app.log-2020-05-17"), "2020-05-17 a\n");
+    common::write_file(&out.join("app.log-2020-06-01"), "2020-06-01 c\n");
+    common::write_file(&out.join("big.log-2020-05-17"), &"2020-05-17 big\n".repeat(100));
+    gunzip(&out.join("app.log-2020-05-18"), Compression::default(), false).unwrap();
+    // Neither daily archives nor files left behind are daily files
+    common::write_file(&out.join("app.log-2020-06-02.gz.tmp"), "partial\n");
+    common::write_file(&out.join("day-2020-05-17.tar.gz"), "bundled\n");
+
+    consolidate_months(&out, 1000).unwrap();
+    assert_eq!(common::list_files(&out),
+               ["app.log-2020-05.gz", "app.log-2020-06-02.gz.tmp", "app.log-2020-06.gz",
+                "big.log-2020-05-17", "day-2020-05-17.tar.gz"]);
+    assert_eq!(common::read_gz(&out.join("app.log-2020-06.gz")), "2020-06-01 c\n");
+    assert_eq!(common::read_gz(&out.join("app.log-2020-05.gz")),
+               "2020-05-17 a\n2020-05-18 b\n");
+    // Days of a month merged before are kept ahead of the new ones
+    common::write_file(&out.join("app.log-2020-05-19"), "2020-05-19 d\n");
+    consolidate_months(&out, 1000).unwrap();
+    assert_eq!(common::read_gz(&out.join("app.log-2020-05.gz")),
+               "2020-05-17 a\n2020-05-18 b\n2020-05-19 d\n");
+}
+
+#[test]
+fn header_lines_are_kept_aside() {
+    let scratch = common::scratch_dir("header-lines");
+    let dir = scratch.path();
+    let input = dir.join("in/app.csv.1");
+    let content = "date,level,message\n2023-10-11,info,up\n2023-10-12,warn,slow\n";
+    common::write_file(&input, content);
+    let options = Options { header_lines: 1, verify: true, ..Options::default() };
+
+    let mut processor = FileProcessor::new(input.clone(), dir.join("out/app.csv"), &options);
+    processor.determine_type().unwrap();
+    assert_eq!(processor.logtype(), Some(LogType::Iso));
+    let mut inspected = Vec::new();
+    processor.inspect(&mut inspected).unwrap();
+    assert_eq!(String::from_utf8(inspected).unwrap(),
+               "header\tdate,level,message\n2023-10-11\t2023-10-11,info,up\n\
+                2023-10-12\t2023-10-12,warn,slow\n");
+    assert_eq!(processor.process().unwrap().lines, 3);
+    assert_eq!(common::list_files(&dir.join("out")),
+               ["app.csv-2023-10-11", "app.csv-2023-10-12", "app.csv.header"]);
+    assert_eq!(std::fs::read_to_string(dir.join("out/app.csv.header")).unwrap(),
+               "date,level,message\n");
+    assert_eq!(std::fs::read_to_string(dir.join("out/app.csv-2023-10-11")).unwrap(),
+               "2023-10-11,info,up\n");
+}
+
+#[test]
+fn repeated_lines_are_counted_per_output_file() {
+    let scratch = common::scratch_dir("dedup");
+    let dir = scratch.path();
+    let input = dir.join("in/app.log.1");
+    common::write_file(&input, "2023-10-11 bot\n2023-10-11 bot\n2023-10-12 other\n\
+                                2023-10-11 bot\n2023-10-11 human\n2023-10-12 other\n");
+    let options = Options { dedup: true, verify: true, ..Options::default() };
+
+    assert_eq!(one_file(&input, dir.join("out/app.log"), &options).unwrap().lines, 6);
+    assert_eq!(std::fs::read_to_string(dir.join("out/app.log-2023-10-11")).unwrap(),
+               "2023-10-11 bot\n... (repeated 2 times)\n2023-10-11 human\n");
+    // Repeats still pending when the file is finished are written too
+    assert_eq!(std::fs::read_to_string(dir.join("out/app.log-2023-10-12")).unwrap(),
+               "2023-10-12 other\n... (repeated 1 times)\n");
+
+    // Checkpoints write what is pending, and the run starts over after them
+    let input = dir.join("in/web.log.1");
+    common::write_file(&input, &format!("{}2023-10-12 other\n{}",
+                                        "2023-10-11 bot\n".repeat(5),
+                                        "2023-10-11 bot\n".repeat(3)));
+    let options = Options { dedup: true, resumable: true, ..Options::default() };
+    assert_eq!(one_file(&input, dir.join("out/web.log"), &options).unwrap().lines, 9);
+    assert_eq!(std::fs::read_to_string(dir.join("out/web.log-2023-10-11")).unwrap(),
+               "2023-10-11 bot\n... (repeated 4 times)\n2023-10-11 bot\n\
+                ... (repeated 2 times)\n");
+}
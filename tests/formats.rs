@@ -0,0 +1,706 @@
+//! Detection and dating of the supported log formats, one line at a time
+use logboop::error::LogBoopError;
+use logboop::options::{Granularity, Options, Timezone};
+use logboop::process::{detect_line, determine_bucket, determine_date, determine_date_at,
+                       split_lines, split_lines_at, BucketWrite, CustomFormat, LogType};
+
+/// Year given to a month written without one, when dated relative to today
+fn year_of(month: u32) -> i32 {
+    let today = chrono::Utc::now().date_naive();
+    let year = chrono::Datelike::year(&today);
+    if month > chrono::Datelike::month(&today) { year - 1 } else { year }
+}
+
+#[test]
+fn cisco_with_priority_sequence_and_host() {
+    let line = "<189>123: core-sw1: *Oct 11 22:14:15.003: %SYS-5-CONFIG_I: Configured from console";
+    assert_eq!(detect_line(line), Some(LogType::CiscoSyslog));
+    let year = year_of(10);
+    assert_eq!(determine_date(&LogType::CiscoSyslog, line).ok(),
+               Some(format!("{}-10-11", year)));
+}
+
+#[test]
+fn cisco_with_explicit_year() {
+    let line = "Oct 11 2023 22:14:15: %ASA-6-302013: Built inbound TCP connection";
+    assert_eq!(detect_line(line), Some(LogType::CiscoSyslog));
+    assert_eq!(determine_date(&LogType::CiscoSyslog, line).ok(),
+               Some("2023-10-11".to_owned()));
+}
+
+#[test]
+fn cisco_with_sequence_and_padded_day() {
+    let line = "000123: *Mar  1 00:00:12.345 UTC: %LINK-3-UPDOWN: Interface up";
+    assert_eq!(detect_line(line), Some(LogType::CiscoSyslog));
+    let year = year_of(3);
+    assert_eq!(determine_date(&LogType::CiscoSyslog, line).ok(),
+               Some(format!("{}-03-01", year)));
+}
+
+#[test]
+fn two_digit_years_pivot_at_seventy() {
+    let line = "11-Oct-23 14:02:11 eth0: link up";
+    assert_eq!(detect_line(line), Some(LogType::DayMonthYear2));
+    assert_eq!(determine_date(&LogType::DayMonthYear2, line).ok(),
+               Some("2023-10-11".to_owned()));
+    assert_eq!(determine_bucket(&LogType::DayMonthYear2, line, Granularity::Hour).ok(),
+               Some("2023-10-11T14".to_owned()));
+    let cases = [("1-Jan-00 boot", "2000-01-01"), ("31-Dec-69 a", "2069-12-31"),
+                 ("01-JAN-70 b", "1970-01-01"), ("31-Dec-99 c", "1999-12-31")];
+    for (line, date) in cases.iter() {
+        assert_eq!(detect_line(line), Some(LogType::DayMonthYear2), "{}", line);
+        assert_eq!(determine_date(&LogType::DayMonthYear2, line).ok(),
+                   Some((*date).to_owned()), "{}", line);
+    }
+    // Four-digit years are not cut short
+    assert_eq!(detect_line("11-Oct-2023 14:02:11 eth0: link up"), None);
+
+    // Custom formats giving a two-digit year follow the same pivot
+    let format = CustomFormat {
+        name: "device".to_owned(),
+        regex: r"^(?P<ts>\d{2}/\d{2}/\d{2})".parse().unwrap(),
+        format: "%y/%m/%d".to_owned()
+    };
+    assert_eq!(format.date("69/12/31 a").map(|date| date.to_string()),
+               Some("2069-12-31".to_owned()));
+    assert_eq!(format.date("70/01/01 b").map(|date| date.to_string()),
+               Some("1970-01-01".to_owned()));
+}
+
+#[test]
+fn plain_syslog_is_not_cisco() {
+    let line = "Oct 11 22:14:15 host sshd[1234]: Accepted publickey";
+    assert_eq!(detect_line(line), Some(LogType::Syslog));
+}
+
+#[test]
+fn ecs_json_from_filebeat() {
+    let line = r#"{"@timestamp":"2023-10-11T22:14:15.003Z","log.level":"info","message":"started","ecs.version":"1.6.0"}"#;
+    assert_eq!(detect_line(line), Some(LogType::EcsJson));
+    assert_eq!(determine_date(&LogType::EcsJson, line).ok(),
+               Some("2023-10-11".to_owned()));
+}
+
+#[test]
+fn ecs_json_from_logstash_with_version_first() {
+    let line = r#"{"@version":"1","host":"web1","@timestamp":"2023-10-11T23:59:59.999+02:00","message":"GET /"}"#;
+    assert_eq!(detect_line(line), Some(LogType::EcsJson));
+    assert_eq!(determine_date(&LogType::EcsJson, line).ok(),
+               Some("2023-10-11".to_owned()));
+}
+
+#[test]
+fn json_without_ecs_fields_is_not_ecs() {
+    let line = r#"{"@timestamp":"2023-10-11T22:14:15.003Z","message":"started"}"#;
+    assert_eq!(detect_line(line), Some(LogType::Json));
+}
+
+#[test]
+fn docker_json_file_lines_with_nanoseconds() {
+    // The log itself gives another date, which is not the one of the line
+    let line = r#"{"log":"{\"time\":\"2020-01-01T00:00:00Z\"}\n","stream":"stderr","time":"2023-10-11T23:55:36.123456789-02:00"}"#;
+    assert_eq!(detect_line(line), Some(LogType::DockerJson));
+    assert_eq!(determine_bucket(&LogType::DockerJson, line, Granularity::Hour).ok(),
+               Some("2023-10-11T23".to_owned()));
+    assert_eq!("docker".parse(), Ok(LogType::DockerJson));
+}
+
+#[test]
+fn json_lines_from_bunyan_and_pino() {
+    let line = r#"{"name":"api","hostname":"web1","pid":42,"level":30,"msg":"hi","time":"2023-10-11T22:14:15.003Z","v":0}"#;
+    assert_eq!(detect_line(line), Some(LogType::Json));
+    assert_eq!(determine_date(&LogType::Json, line).ok(),
+               Some("2023-10-11".to_owned()));
+    // The date is the one written, whatever the offset
+    let line = r#"{"level":"info","ts":"2023-10-11T23:59:59.999+02:00","msg":"GET /"}"#;
+    assert_eq!(detect_line(line), Some(LogType::Json));
+    assert_eq!(determine_bucket(&LogType::Json, line, Granularity::Hour).ok(),
+               Some("2023-10-11T23".to_owned()));
+    // A field of the right name, but not a timestamp
+    let line = r#"{"time":1697062455003,"msg":"epoch"}"#;
+    assert_eq!(detect_line(line), None);
+    assert!(determine_date(&LogType::Json, line).is_err());
+}
+
+#[test]
+fn json_time_fields_are_configurable() {
+    let input = "{\"when\":\"2023-10-11T22:14:15Z\",\"time\":\"nope\"}\n".as_bytes();
+    let options = Options {
+        json_time_fields: vec!["time".to_owned(), "when".to_owned()],
+        ..Options::default()
+    };
+    let mut buckets = Vec::new();
+    split_lines(&LogType::Json, input, &options, |bucket| {
+        buckets.push(bucket.map(ToString::to_string));
+        Ok(std::io::sink())
+    }).unwrap();
+    assert_eq!(buckets, vec![Some("2023-10-11".to_owned())]);
+}
+
+#[test]
+fn mysql_slow_query_log() {
+    let line = "# Time: 2023-10-11T13:55:36.123456Z";
+    assert_eq!(detect_line(line), Some(LogType::MysqlSlow));
+    assert_eq!(determine_date(&LogType::MysqlSlow, line).ok(),
+               Some("2023-10-11".to_owned()));
+    assert_eq!(determine_bucket(&LogType::MysqlSlow, line, Granularity::Hour).ok(),
+               Some("2023-10-11T13".to_owned()));
+    // The rest of an entry is never dated on its own
+    assert_eq!(detect_line("# User@Host: app[app] @ localhost []  Id:     8"), None);
+    assert!(determine_date(&LogType::MysqlSlow, "SET timestamp=1697032536;").is_err());
+}
+
+#[test]
+fn postgres_default_prefix_is_iso() {
+    let line = "2023-10-11 13:55:36.123 UTC [12345] LOG:  duration: 1234.567 ms  statement: SELECT 1";
+    assert_eq!(detect_line(line), Some(LogType::Iso));
+    assert_eq!(determine_date(&LogType::Iso, line).ok(),
+               Some("2023-10-11".to_owned()));
+    assert_eq!(determine_bucket(&LogType::Iso, line, Granularity::Hour).ok(),
+               Some("2023-10-11T13".to_owned()));
+}
+
+#[test]
+fn apache_access_with_ipv6_client() {
+    let line = r#"2001:db8::1 - - [11/Oct/2023:13:55:36 +0000] "GET / HTTP/1.1" 200 42"#;
+    assert_eq!(detect_line(line), Some(LogType::ApacheAccess));
+    assert_eq!(determine_date(&LogType::ApacheAccess, line).ok(),
+               Some("2023-10-11".to_owned()));
+}
+
+#[test]
+fn apache_access_with_bracketed_ipv6_and_port() {
+    let line = r#"[2001:db8::1]:443 [2001:db8::2] - - [11/Oct/2023:13:55:36 +0200] "GET / HTTP/1.1" 200 42"#;
+    assert_eq!(determine_date(&LogType::ApacheAccess, line).ok(),
+               Some("2023-10-11".to_owned()));
+}
+
+#[test]
+fn apache_access_with_forwarded_for_chain() {
+    // A proxy chain logged before the client, holding a bogus bracketed date
+    let line = r#""203.0.113.7, [2001:db8::3], [99/Oct/2023:13:55:36 +0000]" 10.0.0.1 - - [12/Oct/2023:01:02:03 -0700] "GET / HTTP/1.1" 200 42"#;
+    assert_eq!(detect_line(line), Some(LogType::ApacheAccess));
+    assert_eq!(determine_date(&LogType::ApacheAccess, line).ok(),
+               Some("2023-10-12".to_owned()));
+}
+
+#[test]
+fn apache_with_vhost_prefix() {
+    let line = r#"example.com:443 1.2.3.4 - - [11/Oct/2023:13:55:36 +0000] "GET / HTTP/1.1" 200 42"#;
+    assert_eq!(detect_line(line), Some(LogType::ApacheVhost));
+    assert_eq!(determine_date(&LogType::ApacheVhost, line).ok(),
+               Some("2023-10-11".to_owned()));
+    assert_eq!(determine_bucket(&LogType::ApacheVhost, line, Granularity::Hour).ok(),
+               Some("2023-10-11T13".to_owned()));
+    // Without a port either
+    let line = r#"intranet 1.2.3.4 - bob [12/Oct/2023:01:02:03 -0700] "GET / HTTP/1.1" 200 42"#;
+    assert_eq!(detect_line(line), Some(LogType::ApacheVhost));
+    assert_eq!(determine_date(&LogType::ApacheVhost, line).ok(),
+               Some("2023-10-12".to_owned()));
+}
+
+#[test]
+fn nginx_combined_behind_reverse_proxy() {
+    let line = r#"10.0.0.2 203.0.113.9 - - [10/Oct/2023:13:55:36 +0000] "GET /index.html HTTP/1.1" 200 612 "-" "Mozilla/5.0""#;
+    // Still recognized as an Apache line, which dates it the same way
+    assert_eq!(detect_line(line), Some(LogType::ApacheAccess));
+    assert_eq!(determine_date(&LogType::NginxAccess, line).ok(),
+               Some("2023-10-10".to_owned()));
+}
+
+#[test]
+fn nginx_without_offset() {
+    let line = r#"10.0.0.2 203.0.113.9 - - [10/Oct/2023:13:55:36] "GET / HTTP/1.1" 200 612"#;
+    assert_eq!(detect_line(line), Some(LogType::NginxAccess));
+    assert_eq!(determine_date(&LogType::NginxAccess, line).ok(),
+               Some("2023-10-10".to_owned()));
+}
+
+#[test]
+fn haproxy_http_with_milliseconds() {
+    let line = "10.0.1.2:33317 [11/Oct/2023:13:55:36.123] http-in static/srv1 10/0/30/69/109 200 2750 - - ---- 1/1/1/1/0 0/0 \"GET /index.html HTTP/1.1\"";
+    assert_eq!(detect_line(line), Some(LogType::HaproxyHttp));
+    assert_eq!(determine_date(&LogType::HaproxyHttp, line).ok(),
+               Some("2023-10-11".to_owned()));
+    assert_eq!(determine_bucket(&LogType::HaproxyHttp, line, Granularity::Hour).ok(),
+               Some("2023-10-11T13".to_owned()));
+    // Through syslog, the date of syslog comes first
+    let line = format!("Oct 11 13:55:36 lb haproxy[1234]: {}", line);
+    assert_eq!(detect_line(&line), Some(LogType::Syslog));
+}
+
+#[test]
+fn rfc5424_syslog() {
+    let line = "<34>1 2023-10-11T22:14:15.003Z mymachine.example.com su - ID47 - 'su root' failed";
+    assert_eq!(detect_line(line), Some(LogType::Syslog5424));
+    assert_eq!(determine_date(&LogType::Syslog5424, line).ok(),
+               Some("2023-10-11".to_owned()));
+}
+
+#[test]
+fn rfc5424_syslog_with_offset() {
+    let line = "<165>1 2003-08-24T05:14:15.000003-07:00 192.0.2.1 myproc 8710 - - message";
+    assert_eq!(detect_line(line), Some(LogType::Syslog5424));
+    assert_eq!(determine_date(&LogType::Syslog5424, line).ok(),
+               Some("2003-08-24".to_owned()));
+}
+
+#[test]
+fn hourly_buckets_of_timed_formats() {
+    let lines = [
+        (LogType::Iso, "2020-05-17 02:07:16,656 fail2ban.filter [1234]: INFO", "2020-05-17T02"),
+        (LogType::ApacheAccess, r#"127.0.0.1 - - [17/May/2020:14:07:16 +0200] "GET / HTTP/1.1" 200 42"#, "2020-05-17T14"),
+        (LogType::NginxAccess, r#"10.0.0.2 127.0.0.1 - - [17/May/2020:23:07:16] "GET / HTTP/1.1" 200 42"#, "2020-05-17T23"),
+        (LogType::ApacheError, "[Sun May 17 02:07:16.656808 2020] [core:error] [pid 1234] oops", "2020-05-17T02"),
+        (LogType::GrafanaLogs, "t=2020-05-17T18:14:21+0200 lvl=info msg=\"Request Completed\"", "2020-05-17T18"),
+        (LogType::Syslog5424, "<34>1 2020-05-17T22:14:15.003+02:00 host su - ID47 - failed", "2020-05-17T22"),
+        (LogType::EcsJson, r#"{"@timestamp":"2020-05-17T09:14:15.003Z","@version":"1","message":"hi"}"#, "2020-05-17T09"),
+        (LogType::CiscoSyslog, "Oct 11 2023 22:14:15: %ASA-6-302013: Built inbound TCP connection", "2023-10-11T22")
+    ];
+    for (logtype, line, bucket) in &lines {
+        assert_eq!(detect_line(line), Some(logtype.clone()));
+        assert_eq!(determine_bucket(logtype, line, Granularity::Hour).ok(),
+                   Some((*bucket).to_owned()), "{}", line);
+        assert_eq!(determine_bucket(logtype, line, Granularity::Day).ok(),
+                   Some(bucket[..10].to_owned()), "{}", line);
+    }
+}
+
+#[test]
+fn hourly_buckets_fall_back_to_days() {
+    let year = year_of(10);
+    let line = "Oct 11 22:14:15 host sshd[1234]: Accepted publickey";
+    assert_eq!(determine_bucket(&LogType::Syslog, line, Granularity::Hour).ok(),
+               Some(format!("{}-10-11", year)));
+    let line = "2020-05-17 fail2ban restarted";
+    assert_eq!(determine_bucket(&LogType::Iso, line, Granularity::Hour).ok(),
+               Some("2020-05-17".to_owned()));
+}
+
+#[test]
+fn garbage_timestamps_are_parse_errors() {
+    let line = r#"127.0.0.1 - - [31/Feb/2020:02:07:16 +0200] "GET / HTTP/1.1" 200 42"#;
+    assert_eq!(detect_line(line), Some(LogType::ApacheAccess));
+    match determine_date(&LogType::ApacheAccess, line) {
+        Err(LogBoopError::ParseError { line: bad, logtype }) => {
+            assert_eq!(bad, line);
+            assert_eq!(logtype, LogType::ApacheAccess);
+        },
+        other => panic!("expected a parse error, got {:?}", other)
+    }
+    assert!(matches!(determine_date(&LogType::Iso, "no date at all"),
+                     Err(LogBoopError::ParseError { .. })));
+}
+
+/// Writer appending to a shared buffer, to look at buckets once written
+struct Shared(std::rc::Rc<std::cell::RefCell<Vec<u8>>>);
+
+impl std::io::Write for Shared {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl BucketWrite for Shared {}
+
+#[test]
+fn garbage_timestamps_stay_in_the_current_bucket() {
+    let input = "garbage\n2020-05-17 a\n2020-02-31 tampered\n2020-05-18 b\n".as_bytes();
+    let mut buckets = Vec::new();
+    split_lines(&LogType::Iso, input, &Options::default(), |bucket| {
+        let buffer = std::rc::Rc::default();
+        let name = bucket.map_or_else(|| "unknown".to_owned(), ToString::to_string);
+        buckets.push((name, std::rc::Rc::clone(&buffer)));
+        Ok(Shared(buffer))
+    }).unwrap();
+    let buckets: Vec<(String, String)> = buckets.into_iter()
+        .map(|(b, buf)| (b, String::from_utf8(buf.take()).unwrap()))
+        .collect();
+    assert_eq!(buckets, vec![
+        ("unknown".to_owned(), "garbage\n".to_owned()),
+        ("2020-05-17".to_owned(), "2020-05-17 a\n2020-02-31 tampered\n".to_owned()),
+        ("2020-05-18".to_owned(), "2020-05-18 b\n".to_owned())
+    ]);
+}
+
+#[test]
+fn interleaved_dates_keep_their_writers_open() {
+    let input = concat!(
+        "2020-05-17 a\n", "2020-05-18 b\n", "continued\n", "2020-05-17 c\n",
+        "2020-05-19 d\n", "2020-05-18 e\n", "2020-05-17 f\n").as_bytes();
+    let split = |max_open_files: usize| {
+        let options = Options { max_open_files, ..Options::default() };
+        let mut buckets = Vec::new();
+        split_lines(&LogType::Iso, input, &options, |bucket| {
+            let buffer = std::rc::Rc::default();
+            let name = bucket.map_or_else(|| "unknown".to_owned(), ToString::to_string);
+            buckets.push((name, std::rc::Rc::clone(&buffer)));
+            Ok(Shared(buffer))
+        }).unwrap();
+        buckets.into_iter()
+            .map(|(b, buf)| (b, String::from_utf8(buf.take()).unwrap()))
+            .collect::<Vec<(String, String)>>()
+    };
+    let pair = |b: &str, lines: &str| (b.to_owned(), lines.to_owned());
+    assert_eq!(split(3), vec![
+        pair("2020-05-17", "2020-05-17 a\n2020-05-17 c\n2020-05-17 f\n"),
+        pair("2020-05-18", "2020-05-18 b\ncontinued\n2020-05-18 e\n"),
+        pair("2020-05-19", "2020-05-19 d\n")
+    ]);
+    // The least recently used writer makes room for the others
+    assert_eq!(split(2), vec![
+        pair("2020-05-17", "2020-05-17 a\n2020-05-17 c\n"),
+        pair("2020-05-18", "2020-05-18 b\ncontinued\n"),
+        pair("2020-05-19", "2020-05-19 d\n"),
+        pair("2020-05-18", "2020-05-18 e\n"),
+        pair("2020-05-17", "2020-05-17 f\n")
+    ]);
+}
+
+#[test]
+fn stack_traces_stay_with_their_entry() {
+    let input = concat!(
+        "\tat com.foo.Main.main(Main.java:3)\n",
+        "2023-10-11 23:59:59,001 ERROR Request failed\n",
+        "java.lang.IllegalStateException: oops\n",
+        "\tat com.foo.Bar.baz(Bar.java:42)\n",
+        "2023-10-12 00:00:00,002 INFO Next day\n").as_bytes();
+    let mut buckets = Vec::new();
+    split_lines(&LogType::Iso, input, &Options::default(), |bucket| {
+        let buffer = std::rc::Rc::default();
+        let name = bucket.map_or_else(|| "unknown".to_owned(), ToString::to_string);
+        buckets.push((name, std::rc::Rc::clone(&buffer)));
+        Ok(Shared(buffer))
+    }).unwrap();
+    let buckets: Vec<(String, String)> = buckets.into_iter()
+        .map(|(b, buf)| (b, String::from_utf8(buf.take()).unwrap()))
+        .collect();
+    assert_eq!(buckets, vec![
+        // Nothing tells which entry leading continuation lines belong to
+        ("unknown".to_owned(), "\tat com.foo.Main.main(Main.java:3)\n".to_owned()),
+        ("2023-10-11".to_owned(), concat!(
+            "2023-10-11 23:59:59,001 ERROR Request failed\n",
+            "java.lang.IllegalStateException: oops\n",
+            "\tat com.foo.Bar.baz(Bar.java:42)\n").to_owned()),
+        ("2023-10-12".to_owned(), "2023-10-12 00:00:00,002 INFO Next day\n".to_owned())
+    ]);
+}
+
+#[test]
+fn non_json_lines_of_json_logs_stay_out_of_buckets() {
+    let input = concat!("{\"time\":\"2023-10-11T22:14:15Z\",\"msg\":\"a\"}\n",
+                        "Traceback (most recent call last):\n",
+                        "{\"time\":\"2023-10-11T22:14:16Z\",\"msg\":\"b\"}\n").as_bytes();
+    let mut buckets = Vec::new();
+    split_lines(&LogType::Json, input, &Options::default(), |bucket| {
+        let buffer = std::rc::Rc::default();
+        let name = bucket.map_or_else(|| "unknown".to_owned(), ToString::to_string);
+        buckets.push((name, std::rc::Rc::clone(&buffer)));
+        Ok(Shared(buffer))
+    }).unwrap();
+    let buckets: Vec<(String, String)> = buckets.into_iter()
+        .map(|(b, buf)| (b, String::from_utf8(buf.take()).unwrap()))
+        .collect();
+    assert_eq!(buckets, vec![
+        ("2023-10-11".to_owned(), concat!("{\"time\":\"2023-10-11T22:14:15Z\",\"msg\":\"a\"}\n",
+                                          "{\"time\":\"2023-10-11T22:14:16Z\",\"msg\":\"b\"}\n")
+         .to_owned()),
+        ("unknown".to_owned(), "Traceback (most recent call last):\n".to_owned())
+    ]);
+}
+
+#[test]
+fn yearless_dates_follow_the_reference_date() {
+    let january = chrono::NaiveDate::from_ymd_opt(2021, 1, 2).unwrap();
+    let syslog = "Dec 31 23:59:59 host sshd[1234]: Connection closed";
+    assert_eq!(determine_date_at(&LogType::Syslog, syslog, &january).ok(),
+               Some("2020-12-31".to_owned()));
+    let syslog = "Jan  1 00:00:01 host sshd[1234]: Connection closed";
+    assert_eq!(determine_date_at(&LogType::Syslog, syslog, &january).ok(),
+               Some("2021-01-01".to_owned()));
+    let cisco = "000123: *Nov 30 00:00:12.345 UTC: %LINK-3-UPDOWN: Interface up";
+    assert_eq!(determine_date_at(&LogType::CiscoSyslog, cisco, &january).ok(),
+               Some("2020-11-30".to_owned()));
+    // Years written in the line win
+    let cisco = "Nov 30 2017 22:14:15: %ASA-6-302013: Built inbound TCP connection";
+    assert_eq!(determine_date_at(&LogType::CiscoSyslog, cisco, &january).ok(),
+               Some("2017-11-30".to_owned()));
+}
+
+#[test]
+fn yearless_files_crossing_new_year_keep_their_order() {
+    // Last modified after the new year, like any file rotated in February
+    let february = chrono::NaiveDate::from_ymd_opt(2021, 2, 3).unwrap();
+    let input = concat!("Nov 30 23:59:59 host a\n",
+                        "Dec 31 23:59:59 host b\n",
+                        "Jan  1 00:00:01 host c\n",
+                        "Feb  2 00:00:01 host d\n").as_bytes();
+    let mut buckets = Vec::new();
+    split_lines_at(&LogType::Syslog, &february, input, &Options::default(), |bucket| {
+        buckets.push(bucket.map_or_else(|| "unknown".to_owned(), ToString::to_string));
+        Ok(Shared(std::rc::Rc::default()))
+    }).unwrap();
+    assert_eq!(buckets, vec!["2020-11-30", "2020-12-31", "2021-01-01", "2021-02-02"]);
+}
+
+#[test]
+fn offsets_are_converted_to_utc_when_asked() {
+    let buckets_of = |logtype: LogType, input: &str, options: &Options| {
+        let mut buckets = Vec::new();
+        split_lines(&logtype, input.as_bytes(), options, |bucket| {
+            buckets.push(bucket.map_or_else(|| "unknown".to_owned(), ToString::to_string));
+            Ok(Shared(std::rc::Rc::default()))
+        }).unwrap();
+        buckets
+    };
+    let utc = Options { timezone: Timezone::Utc, ..Options::default() };
+    let grafana = "t=2023-10-11T00:30:00+0200 lvl=info msg=a\n";
+    assert_eq!(buckets_of(LogType::GrafanaLogs, grafana, &Options::default()), vec!["2023-10-11"]);
+    assert_eq!(buckets_of(LogType::GrafanaLogs, grafana, &utc), vec!["2023-10-10"]);
+    let apache = "[2001:db8::1] - - [11/Oct/2023:00:30:00 +0200] \"GET / HTTP/1.1\" 200 1\n";
+    assert_eq!(buckets_of(LogType::ApacheAccess, apache, &utc), vec!["2023-10-10"]);
+    let nginx = "10.0.0.2 - - [10/Oct/2023:23:30:00 -0100] \"GET /\"\n";
+    assert_eq!(buckets_of(LogType::NginxAccess, nginx, &utc), vec!["2023-10-11"]);
+    let hourly = Options { granularity: Granularity::Hour, ..utc.clone() };
+    let rfc5424 = "<34>1 2023-10-11T00:30:00.003+02:00 host su - - - a\n";
+    assert_eq!(buckets_of(LogType::Syslog5424, rfc5424, &hourly), vec!["2023-10-10T22"]);
+    // Lines without an offset keep their date
+    let nginx = "10.0.0.2 - - [10/Oct/2023:23:30:00] \"GET /\"\n";
+    assert_eq!(buckets_of(LogType::NginxAccess, nginx, &utc), vec!["2023-10-10"]);
+    assert_eq!(buckets_of(LogType::Iso, "2023-10-11 00:30:00 a\n", &utc), vec!["2023-10-11"]);
+}
+
+#[test]
+fn custom_formats_with_an_offset_follow_the_timezone() {
+    let format = CustomFormat {
+        name: "edge".to_owned(),
+        regex: r"^@(?P<ts>\S+ \S+)".parse().unwrap(),
+        format: "%Y/%m/%d %H:%M:%S%z".to_owned()
+    };
+    let input = "@2023/10/11 00:30:00+0200 a\n".as_bytes();
+    let options = Options {
+        formats: vec![format],
+        granularity: Granularity::Hour,
+        timezone: Timezone::Utc,
+        ..Options::default()
+    };
+    let edge = LogType::Custom("edge".to_owned());
+    let mut buckets = Vec::new();
+    split_lines(&edge, input, &options, |bucket| {
+        buckets.push(bucket.map_or_else(|| "unknown".to_owned(), ToString::to_string));
+        Ok(std::io::sink())
+    }).unwrap();
+    assert_eq!(buckets, vec!["2023-10-10T22"]);
+    // Without the options of the run, the format is unknown
+    assert!(determine_date(&edge, "@2023/10/11 00:30:00+0200 a").is_err());
+}
+
+#[test]
+fn weekly_buckets_cross_years_with_their_week() {
+    let input = concat!("2020-12-27 sunday of week 52\n",
+                        "2020-12-28 monday of week 53\n",
+                        "2021-01-03 sunday of week 53, in 2021\n",
+                        "2021-01-04 monday of week 1\n",
+                        "2023-10-15 22:14:15 sunday of week 41\n",
+                        "2023-10-16 00:00:01 monday of week 42\n").as_bytes();
+    let options = Options { granularity: Granularity::Week, ..Options::default() };
+    let mut buckets = Vec::new();
+    split_lines(&LogType::Iso, input, &options, |bucket| {
+        buckets.push(bucket.map_or_else(|| "unknown".to_owned(), ToString::to_string));
+        Ok(Shared(std::rc::Rc::default()))
+    }).unwrap();
+    assert_eq!(buckets, vec!["2020-W52", "2020-W53", "2021-W01", "2023-W41", "2023-W42"]);
+    assert_eq!(determine_bucket(&LogType::Syslog5424,
+                                "<34>1 2023-10-15T23:30:00-02:00 host su - - - a",
+                                Granularity::Week).ok(),
+               Some("2023-W41".to_owned()));
+}
+
+#[test]
+fn month_names_in_any_case_and_indented_lines() {
+    let cases = [
+        ("JAN 12 02:07:16 host sshd[1234]: closed", LogType::Syslog, "-01-12"),
+        ("   2023-10-11 22:14:15 indented", LogType::Iso, "2023-10-11"),
+        ("\tt=2023-10-11T22:14:15+0200 lvl=info msg=a", LogType::GrafanaLogs, "2023-10-11"),
+        ("  # Time: 2023-10-11T13:55:36Z", LogType::MysqlSlow, "2023-10-11"),
+        ("127.0.0.1 - - [11/OCT/2023:22:14:15 +0200] \"GET /\" 200 1",
+         LogType::ApacheAccess, "2023-10-11"),
+        ("10.0.0.2 - - [11/oct/2023:22:14:15] \"GET /\"", LogType::NginxAccess, "2023-10-11"),
+        ("Oct 11 2023 22:14:15: %ASA-6-302013: Built", LogType::CiscoSyslog, "2023-10-11"),
+        (" <189>123: host: *OCT 11 2023 22:14:15.003: %SYS-5-CONFIG_I: Configured",
+         LogType::CiscoSyslog, "2023-10-11"),
+    ];
+    for (line, logtype, date) in cases.iter() {
+        assert_eq!(detect_line(line).as_ref(), Some(logtype), "{}", line);
+        let found = determine_date(logtype, line).unwrap();
+        assert!(found.ends_with(date), "{} : {}", line, found);
+    }
+    assert_eq!(determine_bucket(&LogType::Iso, "  2023-10-11 22:14:15 a", Granularity::Hour).ok(),
+               Some("2023-10-11T22".to_owned()));
+    // A missing month is not an empty one
+    assert_eq!(detect_line("[Sat  17 02:07:16.656808 2020] [core:error] oops"), None);
+}
+
+#[test]
+fn apache_error_from_apache_2_4() {
+    let line = "[Wed Oct 11 14:32:52.123456 2023] [core:error] [pid 35708:tid 4328636416] \
+                [client 72.15.99.187] File does not exist: /usr/local/apache2/htdocs/favicon.ico";
+    assert_eq!(detect_line(line), Some(LogType::ApacheError));
+    assert_eq!(determine_date(&LogType::ApacheError, line).ok(),
+               Some("2023-10-11".to_owned()));
+    assert_eq!(determine_bucket(&LogType::ApacheError, line, Granularity::Hour).ok(),
+               Some("2023-10-11T14".to_owned()));
+    // The last second of a year is still in that year
+    let line = "[Sun Dec 31 23:59:59.999999 2023] [mpm_event:notice] [pid 1:tid 2] AH00489";
+    assert_eq!(determine_date(&LogType::ApacheError, line).ok(),
+               Some("2023-12-31".to_owned()));
+}
+
+#[test]
+fn docker_envelopes_are_unwrapped_on_demand() {
+    let input = concat!(
+        r#"{"log":"GET / 200\n","stream":"stdout","time":"2023-10-11T13:55:36.123456789Z"}"#, "\n",
+        r#"{"log":"partial","stream":"stdout","time":"2023-10-12T00:00:01.5Z"}"#, "\n",
+        "not json\n");
+    let split = |options: &Options| {
+        let mut buckets = Vec::new();
+        split_lines(&LogType::DockerJson, input.as_bytes(), options, |bucket| {
+            let buffer = std::rc::Rc::default();
+            let name = bucket.map_or_else(|| "unknown".to_owned(), ToString::to_string);
+            buckets.push((name, std::rc::Rc::clone(&buffer)));
+            Ok(Shared(buffer))
+        }).unwrap();
+        buckets.into_iter()
+            .map(|(b, buf)| format!("{}: {}", b, String::from_utf8(buf.take()).unwrap()))
+            .collect::<Vec<String>>()
+    };
+    assert_eq!(split(&Options { unwrap_docker: true, ..Options::default() }), vec![
+        "2023-10-11: GET / 200\n",
+        "2023-10-12: partial\n",
+        "unknown: not json\n"
+    ]);
+    assert!(split(&Options::default())[0].starts_with("2023-10-11: {\"log\":"));
+}
+
+#[test]
+fn mixed_files_date_every_line_by_its_own_format() {
+    let input = concat!(
+        "2023-10-11 12:00:00 fail2ban.filter [1234]: INFO\n",
+        "Oct 12 22:14:15 host sshd[1234]: Accepted publickey\n",
+        "  continued\n",
+        "127.0.0.1 - - [13/Oct/2023:02:07:16 +0200] \"GET / HTTP/1.1\" 200 42\n");
+    let reference = chrono::NaiveDate::from_ymd_opt(2023, 12, 1).unwrap();
+    let split = |options: &Options| {
+        let mut buckets = Vec::new();
+        split_lines_at(&LogType::Iso, &reference, input.as_bytes(), options, |bucket| {
+            let buffer = std::rc::Rc::default();
+            let name = bucket.map_or_else(|| "unknown".to_owned(), ToString::to_string);
+            buckets.push((name, std::rc::Rc::clone(&buffer)));
+            Ok(Shared(buffer))
+        }).unwrap();
+        buckets.into_iter()
+            .map(|(b, buf)| (b, String::from_utf8(buf.take()).unwrap().lines().count()))
+            .collect::<Vec<(String, usize)>>()
+    };
+    let pair = |b: &str, lines: usize| (b.to_owned(), lines);
+    assert_eq!(split(&Options { mixed: true, ..Options::default() }), vec![
+        pair("2023-10-11", 1), pair("2023-10-12", 2), pair("2023-10-13", 1)
+    ]);
+    // Otherwise, lines of other formats follow the last line of the file's
+    assert_eq!(split(&Options::default()), vec![pair("2023-10-11", 4)]);
+}
+
+#[test]
+fn journal_export_records_are_split_whole() {
+    let line = "__REALTIME_TIMESTAMP=1697032536123456";
+    assert_eq!(detect_line(line), Some(LogType::JournalExport));
+    assert_eq!(determine_bucket(&LogType::JournalExport, line, Granularity::Hour).ok(),
+               Some("2023-10-11T13".to_owned()));
+    assert_eq!("journal".parse(), Ok(LogType::JournalExport));
+
+    let first = b"__CURSOR=a\n__REALTIME_TIMESTAMP=1697032536123456\nMESSAGE=one\n\n".to_vec();
+    let undated = b"__CURSOR=b\nMESSAGE=no time\n\n".to_vec();
+    // A binary field, whose value holds a line feed and is not UTF-8, and no
+    // empty line at the end of the input
+    let mut last = b"__REALTIME_TIMESTAMP=1697112000000000\nMESSAGE\n".to_vec();
+    let value = [&b"two\n"[..], &[0xff; 196]].concat();
+    last.extend_from_slice(&(value.len() as u64).to_le_bytes());
+    last.extend_from_slice(&value);
+    last.extend_from_slice(b"\n_PID=1\n");
+    let input = [&first[..], &undated, &last].concat();
+
+    let mut buckets = Vec::new();
+    let lines = split_lines(&LogType::JournalExport, &input[..], &Options::default(), |bucket| {
+        let buffer = std::rc::Rc::default();
+        buckets.push((bucket.map(ToString::to_string), std::rc::Rc::clone(&buffer)));
+        Ok(Shared(buffer))
+    }).unwrap();
+    assert_eq!(lines, input.iter().filter(|b| **b == b'\n').count() as u64);
+    let buckets: Vec<(Option<String>, Vec<u8>)> = buckets.into_iter()
+        .map(|(bucket, buffer)| (bucket, buffer.take()))
+        .collect();
+    assert_eq!(buckets, vec![
+        (Some("2023-10-11".to_owned()), first),
+        (None, undated),
+        (Some("2023-10-12".to_owned()), [&last[..], b"\n"].concat())
+    ]);
+}
+
+#[test]
+fn basic_iso_dates_without_separators() {
+    let line = "20231011 13:55:36 watchdog: ping";
+    assert_eq!(detect_line(line), Some(LogType::IsoBasic));
+    assert_eq!(determine_date(&LogType::IsoBasic, line).ok(), Some("2023-10-11".to_owned()));
+    assert_eq!(determine_bucket(&LogType::IsoBasic, line, Granularity::Hour).ok(),
+               Some("2023-10-11T13".to_owned()));
+    assert_eq!(detect_line("20231011T135536 boot"), Some(LogType::IsoBasic));
+    // Extended dates stay Iso, and plain numbers are no dates
+    assert_eq!(detect_line("2023-10-11 13:55:36 a"), Some(LogType::Iso));
+    assert_eq!(detect_line("20231011: 42 requests"), None);
+    assert_eq!(detect_line("123456789 bytes"), None);
+    assert!(determine_date(&LogType::IsoBasic, "20231345 13:55:36 a").is_err());
+}
+
+#[test]
+fn iis_w3c_directives_stay_out_of_buckets() {
+    let input = concat!("#Software: Microsoft Internet Information Services 10.0\n",
+                        "#Fields: date time s-ip cs-method cs-uri-stem sc-status\n",
+                        "2023-10-11 13:55:36 10.0.0.1 GET /index.html 200\n",
+                        "#Fields: date time cs-method sc-status\n",
+                        "2023-10-12 00:00:01 GET 404\n");
+    assert_eq!(detect_line(input.lines().next().unwrap()), Some(LogType::IisW3c));
+    assert_eq!(detect_line("2023-10-11 13:55:36 10.0.0.1 GET / 200"), Some(LogType::Iso));
+    assert_eq!(determine_bucket(&LogType::IisW3c, "2023-10-11 13:55:36 GET 200",
+                                Granularity::Hour).ok(),
+               Some("2023-10-11T13".to_owned()));
+    let mut buckets = Vec::new();
+    split_lines(&LogType::IisW3c, input.as_bytes(), &Options::default(), |bucket| {
+        let buffer = std::rc::Rc::default();
+        let name = bucket.map_or_else(|| "unknown".to_owned(), ToString::to_string);
+        buckets.push((name, std::rc::Rc::clone(&buffer)));
+        Ok(Shared(buffer))
+    }).unwrap();
+    let buckets: Vec<(String, String)> = buckets.into_iter()
+        .map(|(b, buf)| (b, String::from_utf8(buf.take()).unwrap()))
+        .collect();
+    assert_eq!(buckets, vec![
+        ("unknown".to_owned(), concat!("#Software: Microsoft Internet Information Services 10.0\n",
+                                       "#Fields: date time s-ip cs-method cs-uri-stem sc-status\n",
+                                       "#Fields: date time cs-method sc-status\n").to_owned()),
+        ("2023-10-11".to_owned(), "2023-10-11 13:55:36 10.0.0.1 GET /index.html 200\n".to_owned()),
+        ("2023-10-12".to_owned(), "2023-10-12 00:00:01 GET 404\n".to_owned())
+    ]);
+    // Their times are in UTC
+    let offset = Options { timezone: Timezone::Offset, ..Options::default() };
+    let mut names = Vec::new();
+    split_lines(&LogType::IisW3c, "2023-10-11 13:55:36 GET 200\n".as_bytes(), &offset,
+                |bucket| {
+        names.push(bucket.map_or_else(|| "unknown".to_owned(), ToString::to_string));
+        Ok(Shared(std::rc::Rc::default()))
+    }).unwrap();
+    assert_eq!(names, vec!["2023-10-11+0000"]);
+}